@@ -0,0 +1,201 @@
+use std::{fmt, error::Error, sync::mpsc::{self, Sender, Receiver}, thread::{self, JoinHandle}};
+
+use crate::compgraph::*;
+use crate::output::{Output, Evaluation};
+
+// A graph owned by a thread of its own, for using it from multithreaded programs
+//
+// The graph isn't `Send`, so `GraphActor::spawn` builds it on the actor's thread, naming the
+// inputs and outputs other threads can use, then runs the commands sent from any thread in
+// order: setting inputs (several at once like `config`'s `apply`), computing outputs and
+// subscribing to them. Each command answers on a `Reply` of its own, which can be waited for
+// or polled; a subscription is a channel getting the output's value, then each new one. The
+// actor stops when the `GraphActor` is dropped, after the commands sent before; handles then
+// answer `ActorError::Stopped`
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ActorError {
+    UnknownInput(String),
+    UnknownOutput(String),
+    // the actor stopped, or panicked, before answering
+    Stopped
+}
+
+impl fmt::Display for ActorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActorError::UnknownInput(name) => write!(f, "no input named `{}`", name),
+            ActorError::UnknownOutput(name) => write!(f, "no output named `{}`", name),
+            ActorError::Stopped => write!(f, "the graph actor stopped")
+        }
+    }
+}
+
+impl Error for ActorError {}
+
+// The graph on the actor's thread, while it's being built
+#[derive(Default)]
+pub struct ActorGraph {
+    inputs: Vec<(String, Input)>,
+    // made eager when first subscribed to
+    outputs: Vec<(String, Operand, Option<Output>)>
+}
+
+impl ActorGraph {
+    pub fn add_input(&mut self, name: &str, input: Input) {
+        self.inputs.push((name.to_string(), input));
+    }
+
+    pub fn add_output(&mut self, name: &str, output: impl ComputeNodeRef + 'static) {
+        let output = output.as_dynamic().map_or_else(|| Operand::Constant(output.compute()), Operand::Node);
+        self.outputs.push((name.to_string(), output, None));
+    }
+
+    fn input(&self, name: &str) -> Result<&Input, ActorError> {
+        self.inputs.iter().find(|(input, _)| input == name).map(|(_, input)| input)
+            .ok_or_else(|| ActorError::UnknownInput(name.to_string()))
+    }
+
+    fn output(&mut self, name: &str) -> Result<&mut (String, Operand, Option<Output>), ActorError> {
+        self.outputs.iter_mut().find(|(output, _, _)| output == name)
+            .ok_or_else(|| ActorError::UnknownOutput(name.to_string()))
+    }
+
+    fn run(&mut self, command: Command) {
+        // the replies are dropped unanswered when their receivers are
+        match command {
+            Command::Set(values, reply) => {
+                let inputs: Result<Vec<(&Input, Float)>, _> = values.iter()
+                    .map(|(name, value)| Ok((self.input(name)?, *value))).collect();
+                // nothing is set unless all of them can be
+                let _ = reply.send(inputs.map(set_all));
+            }
+            Command::Get(name, reply) => {
+                let _ = reply.send(self.input(&name).map(|input| input.compute()));
+            }
+            Command::Compute(name, reply) => {
+                let _ = reply.send(self.output(&name).map(|(_, output, _)| output.compute()));
+            }
+            Command::Subscribe(name, reply) => {
+                let subscription = self.output(&name).map(|(_, output, eager)| {
+                    let eager = eager.get_or_insert_with(|| Output::new(output.clone(), Evaluation::Eager));
+                    let (sender, receiver) = mpsc::channel();
+                    let _ = sender.send(eager.get());
+                    eager.forward(sender);
+                    receiver
+                });
+                let _ = reply.send(subscription);
+            }
+            Command::Stop => {}
+        }
+    }
+}
+
+enum Command {
+    Set(Vec<(String, Float)>, Sender<Result<(), ActorError>>),
+    Get(String, Sender<Result<Float, ActorError>>),
+    Compute(String, Sender<Result<Float, ActorError>>),
+    Subscribe(String, Sender<Result<Receiver<Float>, ActorError>>),
+    Stop
+}
+
+// The answer to a command, sent once
+pub struct Reply<T> {
+    receiver: Receiver<Result<T, ActorError>>
+}
+
+impl<T> Reply<T> {
+    pub fn wait(self) -> Result<T, ActorError> {
+        self.receiver.recv().unwrap_or(Err(ActorError::Stopped))
+    }
+
+    // `None` while the command hasn't run yet
+    pub fn try_get(&self) -> Option<Result<T, ActorError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(ActorError::Stopped))
+        }
+    }
+}
+
+// Sending commands to the actor, from any thread
+#[derive(Clone)]
+pub struct ActorHandle {
+    commands: Sender<Command>
+}
+
+impl ActorHandle {
+    fn send<T>(&self, command: impl FnOnce(Sender<Result<T, ActorError>>) -> Command) -> Reply<T> {
+        let (sender, receiver) = mpsc::channel();
+        // a stopped actor drops the sender, which the reply answers as `Stopped`
+        let _ = self.commands.send(command(sender));
+        Reply { receiver }
+    }
+
+    pub fn set(&self, input: &str, value: Float) -> Reply<()> {
+        self.set_all(&[(input, value)])
+    }
+
+    // All of them before any of the work their changes queue runs
+    pub fn set_all(&self, values: &[(&str, Float)]) -> Reply<()> {
+        let values = values.iter().map(|(name, value)| (name.to_string(), *value)).collect();
+        self.send(|reply| Command::Set(values, reply))
+    }
+
+    pub fn get(&self, input: &str) -> Reply<Float> {
+        self.send(|reply| Command::Get(input.to_string(), reply))
+    }
+
+    pub fn compute(&self, output: &str) -> Reply<Float> {
+        self.send(|reply| Command::Compute(output.to_string(), reply))
+    }
+
+    pub fn subscribe(&self, output: &str) -> Reply<Receiver<Float>> {
+        self.send(|reply| Command::Subscribe(output.to_string(), reply))
+    }
+}
+
+pub struct GraphActor {
+    handle: ActorHandle,
+    thread: Option<JoinHandle<()>>
+}
+
+impl GraphActor {
+    pub fn spawn(build: impl FnOnce(&mut ActorGraph) + Send + 'static) -> GraphActor {
+        let (commands, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut graph = ActorGraph::default();
+            build(&mut graph);
+            while let Ok(command) = receiver.recv() {
+                if let Command::Stop = command {
+                    break;
+                }
+                graph.run(command);
+            }
+        });
+        GraphActor { handle: ActorHandle { commands }, thread: Some(thread) }
+    }
+
+    // For other threads, which can keep it after the actor stops
+    pub fn handle(&self) -> ActorHandle {
+        self.handle.clone()
+    }
+}
+
+impl std::ops::Deref for GraphActor {
+    type Target = ActorHandle;
+    fn deref(&self) -> &ActorHandle {
+        &self.handle
+    }
+}
+
+impl Drop for GraphActor {
+    fn drop(&mut self) {
+        let _ = self.handle.commands.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            // a panic on the actor's thread has already answered `Stopped`
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::compgraph::*;
+use crate::autodiff::{self, NoDerivative};
+use crate::random::SplitMix64;
+
+// Sensitivity analysis: how much the output moves when each input does
+//
+// Every input is moved by `relative_delta` of its value (or by `relative_delta` itself for
+// inputs at zero), either by actually setting it and recomputing the output, or estimated
+// from the gradient. The report is sorted by the size of the change in the output, largest
+// first. Inputs are left at their values
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensitivityMethod {
+    // one-sided finite difference, works for any graph
+    Perturbation,
+    // linear estimate from a single autodiff pass
+    Gradient
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sensitivity {
+    pub name: String,
+    pub value: Float,
+    // d output / d input
+    pub derivative: Float,
+    // the change in the output for the perturbation of this input
+    pub change: Float,
+    // relative change in the output per relative change in the input, NaN for a zero output
+    pub elasticity: Float
+}
+
+pub fn sensitivity<P: InputNodeRef>(
+    output: &impl ComputeNodeRef, inputs: &[(&str, P)], relative_delta: Float, method: SensitivityMethod
+) -> Result<Vec<Sensitivity>, NoDerivative> {
+    let base = output.compute();
+    let gradients = match method {
+        SensitivityMethod::Gradient => Some(autodiff::gradients(output)?),
+        SensitivityMethod::Perturbation => None
+    };
+    let mut report: Vec<Sensitivity> = inputs.iter().map(|(name, input)| {
+        let value = input.compute();
+        let step = if value == 0.0 { relative_delta } else { relative_delta * value.abs() };
+        let (derivative, change) = match &gradients {
+            Some(gradients) => {
+                let derivative = gradients.wrt(input);
+                (derivative, derivative * step)
+            }
+            None => {
+                input.set(value + step);
+                let change = output.compute() - base;
+                input.set(value);
+                (change / step, change)
+            }
+        };
+        let elasticity = if base == 0.0 { Float::NAN } else { derivative * value / base };
+        Sensitivity { name: name.to_string(), value, derivative, change, elasticity }
+    }).collect();
+    report.sort_by(|a, b| b.change.abs().total_cmp(&a.change.abs()));
+    Ok(report)
+}
+
+// Monte-Carlo uncertainty propagation
+//
+// Each input is drawn from its distribution and the output computed, `samples` times, with
+// draws reproducible from `seed`. Inputs are left at their values
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Uniform { low: Float, high: Float },
+    Normal { mean: Float, std_dev: Float },
+    // the usual model of a tolerance: most likely at `mode`, never outside [low, high]
+    Triangular { low: Float, mode: Float, high: Float }
+}
+
+impl Distribution {
+    fn sample(&self, rng: &mut SplitMix64) -> Float {
+        match *self {
+            Distribution::Uniform { low, high } => {
+                (low as f64 + (high - low) as f64 * rng.next_f64()) as Float
+            }
+            Distribution::Normal { mean, std_dev } => (mean as f64 + std_dev as f64 * rng.next_normal()) as Float,
+            Distribution::Triangular { low, mode, high } => {
+                let (low, mode, high) = (low as f64, mode as f64, high as f64);
+                let u = rng.next_f64();
+                let split = (mode - low) / (high - low);
+                let value = if u < split {
+                    low + (u * (high - low) * (mode - low)).sqrt()
+                } else {
+                    high - ((1.0 - u) * (high - low) * (high - mode)).sqrt()
+                };
+                value as Float
+            }
+        }
+    }
+}
+
+pub struct MonteCarlo {
+    pub mean: Float,
+    // sample variance
+    pub variance: Float,
+    // sorted
+    samples: Vec<Float>
+}
+
+impl MonteCarlo {
+    pub fn std_dev(&self) -> Float {
+        self.variance.sqrt()
+    }
+
+    pub fn samples(&self) -> &[Float] {
+        &self.samples
+    }
+
+    pub fn min(&self) -> Float {
+        self.samples[0]
+    }
+
+    pub fn max(&self) -> Float {
+        self.samples[self.samples.len() - 1]
+    }
+
+    // The `percent`th percentile, interpolating between samples
+    pub fn percentile(&self, percent: Float) -> Float {
+        assert!((0.0..=100.0).contains(&percent), "percentile out of range: {}", percent);
+        let position = percent as f64 / 100.0 * (self.samples.len() - 1) as f64;
+        let (below, above) = (position.floor() as usize, position.ceil() as usize);
+        let fraction = (position - below as f64) as Float;
+        self.samples[below] + (self.samples[above] - self.samples[below]) * fraction
+    }
+}
+
+pub fn monte_carlo<P: InputNodeRef + 'static>(
+    output: &impl ComputeNodeRef, inputs: &[(P, Distribution)], samples: usize, seed: u64
+) -> MonteCarlo {
+    assert!(samples > 0, "monte_carlo: no samples");
+    let values: Vec<Float> = inputs.iter().map(|(input, _)| input.compute()).collect();
+    let mut rng = SplitMix64::new(seed);
+    // a sample at a time, so that nothing sees one half drawn
+    let mut outputs: Vec<Float> = (0..samples).map(|_| {
+        set_all(inputs.iter().map(|(input, distribution)| (input, distribution.sample(&mut rng))));
+        output.compute()
+    }).collect();
+    set_all(inputs.iter().map(|(input, _)| input).zip(values));
+
+    let n = samples as f64;
+    let mean = outputs.iter().map(|&x| x as f64).sum::<f64>() / n;
+    let variance = if samples > 1 {
+        outputs.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    outputs.sort_by(Float::total_cmp);
+    MonteCarlo { mean: mean as Float, variance: variance as Float, samples: outputs }
+}
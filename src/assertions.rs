@@ -0,0 +1,137 @@
+use std::{rc::{Rc, Weak}, cell::RefCell, collections::HashSet, fmt, error::Error, panic::{self, AssertUnwindSafe}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::graph::{self, NodeId};
+
+// Runtime checks inside the graph
+//
+// Assertion nodes pass their child's value through, and stop the evaluation where a bad value
+// first appears rather than letting it spread to the outputs. As computing a node can't fail,
+// a violated assertion panics, with a message naming the node; `checked` evaluates an output
+// and turns the panic into an `AssertionError` with the path from the output down to the
+// node. Nothing computed by the failed evaluation above the assertion stays cached
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssertionError {
+    pub node: NodeId,
+    // the names of the nodes from the output down to the assertion, `add/mul/assert_finite`,
+    // empty if the assertion isn't below the output
+    pub path: String,
+    pub message: String
+}
+
+impl fmt::Display for AssertionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "assertion {} failed: {}", self.node, self.message)
+        } else {
+            write!(f, "assertion {} failed at {}: {}", self.node, self.path, self.message)
+        }
+    }
+}
+
+impl Error for AssertionError {}
+
+thread_local! {
+    // the assertion being unwound from, for `checked`
+    static FAILED: RefCell<Option<AssertionError>> = const { RefCell::new(None) };
+}
+
+struct Assertion<X> {
+    x: X,
+    range: Option<(Float, Float)>,
+    // set once the node is made, to name it in errors
+    this: Option<Weak<RefCell<dyn ComputeNodeMut>>>
+}
+
+impl<X: ComputeNodeRef> Assertion<X> {
+    fn violation(&self, value: Float) -> Option<String> {
+        match self.range {
+            _ if value.is_nan() => Some("value is NaN".to_string()),
+            None if value.is_infinite() => Some(format!("value is {}", value)),
+            Some((lo, hi)) if !(lo..=hi).contains(&value) => Some(format!("{} is outside [{}, {}]", value, lo, hi)),
+            _ => None
+        }
+    }
+}
+
+impl<X: ComputeNodeRef> ComputeMut for Assertion<X> {
+    fn compute(&mut self) -> Float {
+        let value = self.x.compute();
+        if let Some(message) = self.violation(value) {
+            let node = graph::node_id(&self.this.as_ref().and_then(Weak::upgrade).unwrap());
+            let error = AssertionError { node, path: String::new(), message };
+            let description = error.to_string();
+            FAILED.with(|failed| *failed.borrow_mut() = Some(error));
+            panic!("{}", description);
+        }
+        value
+    }
+    fn name(&self) -> &'static str {
+        if self.range.is_some() { "assert_in_range" } else { "assert_finite" }
+    }
+    fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(assertion(children[0].clone(), self.range))
+    }
+}
+
+fn assertion(x: impl ComputeNodeRef + 'static, range: Option<(Float, Float)>) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(Assertion { x, range, this: None });
+    result.borrow_mut().inner.this = Some(Rc::downgrade(&(result.clone() as DynamicComputeNodeRef)));
+    let subscriber = result.clone() as _;
+    result.borrow().inner.x.subscribe_to_invalidate(&subscriber);
+    result
+}
+
+// Fails on NaN and infinities
+pub fn assert_finite(x: impl IntoNodeRef) -> DynamicComputeNodeRef {
+    assertion(x.into_node_ref(), None)
+}
+
+// Fails outside [lo, hi], and on NaN
+pub fn assert_in_range(x: impl IntoNodeRef, lo: Float, hi: Float) -> DynamicComputeNodeRef {
+    assertion(x.into_node_ref(), Some((lo, hi)))
+}
+
+pub fn checked(output: &impl ComputeNodeRef) -> Result<Float, AssertionError> {
+    FAILED.with(|failed| failed.borrow_mut().take());
+    match panic::catch_unwind(AssertUnwindSafe(|| output.compute())) {
+        Ok(value) => Ok(value),
+        Err(payload) => {
+            let Some(mut error) = FAILED.with(|failed| failed.borrow_mut().take()) else {
+                panic::resume_unwind(payload)
+            };
+            if let Some(output) = output.as_dynamic() {
+                let mut names = Vec::new();
+                if path_to(&output, error.node, &mut names, &mut HashSet::new()) {
+                    error.path = names.join("/");
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+// The names on the way from `node` down to the node with the id, depth first
+fn path_to(node: &DynamicComputeNodeRef, target: NodeId, names: &mut Vec<&'static str>, visited: &mut HashSet<*const ()>) -> bool {
+    if !visited.insert(node_address(node)) {
+        return false;
+    }
+    names.push(node.borrow().name());
+    if graph::node_id(node) == target {
+        return true;
+    }
+    let children = node.borrow().children();
+    for child in children {
+        if let Child::Node(child) = child {
+            if path_to(&child, target, names, visited) {
+                return true;
+            }
+        }
+    }
+    names.pop();
+    false
+}
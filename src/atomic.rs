@@ -0,0 +1,77 @@
+use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+use crate::compgraph::*;
+
+// Inputs written from other threads without locks, for high-rate producers (sensors, audio
+// control, market data)
+//
+// A writer stores the latest value in an atomic cell shared with the graph's thread, where
+// `AtomicInputs::sync` sets the inputs whose cells changed since the last sync, all before
+// the work their changes queue runs, so that a burst of writes costs a single invalidation
+// per input and a single round of deferred work per sync, however many values were written
+// in between. Writers never block nor wait for the graph; only the last value before a sync
+// is seen
+
+#[derive(Clone)]
+pub struct AtomicWriter {
+    cell: Arc<AtomicU32>
+}
+
+impl AtomicWriter {
+    pub fn set(&self, value: Float) {
+        self.cell.store(value.to_bits(), Ordering::Release);
+    }
+    // The value last written, maybe not synced yet
+    pub fn get(&self) -> Float {
+        Float::from_bits(self.cell.load(Ordering::Acquire))
+    }
+}
+
+struct Entry {
+    cell: Arc<AtomicU32>,
+    synced: u32,
+    assign: Box<dyn Fn(Float)>
+}
+
+#[derive(Default)]
+pub struct AtomicInputs {
+    entries: Vec<Entry>
+}
+
+impl AtomicInputs {
+    pub fn new() -> AtomicInputs {
+        AtomicInputs::default()
+    }
+
+    // Starts with the input's current value
+    pub fn add(&mut self, input: impl InputNodeRef + 'static) -> AtomicWriter {
+        let synced = input.compute().to_bits();
+        let cell = Arc::new(AtomicU32::new(synced));
+        let assign = Box::new(move |value| input.assign(value));
+        self.entries.push(Entry { cell: cell.clone(), synced, assign });
+        AtomicWriter { cell }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Sets the inputs written to since the last sync, returning how many changed
+    pub fn sync(&mut self) -> usize {
+        let mut changed = 0;
+        for entry in &mut self.entries {
+            let bits = entry.cell.load(Ordering::Acquire);
+            if bits != entry.synced {
+                entry.synced = bits;
+                (entry.assign)(Float::from_bits(bits));
+                changed += 1;
+            }
+        }
+        run_deferred();
+        changed
+    }
+}
@@ -0,0 +1,183 @@
+use std::{rc::Rc, cell::RefCell, ops::{Index, IndexMut}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::strict;
+
+// Block-based audio processing
+//
+// Graphs of value type `Block<N>` process `N` samples per evaluation instead of one, which
+// amortizes the cost of invalidation and caching over the whole block. Blocks are plain
+// arrays, so evaluation doesn't allocate: every node keeps its last block in its cache, which
+// is the preallocated buffer. `BlockProcessor` runs such a graph from an audio callback
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Block<const N: usize = 256>(pub [Float; N]);
+
+impl<const N: usize> Block<N> {
+    pub const SILENCE: Block<N> = Block([0.0; N]);
+
+    pub fn splat(value: Float) -> Block<N> {
+        Block([value; N])
+    }
+
+    pub fn map(mut self, op: impl Fn(Float) -> Float) -> Block<N> {
+        self.0.iter_mut().for_each(|sample| *sample = op(*sample));
+        self
+    }
+
+    pub fn zip(mut self, other: Block<N>, op: impl Fn(Float, Float) -> Float) -> Block<N> {
+        self.0.iter_mut().zip(other.0).for_each(|(sample, other)| *sample = op(*sample, other));
+        self
+    }
+}
+
+impl<const N: usize> Index<usize> for Block<N> {
+    type Output = Float;
+    fn index(&self, index: usize) -> &Float {
+        &self.0[index]
+    }
+}
+
+impl<const N: usize> IndexMut<usize> for Block<N> {
+    fn index_mut(&mut self, index: usize) -> &mut Float {
+        &mut self.0[index]
+    }
+}
+
+// Constant blocks can be used directly as nodes, like `Float` ones
+impl<const N: usize> ComputeNodeRef<Block<N>> for Block<N> {
+    fn compute(&self) -> Block<N> { *self }
+    fn subscribe_to_invalidate(&self, _subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {}
+}
+
+pub fn add<const N: usize>(
+    a: impl ComputeNodeRef<Block<N>> + 'static, b: impl ComputeNodeRef<Block<N>> + 'static
+) -> DynamicComputeNodeRef<Block<N>> {
+    binary_node(a, b, |a: Block<N>, b| a.zip(b, |a, b| a + b))
+}
+
+pub fn sub<const N: usize>(
+    a: impl ComputeNodeRef<Block<N>> + 'static, b: impl ComputeNodeRef<Block<N>> + 'static
+) -> DynamicComputeNodeRef<Block<N>> {
+    binary_node(a, b, |a: Block<N>, b| a.zip(b, |a, b| a - b))
+}
+
+// Sample by sample, as in ring modulation or applying an envelope
+pub fn mul<const N: usize>(
+    a: impl ComputeNodeRef<Block<N>> + 'static, b: impl ComputeNodeRef<Block<N>> + 'static
+) -> DynamicComputeNodeRef<Block<N>> {
+    binary_node(a, b, |a: Block<N>, b| a.zip(b, |a, b| a * b))
+}
+
+// Scales by a `Float` node, e.g. a volume control, which stays the same over the block
+pub fn gain<const N: usize>(
+    x: impl ComputeNodeRef<Block<N>> + 'static, gain: impl ComputeNodeRef + 'static
+) -> DynamicComputeNodeRef<Block<N>> {
+    binary_node(x, gain, |x: Block<N>, gain: Float| x.map(|x| x * gain))
+}
+
+// Hard clipping to [-limit, limit], for a limit of either sign; a NaN limit doesn't clip
+pub fn clip<const N: usize>(
+    x: impl ComputeNodeRef<Block<N>> + 'static, limit: impl ComputeNodeRef + 'static
+) -> DynamicComputeNodeRef<Block<N>> {
+    binary_node(x, limit, |x: Block<N>, limit: Float| {
+        let limit = limit.abs();
+        x.map(|x| x.max(-limit).min(limit))
+    })
+}
+
+pub fn map<const N: usize>(x: impl ComputeNodeRef<Block<N>> + 'static, op: fn(Float) -> Float) -> DynamicComputeNodeRef<Block<N>> {
+    let result = Rc::new(RefCell::new(CachingNodeWrapper::new(BlockMap { x, op })));
+    let subscriber = result.clone() as _;
+    result.borrow().inner.x.subscribe_to_invalidate(&subscriber);
+    result
+}
+
+struct BlockMap<X> {
+    x: X,
+    op: fn(Float) -> Float
+}
+
+impl<const N: usize, X: ComputeNodeRef<Block<N>>> ComputeMut<Block<N>> for BlockMap<X> {
+    fn compute(&mut self) -> Block<N> {
+        self.x.compute().map(self.op)
+    }
+}
+
+// Runs a block graph on buffers handed in by an audio callback: `process` copies the input
+// samples into the graph's input, evaluates it and copies the output block out
+pub struct BlockProcessor<const N: usize = 256> {
+    input: Input<Block<N>>,
+    output: DynamicComputeNodeRef<Block<N>>
+}
+
+impl<const N: usize> BlockProcessor<N> {
+    // `build` makes the output from the block input
+    pub fn new(build: impl FnOnce(Input<Block<N>>) -> DynamicComputeNodeRef<Block<N>>) -> BlockProcessor<N> {
+        let input = InputNodeImpl::new_ref(Block::SILENCE);
+        let output = build(input.clone());
+        BlockProcessor { input, output }
+    }
+
+    pub fn process(&self, input: &[Float; N], output: &mut [Float; N]) {
+        self.input.set(Block(*input));
+        *output = self.output.compute().0;
+    }
+}
+
+// Spectra of blocks by the FFT, for `N` a power of two
+//
+// `spectrum` transforms its input block once per change of the block; `magnitude` and
+// `phase` read the transform from it, so they share it. Bin `k` is the frequency k / N of
+// the sample rate
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spectrum<const N: usize = 256> {
+    pub re: [Float; N],
+    pub im: [Float; N]
+}
+
+// Iterative radix-2 Cooley-Tukey
+fn fft<const N: usize>(block: Block<N>) -> Spectrum<N> {
+    let (mut re, mut im) = (block.0, [0.0; N]);
+    if N > 1 {
+        let bits = N.trailing_zeros();
+        for i in 0..N {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i {
+                re.swap(i, j);
+            }
+        }
+    }
+    let mut len = 2;
+    while len <= N {
+        let angle = -std::f64::consts::TAU / len as f64;
+        for start in (0..N).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = strict::sin_cos_f64(angle * k as f64);
+                let (sin, cos) = (sin as Float, cos as Float);
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                (re[b], im[b]) = (re[a] - tr, im[a] - ti);
+                (re[a], im[a]) = (re[a] + tr, im[a] + ti);
+            }
+        }
+        len *= 2;
+    }
+    Spectrum { re, im }
+}
+
+pub fn spectrum<const N: usize>(x: impl ComputeNodeRef<Block<N>> + 'static) -> DynamicComputeNodeRef<Spectrum<N>> {
+    assert!(N.is_power_of_two(), "spectrum: block size {} is not a power of two", N);
+    unary_node(x, fft::<N>)
+}
+
+pub fn magnitude<const N: usize>(spectrum: impl ComputeNodeRef<Spectrum<N>> + 'static) -> DynamicComputeNodeRef<Block<N>> {
+    unary_node(spectrum, |spectrum: Spectrum<N>| Block(std::array::from_fn(|k| strict::hypot(spectrum.re[k], spectrum.im[k]))))
+}
+
+// In radians, in (-pi, pi]
+pub fn phase<const N: usize>(spectrum: impl ComputeNodeRef<Spectrum<N>> + 'static) -> DynamicComputeNodeRef<Block<N>> {
+    unary_node(spectrum, |spectrum: Spectrum<N>| Block(std::array::from_fn(|k| strict::atan2(spectrum.im[k], spectrum.re[k]))))
+}
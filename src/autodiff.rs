@@ -0,0 +1,128 @@
+use std::{fmt, error::Error, collections::HashMap};
+
+use crate::compgraph::*;
+use crate::graph;
+
+// Reverse-mode automatic differentiation
+//
+// Every node reports its children and the partial derivatives of its value with respect to
+// them (nodes from `define_nodes!` through their `grad` block). `gradients(&output)` visits
+// the graph below `output` once, parents before children, accumulating the derivative of
+// `output` with respect to every node on the way. Constants and nodes that `output` doesn't
+// depend on have a zero gradient
+//
+// `gradients_with` can differentiate nodes without a derivative rule numerically, such as
+// custom nodes defined without a `grad` block: the node is rebuilt on constants, its
+// children's values moved by epsilon one at a time, for central differences. Nodes that can't
+// be rebuilt, or have opaque children, still have no derivative
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoDerivative {
+    pub node: &'static str
+}
+
+impl fmt::Display for NoDerivative {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node `{}` has no derivative rule", self.node)
+    }
+}
+
+impl Error for NoDerivative {}
+
+pub struct Gradients {
+    adjoints: HashMap<*const (), Float>,
+    // keeps the nodes alive, so that their addresses can't be reused by other nodes
+    _graph: Vec<DynamicComputeNodeRef>
+}
+
+impl Gradients {
+    // d output / d node
+    pub fn wrt(&self, node: &impl ComputeNodeRef) -> Float {
+        node.as_dynamic().and_then(|node| self.adjoints.get(&node_address(&node)).copied()).unwrap_or(0.0)
+    }
+}
+
+// What to do with nodes without a derivative rule
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fallback {
+    Fail,
+    // (f(x + epsilon) - f(x - epsilon)) / 2 epsilon, child by child (see `check_gradients` on
+    // choosing `epsilon`)
+    CentralDifference { epsilon: Float }
+}
+
+impl Fallback {
+    fn partials(self, node: &dyn ComputeNodeMut) -> Option<Vec<Float>> {
+        let Fallback::CentralDifference { epsilon } = self else { return None };
+        let values: Vec<Float> = node.children().into_iter().map(|child| match child {
+            Child::Node(child) => Some(child.compute()),
+            Child::Constant(value) => Some(value),
+            Child::Opaque => None
+        }).collect::<Option<_>>()?;
+        // the probes are temporary, so a build in progress doesn't keep them
+        let at = |i: usize, value: Float| graph::outside_builds(|| {
+            let mut children: Vec<Operand> = values.iter().map(|value| Operand::Constant(*value)).collect();
+            children[i] = Operand::Constant(value);
+            node.rebuild(&children).map(|node| node.compute())
+        });
+        (0..values.len()).map(|i| Some((at(i, values[i] + epsilon)? - at(i, values[i] - epsilon)?) / (2.0 * epsilon))).collect()
+    }
+}
+
+pub fn gradients(output: &impl ComputeNodeRef) -> Result<Gradients, NoDerivative> {
+    gradients_with(output, Fallback::Fail)
+}
+
+pub fn gradients_with(output: &impl ComputeNodeRef, fallback: Fallback) -> Result<Gradients, NoDerivative> {
+    let mut adjoints = HashMap::new();
+    let Some(output) = output.as_dynamic() else {
+        return Ok(Gradients { adjoints, _graph: Vec::new() });
+    };
+    // the partials are computed from the children's cached values
+    output.compute();
+    adjoints.insert(node_address(&output), 1.0);
+
+    let graph = topological_order(&output);
+    for node in graph.iter().rev() {
+        let adjoint = adjoints.get(&node_address(node)).copied().unwrap_or(0.0);
+        let mut node = node.borrow_mut();
+        let partials = node.partials().or_else(|| fallback.partials(&*node)).ok_or(NoDerivative { node: node.name() })?;
+        let children = node.children();
+        assert_eq!(partials.len(), children.len(), "node `{}` has a partial for each child", node.name());
+        for (child, partial) in children.into_iter().zip(partials) {
+            if let Child::Node(child) = child {
+                *adjoints.entry(node_address(&child)).or_insert(0.0) += adjoint * partial;
+            }
+        }
+    }
+    Ok(Gradients { adjoints, _graph: graph })
+}
+
+// Analytic against numeric derivative with respect to one input
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientCheck {
+    pub analytic: Float,
+    pub numeric: Float,
+    // difference relative to the larger of the two, or absolute for small derivatives
+    pub error: Float
+}
+
+// Validates derivative rules by comparing the gradients of `node` with central finite
+// differences (f(x + epsilon) - f(x - epsilon)) / 2 epsilon, input by input. With `Float`
+// being f32, an `epsilon` around 1e-2 balances truncation against rounding error
+pub fn check_gradients<P: InputNodeRef>(node: &impl ComputeNodeRef, inputs: &[P], epsilon: Float) -> Result<Vec<GradientCheck>, NoDerivative> {
+    let gradients = gradients(node)?;
+    Ok(inputs.iter().map(|input| {
+        let x = input.compute();
+        input.set(x + epsilon);
+        let above = node.compute();
+        input.set(x - epsilon);
+        let below = node.compute();
+        input.set(x);
+
+        let analytic = gradients.wrt(input);
+        let numeric = (above - below) / (2.0 * epsilon);
+        let error = (analytic - numeric).abs() / analytic.abs().max(numeric.abs()).max(1.0);
+        GradientCheck { analytic, numeric, error }
+    }).collect())
+}
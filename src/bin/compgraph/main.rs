@@ -0,0 +1,116 @@
+use std::{path::Path, process::ExitCode};
+
+use rust_compgraph::{Float, ComputeNodeRef, DynamicComputeNodeRef};
+use rust_compgraph::config::Config;
+use rust_compgraph::graph;
+use rust_compgraph::reload::LiveGraph;
+
+mod repl;
+
+// Evaluates an expression or a graph description file (see `reload`) from the command line:
+//     compgraph --set x=2 "x * sin(x)"
+//     compgraph --set gain=0.5 --dot model.txt
+// printing the value of the expression, or `name = value` for each definition of the file.
+// With `--repl`, or without an expression or file, it reads definitions, expressions and
+// commands interactively instead, starting from the file if there is one (see `repl`).
+// Inputs are set from `COMPGRAPH_` environment variables, then from `--set` options; those
+// that are set by neither stay 0, with a warning
+
+const USAGE: &str = "\
+usage: compgraph [--set NAME=VALUE]... [--dot] EXPRESSION|FILE
+       compgraph [--set NAME=VALUE]... [--repl] [FILE]
+
+    --set NAME=VALUE  sets an input, also read from COMPGRAPH_NAME variables
+    --dot             prints the graph in Graphviz DOT instead of its values
+    --repl            reads definitions, expressions and commands interactively
+    --help            prints this";
+
+struct Options {
+    inputs: Config,
+    dot: bool,
+    repl: bool,
+    source: Option<String>
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut inputs = Config::from_env("COMPGRAPH_").map_err(|error| error.to_string())?;
+    let (options, rest) = Config::from_args(args).map_err(|error| error.to_string())?;
+    inputs.merge(options);
+    let mut dot = false;
+    let mut repl = false;
+    let mut sources = Vec::new();
+    for arg in rest {
+        match arg.as_str() {
+            "--dot" => dot = true,
+            "--repl" => repl = true,
+            option if option.starts_with("--") => return Err(format!("unknown option `{}`\n{}", option, USAGE)),
+            _ => sources.push(arg)
+        }
+    }
+    if sources.len() > 1 || (dot && (repl || sources.is_empty())) {
+        return Err(USAGE.to_string());
+    }
+    Ok(Options { inputs, dot, repl: repl || sources.is_empty(), source: sources.pop() })
+}
+
+// A file, or an expression as the definition of `value`
+fn load(source: &str) -> Result<(LiveGraph, bool), String> {
+    if Path::new(source).is_file() {
+        return LiveGraph::load(source).map(|graph| (graph, true)).map_err(|error| format!("{}: {}", source, error));
+    }
+    let mut graph = LiveGraph::new();
+    graph.update(&format!("value = {}", source)).map_err(|error| error.to_string())?;
+    Ok((graph, false))
+}
+
+fn run(options: Options) -> Result<String, String> {
+    let (mut graph, is_file) = match &options.source {
+        Some(source) if options.repl && !Path::new(source).is_file() => return Err(format!("`{}` isn't a file", source)),
+        Some(source) => load(source)?,
+        None => (LiveGraph::new(), false)
+    };
+    let names: Vec<String> = graph.inputs().map(str::to_string).collect();
+    let bindings: Vec<_> = names.iter().map(|name| (name.as_str(), graph.input(name).unwrap())).collect();
+    let report = options.inputs.apply(&bindings);
+    if !report.unknown.is_empty() {
+        return Err(format!("no input named {}", report.unknown.join(", ")));
+    }
+    for name in &report.missing {
+        eprintln!("warning: input `{}` isn't set, using 0", name);
+    }
+    if options.repl {
+        repl::Repl::new(graph).run().map_err(|error| error.to_string())?;
+        return Ok(String::new());
+    }
+
+    let definitions: Vec<String> = graph.definitions().map(str::to_string).collect();
+    let outputs: Vec<(&str, DynamicComputeNodeRef)> = definitions.iter()
+        .map(|name| (name.as_str(), graph.output(name).unwrap()))
+        .collect();
+    if options.dot {
+        return Ok(graph::to_dot(&outputs));
+    }
+    let values: Vec<(&str, Float)> = outputs.iter().map(|(name, output)| (*name, output.compute())).collect();
+    Ok(match values[..] {
+        [(_, value)] if !is_file => format!("{}\n", value),
+        _ => values.iter().map(|(name, value)| format!("{} = {}\n", name, value)).collect()
+    })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match parse_options(args).and_then(run) {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{self, BufRead, Write};
+
+use rust_compgraph::{ComputeNodeRef, InputNodeRef, Child};
+use rust_compgraph::graph::{self, NodeKind};
+use rust_compgraph::reload::LiveGraph;
+
+// The interactive mode: each line defines a name, evaluates an expression or runs a command
+//     > area = width * height
+//     > :set width=3
+//     > area / 2
+// Definitions are kept in order as a description, rebuilt as a whole on each change, so that
+// they can use names defined later and be redefined
+
+pub const HELP: &str = "\
+NAME = FORMULA     defines NAME, replacing an earlier definition
+FORMULA            prints the value of the formula
+:set NAME=VALUE    sets an input
+:show [NAME]       prints the definitions and inputs, or one of them with its value
+:nodes NAME        prints the nodes a definition is computed from, children first
+:dot [NAME]        prints the graph, or a definition's, in Graphviz DOT
+:remove NAME       removes a definition
+:help              prints this
+:quit              exits, as does the end of the input";
+
+pub struct Repl {
+    graph: LiveGraph,
+    definitions: Vec<(String, String)>
+}
+
+impl Repl {
+    pub fn new(graph: LiveGraph) -> Repl {
+        let definitions = graph.definitions()
+            .map(|name| (name.to_string(), graph.formula(name).unwrap().to_string()))
+            .collect();
+        Repl { graph, definitions }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                println!();
+                return Ok(());
+            };
+            match self.execute(line.trim()) {
+                Ok(None) => return Ok(()),
+                Ok(Some(output)) => print!("{}", output),
+                Err(message) => eprintln!("error: {}", message)
+            }
+        }
+    }
+
+    // What to print, `None` to quit
+    fn execute(&mut self, line: &str) -> Result<Option<String>, String> {
+        if line.is_empty() {
+            return Ok(Some(String::new()));
+        }
+        let Some(command) = line.strip_prefix(':') else {
+            return self.define_or_evaluate(line).map(Some);
+        };
+        let (command, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let argument = argument.trim();
+        let output = match command {
+            "quit" | "q" => return Ok(None),
+            "help" | "h" => format!("{}\n", HELP),
+            "set" => self.set(argument)?,
+            "show" => self.show(argument)?,
+            "nodes" => self.nodes(argument)?,
+            "dot" => self.dot(argument)?,
+            "remove" => self.remove(argument)?,
+            _ => return Err(format!("unknown command `:{}`, see :help", command))
+        };
+        Ok(Some(output))
+    }
+
+    fn define_or_evaluate(&mut self, line: &str) -> Result<String, String> {
+        let definition = line.split_once('=')
+            .filter(|(name, _)| {
+                let name = name.trim();
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            });
+        let Some((name, formula)) = definition else {
+            return self.graph.evaluate(line).map(|value| format!("{}\n", value)).map_err(|error| error.to_string());
+        };
+        let (name, formula) = (name.trim().to_string(), formula.trim().to_string());
+        let mut definitions = self.definitions.clone();
+        match definitions.iter_mut().find(|(defined, _)| *defined == name) {
+            Some((_, old)) => *old = formula,
+            None => definitions.push((name.clone(), formula))
+        }
+        self.update(definitions)?;
+        Ok(format!("{} = {}\n", name, self.graph.value(&name).unwrap()))
+    }
+
+    // Rebuilds the graph, keeping the definitions as they were if that fails
+    fn update(&mut self, definitions: Vec<(String, String)>) -> Result<(), String> {
+        let text: String = definitions.iter().map(|(name, formula)| format!("{} = {}\n", name, formula)).collect();
+        self.graph.update(&text).map_err(|error| error.to_string())?;
+        self.definitions = definitions;
+        Ok(())
+    }
+
+    fn set(&mut self, argument: &str) -> Result<String, String> {
+        let (name, value) = argument.split_once('=').ok_or("expected `:set NAME=VALUE`")?;
+        let input = self.graph.input(name.trim()).ok_or_else(|| format!("no input named `{}`", name.trim()))?;
+        let value = value.trim().parse().map_err(|_| format!("invalid value `{}`", value.trim()))?;
+        input.set(value);
+        Ok(String::new())
+    }
+
+    fn show(&self, name: &str) -> Result<String, String> {
+        if name.is_empty() {
+            let mut output: String = self.definitions.iter()
+                .map(|(name, formula)| format!("{} = {}\n", name, formula))
+                .collect();
+            for input in self.graph.inputs() {
+                output += &format!("input {} = {}\n", input, self.graph.input(input).unwrap().compute());
+            }
+            return Ok(output);
+        }
+        if let Some(input) = self.graph.input(name) {
+            return Ok(format!("input {} = {}\n", name, input.compute()));
+        }
+        let formula = self.graph.formula(name).ok_or_else(|| format!("`{}` isn't defined", name))?;
+        Ok(format!("{} = {}\n    = {}\n", name, formula, self.graph.value(name).unwrap()))
+    }
+
+    fn nodes(&self, name: &str) -> Result<String, String> {
+        let output = self.graph.node(name).ok_or_else(|| format!("`{}` isn't defined", name))?;
+        let mut text = String::new();
+        for node in graph::traverse(&output) {
+            let kind = match node.kind() {
+                NodeKind::Input => "input",
+                NodeKind::Source => "source",
+                NodeKind::Operation => "operation"
+            };
+            let children: Vec<String> = node.children().iter().map(|child| match child {
+                Child::Node(child) => graph::node_id(child).to_string(),
+                Child::Constant(value) => value.to_string(),
+                Child::Opaque => "external".to_string()
+            }).collect();
+            text += &format!("{} {} ({}) [{}]\n", node.id(), node.name(), kind, children.join(", "));
+        }
+        Ok(text)
+    }
+
+    fn dot(&self, name: &str) -> Result<String, String> {
+        let names: Vec<String> = if name.is_empty() {
+            self.definitions.iter().map(|(name, _)| name.clone()).collect()
+        } else {
+            vec![name.to_string()]
+        };
+        let outputs = names.iter()
+            .map(|name| Ok((name.as_str(), self.graph.node(name).ok_or_else(|| format!("`{}` isn't defined", name))?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(graph::to_dot(&outputs))
+    }
+
+    fn remove(&mut self, name: &str) -> Result<String, String> {
+        let mut definitions = self.definitions.clone();
+        let count = definitions.len();
+        definitions.retain(|(defined, _)| defined != name);
+        if definitions.len() == count {
+            return Err(format!("`{}` isn't defined", name));
+        }
+        self.update(definitions)?;
+        Ok(String::new())
+    }
+}
@@ -0,0 +1,114 @@
+use std::{rc::Rc, cell::{Cell, RefCell}, collections::BTreeMap};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Binding graph outputs to UI state
+//
+// A binding calls its callback with the output's value when bound, and again whenever the
+// value changes. Invalidation only marks bindings dirty, as the graph can't be computed while
+// it's being invalidated; `update()`, called by the UI once per frame or whenever woken,
+// recomputes the dirty outputs and calls the callbacks of those that actually changed. The
+// waker is called once when a binding becomes dirty after an update, to let the UI schedule
+// one (request a repaint, post an event); it must not touch the graph itself
+//
+// Bindings with a higher priority are updated first, so that critical outputs (interlocks,
+// alarms) get their callbacks before cosmetic ones in the same update; those of the same
+// priority are updated in order of binding
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindingId(usize);
+
+type Waker = Rc<RefCell<Option<Box<dyn Fn()>>>>;
+
+struct DirtyFlag {
+    dirty: Rc<Cell<bool>>,
+    pending: Rc<Cell<bool>>,
+    waker: Waker
+}
+
+impl InvalidateCacheMut for DirtyFlag {
+    fn invalidate_cache(&mut self) {
+        self.dirty.set(true);
+        if !self.pending.replace(true) {
+            if let Some(waker) = self.waker.borrow().as_ref() {
+                waker()
+            }
+        }
+    }
+}
+
+struct Binding {
+    priority: i32,
+    output: Box<dyn Fn() -> Float>,
+    value: Float,
+    callback: Box<dyn FnMut(Float)>,
+    dirty: Rc<Cell<bool>>,
+    _flag: Rc<RefCell<DirtyFlag>>
+}
+
+#[derive(Default)]
+pub struct Bindings {
+    bindings: BTreeMap<BindingId, Binding>,
+    next_id: usize,
+    pending: Rc<Cell<bool>>,
+    waker: Waker
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    pub fn set_waker(&mut self, waker: impl Fn() + 'static) {
+        *self.waker.borrow_mut() = Some(Box::new(waker));
+    }
+
+    pub fn bind(&mut self, output: impl ComputeNodeRef + 'static, callback: impl FnMut(Float) + 'static) -> BindingId {
+        self.bind_with_priority(output, 0, callback)
+    }
+
+    pub fn bind_with_priority(
+        &mut self, output: impl ComputeNodeRef + 'static, priority: i32, mut callback: impl FnMut(Float) + 'static
+    ) -> BindingId {
+        let dirty = Rc::new(Cell::new(false));
+        let flag = Rc::new(RefCell::new(DirtyFlag { dirty: dirty.clone(), pending: self.pending.clone(), waker: self.waker.clone() }));
+        output.subscribe_to_invalidate(&(flag.clone() as _));
+        // leaves the output cached, so that its next change reaches the flag
+        let value = output.compute();
+        callback(value);
+
+        let id = BindingId(self.next_id);
+        self.next_id += 1;
+        let binding = Binding { priority, output: Box::new(move || output.compute()), value, callback: Box::new(callback), dirty, _flag: flag };
+        self.bindings.insert(id, binding);
+        id
+    }
+
+    pub fn unbind(&mut self, id: BindingId) -> bool {
+        self.bindings.remove(&id).is_some()
+    }
+
+    // Whether an update would recompute anything
+    pub fn is_dirty(&self) -> bool {
+        self.bindings.values().any(|binding| binding.dirty.get())
+    }
+
+    // Runs the callbacks of the outputs that changed, in order of priority, and returns how many
+    pub fn update(&mut self) -> usize {
+        self.pending.set(false);
+        let mut dirty: Vec<&mut Binding> = self.bindings.values_mut().filter(|binding| binding.dirty.replace(false)).collect();
+        // stable, so the order of binding is kept within a priority
+        dirty.sort_by_key(|binding| std::cmp::Reverse(binding.priority));
+        let mut changed = 0;
+        for binding in dirty {
+            let value = (binding.output)();
+            if value.to_bits() != binding.value.to_bits() {
+                binding.value = value;
+                (binding.callback)(value);
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+
+use crate::compgraph::*;
+
+// Double-buffered inputs, for evaluating consistent snapshots of values written by other
+// threads
+//
+// Writers change a back buffer holding a value for each input, several values at once in an
+// `update` that the graph's thread sees whole or not at all. The graph's thread `flip`s to
+// the back buffer's values at the boundaries between evaluations, setting the inputs that
+// changed before any of the work their changes queue runs; in between, the inputs keep their
+// values whatever writers do, so that each evaluation sees a single snapshot. `compute` flips
+// and evaluates an output. Writers only wait for each other and for the copy made by a flip,
+// never for an evaluation
+
+struct Shared {
+    back: Mutex<Vec<Float>>,
+    // written since the last flip
+    dirty: AtomicBool
+}
+
+#[derive(Clone)]
+pub struct BufferWriter {
+    shared: Arc<Shared>
+}
+
+impl BufferWriter {
+    // Changes the values by the inputs' positions, as a single update
+    pub fn update(&self, f: impl FnOnce(&mut [Float])) {
+        let mut back = self.shared.back.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut back);
+        self.shared.dirty.store(true, Ordering::Release);
+    }
+
+    pub fn set(&self, position: usize, value: Float) {
+        self.update(|values| values[position] = value)
+    }
+
+    // The back buffer's values, not flipped to yet
+    pub fn values(&self) -> Vec<Float> {
+        self.shared.back.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+pub struct DoubleBuffered {
+    shared: Arc<Shared>,
+    front: Vec<Float>,
+    assign: Vec<Box<dyn Fn(Float)>>
+}
+
+impl Default for DoubleBuffered {
+    fn default() -> DoubleBuffered {
+        DoubleBuffered::new()
+    }
+}
+
+impl DoubleBuffered {
+    pub fn new() -> DoubleBuffered {
+        DoubleBuffered {
+            shared: Arc::new(Shared { back: Mutex::new(Vec::new()), dirty: AtomicBool::new(false) }),
+            front: Vec::new(),
+            assign: Vec::new()
+        }
+    }
+
+    // The input's position in the buffers, which start with its current value
+    pub fn add(&mut self, input: impl InputNodeRef + 'static) -> usize {
+        let value = input.compute();
+        self.shared.back.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(value);
+        self.front.push(value);
+        self.assign.push(Box::new(move |value| input.assign(value)));
+        self.front.len() - 1
+    }
+
+    pub fn writer(&self) -> BufferWriter {
+        BufferWriter { shared: self.shared.clone() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+
+    // Sets the inputs to the back buffer's values, returning how many changed
+    pub fn flip(&mut self) -> usize {
+        if !self.shared.dirty.swap(false, Ordering::Acquire) {
+            return 0;
+        }
+        let back = self.shared.back.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        let mut changed = 0;
+        for ((front, value), assign) in self.front.iter_mut().zip(back).zip(&self.assign) {
+            if front.to_bits() != value.to_bits() {
+                *front = value;
+                assign(value);
+                changed += 1;
+            }
+        }
+        run_deferred();
+        changed
+    }
+
+    // Evaluates `output` on the latest values written
+    pub fn compute(&mut self, output: &impl ComputeNodeRef) -> Float {
+        self.flip();
+        output.compute()
+    }
+}
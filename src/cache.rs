@@ -0,0 +1,125 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use crate::compgraph::*;
+use crate::graph;
+
+// Persistent result cache, for expensive graphs evaluated on the same inputs over and over
+// across process runs (CI pipelines, parameter sweeps)
+//
+// Results are keyed by the graph's structural hash (see `graph::structural_hash`) and the
+// values of its inputs, in the order the graph uses them, so a rebuilt graph finds the results
+// of an earlier run. A graph is only cached if its value is a function of its inputs: one with
+// leaves that aren't inputs (stateful nodes, ...) or opaque children (a clock's time, ...) is
+// computed every time. The cache is a text file loaded by `open` and written by `save`
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    graph: u64,
+    inputs: Vec<u32>
+}
+
+pub struct ResultCache {
+    path: PathBuf,
+    results: HashMap<Key, Float>,
+    hits: usize,
+    misses: usize
+}
+
+// The graph's hash and inputs, `None` if it can't be cached
+fn analyze(output: &DynamicComputeNodeRef) -> Option<(u64, Vec<DynamicComputeNodeRef>)> {
+    let order = topological_order(output);
+    let mut inputs = Vec::new();
+    for node in &order {
+        if is_input(node) {
+            inputs.push(node.clone());
+            continue;
+        }
+        let children = node.borrow().children();
+        if children.is_empty() || children.iter().any(|child| matches!(child, Child::Opaque)) {
+            return None;
+        }
+    }
+    Some((graph::structural_hash(output), inputs))
+}
+
+fn invalid_data(line: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("result cache: malformed line {}", line + 1))
+}
+
+impl ResultCache {
+    // Starts empty if there is no file at `path` yet
+    pub fn open(path: impl AsRef<Path>) -> io::Result<ResultCache> {
+        let path = path.as_ref().to_path_buf();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error)
+        };
+        let mut results = HashMap::new();
+        // `<graph> <input,...> <value>`, all in hex, with `-` for no inputs
+        for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [graph, inputs, value] = fields[..] else {
+                return Err(invalid_data(index));
+            };
+            let graph = u64::from_str_radix(graph, 16).map_err(|_| invalid_data(index))?;
+            let inputs = match inputs {
+                "-" => Vec::new(),
+                inputs => inputs.split(',').map(|bits| u32::from_str_radix(bits, 16)).collect::<Result<_, _>>().map_err(|_| invalid_data(index))?
+            };
+            let value = u32::from_str_radix(value, 16).map_err(|_| invalid_data(index))?;
+            results.insert(Key { graph, inputs }, Float::from_bits(value));
+        }
+        Ok(ResultCache { path, results, hits: 0, misses: 0 })
+    }
+
+    // Writes to a temporary file first, so that an interrupted save leaves the old file
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, value) in &self.results {
+            let inputs: Vec<String> = key.inputs.iter().map(|bits| format!("{:08x}", bits)).collect();
+            let inputs = if inputs.is_empty() { "-".to_string() } else { inputs.join(",") };
+            text += &format!("{:016x} {} {:08x}\n", key.graph, inputs, value.to_bits());
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, text)?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    // The value of `output`, from the cache if it was computed on the same inputs before
+    pub fn evaluate(&mut self, output: &DynamicComputeNodeRef) -> Float {
+        let Some((graph, inputs)) = analyze(output) else {
+            return output.compute();
+        };
+        let key = Key { graph, inputs: inputs.iter().map(|input| input.compute().to_bits()).collect() };
+        if let Some(&value) = self.results.get(&key) {
+            self.hits += 1;
+            return value;
+        }
+        self.misses += 1;
+        let value = output.compute();
+        self.results.insert(key, value);
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear()
+    }
+
+    // Evaluations answered from the cache and computed, since it was opened
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
@@ -9,103 +9,635 @@ pub type Float = f32;
 // to allow for inlining constants without `Rc` and extra allocations
 
 // Dependency-tracking functionality to be reused by both input and computational nodes
-struct InvalidatePublisher {
+pub(crate) struct InvalidatePublisher {
     subscribers: Vec<Weak<RefCell<dyn InvalidateCacheMut>>>
 }
 
 impl InvalidatePublisher {
-    pub fn new() -> InvalidatePublisher {
+    pub(crate) fn new() -> InvalidatePublisher {
         InvalidatePublisher { subscribers: Vec::new() }
     }
-    fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+    pub(crate) fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
         self.subscribers.push(Rc::downgrade(subscriber))
     }
-    fn publish_invalidate(&mut self) {
+    // Hands the subscribers over to `target`, for a node standing in for it
+    pub(crate) fn transfer_to<V>(&mut self, target: &DynamicComputeNodeRef<V>) {
+        for subscriber in self.subscribers.drain(..).filter_map(|dep_weak| dep_weak.upgrade()) {
+            target.borrow_mut().subscribe_to_invalidate(&subscriber)
+        }
+    }
+    pub(crate) fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+        self.subscribers.iter().filter_map(Weak::upgrade).collect()
+    }
+    // Subscribers that were dropped, which stay until the next publish
+    pub(crate) fn dangling(&self) -> usize {
+        self.subscribers.iter().filter(|subscriber| subscriber.strong_count() == 0).count()
+    }
+    // Drops those without waiting for a publish, returning how many there were
+    pub(crate) fn compact(&mut self) -> usize {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+        self.subscribers.shrink_to_fit();
+        before - self.subscribers.len()
+    }
+    // Adds the subscribers of `other`, e.g. ones that subscribed while it was publishing
+    pub(crate) fn append(&mut self, other: &mut InvalidatePublisher) {
+        self.subscribers.append(&mut other.subscribers)
+    }
+    pub(crate) fn publish_invalidate(&mut self) {
+        let _wave = Wave::enter();
         self.subscribers.retain(|dep_weak| {
-            dep_weak.upgrade().map_or(false, |dep_rc| {
-                dep_rc.borrow_mut().invalidate_cache();
+            dep_weak.upgrade().is_some_and(|dep_rc| {
+                if Wave::first_notification(&dep_rc) {
+                    dep_rc.borrow_mut().invalidate_cache();
+                }
                 true
             })
         })
     }
 }
 
+// An invalidation wave: everything a single change (a `set()`, a clock tick) invalidates.
+// Subscribers reachable along several paths, as in diamond-shaped graphs or `mul(x, x)`, are
+// only notified the first time they are reached in a wave
+#[derive(Default)]
+struct WaveState {
+    depth: usize,
+    notified: std::collections::HashSet<*const ()>
+}
+
+thread_local! {
+    static WAVE: RefCell<WaveState> = RefCell::new(WaveState::default());
+}
+
+// Guard for a publisher taking part in the current wave, which ends with the outermost one
+struct Wave;
+
+impl Wave {
+    fn enter() -> Wave {
+        WAVE.with(|wave| wave.borrow_mut().depth += 1);
+        Wave
+    }
+
+    fn first_notification(subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) -> bool {
+        WAVE.with(|wave| wave.borrow_mut().notified.insert(Rc::as_ptr(subscriber) as *const ()))
+    }
+}
+
+impl Drop for Wave {
+    fn drop(&mut self) {
+        WAVE.with(|wave| {
+            let mut wave = wave.borrow_mut();
+            wave.depth -= 1;
+            if wave.depth == 0 {
+                wave.notified.clear();
+            }
+        })
+    }
+}
+
 pub mod internals {
     // Things that have to be public as they are used in the expansion of `define_nodes!`
     // The name `internals` suggests that these should not be used directly
 
     use super::*;
+    use std::any::{Any, TypeId};
     pub trait InvalidateCacheMut {
         fn invalidate_cache(&mut self);
+        // The node subscribed, for subscribers that are nodes and know their handle (see
+        // `CachingNodeWrapper::new_ref`), rather than listeners
+        fn as_node(&self) -> Option<DynamicComputeNodeRef> { None }
     }
 
-    pub trait ComputeMut {
-        fn compute(&mut self) -> Float;
+    // The value type defaults to `Float`; other value types (`Fixed`, integers, ...)
+    // reuse the same caching and invalidation machinery
+    pub trait ComputeMut<V = Float> {
+        fn compute(&mut self) -> V;
+
+        // Introspection for graph algorithms (autodiff, export): the node's children and the
+        // partial derivatives of its value with respect to each of them, `None` if the node
+        // has no derivative rule
+        fn name(&self) -> &'static str { "node" }
+        // The lines of the node's doc comment, as written
+        fn doc(&self) -> &'static [&'static str] { &[] }
+        fn children(&self) -> Vec<Child> { Vec::new() }
+        fn partials(&mut self) -> Option<Vec<Float>> { None }
+        // A new node of the same kind with the given children, `None` for nodes that can't
+        // be rebuilt (stateful nodes, nodes with external children, ...)
+        fn rebuild(&self, _children: &[Operand]) -> Option<DynamicComputeNodeRef> { None }
     }
 
     // Caching functionality separated out to minimize the amount of code
     // in the expansion of define_nodes!
-    pub struct CachingNodeWrapper<T: ComputeMut> {
+    pub struct CachingNodeWrapper<T: ComputeMut<V>, V = Float> {
         pub inner: T,
-        cached_value: Option<Float>,
-        invalidate_publisher: InvalidatePublisher
+        cached_value: Option<V>,
+        invalidate_publisher: InvalidatePublisher,
+        // the node computed in place of `inner`, once this one has been replaced
+        redirect: Option<DynamicComputeNodeRef<V>>,
+        version: u64,
+        // earlier values by the values of the children they were computed from, see `memoize`
+        memo: Option<Memo<V>>,
+        // off for nodes recomputed each time, see `set_caching`
+        caching: bool,
+        // the node's own handle, for `as_node`
+        this: Option<Weak<RefCell<dyn ComputeNodeMut>>>
+    }
+
+    impl<T: ComputeMut<V>, V> CachingNodeWrapper<T, V> {
+        pub fn new(inner: T) -> CachingNodeWrapper<T, V> {
+            CachingNodeWrapper {
+                inner, cached_value: None, invalidate_publisher: InvalidatePublisher::new(), redirect: None, version: 0, memo: None,
+                caching: true, this: None
+            }
+        }
     }
 
-    impl<T: ComputeMut> CachingNodeWrapper<T> {
-        pub fn new(inner: T) -> CachingNodeWrapper<T> {
-            CachingNodeWrapper { inner, cached_value: None, invalidate_publisher: InvalidatePublisher::new() }
+    impl<T: ComputeMut + 'static> CachingNodeWrapper<T> {
+        // The handle of a new node, which the node knows, so that it can be found as a parent
+        // of its children (see `graph::parents`)
+        pub fn new_ref(inner: T) -> Rc<RefCell<CachingNodeWrapper<T>>> {
+            Rc::new_cyclic(|this| {
+                let mut node = CachingNodeWrapper::new(inner);
+                node.this = Some(this.clone() as Weak<RefCell<dyn ComputeNodeMut>>);
+                RefCell::new(node)
+            })
         }
     }
 
-    impl<T: ComputeMut> ComputeMut for CachingNodeWrapper<T> {
-        fn compute(&mut self) -> Float {
-            let cached_value = &mut self.cached_value;
-            *cached_value.get_or_insert_with(|| self.inner.compute())
+    // The least recently used entries first out
+    struct Memo<V> {
+        capacity: usize,
+        entries: std::collections::VecDeque<(Vec<u32>, V)>
+    }
+
+    impl<V: Clone> Memo<V> {
+        // The bits of the children's values, `None` if they can't all be seen
+        fn signature(children: Vec<Child>) -> Option<Vec<u32>> {
+            if children.is_empty() {
+                return None;
+            }
+            children.into_iter().map(|child| match child {
+                Child::Node(node) => Some(node.compute().to_bits()),
+                Child::Constant(value) => Some(value.to_bits()),
+                Child::Opaque => None
+            }).collect()
+        }
+
+        fn get_or_insert_with(&mut self, signature: Vec<u32>, compute: impl FnOnce() -> V) -> V {
+            let entry = match self.entries.iter().position(|(entry, _)| *entry == signature) {
+                Some(position) => self.entries.remove(position).unwrap(),
+                None => (signature, compute())
+            };
+            let value = entry.1.clone();
+            self.entries.push_front(entry);
+            self.entries.truncate(self.capacity);
+            value
+        }
+    }
+
+    impl<T: ComputeMut<V>, V: Clone> ComputeMut<V> for CachingNodeWrapper<T, V> {
+        fn compute(&mut self) -> V {
+            if let Some(target) = &self.redirect {
+                return target.borrow_mut().compute();
+            }
+            if let Some(value) = &self.cached_value {
+                return value.clone();
+            }
+            self.version += 1;
+            let signature = self.memo.as_ref().and_then(|_| Memo::<V>::signature(self.inner.children()));
+            let value = match (&mut self.memo, signature) {
+                (Some(memo), Some(signature)) => memo.get_or_insert_with(signature, || self.inner.compute()),
+                _ => self.inner.compute()
+            };
+            if self.caching {
+                self.cached_value = Some(value.clone());
+            }
+            value
+        }
+        // a replaced node looks like its replacement to graph algorithms
+        fn name(&self) -> &'static str {
+            self.redirect.as_ref().map_or_else(|| self.inner.name(), |target| target.borrow().name())
+        }
+        fn doc(&self) -> &'static [&'static str] {
+            self.redirect.as_ref().map_or_else(|| self.inner.doc(), |target| target.borrow().doc())
+        }
+        fn children(&self) -> Vec<Child> {
+            self.redirect.as_ref().map_or_else(|| self.inner.children(), |target| target.borrow().children())
+        }
+        fn partials(&mut self) -> Option<Vec<Float>> {
+            match &self.redirect {
+                Some(target) => target.borrow_mut().partials(),
+                None => self.inner.partials()
+            }
+        }
+        fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+            self.redirect.as_ref().map_or_else(|| self.inner.rebuild(children), |target| target.borrow().rebuild(children))
         }
     }
 
-    impl<T: ComputeMut> ComputeNodeMut for CachingNodeWrapper<T> {
+    impl<T: ComputeMut<V>, V: Clone> ComputeNodeMut<V> for CachingNodeWrapper<T, V> {
         fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
-            self.invalidate_publisher.subscribe_to_invalidate(subscriber)
+            match &self.redirect {
+                Some(target) => target.borrow_mut().subscribe_to_invalidate(subscriber),
+                None => self.invalidate_publisher.subscribe_to_invalidate(subscriber)
+            }
+        }
+        fn redirect(&mut self, target: DynamicComputeNodeRef<V>) -> bool {
+            self.cached_value = None;
+            self.invalidate_publisher.publish_invalidate();
+            self.invalidate_publisher.transfer_to(&target);
+            self.redirect = Some(target);
+            true
+        }
+        // still increasing once redirected, as both versions only ever increase
+        fn version(&self) -> u64 {
+            self.version + self.redirect.as_ref().map_or(0, |target| target.borrow().version())
+        }
+        fn memoize(&mut self, entries: usize) -> bool {
+            self.memo = (entries > 0).then(|| Memo { capacity: entries, entries: Default::default() });
+            true
+        }
+        fn set_caching(&mut self, caching: bool) -> bool {
+            match &self.redirect {
+                Some(target) => target.borrow_mut().set_caching(caching),
+                None => {
+                    self.caching = caching;
+                    self.cached_value = None;
+                    true
+                }
+            }
+        }
+        fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+            match &self.redirect {
+                Some(target) => target.borrow().subscribers(),
+                None => self.invalidate_publisher.subscribers()
+            }
+        }
+        fn dangling_subscribers(&self) -> usize {
+            self.invalidate_publisher.dangling()
+        }
+        fn compact(&mut self) -> usize {
+            self.invalidate_publisher.compact()
+        }
+        fn is_cached(&self) -> Option<bool> {
+            match &self.redirect {
+                Some(target) => target.borrow().is_cached(),
+                None => self.caching.then_some(self.cached_value.is_some())
+            }
         }
     }
 
-    impl<T: ComputeMut> InvalidateCacheMut for CachingNodeWrapper<T> {
+    impl<T: ComputeMut<V>, V> InvalidateCacheMut for CachingNodeWrapper<T, V> {
         fn invalidate_cache(&mut self) {
-            if self.cached_value.is_some() {
+            // without a cache, the subscribers may have cached values computed from it
+            if self.cached_value.is_some() || !self.caching {
                 self.cached_value = None;
                 self.invalidate_publisher.publish_invalidate();
             }
         }
+        fn as_node(&self) -> Option<DynamicComputeNodeRef> {
+            self.this.as_ref()?.upgrade()
+        }
+    }
+
+    // Wrapper for nodes that are cheaper to recompute than to cache (see `#[uncached]` in
+    // `define_nodes!`): the node computes `inner` each time it's asked for its value, and
+    // passes every invalidation it gets on to its subscribers, which may have cached values
+    // computed from it. It can't be replaced or memoized
+    pub struct InlineNodeWrapper<T: ComputeMut> {
+        pub inner: T,
+        invalidate_publisher: InvalidatePublisher,
+        version: u64,
+        this: Option<Weak<RefCell<dyn ComputeNodeMut>>>
+    }
+
+    impl<T: ComputeMut + 'static> InlineNodeWrapper<T> {
+        pub fn new_ref(inner: T) -> Rc<RefCell<InlineNodeWrapper<T>>> {
+            Rc::new_cyclic(|this| RefCell::new(InlineNodeWrapper {
+                inner, invalidate_publisher: InvalidatePublisher::new(), version: 0,
+                this: Some(this.clone() as Weak<RefCell<dyn ComputeNodeMut>>)
+            }))
+        }
     }
 
+    impl<T: ComputeMut> ComputeMut for InlineNodeWrapper<T> {
+        fn compute(&mut self) -> Float {
+            self.inner.compute()
+        }
+        fn name(&self) -> &'static str { self.inner.name() }
+        fn doc(&self) -> &'static [&'static str] { self.inner.doc() }
+        fn children(&self) -> Vec<Child> { self.inner.children() }
+        fn partials(&mut self) -> Option<Vec<Float>> { self.inner.partials() }
+        fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> { self.inner.rebuild(children) }
+    }
+
+    impl<T: ComputeMut> ComputeNodeMut for InlineNodeWrapper<T> {
+        fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+            self.invalidate_publisher.subscribe_to_invalidate(subscriber)
+        }
+        // the number of times its value may have changed
+        fn version(&self) -> u64 { self.version }
+        fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+            self.invalidate_publisher.subscribers()
+        }
+        fn dangling_subscribers(&self) -> usize {
+            self.invalidate_publisher.dangling()
+        }
+        fn compact(&mut self) -> usize {
+            self.invalidate_publisher.compact()
+        }
+    }
+
+    impl<T: ComputeMut> InvalidateCacheMut for InlineNodeWrapper<T> {
+        fn invalidate_cache(&mut self) {
+            self.version += 1;
+            self.invalidate_publisher.publish_invalidate();
+        }
+        fn as_node(&self) -> Option<DynamicComputeNodeRef> {
+            self.this.as_ref()?.upgrade()
+        }
+    }
+
+    // The node a wrapper wraps, for telling nodes apart by kind
+    pub trait NodeWrapper {
+        type Inner: ComputeMut + 'static;
+    }
+
+    impl<T: ComputeMut + 'static> NodeWrapper for CachingNodeWrapper<T> {
+        type Inner = T;
+    }
+
+    impl<T: ComputeMut + 'static> NodeWrapper for InlineNodeWrapper<T> {
+        type Inner = T;
+    }
+
+    // Nodes applying a plain function to their children, for node libraries
+    // that are generic over the value type and so can't use define_nodes!
+    pub struct UnaryNode<A, In, Out> {
+        a: A,
+        op: fn(In) -> Out
+    }
+
+    impl<In: 'static, Out: 'static, A: ComputeNodeRef<In>> ComputeMut<Out> for UnaryNode<A, In, Out> {
+        fn compute(&mut self) -> Out {
+            (self.op)(self.a.compute())
+        }
+        fn children(&self) -> Vec<Child> {
+            float_children(self.a.as_child())
+        }
+        fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+            let op = *(&self.op as &dyn Any).downcast_ref::<fn(Float) -> Float>()?;
+            Some(unary_node(children[0].clone(), op))
+        }
+    }
+
+    pub struct BinaryNode<A, B, In1, In2, Out> {
+        a: A,
+        b: B,
+        op: fn(In1, In2) -> Out
+    }
+
+    impl<In1: 'static, In2: 'static, Out: 'static, A: ComputeNodeRef<In1>, B: ComputeNodeRef<In2>> ComputeMut<Out> for BinaryNode<A, B, In1, In2, Out> {
+        fn compute(&mut self) -> Out {
+            (self.op)(self.a.compute(), self.b.compute())
+        }
+        fn children(&self) -> Vec<Child> {
+            let mut children = float_children(self.a.as_child());
+            children.extend(float_children(self.b.as_child()));
+            children
+        }
+        fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+            let op = *(&self.op as &dyn Any).downcast_ref::<fn(Float, Float) -> Float>()?;
+            Some(binary_node(children[0].clone(), children[1].clone(), op))
+        }
+    }
+
+    // Graph algorithms see `Float` nodes, so children of other value types are seen through, to
+    // the `Float` nodes they compute from. Nodes of `Float`s throughout can be rebuilt
+    fn float_children<V: 'static>(child: Child<V>) -> Vec<Child> {
+        let mut child = Some(child);
+        if let Some(child) = (&mut child as &mut dyn Any).downcast_mut::<Option<Child>>() {
+            return child.take().into_iter().collect();
+        }
+        match child {
+            Some(Child::Node(node)) => node.borrow().children(),
+            Some(Child::Opaque) => vec![Child::Opaque],
+            _ => Vec::new()
+        }
+    }
+
+    pub fn unary_node<In: 'static, Out: Clone + 'static>(
+        a: impl ComputeNodeRef<In> + 'static, op: fn(In) -> Out
+    ) -> DynamicComputeNodeRef<Out> {
+        let result = Rc::new(RefCell::new(CachingNodeWrapper::new(UnaryNode { a, op })));
+        let subscriber = result.clone() as _;
+        result.borrow().inner.a.subscribe_to_invalidate(&subscriber);
+        result
+    }
+
+    pub fn binary_node<In1: 'static, In2: 'static, Out: Clone + 'static>(
+        a: impl ComputeNodeRef<In1> + 'static, b: impl ComputeNodeRef<In2> + 'static, op: fn(In1, In2) -> Out
+    ) -> DynamicComputeNodeRef<Out> {
+        let result = Rc::new(RefCell::new(CachingNodeWrapper::new(BinaryNode { a, b, op })));
+        let subscriber = result.clone() as _;
+        {
+            let inner = &result.borrow().inner;
+            inner.a.subscribe_to_invalidate(&subscriber);
+            inner.b.subscribe_to_invalidate(&subscriber);
+        }
+        result
+    }
+
+    // Hooks for `graph::Graph::build`, which tracks the nodes made while it runs and shares
+    // nodes equal to ones made before. A node looks for one to stand in for it before it's
+    // made, with its contents, then is tracked once made
+    pub fn existing_node<T: ComputeMut + 'static>(inner: &T) -> Option<DynamicComputeNodeRef> {
+        crate::graph::shared_node(|| crate::graph::ShareKey::new(TypeId::of::<T>(), inner.children()))
+    }
+
+    pub fn track_node<W: NodeWrapper + ComputeNodeMut + 'static>(node: &Rc<RefCell<W>>) {
+        let dynamic = node.clone() as DynamicComputeNodeRef;
+        crate::graph::track_node(&dynamic, || crate::graph::ShareKey::new(TypeId::of::<W::Inner>(), node.borrow().children()))
+    }
+
+    // For nodes that aren't shared
+    pub fn track_unshared_node(node: &DynamicComputeNodeRef) {
+        crate::graph::track_node(node, || None)
+    }
 }
 use internals::*;
 
-pub trait ComputeNodeMut: ComputeMut {
+pub trait ComputeNodeMut<V = Float>: ComputeMut<V> {
     fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>);
+    // Makes the node compute `target` instead, moving its subscribers over (see
+    // `graph::replace`); `false` for nodes that can't be replaced
+    fn redirect(&mut self, _target: DynamicComputeNodeRef<V>) -> bool { false }
+    // See `ComputeNodeRef::version`
+    fn version(&self) -> u64 { 0 }
+    // See `memoize`
+    fn memoize(&mut self, _entries: usize) -> bool { false }
+    // Turns the node's cache off or back on; `false` for nodes that don't cache
+    fn set_caching(&mut self, _caching: bool) -> bool { false }
+    // What is notified of the node's changes: its parents and listeners
+    fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> { Vec::new() }
+    fn dangling_subscribers(&self) -> usize { 0 }
+    // Drops the dangling subscribers, returning how many there were (see `Graph::compact`)
+    fn compact(&mut self) -> usize { 0 }
+    // Whether the node holds a value it computed that is still valid, `None` for nodes that
+    // don't cache values
+    fn is_cached(&self) -> Option<bool> { None }
+    // Sets an input from a handle to any node, leaving the deferred work to the caller (see
+    // `InputNodeRef::set`); `false` for nodes other than inputs
+    fn assign(&mut self, _value: V) -> bool { false }
 }
 
 
 // `ComputeNodeRef` and `InputNodeRef` are the public interface traits for the user
-pub trait ComputeNodeRef: Clone {
-    fn compute(&self) -> Float;
+pub trait ComputeNodeRef<V = Float>: Clone {
+    fn compute(&self) -> V;
     fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>);
+    // The node behind the handle, `None` for constants
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef<V>> { None }
+    fn as_child(&self) -> Child<V> {
+        self.as_dynamic().map_or(Child::Opaque, Child::Node)
+    }
+    // Increases whenever the node recomputes (an input whenever it's set), so that a change
+    // since an earlier look can be told without comparing values; it can increase without
+    // the value changing. Only up to date after a `compute()`; constants stay at 0
+    fn version(&self) -> u64 { 0 }
 }
 
-pub trait InputNodeRef: ComputeNodeRef {
-    fn set(&self, value: Float);
+// A child of a node as seen by graph algorithms
+#[derive(Clone)]
+pub enum Child<V = Float> {
+    Node(DynamicComputeNodeRef<V>),
+    Constant(V),
+    // a value from outside the graph, such as a clock's time, or of another value type
+    Opaque
 }
 
-pub type DynamicComputeNodeRef = Rc<RefCell<dyn ComputeNodeMut>>;
+// A child for a node being constructed from data or rebuilt, where constants stay inlined
+#[derive(Clone)]
+pub enum Operand {
+    Node(DynamicComputeNodeRef),
+    Constant(Float)
+}
 
-impl<T: ComputeNodeMut + ?Sized> ComputeNodeRef for Rc<RefCell<T>> {
+impl ComputeNodeRef for Operand {
     fn compute(&self) -> Float {
+        match self {
+            Operand::Node(node) => node.compute(),
+            Operand::Constant(value) => *value
+        }
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        if let Operand::Node(node) = self {
+            node.subscribe_to_invalidate(subscriber)
+        }
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef> {
+        match self {
+            Operand::Node(node) => Some(node.clone()),
+            Operand::Constant(_) => None
+        }
+    }
+    fn as_child(&self) -> Child {
+        match self {
+            Operand::Node(node) => Child::Node(node.clone()),
+            Operand::Constant(value) => Child::Constant(*value)
+        }
+    }
+    fn version(&self) -> u64 {
+        match self {
+            Operand::Node(node) => node.version(),
+            Operand::Constant(_) => 0
+        }
+    }
+}
+
+// Inputs are the only leaf nodes named `input`
+pub(crate) fn is_input(node: &DynamicComputeNodeRef) -> bool {
+    let node = node.borrow();
+    node.name() == "input" && node.children().is_empty()
+}
+
+pub(crate) fn node_address<V>(node: &DynamicComputeNodeRef<V>) -> *const () {
+    Rc::as_ptr(node) as *const ()
+}
+
+// All nodes `output` depends on, including itself, children before parents
+pub(crate) fn topological_order(output: &DynamicComputeNodeRef) -> Vec<DynamicComputeNodeRef> {
+    topological_order_until(output, |_| false)
+}
+
+// Same, but without looking below the nodes `stop` is true for
+pub(crate) fn topological_order_until(
+    output: &DynamicComputeNodeRef, stop: impl Fn(&DynamicComputeNodeRef) -> bool
+) -> Vec<DynamicComputeNodeRef> {
+    let mut order = Vec::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut stack = vec![(output.clone(), false)];
+    while let Some((node, children_done)) = stack.pop() {
+        if !children_done && !visited.insert(node_address(&node)) {
+            continue;
+        }
+        if children_done || stop(&node) {
+            order.push(node);
+            continue;
+        }
+        let children = node.borrow().children();
+        stack.push((node, true));
+        // reversed, so that children are visited in order
+        stack.extend(children.into_iter().rev().filter_map(|child| match child {
+            Child::Node(child) => Some((child, false)),
+            _ => None
+        }));
+    }
+    order
+}
+
+pub trait InputNodeRef<V = Float>: ComputeNodeRef<V> {
+    fn set(&self, value: V);
+    // Sets the value without running the work the change queues, which whoever assigns runs
+    // afterwards (see `set_all`). Inputs that can't be set that way queue setting themselves
+    fn assign(&self, value: V) where Self: 'static, V: 'static {
+        let input = self.clone();
+        defer(move || input.set(value))
+    }
+}
+
+pub type DynamicComputeNodeRef<V = Float> = Rc<RefCell<dyn ComputeNodeMut<V>>>;
+
+impl<V: 'static, T: ComputeNodeMut<V> + 'static> ComputeNodeRef<V> for Rc<RefCell<T>> {
+    fn compute(&self) -> V {
+        self.borrow_mut().compute()
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.borrow_mut().subscribe_to_invalidate(subscriber)
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef<V>> {
+        Some(self.clone())
+    }
+    fn version(&self) -> u64 {
+        self.borrow().version()
+    }
+}
+
+// Separate from sized nodes, which need a coercion in `as_dynamic`
+impl<V: 'static> ComputeNodeRef<V> for DynamicComputeNodeRef<V> {
+    fn compute(&self) -> V {
         self.borrow_mut().compute()
     }
     fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
         self.borrow_mut().subscribe_to_invalidate(subscriber)
     }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef<V>> {
+        Some(self.clone())
+    }
+    fn version(&self) -> u64 {
+        self.borrow().version()
+    }
 }
 
 impl ComputeNodeRef for Float {
@@ -113,69 +645,630 @@ impl ComputeNodeRef for Float {
     fn subscribe_to_invalidate(&self, _subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
         // Float constants trivially satisfy this by never changing
     }
+    fn as_child(&self) -> Child {
+        Child::Constant(*self)
+    }
+}
+
+// What the functions made by `define_nodes!` take as children: handles, which the node
+// keeps, or references to handles, which it clones, so that `add(&x, &y)` leaves `x` and `y`
+// to the caller. References work for the handle types here, which are all that a generic
+// impl could leave room for
+pub trait IntoNodeRef {
+    type Node: ComputeNodeRef + 'static;
+    fn into_node_ref(self) -> Self::Node;
+}
+
+impl<T: ComputeNodeRef + 'static> IntoNodeRef for T {
+    type Node = T;
+    fn into_node_ref(self) -> T { self }
+}
+
+macro_rules! impl_into_node_ref {
+    ($($t:ty),*) => {$(
+        impl IntoNodeRef for &$t {
+            type Node = $t;
+            fn into_node_ref(self) -> $t { self.clone() }
+        }
+    )*};
+}
+
+impl_into_node_ref!(
+    Float, Operand, Input, DynamicComputeNodeRef, crate::stateful::DynamicStatefulNodeRef, crate::stateful::FeedbackDelay,
+    crate::time::TimeInput, crate::stream::StreamSource
+);
+
+// Other numbers become `Float` constants, so that `add(x, 3)` and `mul(x, 0.5f64)` work; an
+// untyped float literal is an `f64` here, rounded to the nearest `Float`
+macro_rules! impl_number_into_node_ref {
+    ($($t:ty),*) => {$(
+        impl IntoNodeRef for $t {
+            type Node = Float;
+            fn into_node_ref(self) -> Float { self as Float }
+        }
+    )*};
+}
+
+impl_number_into_node_ref!(f64, i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+pub struct InputNodeImpl<V = Float> {
+    value: V,
+    invalidate_publisher: InvalidatePublisher,
+    version: u64
+}
+
+// Named handle to an input, for APIs that create inputs and hand them back
+pub type Input<V = Float> = Rc<RefCell<InputNodeImpl<V>>>;
+
+impl<V> InputNodeImpl<V> {
+    pub(crate) fn new_ref(initial: V) -> Rc<RefCell<InputNodeImpl<V>>> {
+        Rc::new(RefCell::new(InputNodeImpl { value: initial, invalidate_publisher: InvalidatePublisher::new(), version: 0 }))
+    }
+}
+
+impl<V: Clone> ComputeMut<V> for InputNodeImpl<V> {
+    fn compute(&mut self) -> V {
+        self.value.clone()
+    }
+    fn name(&self) -> &'static str { "input" }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(Vec::new()) }
+}
+
+impl<V: Clone> ComputeNodeMut<V> for InputNodeImpl<V> {
+    fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.invalidate_publisher.subscribe_to_invalidate(subscriber)
+    }
+    fn version(&self) -> u64 { self.version }
+    fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+        self.invalidate_publisher.subscribers()
+    }
+    fn dangling_subscribers(&self) -> usize {
+        self.invalidate_publisher.dangling()
+    }
+    fn compact(&mut self) -> usize {
+        self.invalidate_publisher.compact()
+    }
+    fn assign(&mut self, value: V) -> bool {
+        self.value = value;
+        self.version += 1;
+        self.invalidate_publisher.publish_invalidate();
+        true
+    }
+}
+
+impl<V: Clone + 'static> InputNodeRef<V> for Rc<RefCell<InputNodeImpl<V>>> {
+    fn set(&self, value: V) {
+        self.borrow_mut().assign(value);
+        run_deferred();
+    }
+    fn assign(&self, value: V) {
+        self.borrow_mut().assign(value);
+    }
+}
+
+pub fn create_input() -> Input {
+    let input = create_typed_input(0.0);
+    track_unshared_node(&(input.clone() as DynamicComputeNodeRef));
+    input
+}
+
+// Inputs of value types other than `Float` need an explicit initial value
+pub fn create_typed_input<V: Clone + 'static>(initial: V) -> Input<V> {
+    InputNodeImpl::new_ref(initial)
+}
+
+// Work queued during invalidation, when the nodes being invalidated are borrowed and can't
+// be computed, to be run once the change has spread (at the end of `set()` or a clock tick)
+thread_local! {
+    static DEFERRED: RefCell<std::collections::VecDeque<Box<dyn FnOnce()>>> = RefCell::new(std::collections::VecDeque::new());
+}
+
+pub(crate) fn defer(work: impl FnOnce() + 'static) {
+    DEFERRED.with(|deferred| deferred.borrow_mut().push_back(Box::new(work)))
+}
+
+pub(crate) fn run_deferred() {
+    // in the order queued, so that inputs queued to be set are set before the work their
+    // neighbours' changes queued afterwards; the work can queue more, and change inputs itself
+    while let Some(work) = DEFERRED.with(|deferred| deferred.borrow_mut().pop_front()) {
+        work()
+    }
+}
+
+// Sets the inputs before any of the work their changes queue runs, so that it sees them all
+// changed
+pub(crate) fn set_all<'a, P: InputNodeRef + 'static>(values: impl IntoIterator<Item = (&'a P, Float)>) {
+    for (input, value) in values {
+        input.assign(value);
+    }
+    run_deferred();
 }
 
-struct InputNodeImpl {
-    value: Float,
-    invalidate_publisher: InvalidatePublisher
+// Equality cutoff: a node passing `x` through that stops the invalidation of its dependents
+// when `x` recomputes to the value it had, e.g. after a threshold or a rounding node
+//
+// On an invalidation from `x` it keeps its value and recomputes `x` once the change has
+// spread; only if the value changed (bit for bit) are its dependents invalidated. The price
+// is computing `x` eagerly, on every change below it, even when nothing asks for the value
+struct CutoffNode<X> {
+    x: X,
+    value: Option<Float>,
+    // invalidated, but not recomputed yet
+    suspect: bool,
+    invalidate_publisher: InvalidatePublisher,
+    // bumped when the value changes, not on every check
+    version: u64,
+    this: Weak<RefCell<CutoffNode<X>>>
+}
+
+impl<X: ComputeNodeRef + 'static> CutoffNode<X> {
+    // Recomputes `x`, returning whether the value changed
+    fn verify(&mut self) -> bool {
+        self.suspect = false;
+        let value = self.x.compute();
+        let changed = self.value.is_some_and(|old| old.to_bits() != value.to_bits());
+        if changed || self.value.is_none() {
+            self.version += 1;
+        }
+        self.value = Some(value);
+        changed
+    }
 }
 
-impl ComputeMut for InputNodeImpl {
+impl<X: ComputeNodeRef + 'static> ComputeMut for CutoffNode<X> {
     fn compute(&mut self) -> Float {
-        self.value
+        match self.value {
+            Some(value) if !self.suspect => value,
+            _ => {
+                // asked for before the deferred check, which is then left with nothing to do;
+                // the dependents that didn't ask are invalidated afterwards
+                if self.verify() {
+                    let this = self.this.clone();
+                    defer(move || if let Some(node) = this.upgrade() {
+                        node.borrow_mut().invalidate_publisher.publish_invalidate()
+                    });
+                }
+                self.value.unwrap()
+            }
+        }
+    }
+    fn name(&self) -> &'static str { "cutoff" }
+    fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(cutoff(children[0].clone()))
     }
 }
 
-impl ComputeNodeMut for InputNodeImpl {
+impl<X: ComputeNodeRef + 'static> ComputeNodeMut for CutoffNode<X> {
     fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
         self.invalidate_publisher.subscribe_to_invalidate(subscriber)
-    }    
+    }
+    fn version(&self) -> u64 { self.version }
+    fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+        self.invalidate_publisher.subscribers()
+    }
+    fn dangling_subscribers(&self) -> usize {
+        self.invalidate_publisher.dangling()
+    }
+    fn compact(&mut self) -> usize {
+        self.invalidate_publisher.compact()
+    }
+    fn is_cached(&self) -> Option<bool> {
+        Some(self.value.is_some() && !self.suspect)
+    }
 }
 
-impl InputNodeRef for Rc<RefCell<InputNodeImpl>> {
-    fn set(&self, value: Float) {
-        let mut inner = self.borrow_mut();
-        inner.value = value;
-        inner.invalidate_publisher.publish_invalidate();
+impl<X: ComputeNodeRef + 'static> InvalidateCacheMut for CutoffNode<X> {
+    fn as_node(&self) -> Option<DynamicComputeNodeRef> {
+        self.this.upgrade().map(|this| this as DynamicComputeNodeRef)
+    }
+    fn invalidate_cache(&mut self) {
+        // nothing depends on a value that was never computed
+        if self.value.is_none() || self.suspect {
+            return;
+        }
+        self.suspect = true;
+        let this = self.this.clone();
+        defer(move || if let Some(node) = this.upgrade() {
+            let mut node = node.borrow_mut();
+            if node.suspect && node.verify() {
+                node.invalidate_publisher.publish_invalidate()
+            }
+        });
     }
 }
 
-pub fn create_input() -> impl InputNodeRef {
-    Rc::new(RefCell::new(InputNodeImpl { value: 0.0, invalidate_publisher: InvalidatePublisher::new() }))
+pub fn cutoff(x: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+    let result = Rc::new_cyclic(|this| RefCell::new(CutoffNode {
+        x, value: None, suspect: false, invalidate_publisher: InvalidatePublisher::new(), version: 0, this: this.clone()
+    }));
+    let subscriber = result.clone() as _;
+    result.borrow().x.subscribe_to_invalidate(&subscriber);
+    result
 }
 
+// Keeps the values a node computed for the last `entries` different values of its children,
+// so that a node switching between a few configurations of its inputs takes the value it had
+// for one instead of recomputing it. The children are computed to look the value up, which
+// they would have been anyway for recomputing it; 0 entries turns it off. `false` for nodes
+// that don't cache, and nodes with opaque children are always recomputed
+pub fn memoize<V>(node: &DynamicComputeNodeRef<V>, entries: usize) -> bool {
+    node.borrow_mut().memoize(entries)
+}
+
+// The body of a node is the body of a function taking the values of the children as `Float`
+// arguments named after the parameters, so it can `return` and use anything in scope where
+// the nodes are defined, but sees nothing of the node itself. The `grad` block is the same.
+// The functions made for nodes take their children as anything `IntoNodeRef`
+//
+// Nodes can be given derivative rules for autodiff with a `grad` block after the body,
+// which evaluates to the array of partial derivatives with respect to the parameters:
+//     mul(a, b) { a * b } grad { [b, a] }
+//
+// Nodes with several outputs name them as the fields of a struct the macro defines, which
+// the body returns; the function returns the struct of a node for each field, projecting
+// the fields out of one node computing them together:
+//     polar(x, y) -> Polar { radius, angle } { Polar { radius: x.hypot(y), angle: y.atan2(x) } }
+// so that `polar(x, y).radius` is a node. Such nodes have no `grad` blocks. To graph
+// algorithms, each of those nodes is a node of the shared node's children, named after it and
+// the field (`polar.radius`)
+//
+// Parameters can have defaults, which are constants in place of the argument when the node
+// is made by the `defaults` function of the module named after it, taking the parameters
+// without defaults:
+//     smooth(x, alpha = 0.5) { ... }
+// makes `smooth::defaults(x)` the same as `smooth(x, 0.5)`
+//
+// Nodes can be generic, with const parameters and type parameters bounded by a single trait
+// in the angle brackets, and any other bounds in a where clause before the body:
+//     polynomial<const DEGREE: usize, C>(x) where C: Coefficients + Default { ... }
+// The type parameters are `'static`, and are given by naming the function:
+// `polynomial::<3, Taylor>(x)`. Nodes with several outputs can't be generic
+//
+// Doc comments and other attributes before a node go on its function (`cfg`s also on the
+// struct and module made for it), and the doc comment can be read back through `doc()`
+//
+// Nodes marked `#[uncached]` recompute their value each time they're asked for it instead of
+// caching it, for nodes so cheap that the cache costs more than it saves, like `add`. They
+// still pass invalidations on to the nodes computed from them. Nodes with several outputs
+// are always cached
+//
+// Nodes marked `#[constant]` also get their body as a `const fn` on values, `evaluate` in the
+// module named after them, so that what only depends on constants (configuration math,
+// tables) can be computed at compile time:
+//     #[constant]
+//     nyquist(rate) { rate / 2.0 }
+//     const CUTOFF: Float = nyquist::evaluate(48000.0);
+// The body then has to be valid in a `const fn`. Nodes with several outputs can't be constant
 #[macro_export]
 macro_rules! define_nodes {
-    {$(
-        $visibility:vis $name:ident($($params:ident),+) $body:block
-       )*} => {
-        $(
-            $visibility fn $name($($params: impl $crate::compgraph::ComputeNodeRef + 'static),+) -> $crate::compgraph::DynamicComputeNodeRef {
+    {} => {};
+    {$(#[$($attrs:tt)*])* $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+) -> $output:ident { $($fields:ident),+ $(,)? } $body:block $($rest:tt)*} => {
+        $crate::define_nodes!(@outputs [$(#[$($attrs)*])*] $visibility $name($($params),+) $output { $($fields),+ } $body);
+        $crate::define_nodes!(@defaults [$(#[$($attrs)*])*] [] [] [] [] $visibility $name [] [$output<$crate::compgraph::DynamicComputeNodeRef>] [] [] [] $($params $(= $defaults)?),+);
+        $crate::define_nodes!{$($rest)*}
+    };
+    {$(#[$($attrs:tt)*])* $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+) $body:block grad $grad:block $($rest:tt)*} => {
+        $crate::define_nodes!(@node [$(#[$($attrs)*])*] [] [] [] [] $visibility $name($($params),+) $body [$grad]);
+        $crate::define_nodes!(@defaults [$(#[$($attrs)*])*] [] [] [] [] $visibility $name [($($params),+) $body] [$crate::compgraph::DynamicComputeNodeRef] [] [] [] $($params $(= $defaults)?),+);
+        $crate::define_nodes!{$($rest)*}
+    };
+    {$(#[$($attrs:tt)*])* $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+) $body:block $($rest:tt)*} => {
+        $crate::define_nodes!(@node [$(#[$($attrs)*])*] [] [] [] [] $visibility $name($($params),+) $body []);
+        $crate::define_nodes!(@defaults [$(#[$($attrs)*])*] [] [] [] [] $visibility $name [($($params),+) $body] [$crate::compgraph::DynamicComputeNodeRef] [] [] [] $($params $(= $defaults)?),+);
+        $crate::define_nodes!{$($rest)*}
+    };
+    {$(#[$($attrs:tt)*])* $visibility:vis $name:ident < $($rest:tt)*} => {
+        $crate::define_nodes!(@generics [$(#[$($attrs)*])*] $visibility $name [] [] [] $($rest)*);
+    };
+    // Collects the generic parameters: their declarations, their names for naming the node's
+    // function, and the type parameters, which the node's struct has to mention
+    (@generics [$($attrs:tt)*] $visibility:vis $name:ident [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*]
+        const $generic:ident : $kind:ty , $($rest:tt)*) => {
+        $crate::define_nodes!(@generics [$($attrs)*] $visibility $name [$($decl)* const $generic: $kind,] [$($names)* $generic,] [$($types)*] $($rest)*);
+    };
+    (@generics [$($attrs:tt)*] $visibility:vis $name:ident [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*]
+        const $generic:ident : $kind:ty > $($rest:tt)*) => {
+        $crate::define_nodes!(@generics [$($attrs)*] $visibility $name [$($decl)* const $generic: $kind,] [$($names)* $generic,] [$($types)*] > $($rest)*);
+    };
+    (@generics [$($attrs:tt)*] $visibility:vis $name:ident [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*]
+        $generic:ident $(: $bound:path)? , $($rest:tt)*) => {
+        $crate::define_nodes!(@generics [$($attrs)*] $visibility $name [$($decl)* $generic $(: $bound)?,] [$($names)* $generic,] [$($types)* $generic] $($rest)*);
+    };
+    (@generics [$($attrs:tt)*] $visibility:vis $name:ident [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*]
+        $generic:ident $(: $bound:path)? > $($rest:tt)*) => {
+        $crate::define_nodes!(@generics [$($attrs)*] $visibility $name [$($decl)* $generic $(: $bound)?,] [$($names)* $generic,] [$($types)* $generic] > $($rest)*);
+    };
+    (@generics [$($attrs:tt)*] $visibility:vis $name:ident [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*]
+        > ($($params:ident $(= $defaults:expr)?),+) $($rest:tt)*) => {
+        $crate::define_nodes!(@where [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [] $visibility $name($($params $(= $defaults)?),+) $($rest)*);
+    };
+    // Collects the where clause, up to the body
+    (@where [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [] $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+)
+        where $($rest:tt)*) => {
+        $crate::define_nodes!(@where [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [] $visibility $name($($params $(= $defaults)?),+) $($rest)*);
+    };
+    (@where [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+)
+        { $($body:tt)* } grad { $($grad:tt)* } $($rest:tt)*) => {
+        $crate::define_nodes!(@node [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name($($params),+) { $($body)* } [{ $($grad)* }]);
+        $crate::define_nodes!(@defaults [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name [($($params),+) { $($body)* }] [$crate::compgraph::DynamicComputeNodeRef] [] [] [] $($params $(= $defaults)?),+);
+        $crate::define_nodes!{$($rest)*}
+    };
+    (@where [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+)
+        { $($body:tt)* } $($rest:tt)*) => {
+        $crate::define_nodes!(@node [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name($($params),+) { $($body)* } []);
+        $crate::define_nodes!(@defaults [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name [($($params),+) { $($body)* }] [$crate::compgraph::DynamicComputeNodeRef] [] [] [] $($params $(= $defaults)?),+);
+        $crate::define_nodes!{$($rest)*}
+    };
+    (@where [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident($($params:ident $(= $defaults:expr)?),+)
+        $next:tt $($rest:tt)*) => {
+        $crate::define_nodes!(@where [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)* $next] $visibility $name($($params $(= $defaults)?),+) $($rest)*);
+    };
+    // Splits the parameters into the required ones and the arguments to call the node with,
+    // marking whether there were any defaults
+    (@defaults [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident [$($constant:tt)*] [$($result:tt)*] [$($required:ident),*] [$($arguments:expr),*] [$($any:tt)?]
+        $param:ident = $default:expr $(, $($rest:tt)*)?) => {
+        $crate::define_nodes!(@defaults [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name [$($constant)*] [$($result)*] [$($required),*] [$($arguments,)* $default] [default] $($($rest)*)?);
+    };
+    (@defaults [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident [$($constant:tt)*] [$($result:tt)*] [$($required:ident),*] [$($arguments:expr),*] [$($any:tt)?]
+        $param:ident $(, $($rest:tt)*)?) => {
+        $crate::define_nodes!(@defaults [$($attrs)*] [$($decl)*] [$($names)*] [$($types)*] [$($where)*] $visibility $name [$($constant)*] [$($result)*] [$($required,)* $param] [$($arguments,)* $param] [$($any)?] $($($rest)*)?);
+    };
+    (@defaults [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident [$($constant:tt)*] [$($result:tt)*] [$($required:ident),*] [$($arguments:expr),*] []) => {
+        $crate::define_nodes!(@constant_module [$($attrs)*] [$($attrs)*] [$($decl)*] [$($types)*] [$($where)*] $visibility $name [$($constant)*]);
+    };
+    (@defaults [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident [$($constant:tt)*] [$($result:tt)*] [$($required:ident),*] [$($arguments:expr),*] [default]) => {
+        // shares the name of the function, which lives in the other namespace
+        $crate::define_nodes!(@cfg [] [$($attrs)*] $visibility mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub fn defaults<$($decl)*>($($required: impl $crate::compgraph::IntoNodeRef),*) -> $($result)*
+            where $($types: 'static,)* $($where)*
+            {
+                super::$name::<$($names)*>($($arguments),*)
+            }
 
-                #[allow(non_camel_case_types)]
-                struct NodeImpl<$($params: $crate::compgraph::ComputeNodeRef),+> {
-                    $($params: $params),+
+            $crate::define_nodes!(@constant [$($attrs)*] [$($decl)*] [$($types)*] [$($where)*] [$($constant)*]);
+        });
+    };
+    // The module named after a `#[constant]` node without defaults, for its `evaluate`
+    (@constant_module [$($attrs:tt)*] [] $($rest:tt)*) => {};
+    (@constant_module [$($attrs:tt)*] [#[constant] $($others:tt)*] [$($decl:tt)*] [$($types:ident)*] [$($where:tt)*] $visibility:vis $name:ident [$($constant:tt)*]) => {
+        $crate::define_nodes!(@cfg [] [$($attrs)*] $visibility mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::define_nodes!(@constant [$($attrs)*] [$($decl)*] [$($types)*] [$($where)*] [$($constant)*]);
+        });
+    };
+    (@constant_module [$($attrs:tt)*] [#[$($other:tt)*] $($others:tt)*] $($rest:tt)*) => {
+        $crate::define_nodes!(@constant_module [$($attrs)*] [$($others)*] $($rest)*);
+    };
+    // The body of a `#[constant]` node as a `const fn` on values
+    (@constant [] $($rest:tt)*) => {};
+    (@constant [$($attrs:tt)*] [$($decl:tt)*] [$($types:ident)*] [$($where:tt)*] []) => {};
+    (@constant [#[constant] $($attrs:tt)*] [$($decl:tt)*] [$($types:ident)*] [$($where:tt)*] [($($params:ident),+) $body:block]) => {
+        pub const fn evaluate<$($decl)*>($($params: $crate::compgraph::Float),+) -> $crate::compgraph::Float
+        where $($types: 'static,)* $($where)*
+        $body
+    };
+    (@constant [#[$($other:tt)*] $($attrs:tt)*] $($rest:tt)*) => {
+        $crate::define_nodes!(@constant [$($attrs)*] $($rest)*);
+    };
+    // The item with only the `cfg` attributes, for the items other than the function
+    (@cfg [$($kept:tt)*] [] $($item:tt)*) => {
+        $($kept)* $($item)*
+    };
+    (@cfg [$($kept:tt)*] [#[cfg $($condition:tt)*] $($attrs:tt)*] $($item:tt)*) => {
+        $crate::define_nodes!(@cfg [$($kept)* #[cfg $($condition)*]] [$($attrs)*] $($item)*);
+    };
+    (@cfg [$($kept:tt)*] [#[$($other:tt)*] $($attrs:tt)*] $($item:tt)*) => {
+        $crate::define_nodes!(@cfg [$($kept)*] [$($attrs)*] $($item)*);
+    };
+    // The item with the attributes other than `#[uncached]` and `#[constant]`, for the function
+    (@attrs [$($kept:tt)*] [] $($item:tt)*) => {
+        $($kept)* $($item)*
+    };
+    (@attrs [$($kept:tt)*] [#[uncached] $($attrs:tt)*] $($item:tt)*) => {
+        $crate::define_nodes!(@attrs [$($kept)*] [$($attrs)*] $($item)*);
+    };
+    (@attrs [$($kept:tt)*] [#[constant] $($attrs:tt)*] $($item:tt)*) => {
+        $crate::define_nodes!(@attrs [$($kept)*] [$($attrs)*] $($item)*);
+    };
+    (@attrs [$($kept:tt)*] [#[$($other:tt)*] $($attrs:tt)*] $($item:tt)*) => {
+        $crate::define_nodes!(@attrs [$($kept)* #[$($other)*]] [$($attrs)*] $($item)*);
+    };
+    // The handle of a new node, wrapped in a cache unless `#[uncached]`
+    (@wrap [] $inner:ident) => {
+        $crate::compgraph::internals::CachingNodeWrapper::new_ref($inner)
+    };
+    (@wrap [#[uncached] $($attrs:tt)*] $inner:ident) => {
+        $crate::compgraph::internals::InlineNodeWrapper::new_ref($inner)
+    };
+    (@wrap [#[$($other:tt)*] $($attrs:tt)*] $inner:ident) => {
+        $crate::define_nodes!(@wrap [$($attrs)*] $inner)
+    };
+    // The lines of the doc comment, for `ComputeMut::doc`
+    (@doc [$($lines:literal),*]) => {
+        &[$($lines),*]
+    };
+    (@doc [$($lines:literal),*] #[doc = $line:literal] $($attrs:tt)*) => {
+        $crate::define_nodes!(@doc [$($lines,)* $line] $($attrs)*)
+    };
+    (@doc [$($lines:literal),*] #[$($other:tt)*] $($attrs:tt)*) => {
+        $crate::define_nodes!(@doc [$($lines),*] $($attrs)*)
+    };
+    // The tokens given in place of a parameter, for repeating something once per parameter
+    (@replace $param:ident $($with:tt)*) => { $($with)* };
+    (@gradient [$($decl:tt)*] [$($types:ident)*] [$($where:tt)*] ($($params:ident),+)) => {};
+    (@gradient [$($decl:tt)*] [$($types:ident)*] [$($where:tt)*] ($($params:ident),+) $grad:block) => {
+        #[allow(unused_variables)]
+        fn gradient<$($decl)*>($($params: $crate::compgraph::Float),+) -> [$crate::compgraph::Float; 0 $(+ $crate::define_nodes!(@replace $params 1))+]
+        where $($types: 'static,)* $($where)*
+        $grad
+    };
+    (@partials $node:ident [$($names:tt)*] ($($params:ident),+)) => { None };
+    (@partials $node:ident [$($names:tt)*] ($($params:ident),+) $grad:block) => {
+        Some(::std::vec::Vec::from(gradient::<$($names)*>($($node.$params.compute()),+)))
+    };
+    (@outputs [$($attrs:tt)*] $visibility:vis $name:ident($($params:ident),+) $output:ident { $($fields:ident),+ } $body:block) => {
+        $crate::define_nodes!(@cfg [] [$($attrs)*]
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            $visibility struct $output<T = $crate::compgraph::Float> {
+                $(pub $fields: T),+
+            }
+        );
+
+        $crate::define_nodes!(@not_constant $($attrs)*);
+        $crate::define_nodes!(@attrs [] [$($attrs)*]
+        $visibility fn $name($($params: impl $crate::compgraph::IntoNodeRef),+) -> $output<$crate::compgraph::DynamicComputeNodeRef> {
+            fn evaluate($($params: $crate::compgraph::Float),+) -> $output $body
+
+            #[allow(non_camel_case_types)]
+            struct NodeImpl<$($params: $crate::compgraph::ComputeNodeRef),+> {
+                $($params: $params),+
+            }
+
+            #[allow(non_camel_case_types)]
+            impl<$($params: $crate::compgraph::ComputeNodeRef),+> $crate::compgraph::internals::ComputeMut<$output> for NodeImpl<$($params),+> {
+                fn compute(&mut self) -> $output {
+                    evaluate($(self.$params.compute()),+)
+                }
+                fn name(&self) -> &'static str {
+                    stringify!($name)
+                }
+                fn doc(&self) -> &'static [&'static str] {
+                    $crate::define_nodes!(@doc [] $($attrs)*)
+                }
+                fn children(&self) -> ::std::vec::Vec<$crate::compgraph::Child> {
+                    ::std::vec![$(self.$params.as_child()),+]
                 }
+            }
+
+            // A field of the outputs, which graph algorithms see as a node of the shared
+            // node's children, rebuilt with a shared node of its own
+            struct Field {
+                node: $crate::compgraph::DynamicComputeNodeRef<$output>,
+                get: fn($output) -> $crate::compgraph::Float,
+                name: &'static str,
+                rebuild: fn(&[$crate::compgraph::Operand]) -> ::std::option::Option<$crate::compgraph::DynamicComputeNodeRef>
+            }
 
-                #[allow(non_camel_case_types)]
-                impl<$($params: $crate::compgraph::ComputeNodeRef),+> $crate::compgraph::internals::ComputeMut for NodeImpl<$($params),+> {
-                    fn compute(&mut self) -> $crate::compgraph::Float {
-                        $(let $params: $crate::compgraph::Float = self.$params.compute());+;
-                        $body
-                    }
+            impl $crate::compgraph::internals::ComputeMut for Field {
+                fn compute(&mut self) -> $crate::compgraph::Float {
+                    (self.get)($crate::compgraph::ComputeNodeRef::compute(&self.node))
+                }
+                fn name(&self) -> &'static str {
+                    self.name
+                }
+                fn doc(&self) -> &'static [&'static str] {
+                    $crate::define_nodes!(@doc [] $($attrs)*)
                 }
+                fn children(&self) -> ::std::vec::Vec<$crate::compgraph::Child> {
+                    self.node.borrow().children()
+                }
+                fn rebuild(&self, children: &[$crate::compgraph::Operand]) -> ::std::option::Option<$crate::compgraph::DynamicComputeNodeRef> {
+                    (self.rebuild)(children)
+                }
+            }
+
+            fn rebuild(children: &[$crate::compgraph::Operand]) -> ::std::option::Option<$output<$crate::compgraph::DynamicComputeNodeRef>> {
+                let mut children = children.iter().cloned();
+                ::std::option::Option::Some($name($({ let _ = stringify!($params); children.next()? }),+))
+            }
+
+            $(let $params = $crate::compgraph::IntoNodeRef::into_node_ref($params);)+
+            let result = ::std::rc::Rc::new(::std::cell::RefCell::new($crate::compgraph::internals::CachingNodeWrapper::new(
+                NodeImpl { $($params),+ }
+            )));
+            let subscriber = result.clone() as _;
+            {
+                let inner = &result.borrow().inner;
+                $($crate::compgraph::ComputeNodeRef::subscribe_to_invalidate(&inner.$params, &subscriber));+;
+            }
+            let outputs = $output {
+                $($fields: {
+                    let field = ::std::rc::Rc::new(::std::cell::RefCell::new($crate::compgraph::internals::CachingNodeWrapper::new(Field {
+                        node: result.clone(),
+                        get: |value: $output| value.$fields,
+                        name: concat!(stringify!($name), ".", stringify!($fields)),
+                        rebuild: |children| ::std::option::Option::Some(rebuild(children)?.$fields)
+                    })));
+                    $crate::compgraph::ComputeNodeRef::subscribe_to_invalidate(&result, &(field.clone() as _));
+                    field as $crate::compgraph::DynamicComputeNodeRef
+                }),+
+            };
+            $($crate::compgraph::internals::track_unshared_node(&outputs.$fields);)+
+            outputs
+        }
+        );
+    };
+    // Nodes with several outputs can't be constant
+    (@not_constant) => {};
+    (@not_constant #[constant] $($attrs:tt)*) => {
+        compile_error!("nodes with several outputs can't be `#[constant]`");
+    };
+    (@not_constant #[$($other:tt)*] $($attrs:tt)*) => {
+        $crate::define_nodes!(@not_constant $($attrs)*);
+    };
+    (@node [$($attrs:tt)*] [$($decl:tt)*] [$($names:tt)*] [$($types:ident)*] [$($where:tt)*]
+        $visibility:vis $name:ident($($params:ident),+) $body:block [$($grad:block)?]) => {
+        $crate::define_nodes!(@attrs [] [$($attrs)*]
+        $visibility fn $name<$($decl)*>($($params: impl $crate::compgraph::IntoNodeRef),+) -> $crate::compgraph::DynamicComputeNodeRef
+        where $($types: 'static,)* $($where)*
+        {
+            fn evaluate<$($decl)*>($($params: $crate::compgraph::Float),+) -> $crate::compgraph::Float
+            where $($types: 'static,)* $($where)*
+            $body
+
+            $crate::define_nodes!(@gradient [$($decl)*] [$($types)*] [$($where)*] ($($params),+) $($grad)?);
+
+            #[allow(non_camel_case_types)]
+            struct NodeImpl<$($decl)* $($params: $crate::compgraph::ComputeNodeRef),+> {
+                $($params: $params,)+
+                _generics: ::std::marker::PhantomData<fn() -> ($($types,)*)>
+            }
 
-                let result = ::std::rc::Rc::new(::std::cell::RefCell::new($crate::compgraph::internals::CachingNodeWrapper::new(
-                    NodeImpl { $($params),+ }
-                )));
-                let subscriber = result.clone() as _;
-                {
-                    let inner = &result.borrow().inner;
-                    $(inner.$params.subscribe_to_invalidate(&subscriber));+;
+            #[allow(non_camel_case_types)]
+            impl<$($decl)* $($params: $crate::compgraph::ComputeNodeRef),+> $crate::compgraph::internals::ComputeMut for NodeImpl<$($names)* $($params),+>
+            where $($types: 'static,)* $($where)*
+            {
+                fn compute(&mut self) -> $crate::compgraph::Float {
+                    evaluate::<$($names)*>($(self.$params.compute()),+)
+                }
+                fn name(&self) -> &'static str {
+                    stringify!($name)
+                }
+                fn doc(&self) -> &'static [&'static str] {
+                    $crate::define_nodes!(@doc [] $($attrs)*)
+                }
+                fn children(&self) -> ::std::vec::Vec<$crate::compgraph::Child> {
+                    ::std::vec![$(self.$params.as_child()),+]
+                }
+                fn partials(&mut self) -> ::std::option::Option<::std::vec::Vec<$crate::compgraph::Float>> {
+                    $crate::define_nodes!(@partials self [$($names)*] ($($params),+) $($grad)?)
+                }
+                fn rebuild(&self, children: &[$crate::compgraph::Operand]) -> ::std::option::Option<$crate::compgraph::DynamicComputeNodeRef> {
+                    let mut children = children.iter().cloned();
+                    ::std::option::Option::Some($name::<$($names)*>($({ let _ = stringify!($params); children.next()? }),+))
                 }
-                result
             }
-        )*
+
+            $(let $params = $crate::compgraph::IntoNodeRef::into_node_ref($params);)+
+            let inner = NodeImpl::<$($names)* $($crate::define_nodes!(@replace $params _)),+> { $($params,)+ _generics: ::std::marker::PhantomData };
+            if let ::std::option::Option::Some(existing) = $crate::compgraph::internals::existing_node(&inner) {
+                return existing;
+            }
+            let result = $crate::define_nodes!(@wrap [$($attrs)*] inner);
+            let subscriber = result.clone() as _;
+            {
+                let inner = &result.borrow().inner;
+                $($crate::compgraph::ComputeNodeRef::subscribe_to_invalidate(&inner.$params, &subscriber));+;
+            }
+            $crate::compgraph::internals::track_node(&result);
+            result
+        });
     };
 }
-
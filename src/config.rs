@@ -0,0 +1,328 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt, error::Error, path::Path, iter::Peekable, str::Chars};
+
+use crate::compgraph::*;
+
+// Input values from configuration files
+//
+// A `Config` holds numbers by key, read from JSON or TOML; nested objects and tables give
+// dotted keys, `{"filter": {"cutoff": 0.5}}` and `[filter] cutoff = 0.5` both `filter.cutoff`.
+// Only numbers are values, so the files are a subset of each format: no strings, arrays or
+// booleans as values. `apply` sets the inputs bound to the keys in one transaction: all of
+// them are set before any of the work their changes queue runs, so listeners never see a
+// half-applied configuration. Keys without an input and inputs without a key are reported;
+// `apply_strict` refuses to set anything when there are any
+//
+// Values can also come from the environment and the command line, for running a model from
+// scripts: `MODEL_FILTER__CUTOFF=0.5` is `filter.cutoff` under the prefix `MODEL_`, and
+// `--set filter.cutoff=0.5` an option. `merge` layers them, e.g. a file, then the environment,
+// then the command line, each overriding the one before
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    // `line` counts from 1
+    Parse { line: usize, message: String },
+    Io { path: String, message: String },
+    Mismatch { unknown: Vec<String>, missing: Vec<String> },
+    // an environment variable or a command line argument
+    Invalid { source: String, message: String }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Parse { line, message } => write!(f, "{} on line {}", message, line),
+            ConfigError::Io { path, message } => write!(f, "can't read `{}`: {}", path, message),
+            ConfigError::Mismatch { unknown, missing } => {
+                write!(f, "unknown keys [{}], missing keys [{}]", unknown.join(", "), missing.join(", "))
+            }
+            ConfigError::Invalid { source, message } => write!(f, "{} in `{}`", message, source)
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// What `apply` did, keys in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigReport {
+    pub set: Vec<String>,
+    // in the configuration but not bound to an input
+    pub unknown: Vec<String>,
+    // bound to an input but not in the configuration; the input keeps its value
+    pub missing: Vec<String>
+}
+
+impl ConfigReport {
+    pub fn is_complete(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    values: BTreeMap<String, Float>
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    // By the extension, `.json` or `.toml`
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let io_error = |message: String| ConfigError::Io { path: path.display().to_string(), message };
+        let text = std::fs::read_to_string(path).map_err(|error| io_error(error.to_string()))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Config::from_json(&text),
+            Some("toml") => Config::from_toml(&text),
+            _ => Err(io_error("expected a .json or .toml file".to_string()))
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Config, ConfigError> {
+        let mut parser = JsonParser { chars: text.chars().peekable(), line: 1 };
+        let mut config = Config::new();
+        parser.skip_whitespace();
+        parser.object("", 0, &mut config)?;
+        parser.skip_whitespace();
+        match parser.chars.peek() {
+            None => Ok(config),
+            Some(_) => Err(parser.error("unexpected text after the object"))
+        }
+    }
+
+    // `key = value` lines under `[table]` headers, with `#` comments
+    pub fn from_toml(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::new();
+        let mut table = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| ConfigError::Parse { line: index + 1, message: message.to_string() };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or_else(|| error("unclosed table header"))?;
+                table = toml_key(header).ok_or_else(|| error("invalid table name"))?;
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+            let key = toml_key(key).ok_or_else(|| error("invalid key"))?;
+            let value = toml_number(value.trim()).ok_or_else(|| error("expected a number"))?;
+            let key = if table.is_empty() { key } else { format!("{}.{}", table, key) };
+            if config.values.insert(key.clone(), value).is_some() {
+                return Err(error(&format!("duplicate key `{}`", key)));
+            }
+        }
+        Ok(config)
+    }
+
+    // The variables starting with `prefix`, lowercased after it with `__` between parts
+    pub fn from_env(prefix: &str) -> Result<Config, ConfigError> {
+        Config::from_vars(prefix, std::env::vars())
+    }
+
+    // Same, from given variables
+    pub fn from_vars(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config, ConfigError> {
+        let mut config = Config::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(prefix).filter(|key| !key.is_empty()) else { continue };
+            let invalid = |message: &str| ConfigError::Invalid { source: format!("{}={}", name, value), message: message.to_string() };
+            let key = toml_key(&key.to_lowercase().replace("__", ".")).ok_or_else(|| invalid("invalid key"))?;
+            let value = toml_number(value.trim()).ok_or_else(|| invalid("expected a number"))?;
+            config.values.insert(key, value);
+        }
+        Ok(config)
+    }
+
+    // Takes the `--set key=value` and `--set=key=value` options out of `args`, handing back
+    // the other arguments in order; a later option for a key overrides an earlier one
+    pub fn from_args<A: Into<String>>(args: impl IntoIterator<Item = A>) -> Result<(Config, Vec<String>), ConfigError> {
+        let mut config = Config::new();
+        let mut rest = Vec::new();
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            let assignment = match arg.strip_prefix("--set") {
+                Some("") => args.next().ok_or_else(|| ConfigError::Invalid { source: arg.clone(), message: "missing `key=value`".to_string() })?,
+                Some(assignment) if assignment.starts_with('=') => assignment[1..].to_string(),
+                _ => {
+                    rest.push(arg);
+                    continue;
+                }
+            };
+            let invalid = |message: &str| ConfigError::Invalid { source: assignment.clone(), message: message.to_string() };
+            let (key, value) = assignment.split_once('=').ok_or_else(|| invalid("expected `key=value`"))?;
+            let key = toml_key(key).ok_or_else(|| invalid("invalid key"))?;
+            let value = toml_number(value.trim()).ok_or_else(|| invalid("expected a number"))?;
+            config.values.insert(key, value);
+        }
+        Ok((config, rest))
+    }
+
+    // The values of `other` override those of `self`
+    pub fn merge(&mut self, other: Config) {
+        self.values.extend(other.values);
+    }
+
+    pub fn get(&self, key: &str) -> Option<Float> {
+        self.values.get(key).copied()
+    }
+    pub fn set(&mut self, key: impl Into<String>, value: Float) {
+        self.values.insert(key.into(), value);
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn apply<P: InputNodeRef + 'static>(&self, bindings: &[(&str, P)]) -> ConfigReport {
+        let report = self.report(bindings);
+        set_all(bindings.iter().filter_map(|(key, input)| Some((input, self.get(key)?))));
+        report
+    }
+
+    // Fails before setting anything unless every key has an input and every input a key
+    pub fn apply_strict<P: InputNodeRef + 'static>(&self, bindings: &[(&str, P)]) -> Result<ConfigReport, ConfigError> {
+        let report = self.report(bindings);
+        if !report.is_complete() {
+            return Err(ConfigError::Mismatch { unknown: report.unknown, missing: report.missing });
+        }
+        Ok(self.apply(bindings))
+    }
+
+    fn report<P>(&self, bindings: &[(&str, P)]) -> ConfigReport {
+        let bound: BTreeSet<&str> = bindings.iter().map(|(key, _)| *key).collect();
+        let (set, missing) = bound.iter().map(|key| key.to_string()).partition(|key| self.values.contains_key(key));
+        let unknown = self.keys().filter(|key| !bound.contains(key)).map(str::to_string).collect();
+        ConfigReport { set, unknown, missing }
+    }
+}
+
+// Bare or quoted parts joined by dots
+fn toml_key(text: &str) -> Option<String> {
+    let parts = text.split('.').map(|part| {
+        let part = part.trim();
+        let part = part.strip_prefix('"').and_then(|part| part.strip_suffix('"')).unwrap_or(part);
+        let bare = part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        (!part.is_empty() && bare).then_some(part)
+    }).collect::<Option<Vec<_>>>()?;
+    Some(parts.join("."))
+}
+
+fn toml_number(text: &str) -> Option<Float> {
+    let text = text.replace('_', "");
+    match text.trim_start_matches(['+', '-']) {
+        "inf" | "nan" => {
+            let value = if text.ends_with("inf") { Float::INFINITY } else { Float::NAN };
+            Some(if text.starts_with('-') { -value } else { value })
+        }
+        digits if digits.starts_with(|c: char| c.is_ascii_digit()) => text.parse().ok(),
+        _ => None
+    }
+}
+
+// Deeper than any configuration nests, so that malformed input can't exhaust the stack
+const MAX_DEPTH: usize = 32;
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> ConfigError {
+        ConfigError::Parse { line: self.line, message: message.to_string() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ConfigError> {
+        self.skip_whitespace();
+        if self.chars.next() == Some(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", expected)))
+        }
+    }
+
+    // The keys of the object's numbers go into `config` after `prefix`
+    fn object(&mut self, prefix: &str, depth: usize, config: &mut Config) -> Result<(), ConfigError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("objects nested too deeply"));
+        }
+        self.expect('{')?;
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+            self.expect(':')?;
+            self.skip_whitespace();
+            if self.chars.peek() == Some(&'{') {
+                self.object(&key, depth + 1, config)?;
+            } else {
+                let value = self.number()?;
+                if config.values.insert(key.clone(), value).is_some() {
+                    return Err(self.error(&format!("duplicate key `{}`", key)));
+                }
+            }
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(()),
+                _ => return Err(self.error("expected `,` or `}`"))
+            }
+        }
+    }
+
+    // Without escapes other than `\"` and `\\`, which keys don't need
+    fn string(&mut self) -> Result<String, ConfigError> {
+        if self.chars.next() != Some('"') {
+            return Err(self.error("expected a key"));
+        }
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some(c @ ('"' | '\\')) => string.push(c),
+                    _ => return Err(self.error("unsupported escape"))
+                },
+                Some('\n') | None => return Err(self.error("unclosed string")),
+                Some(c) => string.push(c)
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Float, ConfigError> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        text.parse().map_err(|_| self.error("expected a number or an object"))
+    }
+}
@@ -0,0 +1,118 @@
+use std::{fmt, error::Error, iter::Peekable};
+
+use crate::compgraph::*;
+use crate::graph::{self, NodeId, NodeView, Traversal};
+
+// Step-through evaluation, for finding where a large graph goes wrong
+//
+// A `Debugger` evaluates an output a node at a time, children first (see `graph::traverse`),
+// and can stop between any two nodes: `pending()` shows the node about to be computed, with
+// its children's values and whether it still holds a valid value, and `step()` computes it.
+// `run` goes through the remaining nodes calling hooks before and after each, which can
+// abort the evaluation. What was computed stays cached, so that a later evaluation picks up
+// where an aborted one stopped
+
+pub struct Step {
+    view: NodeView,
+    children: Vec<Option<Float>>,
+    cached: Option<bool>,
+    value: Option<Float>
+}
+
+impl Step {
+    pub fn view(&self) -> &NodeView {
+        &self.view
+    }
+    // In the order of the node's children, `None` for those that can't be seen
+    pub fn children(&self) -> &[Option<Float>] {
+        &self.children
+    }
+    // Whether the node held a valid value before the step, `None` for nodes that don't cache
+    pub fn was_cached(&self) -> Option<bool> {
+        self.cached
+    }
+    // Once computed
+    pub fn value(&self) -> Option<Float> {
+        self.value
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Abort
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aborted {
+    pub node: NodeId,
+    pub name: &'static str,
+    // whether the node was computed before the evaluation was aborted
+    pub computed: bool
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let when = if self.computed { "after" } else { "before" };
+        write!(f, "evaluation aborted {} node {} (`{}`)", when, self.node, self.name)
+    }
+}
+
+impl Error for Aborted {}
+
+pub struct Debugger {
+    output: DynamicComputeNodeRef,
+    nodes: Peekable<Traversal>
+}
+
+impl Debugger {
+    pub fn new(output: &DynamicComputeNodeRef) -> Debugger {
+        Debugger { output: output.clone(), nodes: graph::traverse(output).peekable() }
+    }
+
+    // `None` once every node has been computed
+    pub fn pending(&mut self) -> Option<Step> {
+        self.nodes.peek().map(|view| Debugger::before(view.clone()))
+    }
+
+    fn before(view: NodeView) -> Step {
+        // the children come first, so are computed already
+        let children = view.children().iter().map(|child| match child {
+            Child::Node(node) => Some(node.compute()),
+            Child::Constant(value) => Some(*value),
+            Child::Opaque => None
+        }).collect();
+        let cached = view.node().borrow().is_cached();
+        Step { view, children, cached, value: None }
+    }
+
+    // Computes the pending node
+    pub fn step(&mut self) -> Option<Step> {
+        let mut step = Debugger::before(self.nodes.next()?);
+        step.value = Some(step.view.node().compute());
+        Some(step)
+    }
+
+    pub fn run(
+        &mut self,
+        mut before: impl FnMut(&Step) -> Control, mut after: impl FnMut(&Step) -> Control
+    ) -> Result<Float, Aborted> {
+        let aborted = |step: &Step, computed| Aborted { node: step.view.id(), name: step.view.name(), computed };
+        while let Some(pending) = self.pending() {
+            if before(&pending) == Control::Abort {
+                return Err(aborted(&pending, false));
+            }
+            let step = self.step().unwrap();
+            if after(&step) == Control::Abort {
+                return Err(aborted(&step, true));
+            }
+        }
+        Ok(self.output.compute())
+    }
+
+    // Computes the remaining nodes without stopping
+    pub fn finish(&mut self) -> Float {
+        self.nodes.by_ref().for_each(|view| { view.node().compute(); });
+        self.output.compute()
+    }
+}
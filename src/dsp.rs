@@ -0,0 +1,197 @@
+use crate::compgraph::*;
+use crate::stateful::{State, DynamicStatefulNodeRef, stateful_node};
+use crate::strict;
+
+// Signal processing nodes: filters and envelopes over a signal sampled once per step
+//
+// These are stateful nodes, so the signal advances by one sample on each `step(dt)` (or
+// clock tick), with `dt` the sample period. The output is the processed value of the last
+// sample. Frequencies are in Hz and times in seconds
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Low,
+    High,
+    Band
+}
+
+// Second-order IIR filter with the coefficients of the Audio EQ Cookbook
+struct Biquad {
+    pass: Pass,
+    cutoff: f64,
+    q: f64,
+    // b0, b1, b2, a1, a2 normalized by a0, for the sample period they were computed for
+    coefficients: Option<(Float, [f64; 5])>,
+    inputs: [f64; 2],
+    outputs: [f64; 2]
+}
+
+impl Biquad {
+    fn coefficients(&mut self, dt: Float) -> [f64; 5] {
+        match self.coefficients {
+            Some((period, coefficients)) if period == dt => coefficients,
+            _ => {
+                let omega = std::f64::consts::TAU * self.cutoff * dt as f64;
+                let (sin, cos) = strict::sin_cos_f64(omega);
+                let alpha = sin / (2.0 * self.q);
+                let (b0, b1, b2) = match self.pass {
+                    Pass::Low => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0),
+                    Pass::High => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0),
+                    Pass::Band => (alpha, 0.0, -alpha)
+                };
+                let a0 = 1.0 + alpha;
+                let coefficients = [b0 / a0, b1 / a0, b2 / a0, -2.0 * cos / a0, (1.0 - alpha) / a0];
+                self.coefficients = Some((dt, coefficients));
+                coefficients
+            }
+        }
+    }
+}
+
+impl State for Biquad {
+    fn output(&self) -> Float { self.outputs[0] as Float }
+    fn advance(&mut self, input: Float, dt: Float) {
+        let [b0, b1, b2, a1, a2] = self.coefficients(dt);
+        let x = input as f64;
+        let y = b0 * x + b1 * self.inputs[0] + b2 * self.inputs[1] - a1 * self.outputs[0] - a2 * self.outputs[1];
+        self.inputs = [x, self.inputs[0]];
+        self.outputs = [y, self.outputs[0]];
+    }
+    fn reset(&mut self) {
+        self.inputs = [0.0; 2];
+        self.outputs = [0.0; 2];
+    }
+}
+
+fn biquad(x: impl ComputeNodeRef + 'static, pass: Pass, cutoff: Float, q: Float) -> DynamicStatefulNodeRef {
+    stateful_node(x, Biquad { pass, cutoff: cutoff as f64, q: q as f64, coefficients: None, inputs: [0.0; 2], outputs: [0.0; 2] })
+}
+
+// A `q` of 1/sqrt(2) gives the flattest passband (Butterworth)
+pub fn low_pass(x: impl ComputeNodeRef + 'static, cutoff: Float, q: Float) -> DynamicStatefulNodeRef {
+    biquad(x, Pass::Low, cutoff, q)
+}
+
+pub fn high_pass(x: impl ComputeNodeRef + 'static, cutoff: Float, q: Float) -> DynamicStatefulNodeRef {
+    biquad(x, Pass::High, cutoff, q)
+}
+
+// Unity gain at `center`, with a bandwidth of center / q
+pub fn band_pass(x: impl ComputeNodeRef + 'static, center: Float, q: Float) -> DynamicStatefulNodeRef {
+    biquad(x, Pass::Band, center, q)
+}
+
+// Exponential smoothing towards the input, e.g. for parameter changes without clicks
+struct OnePole {
+    time_constant: Float,
+    initial: Float,
+    value: Float
+}
+
+impl State for OnePole {
+    fn output(&self) -> Float { self.value }
+    fn advance(&mut self, input: Float, dt: Float) {
+        let factor = if self.time_constant > 0.0 { 1.0 - strict::exp(-dt / self.time_constant) } else { 1.0 };
+        self.value += factor * (input - self.value);
+    }
+    fn reset(&mut self) { self.value = self.initial }
+}
+
+// Covers about 63% of the way to a new input value in `time_constant`
+pub fn one_pole(x: impl ComputeNodeRef + 'static, time_constant: Float, initial: Float) -> DynamicStatefulNodeRef {
+    stateful_node(x, OnePole { time_constant, initial, value: initial })
+}
+
+// Follows the input at a limited speed, in units per second
+struct SlewLimiter {
+    rise: Float,
+    fall: Float,
+    initial: Float,
+    value: Float
+}
+
+impl State for SlewLimiter {
+    fn output(&self) -> Float { self.value }
+    fn advance(&mut self, input: Float, dt: Float) {
+        // a NaN step doesn't limit, rather than panicking
+        self.value += (input - self.value).max(-self.fall * dt).min(self.rise * dt);
+    }
+    fn reset(&mut self) { self.value = self.initial }
+}
+
+// Unlike `one_pole`, it reaches the input in a bounded time: a jump of d takes d / rise
+// (or d / fall downwards) seconds
+pub fn slew_limiter(x: impl ComputeNodeRef + 'static, rise: Float, fall: Float, initial: Float) -> DynamicStatefulNodeRef {
+    assert!(rise >= 0.0 && fall >= 0.0, "slew limiter: rates must be non-negative, not {} and {}", rise, fall);
+    stateful_node(x, SlewLimiter { rise, fall, initial, value: initial })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release
+}
+
+// Linear attack, decay and release segments
+struct Adsr {
+    attack: Float,
+    decay: Float,
+    sustain: Float,
+    release: Float,
+    stage: Stage,
+    level: Float,
+    // per second, from the level the release started at
+    release_rate: Float
+}
+
+impl State for Adsr {
+    fn output(&self) -> Float { self.level }
+    fn advance(&mut self, gate: Float, dt: Float) {
+        if gate > 0.5 {
+            if matches!(self.stage, Stage::Idle | Stage::Release) {
+                self.stage = Stage::Attack;
+            }
+        } else if !matches!(self.stage, Stage::Idle | Stage::Release) {
+            self.stage = Stage::Release;
+            self.release_rate = if self.release > 0.0 { self.level / self.release } else { 0.0 };
+        }
+        match self.stage {
+            Stage::Idle | Stage::Sustain => {}
+            Stage::Attack => {
+                self.level = if self.attack > 0.0 { self.level + dt / self.attack } else { 1.0 };
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level = if self.decay > 0.0 { self.level - dt * (1.0 - self.sustain) / self.decay } else { self.sustain };
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Release => {
+                self.level = if self.release > 0.0 { self.level - dt * self.release_rate } else { 0.0 };
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+    }
+    fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+    }
+}
+
+// Envelope in [0, 1] following `gate`: on while the gate is above 0.5, it rises to 1 over
+// `attack`, falls to `sustain` over `decay` and stays there; once the gate is off, it falls
+// to 0 over `release`
+pub fn adsr(gate: impl ComputeNodeRef + 'static, attack: Float, decay: Float, sustain: Float, release: Float) -> DynamicStatefulNodeRef {
+    stateful_node(gate, Adsr { attack, decay, sustain, release, stage: Stage::Idle, level: 0.0, release_rate: 0.0 })
+}
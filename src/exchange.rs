@@ -0,0 +1,422 @@
+use std::{collections::HashMap, fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::proto::{Writer, Reader, Value};
+use crate::msgpack;
+use crate::registry::{NodeRegistry, RegistryError};
+
+// Graphs and values in protocol buffers, for exchange with programs in other languages
+//
+// `SCHEMA` is the contract: other programs generate their code from it, and the encoding
+// here follows it field for field. A graph is its nodes in topological order, children
+// first, each naming its kind as in a `NodeRegistry` and referring to its children by
+// position, with its named outputs; inputs carry their names and current values. Values
+// from outside the graph (a clock's time, ...) become inputs named `external`. The schema's
+// service is for gRPC servers and clients built on it; this crate only does the messages
+//
+// The same graphs and values also go in MessagePack, which needs no schema to be read:
+// a graph is a map of `nodes`, maps with the fields of `Node`, and `outputs`, pairs of a name
+// and an operand, where operands are unsigned integers for positions and floats for
+// constants; values are a map from names to floats
+
+pub const SCHEMA: &str = r#"syntax = "proto3";
+
+package compgraph;
+
+message Graph {
+  // in topological order, children first
+  repeated Node nodes = 1;
+  repeated Output outputs = 2;
+}
+
+message Node {
+  // "input", or the name of the node in a registry
+  string kind = 1;
+  repeated Operand children = 2;
+  // of inputs
+  string name = 3;
+  float value = 4;
+}
+
+message Operand {
+  oneof operand {
+    // the position of a node in `Graph.nodes`, before the node using it
+    uint32 node = 1;
+    float constant = 2;
+  }
+}
+
+message Output {
+  string name = 1;
+  Operand value = 2;
+}
+
+// Input or output values by name
+message Values {
+  repeated NamedValue values = 1;
+}
+
+message NamedValue {
+  string name = 1;
+  float value = 2;
+}
+
+service GraphService {
+  rpc SetInputs(Values) returns (Values);
+  rpc GetOutputs(Values) returns (Values);
+  rpc GetGraph(Values) returns (Graph);
+}
+"#;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExchangeError {
+    Malformed(&'static str),
+    // a reference to a node that isn't before the node using it
+    UnknownNode(u64),
+    Registry(RegistryError)
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExchangeError::Malformed(what) => write!(f, "malformed message: {}", what),
+            ExchangeError::UnknownNode(position) => write!(f, "node {} is used before it is defined", position),
+            ExchangeError::Registry(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl Error for ExchangeError {}
+
+impl From<RegistryError> for ExchangeError {
+    fn from(error: RegistryError) -> ExchangeError {
+        ExchangeError::Registry(error)
+    }
+}
+
+// A graph as the formats write it, nodes referring to each other by position
+struct FlatGraph {
+    nodes: Vec<FlatNode>,
+    outputs: Vec<(String, Reference)>
+}
+
+struct FlatNode {
+    kind: String,
+    children: Vec<Reference>,
+    // of inputs
+    name: String,
+    value: Float
+}
+
+enum Reference {
+    Node(u64),
+    Constant(Float)
+}
+
+impl FlatGraph {
+    fn new(inputs: &[(&str, Input)], outputs: &[(&str, Operand)]) -> FlatGraph {
+        let names: HashMap<*const (), &str> = inputs.iter()
+            .map(|(name, input)| (node_address(&(input.clone() as DynamicComputeNodeRef)), *name))
+            .collect();
+        let mut nodes = Vec::new();
+        let mut positions = HashMap::new();
+        for (_, output) in outputs {
+            let Operand::Node(output) = output else { continue };
+            for node in topological_order(output) {
+                if positions.contains_key(&node_address(&node)) {
+                    continue;
+                }
+                let (kind, children) = { let node = node.borrow(); (node.name(), node.children()) };
+                let children = children.into_iter().map(|child| match child {
+                    Child::Node(child) => Reference::Node(positions[&node_address(&child)]),
+                    Child::Constant(value) => Reference::Constant(value),
+                    Child::Opaque => {
+                        nodes.push(FlatNode { kind: "input".into(), children: Vec::new(), name: "external".into(), value: 0.0 });
+                        Reference::Node(nodes.len() as u64 - 1)
+                    }
+                }).collect();
+                let (name, value) = match is_input(&node) {
+                    true => (names.get(&node_address(&node)).copied().unwrap_or("").to_string(), node.compute()),
+                    false => (String::new(), 0.0)
+                };
+                positions.insert(node_address(&node), nodes.len() as u64);
+                nodes.push(FlatNode { kind: kind.to_string(), children, name, value });
+            }
+        }
+        let outputs = outputs.iter().map(|(name, output)| {
+            let output = match output {
+                Operand::Node(node) => Reference::Node(positions[&node_address(node)]),
+                Operand::Constant(value) => Reference::Constant(*value)
+            };
+            (name.to_string(), output)
+        }).collect();
+        FlatGraph { nodes, outputs }
+    }
+
+    // Builds the graph with the registry's nodes
+    fn build(self, registry: &NodeRegistry) -> Result<DecodedGraph, ExchangeError> {
+        let mut nodes: Vec<Operand> = Vec::new();
+        let operand = |reference: Reference, nodes: &[Operand]| match reference {
+            Reference::Node(position) => nodes.get(position as usize).cloned().ok_or(ExchangeError::UnknownNode(position)),
+            Reference::Constant(value) => Ok(Operand::Constant(value))
+        };
+        let mut graph = DecodedGraph { inputs: Vec::new(), outputs: Vec::new() };
+        for node in self.nodes {
+            let children = node.children.into_iter().map(|child| operand(child, &nodes)).collect::<Result<Vec<_>, _>>()?;
+            let node = if node.kind == "input" && children.is_empty() {
+                let input = InputNodeImpl::new_ref(node.value);
+                graph.inputs.push((node.name, input.clone()));
+                input as DynamicComputeNodeRef
+            } else {
+                registry.create(&node.kind, &children)?
+            };
+            nodes.push(Operand::Node(node));
+        }
+        for (name, output) in self.outputs {
+            graph.outputs.push((name, operand(output, &nodes)?));
+        }
+        Ok(graph)
+    }
+}
+
+pub struct DecodedGraph {
+    // named or not, in the order of the nodes
+    pub inputs: Vec<(String, Input)>,
+    pub outputs: Vec<(String, Operand)>
+}
+
+impl DecodedGraph {
+    // The first input with the name
+    pub fn input(&self, name: &str) -> Option<&Input> {
+        self.inputs.iter().find(|(n, _)| n == name).map(|(_, input)| input)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Operand> {
+        self.outputs.iter().find(|(n, _)| n == name).map(|(_, output)| output)
+    }
+}
+
+fn write_reference(writer: &mut Writer, field: u64, reference: &Reference) {
+    writer.message(field, |operand| match *reference {
+        Reference::Node(position) => operand.int(1, position as i64),
+        Reference::Constant(value) => operand.float(2, value)
+    });
+}
+
+// `inputs` names the inputs they contain; other inputs get empty names
+pub fn encode(inputs: &[(&str, Input)], outputs: &[(&str, Operand)]) -> Vec<u8> {
+    let graph = FlatGraph::new(inputs, outputs);
+    let mut writer = Writer::new();
+    for node in &graph.nodes {
+        writer.message(1, |message| {
+            message.string(1, &node.kind);
+            node.children.iter().for_each(|child| write_reference(message, 2, child));
+            if node.kind == "input" {
+                message.string(3, &node.name);
+                message.float(4, node.value);
+            }
+        });
+    }
+    for (name, output) in &graph.outputs {
+        writer.message(2, |message| {
+            message.string(1, name);
+            write_reference(message, 2, output);
+        });
+    }
+    writer.into_bytes()
+}
+
+fn fields<'a>(bytes: &'a [u8], what: &'static str) -> Result<Vec<(u64, Value<'a>)>, ExchangeError> {
+    Reader::new(bytes).fields().ok_or(ExchangeError::Malformed(what))
+}
+
+fn message<'a>(value: &Value<'a>, what: &'static str) -> Result<&'a [u8], ExchangeError> {
+    value.as_bytes().ok_or(ExchangeError::Malformed(what))
+}
+
+fn string(value: &Value, what: &'static str) -> Result<String, ExchangeError> {
+    value.as_str().map(str::to_string).ok_or(ExchangeError::Malformed(what))
+}
+
+fn float(value: &Value, what: &'static str) -> Result<Float, ExchangeError> {
+    match *value {
+        Value::Fixed32(bits) => Ok(f32::from_bits(bits) as Float),
+        _ => Err(ExchangeError::Malformed(what))
+    }
+}
+
+// An absent operand is the constant 0, as proto3 leaves out default values
+fn read_reference(bytes: &[u8]) -> Result<Reference, ExchangeError> {
+    let mut reference = Reference::Constant(0.0);
+    for (field, value) in fields(bytes, "operand")? {
+        match (field, value) {
+            (1, Value::Varint(position)) => reference = Reference::Node(position),
+            (2, value) => reference = Reference::Constant(float(&value, "constant")?),
+            _ => {}
+        }
+    }
+    Ok(reference)
+}
+
+pub fn decode(bytes: &[u8], registry: &NodeRegistry) -> Result<DecodedGraph, ExchangeError> {
+    let mut graph = FlatGraph { nodes: Vec::new(), outputs: Vec::new() };
+    for (field, value) in fields(bytes, "graph")? {
+        match field {
+            1 => {
+                let mut node = FlatNode { kind: String::new(), children: Vec::new(), name: String::new(), value: 0.0 };
+                for (field, value) in fields(message(&value, "node")?, "node")? {
+                    match field {
+                        1 => node.kind = string(&value, "node kind")?,
+                        2 => node.children.push(read_reference(message(&value, "operand")?)?),
+                        3 => node.name = string(&value, "input name")?,
+                        4 => node.value = float(&value, "input value")?,
+                        _ => {}
+                    }
+                }
+                graph.nodes.push(node);
+            }
+            2 => {
+                let (mut name, mut output) = (String::new(), Reference::Constant(0.0));
+                for (field, value) in fields(message(&value, "output")?, "output")? {
+                    match field {
+                        1 => name = string(&value, "output name")?,
+                        2 => output = read_reference(message(&value, "operand")?)?,
+                        _ => {}
+                    }
+                }
+                graph.outputs.push((name, output));
+            }
+            _ => {}
+        }
+    }
+    graph.build(registry)
+}
+
+// A `Values` message
+pub fn encode_values(values: &[(&str, Float)]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    for (name, value) in values {
+        writer.message(1, |named| {
+            named.string(1, name);
+            named.float(2, *value);
+        });
+    }
+    writer.into_bytes()
+}
+
+pub fn decode_values(bytes: &[u8]) -> Result<Vec<(String, Float)>, ExchangeError> {
+    let mut values = Vec::new();
+    for (field, value) in fields(bytes, "values")? {
+        if field != 1 {
+            continue;
+        }
+        let (mut name, mut named_value) = (String::new(), 0.0);
+        for (field, value) in fields(message(&value, "named value")?, "named value")? {
+            match field {
+                1 => name = string(&value, "name")?,
+                2 => named_value = float(&value, "value")?,
+                _ => {}
+            }
+        }
+        values.push((name, named_value));
+    }
+    Ok(values)
+}
+
+fn write_msgpack_reference(writer: &mut msgpack::Writer, reference: &Reference) {
+    match *reference {
+        Reference::Node(position) => writer.uint(position),
+        Reference::Constant(value) => writer.float(value)
+    }
+}
+
+pub fn encode_msgpack(inputs: &[(&str, Input)], outputs: &[(&str, Operand)]) -> Vec<u8> {
+    let graph = FlatGraph::new(inputs, outputs);
+    let mut writer = msgpack::Writer::new();
+    writer.map(2);
+    writer.string("nodes");
+    writer.array(graph.nodes.len());
+    for node in &graph.nodes {
+        let is_input = node.kind == "input";
+        writer.map(if is_input { 4 } else { 2 });
+        writer.string("kind");
+        writer.string(&node.kind);
+        writer.string("children");
+        writer.array(node.children.len());
+        node.children.iter().for_each(|child| write_msgpack_reference(&mut writer, child));
+        if is_input {
+            writer.string("name");
+            writer.string(&node.name);
+            writer.string("value");
+            writer.float(node.value);
+        }
+    }
+    writer.string("outputs");
+    writer.array(graph.outputs.len());
+    for (name, output) in &graph.outputs {
+        writer.array(2);
+        writer.string(name);
+        write_msgpack_reference(&mut writer, output);
+    }
+    writer.into_bytes()
+}
+
+fn msgpack_float(value: &msgpack::Value, what: &'static str) -> Result<Float, ExchangeError> {
+    match *value {
+        msgpack::Value::Float(value) => Ok(value as Float),
+        msgpack::Value::Int(value) => Ok(value as Float),
+        _ => Err(ExchangeError::Malformed(what))
+    }
+}
+
+fn msgpack_reference(value: &msgpack::Value) -> Result<Reference, ExchangeError> {
+    match *value {
+        msgpack::Value::Int(position) => u64::try_from(position).map(Reference::Node).map_err(|_| ExchangeError::Malformed("operand")),
+        msgpack::Value::Float(value) => Ok(Reference::Constant(value as Float)),
+        _ => Err(ExchangeError::Malformed("operand"))
+    }
+}
+
+fn msgpack_string(value: Option<&msgpack::Value>, what: &'static str) -> Result<String, ExchangeError> {
+    value.and_then(msgpack::Value::as_str).map(str::to_string).ok_or(ExchangeError::Malformed(what))
+}
+
+pub fn decode_msgpack(bytes: &[u8], registry: &NodeRegistry) -> Result<DecodedGraph, ExchangeError> {
+    let value = msgpack::Reader::new(bytes).read().ok_or(ExchangeError::Malformed("graph"))?;
+    let array = |key, what| value.get(key).and_then(msgpack::Value::as_array).ok_or(ExchangeError::Malformed(what));
+    let mut graph = FlatGraph { nodes: Vec::new(), outputs: Vec::new() };
+    for node in array("nodes", "graph without nodes")? {
+        let children = node.get("children").and_then(msgpack::Value::as_array).ok_or(ExchangeError::Malformed("node children"))?;
+        graph.nodes.push(FlatNode {
+            kind: msgpack_string(node.get("kind"), "node kind")?,
+            children: children.iter().map(msgpack_reference).collect::<Result<_, _>>()?,
+            name: node.get("name").map_or(Ok(String::new()), |name| msgpack_string(Some(name), "input name"))?,
+            value: node.get("value").map_or(Ok(0.0), |value| msgpack_float(value, "input value"))?
+        });
+    }
+    for output in array("outputs", "graph without outputs")? {
+        let [name, output] = output.as_array().ok_or(ExchangeError::Malformed("output"))? else {
+            return Err(ExchangeError::Malformed("output"));
+        };
+        graph.outputs.push((msgpack_string(Some(name), "output name")?, msgpack_reference(output)?));
+    }
+    graph.build(registry)
+}
+
+pub fn encode_values_msgpack(values: &[(&str, Float)]) -> Vec<u8> {
+    let mut writer = msgpack::Writer::new();
+    writer.map(values.len());
+    for (name, value) in values {
+        writer.string(name);
+        writer.float(*value);
+    }
+    writer.into_bytes()
+}
+
+pub fn decode_values_msgpack(bytes: &[u8]) -> Result<Vec<(String, Float)>, ExchangeError> {
+    let value = msgpack::Reader::new(bytes).read().ok_or(ExchangeError::Malformed("values"))?;
+    let entries = value.as_map().ok_or(ExchangeError::Malformed("values"))?;
+    entries.iter()
+        .map(|(name, value)| Ok((msgpack_string(Some(name), "name")?, msgpack_float(value, "value")?)))
+        .collect()
+}
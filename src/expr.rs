@@ -0,0 +1,254 @@
+use std::{fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::registry::{NodeRegistry, RegistryError};
+use crate::limits::{Limits, LimitError};
+
+// Arithmetic expressions as text, e.g. `2 * x + sin(y) ^ 2`
+//
+// Operators stand for the registry's nodes of the same meaning (`+` for `add`, unary `-` for
+// `neg`, `^` for `pow`, ...), so an expression is a tree of calls once parsed, and any node
+// in the registry can be called by name. `^` binds tighter than unary minus and is right
+// associative, as in mathematics: `-2 ^ 2` is -4 and `2 ^ 3 ^ 2` is 512
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(Float),
+    Variable(String),
+    Call { function: String, arguments: Vec<Expr> }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprError {
+    // `position` is a byte offset into the text
+    Parse { position: usize, message: String },
+    UnknownVariable(String),
+    Registry(RegistryError),
+    Limit(LimitError)
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::Parse { position, message } => write!(f, "{} at position {}", message, position),
+            ExprError::UnknownVariable(name) => write!(f, "unknown variable `{}`", name),
+            ExprError::Registry(error) => write!(f, "{}", error),
+            ExprError::Limit(error) => write!(f, "expression with {}", error)
+        }
+    }
+}
+
+impl Error for ExprError {}
+
+impl From<RegistryError> for ExprError {
+    fn from(error: RegistryError) -> ExprError {
+        ExprError::Registry(error)
+    }
+}
+
+impl From<LimitError> for ExprError {
+    fn from(error: LimitError) -> ExprError {
+        ExprError::Limit(error)
+    }
+}
+
+impl Expr {
+    // The variables the expression uses, each once, in order of first use
+    pub fn variables(&self) -> Vec<&str> {
+        fn collect<'a>(expr: &'a Expr, variables: &mut Vec<&'a str>) {
+            match expr {
+                Expr::Number(_) => {}
+                Expr::Variable(name) => {
+                    if !variables.contains(&name.as_str()) {
+                        variables.push(name)
+                    }
+                }
+                Expr::Call { arguments, .. } => arguments.iter().for_each(|argument| collect(argument, variables))
+            }
+        }
+        let mut variables = Vec::new();
+        collect(self, &mut variables);
+        variables
+    }
+
+    // Builds the expression's graph, with `variable` giving the operand for each variable
+    pub fn build(
+        &self, registry: &NodeRegistry, variable: &mut impl FnMut(&str) -> Option<Operand>
+    ) -> Result<Operand, ExprError> {
+        match self {
+            Expr::Number(value) => Ok(Operand::Constant(*value)),
+            Expr::Variable(name) => variable(name).ok_or_else(|| ExprError::UnknownVariable(name.clone())),
+            Expr::Call { function, arguments } => {
+                let arguments = arguments.iter().map(|argument| argument.build(registry, variable)).collect::<Result<Vec<_>, _>>()?;
+                Ok(Operand::Node(registry.create(function, &arguments)?))
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Expr, ExprError> {
+    parse_with_limits(text, &Limits::new())
+}
+
+// Fails as soon as the expression has more calls (the nodes it builds) than the node limit,
+// or is deeper than the depth limit, numbers and variables counting as a level but not what
+// variables stand for, so that untrusted text can't build too big a graph or nest deep enough
+// to exhaust the stack
+pub fn parse_with_limits(text: &str, limits: &Limits) -> Result<Expr, ExprError> {
+    let mut parser = Parser { text, position: 0, limits: *limits, calls: 0, nesting: 0 };
+    let (expr, _) = parser.sum()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error("unexpected input"));
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    limits: Limits,
+    calls: usize,
+    // of the parser's own recursion, which can be deeper than the expression so far
+    nesting: usize
+}
+
+// The parser's results are expressions with their depths
+type Parsed = Result<(Expr, usize), ExprError>;
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ExprError {
+        ExprError::Parse { position: self.position, message: message.to_string() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += c.len_utf8();
+        }
+        found
+    }
+
+    // Characters from the current position while `accept` holds
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> &str {
+        let start = self.position;
+        let rest = &self.text[start..];
+        self.position += rest.find(|c| !accept(c)).unwrap_or(rest.len());
+        &self.text[start..self.position]
+    }
+
+    fn call(&mut self, function: &str, arguments: Vec<(Expr, usize)>) -> Parsed {
+        self.calls += 1;
+        self.limits.check_nodes(self.calls)?;
+        let depth = 1 + arguments.iter().map(|&(_, depth)| depth).max().unwrap_or(0);
+        self.limits.check_depth(depth)?;
+        let arguments = arguments.into_iter().map(|(argument, _)| argument).collect();
+        Ok((Expr::Call { function: function.to_string(), arguments }, depth))
+    }
+
+    // Runs a step of the parser that recurses, within the depth limit
+    fn nested(&mut self, step: impl FnOnce(&mut Self) -> Parsed) -> Parsed {
+        self.nesting += 1;
+        self.limits.check_depth(self.nesting)?;
+        let parsed = step(self);
+        self.nesting -= 1;
+        parsed
+    }
+
+    fn sum(&mut self) -> Parsed {
+        let mut expr = self.product()?;
+        loop {
+            let function = if self.eat('+') { "add" } else if self.eat('-') { "sub" } else { return Ok(expr) };
+            let operand = self.product()?;
+            expr = self.call(function, vec![expr, operand])?;
+        }
+    }
+
+    fn product(&mut self) -> Parsed {
+        let mut expr = self.unary()?;
+        loop {
+            let function = if self.eat('*') { "mul" } else if self.eat('/') { "div" } else { return Ok(expr) };
+            let operand = self.unary()?;
+            expr = self.call(function, vec![expr, operand])?;
+        }
+    }
+
+    fn unary(&mut self) -> Parsed {
+        if self.eat('-') {
+            let operand = self.nested(Self::unary)?;
+            self.call("neg", vec![operand])
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Parsed {
+        let base = self.atom()?;
+        if self.eat('^') {
+            let exponent = self.nested(Self::unary)?;
+            self.call("pow", vec![base, exponent])
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Parsed {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expr = self.nested(Self::sum)?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                self.take_while(|c| c.is_ascii_digit() || c == '.');
+                // an exponent, if the `e` is followed by one
+                let rest = &self.text[self.position..];
+                let sign = usize::from(rest.get(1..).is_some_and(|rest| rest.starts_with(['+', '-'])));
+                if rest.starts_with(['e', 'E']) && rest.get(1 + sign..).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())) {
+                    self.position += 1 + sign;
+                    self.take_while(|c| c.is_ascii_digit());
+                }
+                let literal = &self.text[start..self.position];
+                let value = literal.parse().map_err(|_| {
+                    ExprError::Parse { position: start, message: format!("invalid number `{}`", literal) }
+                })?;
+                Ok((Expr::Number(value), 1))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_').to_string();
+                if !self.eat('(') {
+                    return Ok((Expr::Variable(name), 1));
+                }
+                let mut arguments = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        arguments.push(self.nested(Self::sum)?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.error("expected `,` or `)`"));
+                        }
+                    }
+                }
+                self.call(&name, arguments)
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+}
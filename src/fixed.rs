@@ -0,0 +1,150 @@
+use std::{fmt, rc::Rc, cell::RefCell, ops::{Add, Sub, Mul, Div, Neg}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::numeric::Numeric;
+
+// Q16.16 fixed-point value for targets without an FPU
+//
+// All arithmetic (including `sqrt`, `sin` and `cos`) is done on integers and saturates
+// instead of overflowing; `from_f32`/`to_f32` are only needed at the boundaries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i32);
+
+const FRAC_PI_2_BITS: i64 = 102944;
+const PI_BITS: i64 = 205887;
+const TAU_BITS: i64 = 411775;
+
+impl Fixed {
+    pub const FRAC_BITS: u32 = 16;
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << Self::FRAC_BITS);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+    pub const MAX: Fixed = Fixed(i32::MAX);
+    pub const PI: Fixed = Fixed(PI_BITS as i32);
+
+    pub const fn from_bits(bits: i32) -> Fixed {
+        Fixed(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    pub const fn from_int(value: i16) -> Fixed {
+        Fixed((value as i32) << Self::FRAC_BITS)
+    }
+
+    pub fn from_f32(value: f32) -> Fixed {
+        // `as` saturates out-of-range values and maps NaN to zero
+        Fixed((value * (1 << Self::FRAC_BITS) as f32).round() as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1 << Self::FRAC_BITS) as f32
+    }
+
+    fn saturating(bits: i64) -> Fixed {
+        Fixed(bits.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.saturating_abs())
+    }
+
+    // Negative values have no real square root and yield zero
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        Fixed::saturating(((self.0 as u64) << Self::FRAC_BITS).isqrt() as i64)
+    }
+
+    // Taylor polynomial after reducing to [-pi/2, pi/2], accurate to about 2e-4
+    pub fn sin(self) -> Fixed {
+        Fixed(sin_bits(self.0 as i64))
+    }
+
+    pub fn cos(self) -> Fixed {
+        Fixed(sin_bits(self.0 as i64 + FRAC_PI_2_BITS))
+    }
+}
+
+fn sin_bits(x: i64) -> i32 {
+    let mut x = x.rem_euclid(TAU_BITS);
+    if x > PI_BITS {
+        x -= TAU_BITS;
+    }
+    if x > FRAC_PI_2_BITS {
+        x = PI_BITS - x;
+    } else if x < -FRAC_PI_2_BITS {
+        x = -PI_BITS - x;
+    }
+    let one = 1i64 << Fixed::FRAC_BITS;
+    let x2 = (x * x) >> Fixed::FRAC_BITS;
+    let mut t = one - x2 / 42;
+    t = one - ((x2 * t) >> Fixed::FRAC_BITS) / 20;
+    t = one - ((x2 * t) >> Fixed::FRAC_BITS) / 6;
+    ((x * t) >> Fixed::FRAC_BITS) as i32
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, rhs: Fixed) -> Fixed { Fixed(self.0.saturating_add(rhs.0)) }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, rhs: Fixed) -> Fixed { Fixed(self.0.saturating_sub(rhs.0)) }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, rhs: Fixed) -> Fixed {
+        Fixed::saturating((self.0 as i64 * rhs.0 as i64) >> Fixed::FRAC_BITS)
+    }
+}
+
+// Division by zero saturates towards the sign of the dividend
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, rhs: Fixed) -> Fixed {
+        if rhs.0 == 0 {
+            return match self.0.signum() {
+                1 => Fixed::MAX,
+                -1 => Fixed::MIN,
+                _ => Fixed::ZERO
+            };
+        }
+        Fixed::saturating(((self.0 as i64) << Fixed::FRAC_BITS) / rhs.0 as i64)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed { Fixed(self.0.saturating_neg()) }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
+// Like `Float`, `Fixed` constants can be used directly as nodes
+impl ComputeNodeRef<Fixed> for Fixed {
+    fn compute(&self) -> Fixed { *self }
+    fn subscribe_to_invalidate(&self, _subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {}
+}
+
+impl Numeric for Fixed {
+    const ZERO: Fixed = Fixed::ZERO;
+    const ONE: Fixed = Fixed::ONE;
+
+    fn from_f32(value: f32) -> Fixed { Fixed::from_f32(value) }
+    fn to_f32(self) -> f32 { Fixed::to_f32(self) }
+    fn abs(self) -> Fixed { Fixed::abs(self) }
+    fn sqrt(self) -> Fixed { Fixed::sqrt(self) }
+    fn sin(self) -> Fixed { Fixed::sin(self) }
+    fn cos(self) -> Fixed { Fixed::cos(self) }
+}
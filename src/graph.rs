@@ -0,0 +1,1156 @@
+use std::{any::TypeId, cell::RefCell, rc::{Rc, Weak}, collections::{BTreeMap, BTreeSet, HashMap, HashSet}, fmt::{self, Write}, error::Error, marker::PhantomData};
+
+use crate::compgraph::*;
+
+// Structural utilities working on graphs through node introspection
+
+// Diff of two graphs, e.g. two versions of a formula
+//
+// The graphs are compared as trees from their outputs down, child by child, so a change is
+// located by its path: the child indices leading to it from the output. Nodes are compared
+// by name and constants by value; inputs match any input at the same position, and a node
+// shared by both graphs is unchanged without looking further. Nodes reached again through
+// sharing inside a graph are compared once, with their changes located by the first path
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added { path: Vec<usize>, node: String },
+    Removed { path: Vec<usize>, node: String },
+    // a different node, or a node replaced by a constant or vice versa
+    ChangedNode { path: Vec<usize>, old: String, new: String },
+    ChangedConstant { path: Vec<usize>, old: Float, new: Float }
+}
+
+pub struct GraphDiff {
+    pub changes: Vec<Change>,
+    dot: String
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // The new graph as a tree in Graphviz DOT, with added nodes in green, changed ones in
+    // orange (labeled old -> new) and removed ones dashed red
+    pub fn to_dot(&self) -> String {
+        format!("digraph diff {{\n    node [shape=box];\n{}}}\n", self.dot)
+    }
+}
+
+fn label(child: &Child) -> String {
+    match child {
+        Child::Node(node) => node.borrow().name().to_string(),
+        Child::Constant(value) => value.to_string(),
+        Child::Opaque => "external".to_string()
+    }
+}
+
+fn children_of(child: &Child) -> Vec<Child> {
+    match child {
+        Child::Node(node) => node.borrow().children(),
+        _ => Vec::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Status {
+    Unchanged,
+    Added,
+    Removed,
+    Changed
+}
+
+// What a node of the tree was drawn for: a comparison of nodes of the old and new graph, or
+// a node on one side only. Nodes reached again through sharing are drawn once
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Origin {
+    Compared(Option<*const ()>, Option<*const ()>),
+    Removed(*const ()),
+    Added(*const ())
+}
+
+fn address(child: &Child) -> Option<*const ()> {
+    match child {
+        Child::Node(node) => Some(node_address(node)),
+        _ => None
+    }
+}
+
+struct Differ {
+    changes: Vec<Change>,
+    dot: String,
+    next_id: usize,
+    drawn: HashMap<Origin, usize>
+}
+
+impl Differ {
+    fn edge(&mut self, parent: Option<usize>, id: usize, status: Status) {
+        if let Some(parent) = parent {
+            let edge_style = if let Status::Removed = status { " [style=dashed, color=red]" } else { "" };
+            writeln!(self.dot, "    n{} -> n{}{};", parent, id, edge_style).unwrap();
+        }
+    }
+
+    fn emit(&mut self, parent: Option<usize>, text: &str, status: Status) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let style = match status {
+            Status::Unchanged => "",
+            Status::Added => ", color=green, fontcolor=green",
+            Status::Removed => ", color=red, fontcolor=red, style=dashed",
+            Status::Changed => ", color=orange, fontcolor=orange"
+        };
+        let text = text.replace('"', "\\\"");
+        writeln!(self.dot, "    n{} [label=\"{}\"{}];", id, text, style).unwrap();
+        self.edge(parent, id, status);
+        id
+    }
+
+    // Links to the node drawn for `origin` if there is one, otherwise draws it and returns its
+    // id, for drawing its children. Constants have no origin, and are drawn every time
+    fn visit(&mut self, parent: Option<usize>, origin: Option<Origin>, text: &str, status: Status) -> Option<usize> {
+        if let Some(&id) = origin.and_then(|origin| self.drawn.get(&origin)) {
+            self.edge(parent, id, status);
+            return None;
+        }
+        let id = self.emit(parent, text, status);
+        if let Some(origin) = origin {
+            self.drawn.insert(origin, id);
+        }
+        Some(id)
+    }
+
+    // A subtree present on one side only
+    fn subtree(&mut self, parent: Option<usize>, child: &Child, status: Status) {
+        let origin = match status {
+            Status::Removed => address(child).map(Origin::Removed),
+            _ => address(child).map(Origin::Added)
+        };
+        if let Some(id) = self.visit(parent, origin, &label(child), status) {
+            for grandchild in children_of(child) {
+                self.subtree(Some(id), &grandchild, status);
+            }
+        }
+    }
+
+    fn compare(&mut self, parent: Option<usize>, path: &mut Vec<usize>, old: &Child, new: &Child) {
+        let origin = Some(Origin::Compared(address(old), address(new))).filter(|_| address(old).or(address(new)).is_some());
+        if let Some(&id) = origin.and_then(|origin| self.drawn.get(&origin)) {
+            // compared already, through another path, with the changes found there
+            self.edge(parent, id, Status::Unchanged);
+            return;
+        }
+        let status = match (old, new) {
+            (Child::Node(a), Child::Node(b)) if node_address(a) == node_address(b) => {
+                self.visit(parent, origin, &label(new), Status::Unchanged);
+                return;
+            }
+            (Child::Constant(a), Child::Constant(b)) => {
+                if a.to_bits() != b.to_bits() {
+                    self.changes.push(Change::ChangedConstant { path: path.clone(), old: *a, new: *b });
+                    self.emit(parent, &format!("{} -> {}", a, b), Status::Changed);
+                } else {
+                    self.emit(parent, &label(new), Status::Unchanged);
+                }
+                return;
+            }
+            (Child::Opaque, Child::Opaque) => Status::Unchanged,
+            (Child::Node(_), Child::Node(_)) if label(old) == label(new) => Status::Unchanged,
+            _ => {
+                self.changes.push(Change::ChangedNode { path: path.clone(), old: label(old), new: label(new) });
+                Status::Changed
+            }
+        };
+        let text = match status {
+            Status::Changed => format!("{} -> {}", label(old), label(new)),
+            _ => label(new)
+        };
+        let Some(id) = self.visit(parent, origin, &text, status) else {
+            return;
+        };
+
+        let (old_children, new_children) = (children_of(old), children_of(new));
+        // a changed node with a different number of children has nothing to line up
+        let aligned = !matches!(status, Status::Changed) || old_children.len() == new_children.len();
+        for index in 0..old_children.len().max(new_children.len()) {
+            path.push(index);
+            match (old_children.get(index), new_children.get(index)) {
+                (Some(a), Some(b)) if aligned => self.compare(Some(id), path, a, b),
+                (a, b) => {
+                    if let Some(a) = a {
+                        self.changes.push(Change::Removed { path: path.clone(), node: label(a) });
+                        self.subtree(Some(id), a, Status::Removed);
+                    }
+                    if let Some(b) = b {
+                        self.changes.push(Change::Added { path: path.clone(), node: label(b) });
+                        self.subtree(Some(id), b, Status::Added);
+                    }
+                }
+            }
+            path.pop();
+        }
+    }
+}
+
+pub fn diff(old: &impl ComputeNodeRef, new: &impl ComputeNodeRef) -> GraphDiff {
+    let mut differ = Differ { changes: Vec::new(), dot: String::new(), next_id: 0, drawn: HashMap::new() };
+    differ.compare(None, &mut Vec::new(), &old.as_child(), &new.as_child());
+    GraphDiff { changes: differ.changes, dot: differ.dot }
+}
+
+// Deep copy of a graph with new, independent inputs
+//
+// Every node `output` depends on is rebuilt on top of the copied children, and every input
+// replaced by a new one with the same value, found through the returned mapping. Nodes that
+// can't be rebuilt (stateful nodes, nodes with external children, ...) are shared with the
+// original graph, together with everything below them
+
+pub struct InputMapping {
+    inputs: HashMap<*const (), Input>,
+    // keeps the original inputs alive, so that their addresses can't be reused
+    _originals: Vec<DynamicComputeNodeRef>
+}
+
+impl InputMapping {
+    // The copy of an input of the original graph
+    pub fn get(&self, original: &impl ComputeNodeRef) -> Option<&Input> {
+        original.as_dynamic().and_then(|original| self.inputs.get(&node_address(&original)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CopyMode {
+    Deep,
+    // nodes none of whose children changed are kept rather than copied
+    Share,
+    // like `Share`, and nodes whose children are all constants are evaluated to constants
+    Fold
+}
+
+// Copies the graph above the nodes `cut` is true for, which are replaced by the operands
+// `replace` makes for them. Nodes that can't be rebuilt are kept, with the names of those
+// whose children were copied or replaced, which still compute from the originals
+fn copy_graph(
+    output: &DynamicComputeNodeRef,
+    cut: impl Fn(&DynamicComputeNodeRef) -> bool, mut replace: impl FnMut(&DynamicComputeNodeRef) -> Operand,
+    mode: CopyMode
+) -> (Operand, Vec<&'static str>) {
+    // copies by address of the original
+    let mut copies: HashMap<*const (), Operand> = HashMap::new();
+    let mut stale = Vec::new();
+    for node in topological_order_until(output, &cut) {
+        let copy = if cut(&node) {
+            replace(&node)
+        } else {
+            let node_ref = node.borrow();
+            let original_children = node_ref.children();
+            let children: Option<Vec<Operand>> = original_children.iter().map(|child| match child {
+                Child::Node(child) => copies.get(&node_address(child)).cloned(),
+                Child::Constant(value) => Some(Operand::Constant(*value)),
+                Child::Opaque => None
+            }).collect();
+            let unchanged = |children: &Vec<Operand>| children.iter().zip(&original_children).all(|pair| match pair {
+                (Operand::Node(copy), Child::Node(child)) => node_address(copy) == node_address(child),
+                (Operand::Constant(_), Child::Constant(_)) => true,
+                _ => false
+            });
+            let share = mode != CopyMode::Deep;
+            let constant = |children: &Vec<Operand>| children.iter().all(|child| matches!(child, Operand::Constant(_)));
+            let rebuilt = match &children {
+                Some(children) if mode == CopyMode::Fold && constant(children) => {
+                    node_ref.rebuild(children).map(|copy| Operand::Constant(copy.compute()))
+                }
+                Some(children) if !(share && unchanged(children)) => node_ref.rebuild(children).map(Operand::Node),
+                _ => None
+            };
+            rebuilt.unwrap_or_else(|| {
+                let replaced_below = original_children.iter().any(|child| match child {
+                    Child::Node(child) => !matches!(&copies[&node_address(child)], Operand::Node(copy) if node_address(copy) == node_address(child)),
+                    _ => false
+                });
+                if replaced_below {
+                    stale.push(node_ref.name());
+                }
+                Operand::Node(node.clone())
+            })
+        };
+        copies.insert(node_address(&node), copy);
+    }
+    (copies.remove(&node_address(output)).unwrap(), stale)
+}
+
+fn expect_node(operand: Operand) -> DynamicComputeNodeRef {
+    match operand {
+        Operand::Node(node) => node,
+        Operand::Constant(_) => unreachable!()
+    }
+}
+
+pub fn clone_graph(output: &DynamicComputeNodeRef) -> (DynamicComputeNodeRef, InputMapping) {
+    let mut inputs = HashMap::new();
+    let mut originals = Vec::new();
+    let (copy, _) = copy_graph(output, is_input, |original| {
+        let input = InputNodeImpl::new_ref(original.compute());
+        inputs.insert(node_address(original), input.clone());
+        originals.push(original.clone());
+        Operand::Node(input)
+    }, CopyMode::Deep);
+    (expect_node(copy), InputMapping { inputs, _originals: originals })
+}
+
+// Subgraph extraction
+//
+// Copies the part of the graph between `boundary` and `output` (like `clone_graph`), with
+// a new input in place of each boundary node, in the order of `boundary`. The new inputs
+// start at the values of the nodes they replace. Inputs of the original graph that aren't
+// on the boundary are shared, as the subgraph doesn't cut them off
+
+pub fn extract(output: &DynamicComputeNodeRef, boundary: &[DynamicComputeNodeRef]) -> (DynamicComputeNodeRef, Vec<Input>) {
+    let inputs: Vec<Input> = boundary.iter().map(|node| InputNodeImpl::new_ref(node.compute())).collect();
+    let cuts: HashMap<*const (), Input> = boundary.iter().map(node_address).zip(inputs.iter().cloned()).collect();
+    let (copy, _) = copy_graph(
+        output, |node| cuts.contains_key(&node_address(node)), |node| Operand::Node(cuts[&node_address(node)].clone()), CopyMode::Deep
+    );
+    (expect_node(copy), inputs)
+}
+
+// Partial evaluation
+//
+// A copy of the graph with the given inputs fixed to constants, folded: every node that only
+// depends on constants is evaluated once and replaced by its value, so that what's left only
+// computes what depends on the remaining inputs. The parts that depend on neither are shared
+// with the original graph. A node depending on a fixed input that can't be rebuilt would
+// keep reading the input, so it fails the specialization instead; nodes hiding their
+// children from graph algorithms (see `children`) can't be told apart from ones depending on
+// nothing, and are shared
+
+// The first node that couldn't be rebuilt on the fixed inputs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotRebuildable {
+    pub node: &'static str
+}
+
+impl fmt::Display for NotRebuildable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node `{}` depends on a fixed input but can't be rebuilt", self.node)
+    }
+}
+
+impl Error for NotRebuildable {}
+
+pub fn specialize(output: &DynamicComputeNodeRef, values: &[(DynamicComputeNodeRef, Float)]) -> Result<Operand, NotRebuildable> {
+    let values: HashMap<*const (), Float> = values.iter().map(|(input, value)| (node_address(input), *value)).collect();
+    let (copy, stale) = copy_graph(
+        output, |node| values.contains_key(&node_address(node)), |node| Operand::Constant(values[&node_address(node)]),
+        CopyMode::Fold
+    );
+    match stale.first() {
+        Some(&node) => Err(NotRebuildable { node }),
+        None => Ok(copy)
+    }
+}
+
+// Reusable graph templates
+//
+// A subgraph built once over formal inputs, then instantiated on different arguments, each
+// instance a copy of the template (see `clone_graph`) with the arguments in place of the
+// formal inputs. Other inputs the template uses are shared by all instances. With
+// `with_shared_constants`, the parts of the template that don't depend on the formal inputs
+// are shared too instead of being copied into every instance
+
+pub struct GraphTemplate {
+    inputs: Vec<Input>,
+    output: DynamicComputeNodeRef,
+    share_constants: bool
+}
+
+impl GraphTemplate {
+    // `build` makes the template's output from `arity` formal inputs
+    pub fn new(arity: usize, build: impl FnOnce(&[Input]) -> DynamicComputeNodeRef) -> GraphTemplate {
+        let inputs: Vec<Input> = (0..arity).map(|_| InputNodeImpl::new_ref(0.0)).collect();
+        let output = build(&inputs);
+        GraphTemplate { inputs, output, share_constants: false }
+    }
+
+    pub fn with_shared_constants(mut self, share_constants: bool) -> GraphTemplate {
+        self.share_constants = share_constants;
+        self
+    }
+
+    pub fn arity(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // A new instance with `arguments` for the formal inputs, in order
+    pub fn instantiate(&self, arguments: &[Operand]) -> Operand {
+        assert_eq!(arguments.len(), self.arity(), "template instantiated with the wrong number of arguments");
+        let formals: HashMap<*const (), Operand> = self.inputs.iter()
+            .map(|input| node_address(&(input.clone() as DynamicComputeNodeRef)))
+            .zip(arguments.iter().cloned())
+            .collect();
+        copy_graph(
+            &self.output, |node| formals.contains_key(&node_address(node)), |node| formals[&node_address(node)].clone(),
+            if self.share_constants { CopyMode::Share } else { CopyMode::Deep }
+        ).0
+    }
+}
+
+// Editing a live graph
+//
+// The nodes depending on `old` hold it directly, so rather than being taken out, `old` is
+// made to compute `new` from then on: its subscribers are moved over to `new` and
+// invalidated, and graph algorithms see `new` in its place. The nodes `old` itself depended
+// on are left alone
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplaceError {
+    // `new` depends on `old`, so the replacement would be a cycle
+    Cycle,
+    // a node that can't stand in for another, such as an input
+    Unsupported { node: &'static str }
+}
+
+impl fmt::Display for ReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplaceError::Cycle => write!(f, "the new node depends on the node it replaces"),
+            ReplaceError::Unsupported { node } => write!(f, "node `{}` can't be replaced", node)
+        }
+    }
+}
+
+impl Error for ReplaceError {}
+
+pub fn replace(old: &DynamicComputeNodeRef, new: &DynamicComputeNodeRef) -> Result<(), ReplaceError> {
+    if topological_order(new).iter().any(|node| node_address(node) == node_address(old)) {
+        return Err(ReplaceError::Cycle);
+    }
+    let mut old_ref = old.borrow_mut();
+    let name = old_ref.name();
+    if old_ref.redirect(new.clone()) { Ok(()) } else { Err(ReplaceError::Unsupported { node: name }) }
+}
+
+// Structural hashing
+//
+// A hash of the graph's shape: node names, which children they have (by position in the
+// graph) and the bits of constants. Inputs are only hashed as inputs, so graphs built the
+// same way hash the same, also across process runs. What nodes hold besides their children
+// (the contents of a lookup table, the function of a block map) isn't seen, nor is anything
+// behind opaque children
+pub fn structural_hash(output: &DynamicComputeNodeRef) -> u64 {
+    // FNV-1a, which unlike the standard library's hasher is fixed
+    struct Fnv(u64);
+    impl Fnv {
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+    let mut hash = Fnv(0xcbf29ce484222325);
+    let order = topological_order(output);
+    let positions: HashMap<_, _> = order.iter().enumerate().map(|(position, node)| (node_address(node), position)).collect();
+    for node in &order {
+        let node = node.borrow();
+        hash.write(node.name().as_bytes());
+        for child in node.children() {
+            match child {
+                Child::Node(child) => {
+                    hash.write(b"n");
+                    hash.write(&(positions[&node_address(&child)] as u64).to_le_bytes());
+                }
+                Child::Constant(value) => {
+                    hash.write(b"c");
+                    hash.write(&value.to_bits().to_le_bytes());
+                }
+                Child::Opaque => hash.write(b"o")
+            }
+        }
+        hash.write(b";");
+    }
+    hash.0
+}
+
+// Node ownership
+//
+// Nodes live as long as a handle to them or a parent does; what only listens to a node
+// (a subscribed cache, an `Output`'s refresh, a parent that was rebuilt elsewhere) holds it
+// weakly. A `Graph` keeps the nodes handed to it alive regardless of the handles the program
+// holds, until they are released, the scope they were kept in ends, or the graph is dropped
+#[derive(Default)]
+pub struct Graph {
+    nodes: Vec<DynamicComputeNodeRef>,
+    kept: HashSet<*const ()>,
+    // the number of nodes kept before each of the scopes entered
+    scopes: Vec<usize>,
+    // for graphs made by `build`
+    labels: HashMap<*const (), String>,
+    statistics: BuildStatistics
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+    // Keeps `node` alive with the graph, handing it back; constants aren't kept
+    pub fn keep<N: ComputeNodeRef>(&mut self, node: N) -> N {
+        if let Some(dynamic) = node.as_dynamic() {
+            if self.kept.insert(node_address(&dynamic)) {
+                self.nodes.push(dynamic);
+            }
+        }
+        node
+    }
+    // Keeps every node `output` depends on, including itself
+    pub fn keep_all(&mut self, output: &DynamicComputeNodeRef) {
+        for node in topological_order(output) {
+            self.keep(node);
+        }
+    }
+    pub fn contains(&self, node: &DynamicComputeNodeRef) -> bool {
+        self.kept.contains(&node_address(node))
+    }
+    // `false` if the graph didn't keep `node`. The node lives on if something else holds it
+    pub fn release(&mut self, node: &DynamicComputeNodeRef) -> bool {
+        if !self.kept.remove(&node_address(node)) {
+            return false;
+        }
+        self.labels.remove(&node_address(node));
+        let position = self.nodes.iter().position(|kept| node_address(kept) == node_address(node)).unwrap();
+        self.nodes.remove(position);
+        for start in &mut self.scopes {
+            if *start > position {
+                *start -= 1;
+            }
+        }
+        true
+    }
+    // Runs `f` with the graph, releasing the nodes it kept once it returns; nodes that were
+    // kept before aren't released, even if `f` keeps them again
+    pub fn scope<R>(&mut self, f: impl FnOnce(&mut Graph) -> R) -> R {
+        self.scopes.push(self.nodes.len());
+        let result = f(self);
+        let start = self.scopes.pop().unwrap();
+        for node in self.nodes.drain(start..) {
+            self.kept.remove(&node_address(&node));
+            self.labels.remove(&node_address(&node));
+        }
+        result
+    }
+    // The kept node with the id, if it's kept
+    pub fn node(&self, id: NodeId) -> Option<DynamicComputeNodeRef> {
+        node(id).filter(|node| self.contains(node))
+    }
+    // The kept nodes, in the order they were kept
+    pub fn nodes(&self) -> &[DynamicComputeNodeRef] {
+        &self.nodes
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    // The name of a node made by `build`, after the prefixes of the scopes it was made in:
+    // `layer/mul`
+    pub fn label(&self, node: &DynamicComputeNodeRef) -> Option<&str> {
+        self.labels.get(&node_address(node)).map(String::as_str)
+    }
+    // What `build` made, all zeros for other graphs
+    pub fn statistics(&self) -> &BuildStatistics {
+        &self.statistics
+    }
+    // Releases every node, also those kept in scopes still running
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.kept.clear();
+        self.labels.clear();
+        for start in &mut self.scopes {
+            *start = 0;
+        }
+    }
+}
+
+// Input state
+//
+// The values of a graph's kept inputs at one moment, by node id, so that scenarios can be
+// saved, compared and switched between. Restoring sets all the inputs before any of the
+// work their changes queue runs, so that listeners only see the restored state as a whole
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputState {
+    values: BTreeMap<NodeId, Float>
+}
+
+impl InputState {
+    pub fn get(&self, input: &DynamicComputeNodeRef) -> Option<Float> {
+        self.values.get(&node_id(input)).copied()
+    }
+    // By node id
+    pub fn values(&self) -> impl Iterator<Item = (NodeId, Float)> + '_ {
+        self.values.iter().map(|(&id, &value)| (id, value))
+    }
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    // The inputs whose values differ from those in `other`, with those in `self` and in
+    // `other`, `None` where an input is only in one of them
+    pub fn changes(&self, other: &InputState) -> Vec<(NodeId, Option<Float>, Option<Float>)> {
+        let ids: BTreeSet<NodeId> = self.values.keys().chain(other.values.keys()).copied().collect();
+        ids.into_iter()
+            .map(|id| (id, self.values.get(&id).copied(), other.values.get(&id).copied()))
+            .filter(|(_, a, b)| a != b)
+            .collect()
+    }
+}
+
+impl Graph {
+    pub fn snapshot_inputs(&self) -> InputState {
+        let values = self.nodes.iter()
+            .filter(|node| is_input(node))
+            .map(|input| (node_id(input), input.compute()))
+            .collect();
+        InputState { values }
+    }
+    // Sets the inputs still kept to their values in `state`, returning how many changed;
+    // inputs already at their value aren't set, so what depends on them stays cached
+    pub fn restore_inputs(&self, state: &InputState) -> usize {
+        let mut changed = 0;
+        for (&id, &value) in &state.values {
+            let Some(input) = self.node(id) else { continue };
+            if input.compute().to_bits() != value.to_bits() {
+                input.borrow_mut().assign(value);
+                changed += 1;
+            }
+        }
+        run_deferred();
+        changed
+    }
+}
+
+// Scoped construction
+//
+// `Graph::build` runs a function with a context tracking the nodes made while it runs by the
+// functions of `define_nodes!` and by `create_input`. The graph it returns keeps them, so that
+// they are torn down together, labelled with the prefixes of the scopes they were made in, and
+// counted. A node of the same kind with the same children as one made before in the build is
+// shared instead of made again (common subexpression elimination), unless turned off. Builds
+// can nest, the innermost one tracking the nodes; a context always works on its own build,
+// so an outer one used while an inner build runs keeps nodes and sets prefixes for the outer
+// build, while the nodes made meanwhile go to the inner one
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildStatistics {
+    // made, so not counting those shared
+    pub nodes: usize,
+    pub shared: usize,
+    pub by_name: BTreeMap<&'static str, usize>
+}
+
+// A node's kind and children, the same for nodes computing the same thing
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) struct ShareKey {
+    kind: TypeId,
+    children: Vec<(Option<*const ()>, u64)>
+}
+
+impl ShareKey {
+    // `None` for leaves and nodes with opaque children, which aren't known to be the same
+    pub(crate) fn new(kind: TypeId, children: Vec<Child>) -> Option<ShareKey> {
+        if children.is_empty() {
+            return None;
+        }
+        let children = children.into_iter().map(|child| match child {
+            Child::Node(node) => Some((Some(node_address(&node)), 0)),
+            Child::Constant(value) => Some((None, value.to_bits() as u64)),
+            Child::Opaque => None
+        }).collect::<Option<_>>()?;
+        Some(ShareKey { kind, children })
+    }
+}
+
+struct BuildState {
+    graph: Graph,
+    prefixes: Vec<String>,
+    share: bool,
+    shared: HashMap<ShareKey, DynamicComputeNodeRef>
+}
+
+thread_local! {
+    static BUILDS: RefCell<Vec<BuildState>> = const { RefCell::new(Vec::new()) };
+}
+
+// The node to use in place of a new one with `key`, if the current build has one
+pub(crate) fn shared_node(key: impl FnOnce() -> Option<ShareKey>) -> Option<DynamicComputeNodeRef> {
+    BUILDS.with(|builds| {
+        let mut builds = builds.borrow_mut();
+        let build = builds.last_mut().filter(|build| build.share)?;
+        let node = build.shared.get(&key()?)?.clone();
+        build.graph.statistics.shared += 1;
+        Some(node)
+    })
+}
+
+// Adds a node just made to the current build, if any
+pub(crate) fn track_node(node: &DynamicComputeNodeRef, key: impl FnOnce() -> Option<ShareKey>) {
+    BUILDS.with(|builds| {
+        let mut builds = builds.borrow_mut();
+        let Some(build) = builds.last_mut() else {
+            return;
+        };
+        if build.graph.contains(node) {
+            return;
+        }
+        let name = node.borrow().name();
+        build.graph.keep(node.clone());
+        build.graph.labels.insert(node_address(node), build.prefixes.iter().map(|prefix| format!("{}/", prefix)).collect::<String>() + name);
+        build.graph.statistics.nodes += 1;
+        *build.graph.statistics.by_name.entry(name).or_default() += 1;
+        if let Some(key) = key().filter(|_| build.share) {
+            build.shared.insert(key, node.clone());
+        }
+    })
+}
+
+// The build of the context, taken off the stack when it ends, also by a panic
+struct Building;
+
+impl Building {
+    fn finish(self) -> Graph {
+        std::mem::forget(self);
+        BUILDS.with(|builds| builds.borrow_mut().pop().unwrap().graph)
+    }
+}
+
+impl Drop for Building {
+    fn drop(&mut self) {
+        BUILDS.with(|builds| builds.borrow_mut().pop());
+    }
+}
+
+// The builds set aside by `outside_builds`, put back when it ends, also by a panic
+struct Suspended(Vec<BuildState>);
+
+impl Drop for Suspended {
+    fn drop(&mut self) {
+        BUILDS.with(|builds| *builds.borrow_mut() = std::mem::take(&mut self.0));
+    }
+}
+
+// Runs `f` as if no build were in progress, for temporary nodes that the builds shouldn't
+// keep or share
+pub(crate) fn outside_builds<R>(f: impl FnOnce() -> R) -> R {
+    let _suspended = Suspended(BUILDS.with(RefCell::take));
+    f()
+}
+
+// Handed to the function of a build; tied to its thread, like the build
+pub struct BuildContext {
+    // of its build in the stack of builds
+    depth: usize,
+    _thread: PhantomData<*const ()>
+}
+
+impl BuildContext {
+    fn with<R>(&self, f: impl FnOnce(&mut BuildState) -> R) -> R {
+        BUILDS.with(|builds| f(&mut builds.borrow_mut()[self.depth]))
+    }
+    // Runs `f`, labelling the nodes it makes with `prefix` inside the enclosing prefixes
+    pub fn prefix<R>(&mut self, prefix: &str, f: impl FnOnce(&mut BuildContext) -> R) -> R {
+        self.with(|build| build.prefixes.push(prefix.to_string()));
+        let result = f(self);
+        self.with(|build| build.prefixes.pop());
+        result
+    }
+    // Whether nodes made from now on are shared with equal ones, on by default
+    pub fn share_common(&mut self, share: bool) {
+        self.with(|build| build.share = share)
+    }
+    // Keeps a node made elsewhere with the graph being built
+    pub fn keep<N: ComputeNodeRef>(&mut self, node: N) -> N {
+        self.with(|build| build.graph.keep(node))
+    }
+    // So far
+    pub fn statistics(&self) -> BuildStatistics {
+        self.with(|build| build.graph.statistics.clone())
+    }
+}
+
+impl Graph {
+    pub fn build<R>(f: impl FnOnce(&mut BuildContext) -> R) -> (Graph, R) {
+        let depth = BUILDS.with(|builds| {
+            let mut builds = builds.borrow_mut();
+            builds.push(BuildState { graph: Graph::new(), prefixes: Vec::new(), share: true, shared: HashMap::new() });
+            builds.len() - 1
+        });
+        let building = Building;
+        let result = f(&mut BuildContext { depth, _thread: PhantomData });
+        (building.finish(), result)
+    }
+}
+
+// Traversal
+//
+// The nodes of a graph in topological order, children before the nodes using them and each
+// node once, as views giving what analyses need without going through the node traits. A
+// `Visitor` is called for each node by its kind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Input,
+    // leaves other than inputs: stateful nodes, streams, ...
+    Source,
+    Operation
+}
+
+#[derive(Clone)]
+pub struct NodeView {
+    node: DynamicComputeNodeRef,
+    position: usize,
+    children: Vec<Child>
+}
+
+impl NodeView {
+    pub fn node(&self) -> &DynamicComputeNodeRef {
+        &self.node
+    }
+    pub fn id(&self) -> NodeId {
+        node_id(&self.node)
+    }
+    // In the traversal, counting from 0
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    pub fn name(&self) -> &'static str {
+        self.node.borrow().name()
+    }
+    pub fn doc(&self) -> &'static [&'static str] {
+        self.node.borrow().doc()
+    }
+    pub fn kind(&self) -> NodeKind {
+        if is_input(&self.node) {
+            NodeKind::Input
+        } else if self.children.is_empty() {
+            NodeKind::Source
+        } else {
+            NodeKind::Operation
+        }
+    }
+    pub fn children(&self) -> &[Child] {
+        &self.children
+    }
+}
+
+pub struct Traversal {
+    order: std::iter::Enumerate<std::vec::IntoIter<DynamicComputeNodeRef>>
+}
+
+impl Iterator for Traversal {
+    type Item = NodeView;
+
+    fn next(&mut self) -> Option<NodeView> {
+        let (position, node) = self.order.next()?;
+        let children = node.borrow().children();
+        Some(NodeView { node, position, children })
+    }
+}
+
+// Everything `output` depends on, ending with `output`
+pub fn traverse(output: &DynamicComputeNodeRef) -> Traversal {
+    Traversal { order: topological_order(output).into_iter().enumerate() }
+}
+
+pub trait Visitor {
+    fn visit_input(&mut self, _node: &NodeView) {}
+    fn visit_source(&mut self, _node: &NodeView) {}
+    fn visit_operation(&mut self, _node: &NodeView) {}
+}
+
+pub fn visit(output: &DynamicComputeNodeRef, visitor: &mut impl Visitor) {
+    for node in traverse(output) {
+        match node.kind() {
+            NodeKind::Input => visitor.visit_input(&node),
+            NodeKind::Source => visitor.visit_source(&node),
+            NodeKind::Operation => visitor.visit_operation(&node)
+        }
+    }
+}
+
+// The graph of the outputs in Graphviz DOT, each node once however many nodes use it, with
+// the outputs' names pointing at their nodes
+pub fn to_dot(outputs: &[(&str, DynamicComputeNodeRef)]) -> String {
+    let mut dot = String::from("digraph compgraph {\n    node [shape=box];\n");
+    let mut ids = HashMap::new();
+    let mut constants = 0;
+    for (index, (name, output)) in outputs.iter().enumerate() {
+        for node in traverse(output) {
+            if ids.contains_key(&node_address(node.node())) {
+                continue;
+            }
+            let id = ids.len();
+            ids.insert(node_address(node.node()), id);
+            writeln!(dot, "    n{} [label=\"{}\"];", id, node.name()).unwrap();
+            for child in node.children() {
+                match child {
+                    Child::Node(child) => writeln!(dot, "    n{} -> n{};", id, ids[&node_address(child)]).unwrap(),
+                    _ => {
+                        writeln!(dot, "    c{} [label=\"{}\", shape=plaintext];", constants, label(child)).unwrap();
+                        writeln!(dot, "    n{} -> c{};", id, constants).unwrap();
+                        constants += 1;
+                    }
+                }
+            }
+        }
+        let name = name.replace('"', "\\\"");
+        writeln!(dot, "    o{} [label=\"{}\", shape=ellipse];", index, name).unwrap();
+        writeln!(dot, "    o{} -> n{};", index, ids[&node_address(output)]).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// Node identity
+//
+// Ids name nodes for systems outside of the program (UIs, serializers, network protocols)
+// across calls. A node gets its id the first time it's asked for, and keeps it for as long as
+// it lives; ids are never reused, unlike the addresses of dropped nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+type WeakNode = Weak<RefCell<dyn ComputeNodeMut>>;
+
+#[derive(Default)]
+struct NodeIds {
+    by_address: HashMap<*const (), (NodeId, WeakNode)>,
+    by_id: HashMap<NodeId, WeakNode>,
+    next: u64,
+    // the number of entries after the last time those of dropped nodes were removed
+    pruned: usize
+}
+
+impl NodeIds {
+    fn prune(&mut self) {
+        self.by_address.retain(|_, (_, weak)| weak.strong_count() > 0);
+        self.by_id.retain(|_, weak| weak.strong_count() > 0);
+        self.pruned = self.by_address.len();
+    }
+}
+
+thread_local! {
+    static NODE_IDS: RefCell<NodeIds> = RefCell::new(NodeIds::default());
+}
+
+pub fn node_id(node: &DynamicComputeNodeRef) -> NodeId {
+    NODE_IDS.with(|ids| {
+        let mut ids = ids.borrow_mut();
+        let address = node_address(node);
+        // an entry of a dropped node at the same address doesn't count
+        if let Some((id, weak)) = ids.by_address.get(&address) {
+            if weak.strong_count() > 0 {
+                return *id;
+            }
+        }
+        if ids.by_address.len() >= 2 * ids.pruned.max(64) {
+            ids.prune();
+        }
+        let id = NodeId(ids.next);
+        ids.next += 1;
+        ids.by_address.insert(address, (id, Rc::downgrade(node)));
+        ids.by_id.insert(id, Rc::downgrade(node));
+        id
+    })
+}
+
+// The node with the id, if it's still alive
+pub fn node(id: NodeId) -> Option<DynamicComputeNodeRef> {
+    NODE_IDS.with(|ids| ids.borrow().by_id.get(&id).and_then(Weak::upgrade))
+}
+
+// Edges
+//
+// A node's children are what it computes from, as it reports them. Its parents are the nodes
+// computing from it that are alive, found through its subscribers: those of `define_nodes!`
+// and of the node libraries, which know their handles, but not listeners (outputs, bindings)
+// or nodes that don't (see `CachingNodeWrapper::new_ref`). Each is listed once, in the order
+// they subscribed
+pub fn children(node: &DynamicComputeNodeRef) -> Vec<Child> {
+    node.borrow().children()
+}
+
+pub fn parents(node: &DynamicComputeNodeRef) -> Vec<DynamicComputeNodeRef> {
+    let subscribers = node.borrow().subscribers();
+    let mut seen = HashSet::new();
+    subscribers.iter().filter_map(|subscriber| subscriber.borrow().as_node())
+        .filter(|parent| seen.insert(node_address(parent)))
+        .collect()
+}
+
+// Everything a change of `node` reaches: the nodes depending on it, directly or not, nearest
+// first, and the number of listeners (outputs, bindings, ...) notified along the way. Found
+// through the parents, so with the same blind spots
+pub struct Dependents {
+    pub nodes: Vec<DynamicComputeNodeRef>,
+    pub listeners: usize
+}
+
+pub fn dependents_of(node: &impl ComputeNodeRef) -> Dependents {
+    let mut dependents = Dependents { nodes: Vec::new(), listeners: 0 };
+    let Some(node) = node.as_dynamic() else {
+        return dependents;
+    };
+    let mut seen = HashSet::from([node_address(&node)]);
+    let mut queue = std::collections::VecDeque::from([node]);
+    while let Some(node) = queue.pop_front() {
+        let subscribers = node.borrow().subscribers();
+        for subscriber in subscribers {
+            if !seen.insert(Rc::as_ptr(&subscriber) as *const ()) {
+                continue;
+            }
+            match subscriber.borrow().as_node() {
+                Some(parent) => {
+                    dependents.nodes.push(parent.clone());
+                    queue.push_back(parent);
+                }
+                None => dependents.listeners += 1
+            }
+        }
+    }
+    dependents
+}
+
+// Health checks
+//
+// What heavy editing of a graph can leave behind, as found by `Graph::validate` among the
+// nodes the graph keeps and those its outputs depend on:
+// - subscribers that were dropped, which stay with the nodes they subscribed to until those
+//   change again (or are compacted)
+// - orphaned nodes, kept but not used by any output
+// - unreachable outputs, which no input reaches, so they can't change anymore
+// - inconsistent caches: nodes holding a value while one of their children doesn't, which a
+//   missed invalidation leaves
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HealthReport {
+    // with the number of dropped subscribers of each
+    pub dangling_subscribers: Vec<(NodeId, usize)>,
+    pub orphaned: Vec<NodeId>,
+    pub unreachable_outputs: Vec<NodeId>,
+    pub inconsistent_caches: Vec<NodeId>
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.dangling_subscribers.is_empty() && self.orphaned.is_empty()
+            && self.unreachable_outputs.is_empty() && self.inconsistent_caches.is_empty()
+    }
+}
+
+impl Graph {
+    pub fn validate(&self, outputs: &[DynamicComputeNodeRef]) -> HealthReport {
+        let mut report = HealthReport::default();
+        let mut used = HashSet::new();
+        let mut nodes = Vec::new();
+        for output in outputs {
+            let order = topological_order(output);
+            if !order.iter().any(is_input) {
+                report.unreachable_outputs.push(node_id(output));
+            }
+            for node in order {
+                if used.insert(node_address(&node)) {
+                    nodes.push(node);
+                }
+            }
+        }
+        for node in &self.nodes {
+            if !used.contains(&node_address(node)) {
+                report.orphaned.push(node_id(node));
+                nodes.push(node.clone());
+            }
+        }
+        for node in &nodes {
+            let node_ref = node.borrow();
+            let dangling = node_ref.dangling_subscribers();
+            if dangling > 0 {
+                report.dangling_subscribers.push((node_id(node), dangling));
+            }
+            let stale_child = node_ref.children().iter().any(|child| match child {
+                Child::Node(child) => child.borrow().is_cached() == Some(false),
+                _ => false
+            });
+            if node_ref.is_cached() == Some(true) && stale_child {
+                report.inconsistent_caches.push(node_id(node));
+            }
+        }
+        report
+    }
+}
+
+// Compaction
+//
+// Replacing and releasing nodes leaves things behind until the graph changes again: nodes
+// kept but no longer used, dropped subscribers, room in the vectors holding them. `compact`
+// releases the kept nodes none of `outputs` depends on, drops the dangling subscribers of
+// the rest and shrinks what holds them, including the ids of dropped nodes. A replaced node
+// is still used while its parents hold it, computing its replacement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Compaction {
+    pub released: usize,
+    pub subscribers: usize
+}
+
+impl Graph {
+    pub fn compact(&mut self, outputs: &[DynamicComputeNodeRef]) -> Compaction {
+        let mut compaction = Compaction::default();
+        let mut used = HashSet::new();
+        let mut nodes = Vec::new();
+        for output in outputs {
+            for node in topological_order(output) {
+                if used.insert(node_address(&node)) {
+                    nodes.push(node);
+                }
+            }
+        }
+        let orphaned: Vec<_> = self.nodes.iter()
+            .filter(|node| !used.contains(&node_address(node)))
+            .cloned().collect();
+        for node in &orphaned {
+            self.release(node);
+        }
+        compaction.released = orphaned.len();
+        // releasing may have dropped subscribers of the nodes still in use
+        drop(orphaned);
+        for node in &nodes {
+            compaction.subscribers += node.borrow_mut().compact();
+        }
+        self.nodes.shrink_to_fit();
+        self.kept.shrink_to_fit();
+        self.labels.shrink_to_fit();
+        NODE_IDS.with(|ids| {
+            let mut ids = ids.borrow_mut();
+            ids.prune();
+            ids.by_address.shrink_to_fit();
+            ids.by_id.shrink_to_fit();
+        });
+        compaction
+    }
+}
@@ -0,0 +1,123 @@
+use std::{rc::Rc, cell::RefCell, num::{Wrapping, Saturating}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Integer-valued graphs, for counters and indices that lose precision as `Float`
+//
+// The overflow behavior is chosen by the value type of the graph:
+// - `Wrap` wraps around like `i64::wrapping_*`
+// - `Saturate` clamps to `i64::MIN`/`i64::MAX`
+// - `Checked` becomes `None` on overflow (or division by zero), and `None` propagates
+//   through all downstream integer nodes
+//
+// Division and remainder by zero give 0 for `Wrap` and `Saturate`, rather than panicking
+// like the std wrappers, as a divisor can be any input value
+pub type Wrap = Wrapping<i64>;
+pub type Saturate = Saturating<i64>;
+pub type Checked = Option<i64>;
+
+pub trait Integer: Copy + PartialEq + ComputeNodeRef<Self> + 'static {
+    fn from_i64(value: i64) -> Self;
+    // Rounds to the nearest integer; out-of-range values overflow according to the policy
+    fn from_float(value: Float) -> Self;
+    // `None` only for a `Checked` value that has overflowed
+    fn to_i64(self) -> Option<i64>;
+
+    fn add(self, rhs: Self) -> Self;
+    fn sub(self, rhs: Self) -> Self;
+    fn mul(self, rhs: Self) -> Self;
+    fn div(self, rhs: Self) -> Self;
+    fn rem(self, rhs: Self) -> Self;
+    fn neg(self) -> Self;
+}
+
+impl Integer for Wrap {
+    fn from_i64(value: i64) -> Wrap { Wrapping(value) }
+    fn from_float(value: Float) -> Wrap { Wrapping(value.round() as i128 as i64) }
+    fn to_i64(self) -> Option<i64> { Some(self.0) }
+
+    fn add(self, rhs: Wrap) -> Wrap { self + rhs }
+    fn sub(self, rhs: Wrap) -> Wrap { self - rhs }
+    fn mul(self, rhs: Wrap) -> Wrap { self * rhs }
+    fn div(self, rhs: Wrap) -> Wrap { if rhs.0 == 0 { Wrapping(0) } else { self / rhs } }
+    fn rem(self, rhs: Wrap) -> Wrap { if rhs.0 == 0 { Wrapping(0) } else { self % rhs } }
+    fn neg(self) -> Wrap { -self }
+}
+
+impl Integer for Saturate {
+    fn from_i64(value: i64) -> Saturate { Saturating(value) }
+    fn from_float(value: Float) -> Saturate { Saturating(value.round() as i64) }
+    fn to_i64(self) -> Option<i64> { Some(self.0) }
+
+    fn add(self, rhs: Saturate) -> Saturate { self + rhs }
+    fn sub(self, rhs: Saturate) -> Saturate { self - rhs }
+    fn mul(self, rhs: Saturate) -> Saturate { self * rhs }
+    fn div(self, rhs: Saturate) -> Saturate { if rhs.0 == 0 { Saturating(0) } else { self / rhs } }
+    // `i64::MIN % -1` overflows on the way to 0
+    fn rem(self, rhs: Saturate) -> Saturate { Saturating(self.0.checked_rem(rhs.0).unwrap_or(0)) }
+    fn neg(self) -> Saturate { -self }
+}
+
+impl Integer for Checked {
+    fn from_i64(value: i64) -> Checked { Some(value) }
+    fn from_float(value: Float) -> Checked {
+        let rounded = value.round();
+        // `i64::MAX as Float` rounds up to 2^63, which is already out of range
+        (rounded.is_finite() && rounded >= i64::MIN as Float && rounded < i64::MAX as Float)
+            .then_some(rounded as i64)
+    }
+    fn to_i64(self) -> Option<i64> { self }
+
+    fn add(self, rhs: Checked) -> Checked { self?.checked_add(rhs?) }
+    fn sub(self, rhs: Checked) -> Checked { self?.checked_sub(rhs?) }
+    fn mul(self, rhs: Checked) -> Checked { self?.checked_mul(rhs?) }
+    fn div(self, rhs: Checked) -> Checked { self?.checked_div(rhs?) }
+    fn rem(self, rhs: Checked) -> Checked { self?.checked_rem(rhs?) }
+    fn neg(self) -> Checked { self?.checked_neg() }
+}
+
+// Integer constants can be used directly as nodes, like `Float` ones
+macro_rules! impl_constant_node {
+    ($($t:ty),*) => {$(
+        impl ComputeNodeRef<$t> for $t {
+            fn compute(&self) -> $t { *self }
+            fn subscribe_to_invalidate(&self, _subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {}
+        }
+    )*};
+}
+
+impl_constant_node!(Wrap, Saturate, Checked);
+
+pub fn add<I: Integer>(a: impl ComputeNodeRef<I> + 'static, b: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    binary_node(a, b, I::add)
+}
+
+pub fn sub<I: Integer>(a: impl ComputeNodeRef<I> + 'static, b: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    binary_node(a, b, I::sub)
+}
+
+pub fn mul<I: Integer>(a: impl ComputeNodeRef<I> + 'static, b: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    binary_node(a, b, I::mul)
+}
+
+pub fn div<I: Integer>(a: impl ComputeNodeRef<I> + 'static, b: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    binary_node(a, b, I::div)
+}
+
+pub fn rem<I: Integer>(a: impl ComputeNodeRef<I> + 'static, b: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    binary_node(a, b, I::rem)
+}
+
+pub fn neg<I: Integer>(x: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef<I> {
+    unary_node(x, I::neg)
+}
+
+// Bridges to `Float` graphs; an overflowed `Checked` value converts to NaN
+pub fn to_float<I: Integer>(x: impl ComputeNodeRef<I> + 'static) -> DynamicComputeNodeRef {
+    unary_node(x, |x: I| x.to_i64().map_or(Float::NAN, |x| x as Float))
+}
+
+pub fn from_float<I: Integer>(x: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef<I> {
+    unary_node(x, I::from_float)
+}
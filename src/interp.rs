@@ -0,0 +1,265 @@
+use std::rc::Rc;
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Interpolation and easing, for animation curves and transfer functions inside the graph
+
+define_nodes! {
+    // a at t = 0, b at t = 1, extrapolating beyond
+    pub lerp(a, b, t) { a + (b - a) * t } grad { [1.0 - t, t, b - a] }
+    // 0 below edge0, 1 above edge1, with a smooth cubic step in between
+    pub smoothstep(edge0, edge1, x) {
+        let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    } grad {
+        let width = edge1 - edge0;
+        let t = ((x - edge0) / width).clamp(0.0, 1.0);
+        // zero where clamped, as the slope of the cubic is zero at both ends
+        let slope = 6.0 * t * (1.0 - t);
+        [slope * (x - edge1) / (width * width), -slope * (x - edge0) / (width * width), slope / width]
+    }
+}
+
+// Natural cubic spline through control points, whose coordinates can be nodes themselves
+//
+// The curve passes through every point, with continuous first and second derivatives, and is
+// held at the first and last point's value outside of them. Points are taken in order of their
+// x, which must be distinct; two points give a straight line
+struct Spline<X, PX, PY> {
+    x: X,
+    points: Vec<(PX, PY)>
+}
+
+impl<X: ComputeNodeRef, PX: ComputeNodeRef, PY: ComputeNodeRef> ComputeMut for Spline<X, PX, PY> {
+    fn compute(&mut self) -> Float {
+        let mut points: Vec<(f64, f64)> = self.points.iter().map(|(x, y)| (x.compute() as f64, y.compute() as f64)).collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        evaluate_spline(&points, self.x.compute() as f64) as Float
+    }
+    fn name(&self) -> &'static str { "spline" }
+    fn children(&self) -> Vec<Child> {
+        let points = self.points.iter().flat_map(|(x, y)| [x.as_child(), y.as_child()]);
+        std::iter::once(self.x.as_child()).chain(points).collect()
+    }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        let points = children[1..].chunks(2).map(|point| (point[0].clone(), point[1].clone())).collect();
+        Some(spline(children[0].clone(), points))
+    }
+}
+
+fn evaluate_spline(points: &[(f64, f64)], x: f64) -> f64 {
+    let n = points.len();
+    let (first, last) = (points[0], points[n - 1]);
+    // NaN falls in no segment
+    if x.is_nan() {
+        return x;
+    }
+    if x <= first.0 {
+        return first.1;
+    }
+    if x >= last.0 {
+        return last.1;
+    }
+    // second derivatives at the points, zero at both ends, by the Thomas algorithm for the
+    // tridiagonal system of the inner points
+    let h: Vec<f64> = points.windows(2).map(|pair| pair[1].0 - pair[0].0).collect();
+    let mut second = vec![0.0; n];
+    let (mut diagonal, mut rhs) = (vec![0.0; n], vec![0.0; n]);
+    for i in 1..n - 1 {
+        let slope_change = (points[i + 1].1 - points[i].1) / h[i] - (points[i].1 - points[i - 1].1) / h[i - 1];
+        diagonal[i] = 2.0 * (h[i - 1] + h[i]);
+        rhs[i] = 6.0 * slope_change;
+        if i > 1 {
+            let factor = h[i - 1] / diagonal[i - 1];
+            diagonal[i] -= factor * h[i - 1];
+            rhs[i] -= factor * rhs[i - 1];
+        }
+    }
+    for i in (1..n - 1).rev() {
+        second[i] = (rhs[i] - h[i] * second[i + 1]) / diagonal[i];
+    }
+
+    let i = points.partition_point(|point| point.0 <= x) - 1;
+    let (a, b) = (points[i + 1].0 - x, x - points[i].0);
+    (second[i] * a.powi(3) + second[i + 1] * b.powi(3)) / (6.0 * h[i])
+        + (points[i].1 / h[i] - second[i] * h[i] / 6.0) * a
+        + (points[i + 1].1 / h[i] - second[i + 1] * h[i] / 6.0) * b
+}
+
+pub fn spline<X, PX, PY>(x: X, points: Vec<(PX, PY)>) -> DynamicComputeNodeRef
+where
+    X: ComputeNodeRef + 'static, PX: ComputeNodeRef + 'static, PY: ComputeNodeRef + 'static
+{
+    assert!(points.len() >= 2, "spline: at least two control points needed");
+    let result = CachingNodeWrapper::new_ref(Spline { x, points });
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.x.subscribe_to_invalidate(&subscriber);
+        for (x, y) in &inner.points {
+            x.subscribe_to_invalidate(&subscriber);
+            y.subscribe_to_invalidate(&subscriber);
+        }
+    }
+    result
+}
+
+// Lookup tables: values at evenly spaced points of [start, end], linearly interpolated in
+// between, for approximating expensive functions cheaply. The table is shared by all nodes
+// made from it
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfRange {
+    // the value at the nearer end
+    Clamp,
+    // continues the slope of the first or last segment
+    Extrapolate,
+    // repeats the table with period end - start, e.g. for wavetables
+    Wrap,
+    Nan
+}
+
+#[derive(Clone, Debug)]
+pub struct LookupTable {
+    start: Float,
+    end: Float,
+    values: Rc<[Float]>,
+    out_of_range: OutOfRange
+}
+
+impl LookupTable {
+    pub fn new(start: Float, end: Float, values: Vec<Float>) -> LookupTable {
+        assert!(values.len() >= 2, "lookup table: at least two values needed");
+        assert!(start < end, "lookup table: empty range");
+        LookupTable { start, end, values: values.into(), out_of_range: OutOfRange::Clamp }
+    }
+
+    // `size` samples of `f` over [start, end]
+    pub fn sample(f: impl Fn(Float) -> Float, start: Float, end: Float, size: usize) -> LookupTable {
+        assert!(size >= 2, "lookup table: at least two values needed");
+        let step = (end - start) / (size - 1) as Float;
+        LookupTable::new(start, end, (0..size).map(|i| f(start + step * i as Float)).collect())
+    }
+
+    pub fn with_out_of_range(mut self, out_of_range: OutOfRange) -> LookupTable {
+        self.out_of_range = out_of_range;
+        self
+    }
+
+    // The segment `x` falls in and its position in it, in [0, 1] inside the range
+    fn locate(&self, x: Float) -> Option<(usize, Float)> {
+        let width = self.end - self.start;
+        let x = match self.out_of_range {
+            OutOfRange::Clamp => x.clamp(self.start, self.end),
+            OutOfRange::Wrap => self.start + (x - self.start).rem_euclid(width),
+            OutOfRange::Nan if !(self.start..=self.end).contains(&x) => return None,
+            OutOfRange::Nan | OutOfRange::Extrapolate => x
+        };
+        let position = (x - self.start) / width * (self.values.len() - 1) as Float;
+        if position.is_nan() {
+            return None;
+        }
+        let segment = (position.floor().max(0.0) as usize).min(self.values.len() - 2);
+        Some((segment, position - segment as Float))
+    }
+
+    pub fn get(&self, x: Float) -> Float {
+        self.locate(x).map_or(Float::NAN, |(segment, t)| {
+            let (a, b) = (self.values[segment], self.values[segment + 1]);
+            a + (b - a) * t
+        })
+    }
+
+    // d get / dx, zero where clamped
+    fn slope(&self, x: Float) -> Float {
+        if self.out_of_range == OutOfRange::Clamp && !(self.start..=self.end).contains(&x) {
+            return 0.0;
+        }
+        self.locate(x).map_or(Float::NAN, |(segment, _)| {
+            (self.values[segment + 1] - self.values[segment]) * (self.values.len() - 1) as Float / (self.end - self.start)
+        })
+    }
+}
+
+struct Lookup<X> {
+    x: X,
+    table: LookupTable
+}
+
+impl<X: ComputeNodeRef> ComputeMut for Lookup<X> {
+    fn compute(&mut self) -> Float {
+        self.table.get(self.x.compute())
+    }
+    fn name(&self) -> &'static str { "lookup" }
+    fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![self.table.slope(self.x.compute())]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(lookup(children[0].clone(), self.table.clone()))
+    }
+}
+
+pub fn lookup(x: impl ComputeNodeRef + 'static, table: LookupTable) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(Lookup { x, table });
+    let subscriber = result.clone() as _;
+    result.borrow().inner.x.subscribe_to_invalidate(&subscriber);
+    result
+}
+
+// Piecewise functions: `segments[i]` between `breakpoints[i - 1]` and `breakpoints[i]`, so
+// `segments[0]` below the first breakpoint and the last segment from the last one on, with
+// each breakpoint belonging to the segment above it. Breakpoints must be ascending, and can
+// be nodes as well as the segments, which are usually built from `x` themselves (tariffs,
+// tax brackets, transfer curves). Only the segment `x` is in gets computed
+struct Piecewise<X, B, S> {
+    x: X,
+    breakpoints: Vec<B>,
+    segments: Vec<S>
+}
+
+impl<X: ComputeNodeRef, B: ComputeNodeRef, S: ComputeNodeRef> Piecewise<X, B, S> {
+    fn segment(&self) -> usize {
+        let x = self.x.compute();
+        self.breakpoints.iter().take_while(|breakpoint| breakpoint.compute() <= x).count()
+    }
+}
+
+impl<X: ComputeNodeRef, B: ComputeNodeRef, S: ComputeNodeRef> ComputeMut for Piecewise<X, B, S> {
+    fn compute(&mut self) -> Float {
+        self.segments[self.segment()].compute()
+    }
+    fn name(&self) -> &'static str { "piecewise" }
+    fn children(&self) -> Vec<Child> {
+        std::iter::once(self.x.as_child())
+            .chain(self.breakpoints.iter().map(ComputeNodeRef::as_child))
+            .chain(self.segments.iter().map(ComputeNodeRef::as_child))
+            .collect()
+    }
+    // x only selects the segment, the segments depend on it themselves
+    fn partials(&mut self) -> Option<Vec<Float>> {
+        let selected = self.segment();
+        let mut partials = vec![0.0; 1 + self.breakpoints.len()];
+        partials.extend((0..self.segments.len()).map(|segment| if segment == selected { 1.0 } else { 0.0 }));
+        Some(partials)
+    }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        let (breakpoints, segments) = children[1..].split_at(self.breakpoints.len());
+        Some(piecewise(children[0].clone(), breakpoints.to_vec(), segments.to_vec()))
+    }
+}
+
+pub fn piecewise<X, B, S>(x: X, breakpoints: Vec<B>, segments: Vec<S>) -> DynamicComputeNodeRef
+where
+    X: ComputeNodeRef + 'static, B: ComputeNodeRef + 'static, S: ComputeNodeRef + 'static
+{
+    assert_eq!(segments.len(), breakpoints.len() + 1, "piecewise: one more segment than breakpoints needed");
+    let result = CachingNodeWrapper::new_ref(Piecewise { x, breakpoints, segments });
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.x.subscribe_to_invalidate(&subscriber);
+        inner.breakpoints.iter().for_each(|breakpoint| breakpoint.subscribe_to_invalidate(&subscriber));
+        inner.segments.iter().for_each(|segment| segment.subscribe_to_invalidate(&subscriber));
+    }
+    result
+}
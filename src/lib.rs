@@ -2,5 +2,57 @@
 mod compgraph;
 pub use compgraph::*;
 
+pub mod numeric;
+pub mod fixed;
+pub mod units;
+pub mod integer;
+pub mod logic;
+pub mod random;
+pub mod noise;
+pub mod time;
+pub mod stateful;
+pub mod ode;
+pub mod autodiff;
+pub mod uncertainty;
+pub mod optim;
+pub mod loss;
+pub mod nn;
+pub mod onnx;
+pub mod exchange;
+pub mod ops;
+pub mod registry;
+pub mod graph;
+pub mod solver;
+pub mod analysis;
+pub mod expr;
+pub mod sheet;
+pub mod reload;
+pub mod binding;
+pub mod stream;
+pub mod audio;
+pub mod dsp;
+pub mod interp;
+pub mod scheduler;
+pub mod atomic;
+pub mod buffered;
+pub mod actor;
+pub mod record;
+pub mod config;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod cache;
+pub mod output;
+pub mod limits;
+pub mod assertions;
+pub mod probe;
+pub mod debugger;
+pub mod profile;
+pub mod static_graph;
+pub mod strict;
+pub mod prelude;
+
+mod proto;
+mod msgpack;
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,109 @@
+use std::{collections::HashMap, fmt, error::Error};
+
+use crate::compgraph::*;
+
+// Resource limits, for graphs built from formulas that can't be trusted
+//
+// A graph can be limited in its number of nodes, its depth (the most nodes on a path from the
+// output down to a leaf, 1 for a lone input) and the nodes recomputed by an evaluation. Parsing
+// an expression with limits fails as soon as they are exceeded, before it builds anything (see
+// `expr::parse_with_limits`). `evaluate` computes a graph a node at a time, children first, so
+// that a deep graph doesn't exhaust the stack, and fails once it has had to recompute more
+// than `steps` nodes; what it computed stays cached. Work done inside a node (an ODE stepper's steps, a solver's
+// iterations) counts as one step
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    nodes: Option<usize>,
+    depth: Option<usize>,
+    steps: Option<usize>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    Nodes { limit: usize },
+    Depth { limit: usize },
+    Steps { limit: usize }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::Nodes { limit } => write!(f, "more than {} nodes", limit),
+            LimitError::Depth { limit } => write!(f, "deeper than {} nodes", limit),
+            LimitError::Steps { limit } => write!(f, "more than {} evaluation steps", limit)
+        }
+    }
+}
+
+impl Error for LimitError {}
+
+impl Limits {
+    // No limits until some are set
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+    pub fn nodes(mut self, limit: usize) -> Limits {
+        self.nodes = Some(limit);
+        self
+    }
+    pub fn depth(mut self, limit: usize) -> Limits {
+        self.depth = Some(limit);
+        self
+    }
+    pub fn steps(mut self, limit: usize) -> Limits {
+        self.steps = Some(limit);
+        self
+    }
+
+    pub(crate) fn check_nodes(&self, nodes: usize) -> Result<(), LimitError> {
+        match self.nodes {
+            Some(limit) if nodes > limit => Err(LimitError::Nodes { limit }),
+            _ => Ok(())
+        }
+    }
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), LimitError> {
+        match self.depth {
+            Some(limit) if depth > limit => Err(LimitError::Depth { limit }),
+            _ => Ok(())
+        }
+    }
+
+    // Whether the graph of `output` is within the node and depth limits
+    pub fn check(&self, output: &DynamicComputeNodeRef) -> Result<(), LimitError> {
+        self.check_order(&topological_order(output)).map(|_| ())
+    }
+
+    // Checks the graph, children before parents, returning the depths of its nodes
+    fn check_order(&self, order: &[DynamicComputeNodeRef]) -> Result<HashMap<*const (), usize>, LimitError> {
+        self.check_nodes(order.len())?;
+        let mut depths = HashMap::new();
+        for node in order {
+            let depth = 1 + node.borrow().children().iter().filter_map(|child| match child {
+                Child::Node(child) => depths.get(&node_address(child)).copied(),
+                _ => None
+            }).max().unwrap_or(0);
+            self.check_depth(depth)?;
+            depths.insert(node_address(node), depth);
+        }
+        Ok(depths)
+    }
+
+    // The value of `output`, within all the limits
+    pub fn evaluate(&self, output: &DynamicComputeNodeRef) -> Result<Float, LimitError> {
+        let order = topological_order(output);
+        self.check_order(&order)?;
+        let mut steps = 0;
+        for node in &order {
+            let version = node.version();
+            node.compute();
+            if node.version() != version {
+                steps += 1;
+                if let Some(limit) = self.steps.filter(|&limit| steps > limit) {
+                    return Err(LimitError::Steps { limit });
+                }
+            }
+        }
+        Ok(output.compute())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Boolean subgraphs, so gating logic doesn't have to be encoded as 0.0/1.0
+//
+// Comparisons bridge from numeric graphs into boolean ones and `select` bridges back
+
+impl ComputeNodeRef<bool> for bool {
+    fn compute(&self) -> bool { *self }
+    fn subscribe_to_invalidate(&self, _subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {}
+}
+
+// `and`/`or` only compute their second child when the first one doesn't decide the result
+struct ShortCircuitNode<A, B> {
+    a: A,
+    b: B,
+    decisive: bool
+}
+
+impl<A: ComputeNodeRef<bool>, B: ComputeNodeRef<bool>> ComputeMut<bool> for ShortCircuitNode<A, B> {
+    fn compute(&mut self) -> bool {
+        let a = self.a.compute();
+        if a == self.decisive { a } else { self.b.compute() }
+    }
+}
+
+fn short_circuit(
+    a: impl ComputeNodeRef<bool> + 'static, b: impl ComputeNodeRef<bool> + 'static, decisive: bool
+) -> DynamicComputeNodeRef<bool> {
+    let result = Rc::new(RefCell::new(CachingNodeWrapper::new(ShortCircuitNode { a, b, decisive })));
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.a.subscribe_to_invalidate(&subscriber);
+        inner.b.subscribe_to_invalidate(&subscriber);
+    }
+    result
+}
+
+pub fn and(a: impl ComputeNodeRef<bool> + 'static, b: impl ComputeNodeRef<bool> + 'static) -> DynamicComputeNodeRef<bool> {
+    short_circuit(a, b, false)
+}
+
+pub fn or(a: impl ComputeNodeRef<bool> + 'static, b: impl ComputeNodeRef<bool> + 'static) -> DynamicComputeNodeRef<bool> {
+    short_circuit(a, b, true)
+}
+
+pub fn xor(a: impl ComputeNodeRef<bool> + 'static, b: impl ComputeNodeRef<bool> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: bool, b: bool| a ^ b)
+}
+
+pub fn not(x: impl ComputeNodeRef<bool> + 'static) -> DynamicComputeNodeRef<bool> {
+    unary_node(x, |x: bool| !x)
+}
+
+pub fn lt<V: PartialOrd + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a < b)
+}
+
+pub fn le<V: PartialOrd + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a <= b)
+}
+
+pub fn gt<V: PartialOrd + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a > b)
+}
+
+pub fn ge<V: PartialOrd + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a >= b)
+}
+
+pub fn eq<V: PartialEq + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a == b)
+}
+
+pub fn ne<V: PartialEq + 'static>(a: impl ComputeNodeRef<V> + 'static, b: impl ComputeNodeRef<V> + 'static) -> DynamicComputeNodeRef<bool> {
+    binary_node(a, b, |a: V, b: V| a != b)
+}
+
+// Only the chosen branch is computed, so an unused branch costs nothing
+struct SelectNode<C, A, B> {
+    condition: C,
+    if_true: A,
+    if_false: B
+}
+
+impl<V, C: ComputeNodeRef<bool>, A: ComputeNodeRef<V>, B: ComputeNodeRef<V>> ComputeMut<V> for SelectNode<C, A, B> {
+    fn compute(&mut self) -> V {
+        if self.condition.compute() { self.if_true.compute() } else { self.if_false.compute() }
+    }
+}
+
+pub fn select<V: Clone + 'static>(
+    condition: impl ComputeNodeRef<bool> + 'static, if_true: impl ComputeNodeRef<V> + 'static, if_false: impl ComputeNodeRef<V> + 'static
+) -> DynamicComputeNodeRef<V> {
+    let result = Rc::new(RefCell::new(CachingNodeWrapper::new(SelectNode { condition, if_true, if_false })));
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.condition.subscribe_to_invalidate(&subscriber);
+        inner.if_true.subscribe_to_invalidate(&subscriber);
+        inner.if_false.subscribe_to_invalidate(&subscriber);
+    }
+    result
+}
+
+// 1.0 for `true` and 0.0 for `false`
+pub fn to_float(x: impl ComputeNodeRef<bool> + 'static) -> DynamicComputeNodeRef {
+    unary_node(x, |x: bool| if x { 1.0 } else { 0.0 })
+}
@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use crate::compgraph::*;
+use crate::{ops, strict};
+use crate::compgraph::internals::*;
+
+// Loss nodes over a batch of predictions and targets, averaged over the batch
+//
+// Each loss has a derivative rule, so the losses can be minimized with `optim`. The targets
+// are usually constants, but can be nodes too
+
+struct BatchLoss<P, T> {
+    name: &'static str,
+    predictions: Vec<P>,
+    targets: Vec<T>,
+    // the loss for a single prediction and target, and its partials with respect to both
+    element: Rc<dyn Fn(Float, Float) -> (Float, Float, Float)>
+}
+
+impl<P: ComputeNodeRef, T: ComputeNodeRef> BatchLoss<P, T> {
+    fn elements(&self) -> impl Iterator<Item = (Float, Float, Float)> + '_ {
+        self.predictions.iter().zip(&self.targets).map(|(p, t)| (self.element)(p.compute(), t.compute()))
+    }
+}
+
+impl<P: ComputeNodeRef, T: ComputeNodeRef> ComputeMut for BatchLoss<P, T> {
+    fn compute(&mut self) -> Float {
+        ops::compensated_sum(self.elements().map(|(loss, _, _)| loss)) / self.predictions.len() as Float
+    }
+    fn name(&self) -> &'static str { self.name }
+    fn children(&self) -> Vec<Child> {
+        self.predictions.iter().map(ComputeNodeRef::as_child).chain(self.targets.iter().map(ComputeNodeRef::as_child)).collect()
+    }
+    fn partials(&mut self) -> Option<Vec<Float>> {
+        let n = self.predictions.len() as Float;
+        let (by_prediction, by_target): (Vec<Float>, Vec<Float>) = self.elements().map(|(_, dp, dt)| (dp / n, dt / n)).unzip();
+        Some(by_prediction.into_iter().chain(by_target).collect())
+    }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        let (predictions, targets) = children.split_at(self.predictions.len());
+        Some(batch_loss(self.name, predictions.to_vec(), targets.to_vec(), self.element.clone()))
+    }
+}
+
+fn batch_loss(
+    name: &'static str,
+    predictions: Vec<impl ComputeNodeRef + 'static>, targets: Vec<impl ComputeNodeRef + 'static>,
+    element: Rc<dyn Fn(Float, Float) -> (Float, Float, Float)>
+) -> DynamicComputeNodeRef {
+    assert_eq!(predictions.len(), targets.len(), "{}: as many targets as predictions", name);
+    assert!(!predictions.is_empty(), "{}: empty batch", name);
+    let result = CachingNodeWrapper::new_ref(BatchLoss { name, predictions, targets, element });
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.predictions.iter().for_each(|p| p.subscribe_to_invalidate(&subscriber));
+        inner.targets.iter().for_each(|t| t.subscribe_to_invalidate(&subscriber));
+    }
+    result
+}
+
+// Mean squared error
+pub fn mse(predictions: Vec<impl ComputeNodeRef + 'static>, targets: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+    batch_loss("mse", predictions, targets, Rc::new(|p, t| {
+        let error = p - t;
+        (error * error, 2.0 * error, -2.0 * error)
+    }))
+}
+
+// Mean absolute error, with a zero derivative where prediction and target are equal
+pub fn mae(predictions: Vec<impl ComputeNodeRef + 'static>, targets: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+    batch_loss("mae", predictions, targets, Rc::new(|p, t| {
+        let error = p - t;
+        let sign = if error == 0.0 { 0.0 } else { error.signum() };
+        (error.abs(), sign, -sign)
+    }))
+}
+
+// Squared error for errors up to `delta`, linear beyond, so outliers pull less
+pub fn huber(predictions: Vec<impl ComputeNodeRef + 'static>, targets: Vec<impl ComputeNodeRef + 'static>, delta: Float) -> DynamicComputeNodeRef {
+    batch_loss("huber", predictions, targets, Rc::new(move |p, t| {
+        let error = p - t;
+        let slope = error.clamp(-delta, delta);
+        let loss = if error.abs() <= delta { error * error / 2.0 } else { delta * (error.abs() - delta / 2.0) };
+        (loss, slope, -slope)
+    }))
+}
+
+// Binary cross-entropy of targets in [0, 1] against the probabilities sigmoid(logit).
+// Taking logits rather than probabilities keeps it finite when the sigmoid saturates
+pub fn cross_entropy(logits: Vec<impl ComputeNodeRef + 'static>, targets: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+    batch_loss("cross_entropy", logits, targets, Rc::new(|z, t| {
+        let loss = z.max(0.0) - z * t + strict::ln_1p(strict::exp(-z.abs()));
+        let probability = 1.0 / (1.0 + strict::exp(-z));
+        (loss, probability - t, -z)
+    }))
+}
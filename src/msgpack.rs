@@ -0,0 +1,204 @@
+// Minimal MessagePack encoding, for the formats built on it (see `exchange`)
+
+#[derive(Default)]
+pub(crate) struct Writer {
+    bytes: Vec<u8>
+}
+
+impl Writer {
+    pub(crate) fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    // The smallest of the forms for lengths up to 15 (if any), 255 (if any), 65535 and 2^32 - 1
+    fn header(&mut self, length: usize, fixed: Option<u8>, forms: [Option<u8>; 3]) {
+        match (fixed, forms) {
+            (Some(fixed), _) if length < 16 => self.bytes.push(fixed | length as u8),
+            (_, [Some(form), _, _]) if length <= u8::MAX as usize => self.bytes.extend([form, length as u8]),
+            (_, [_, Some(form), _]) if length <= u16::MAX as usize => {
+                self.bytes.push(form);
+                self.bytes.extend((length as u16).to_be_bytes());
+            }
+            (_, [_, _, Some(form)]) => {
+                self.bytes.push(form);
+                self.bytes.extend((length as u32).to_be_bytes());
+            }
+            _ => unreachable!()
+        }
+    }
+
+    pub(crate) fn uint(&mut self, value: u64) {
+        match value {
+            0..=0x7f => self.bytes.push(value as u8),
+            0x80..=0xff => self.bytes.extend([0xcc, value as u8]),
+            0x100..=0xffff => {
+                self.bytes.push(0xcd);
+                self.bytes.extend((value as u16).to_be_bytes());
+            }
+            0x10000..=0xffff_ffff => {
+                self.bytes.push(0xce);
+                self.bytes.extend((value as u32).to_be_bytes());
+            }
+            _ => {
+                self.bytes.push(0xcf);
+                self.bytes.extend(value.to_be_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn float(&mut self, value: f32) {
+        self.bytes.push(0xca);
+        self.bytes.extend(value.to_be_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.header(value.len(), Some(0xa0), [Some(0xd9), Some(0xda), Some(0xdb)]);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    // Followed by the elements
+    pub(crate) fn array(&mut self, length: usize) {
+        self.header(length, Some(0x90), [None, Some(0xdc), Some(0xdd)]);
+    }
+
+    // Followed by the keys and values, alternating
+    pub(crate) fn map(&mut self, length: usize) {
+        self.header(length, Some(0x80), [None, Some(0xde), Some(0xdf)]);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Binary(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>)
+}
+
+impl Value {
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self { Value::String(value) => Some(value), _ => None }
+    }
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self { Value::Array(values) => Some(values), _ => None }
+    }
+    pub(crate) fn as_map(&self) -> Option<&[(Value, Value)]> {
+        match self { Value::Map(entries) => Some(entries), _ => None }
+    }
+    // The value of a string key of a map
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.as_map()?.iter().find(|(k, _)| k.as_str() == Some(key)).map(|(_, value)| value)
+    }
+}
+
+// Deeper than any format here nests, so that malformed input can't exhaust the stack
+const MAX_DEPTH: usize = 16;
+
+// Decoding, where `None` means malformed input
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn length(&mut self, size: usize) -> Option<usize> {
+        Some(match size {
+            1 => self.array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.array()?) as usize,
+            _ => u32::from_be_bytes(self.array()?) as usize
+        })
+    }
+
+    fn string(&mut self, length: usize) -> Option<Value> {
+        Some(Value::String(std::str::from_utf8(self.take(length)?).ok()?.to_string()))
+    }
+
+    fn values(&mut self, length: usize, depth: usize) -> Option<Vec<Value>> {
+        // each element takes a byte at least, which bounds what a bad length can allocate
+        if length > self.bytes.len() {
+            return None;
+        }
+        (0..length).map(|_| self.value(depth + 1)).collect()
+    }
+
+    fn entries(&mut self, length: usize, depth: usize) -> Option<Value> {
+        let values = self.values(length.checked_mul(2)?, depth)?;
+        let mut values = values.into_iter();
+        Some(Value::Map((0..length).map(|_| (values.next().unwrap(), values.next().unwrap())).collect()))
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let marker = self.array::<1>()?[0];
+        Some(match marker {
+            0x00..=0x7f => Value::Int(marker as i64),
+            0x80..=0x8f => self.entries((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => Value::Array(self.values((marker & 0x0f) as usize, depth)?),
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize)?,
+            0xc0 => Value::Nil,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let length = self.length(1 << (marker - 0xc4))?;
+                Value::Binary(self.take(length)?.to_vec())
+            }
+            0xca => Value::Float(f32::from_be_bytes(self.array()?) as f64),
+            0xcb => Value::Float(f64::from_be_bytes(self.array()?)),
+            0xcc => Value::Int(self.array::<1>()?[0] as i64),
+            0xcd => Value::Int(u16::from_be_bytes(self.array()?) as i64),
+            0xce => Value::Int(u32::from_be_bytes(self.array()?) as i64),
+            0xcf => Value::Int(i64::try_from(u64::from_be_bytes(self.array()?)).ok()?),
+            0xd0 => Value::Int(i8::from_be_bytes(self.array()?) as i64),
+            0xd1 => Value::Int(i16::from_be_bytes(self.array()?) as i64),
+            0xd2 => Value::Int(i32::from_be_bytes(self.array()?) as i64),
+            0xd3 => Value::Int(i64::from_be_bytes(self.array()?)),
+            0xd9..=0xdb => {
+                let length = self.length(1 << (marker - 0xd9))?;
+                self.string(length)?
+            }
+            0xdc | 0xdd => {
+                let length = self.length(2 << (marker - 0xdc))?;
+                Value::Array(self.values(length, depth)?)
+            }
+            0xde | 0xdf => {
+                let length = self.length(2 << (marker - 0xde))?;
+                self.entries(length, depth)?
+            }
+            0xe0..=0xff => Value::Int(marker as i8 as i64),
+            // extensions aren't used by any format here
+            _ => return None
+        })
+    }
+
+    // A single value taking all the bytes
+    pub(crate) fn read(mut self) -> Option<Value> {
+        let value = self.value(0)?;
+        self.bytes.is_empty().then_some(value)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::compgraph::*;
+use crate::strict;
+use crate::compgraph::internals::*;
+use crate::random::SplitMix64;
+
+// Neural network building blocks
+//
+// Vectors are slices of scalar nodes. A layer owns its weights and biases as inputs, which
+// `parameters()` hands to an optimizer; `forward` builds the layer's nodes on top of the
+// given vector, so the same layer can be applied to several inputs that share its weights
+
+define_nodes! {
+    pub relu(x) { x.max(0.0) } grad { [if x > 0.0 { 1.0 } else { 0.0 }] }
+    pub sigmoid(x) { 1.0 / (1.0 + strict::exp(-x)) } grad { [{ let s = 1.0 / (1.0 + strict::exp(-x)); s * (1.0 - s) }] }
+    pub tanh(x) { strict::tanh(x) } grad { [1.0 - strict::tanh(x) * strict::tanh(x)] }
+}
+
+type Parameter = Rc<RefCell<InputNodeImpl>>;
+
+// bias + weights . inputs
+struct Neuron<X> {
+    weights: Vec<Parameter>,
+    bias: Parameter,
+    inputs: Vec<X>
+}
+
+impl<X: ComputeNodeRef> ComputeMut for Neuron<X> {
+    fn compute(&mut self) -> Float {
+        self.bias.compute() + self.weights.iter().zip(&self.inputs).map(|(w, x)| w.compute() * x.compute()).sum::<Float>()
+    }
+    fn name(&self) -> &'static str { "neuron" }
+    fn children(&self) -> Vec<Child> {
+        let weights = self.weights.iter().map(ComputeNodeRef::as_child);
+        let inputs = self.inputs.iter().map(ComputeNodeRef::as_child);
+        weights.chain(inputs).chain([self.bias.as_child()]).collect()
+    }
+    fn partials(&mut self) -> Option<Vec<Float>> {
+        let by_weight = self.inputs.iter().map(ComputeNodeRef::compute);
+        let by_input = self.weights.iter().map(ComputeNodeRef::compute);
+        Some(by_weight.chain(by_input).chain([1.0]).collect())
+    }
+}
+
+// Fully connected layer
+pub struct Dense {
+    // one row of weights per output
+    weights: Vec<Vec<Parameter>>,
+    biases: Vec<Parameter>
+}
+
+impl Dense {
+    // Uniform (Glorot) initial weights from `seed`, zero biases
+    pub fn new(inputs: usize, outputs: usize, seed: u64) -> Dense {
+        let mut rng = SplitMix64::new(seed);
+        let limit = (6.0 / (inputs + outputs) as f64).sqrt();
+        let weights = (0..outputs).map(|_| {
+            (0..inputs).map(|_| InputNodeImpl::new_ref(((2.0 * rng.next_f64() - 1.0) * limit) as Float)).collect()
+        }).collect();
+        Dense { weights, biases: (0..outputs).map(|_| InputNodeImpl::new_ref(0.0)).collect() }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.weights.first().map_or(0, Vec::len)
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.biases.len()
+    }
+
+    pub fn forward(&self, x: &[impl ComputeNodeRef + 'static]) -> Vec<DynamicComputeNodeRef> {
+        assert_eq!(x.len(), self.inputs(), "dense layer input size");
+        self.weights.iter().zip(&self.biases).map(|(row, bias)| {
+            let result = CachingNodeWrapper::new_ref(Neuron {
+                weights: row.clone(), bias: bias.clone(), inputs: x.to_vec()
+            });
+            let subscriber = result.clone() as _;
+            {
+                let inner = &result.borrow().inner;
+                inner.weights.iter().for_each(|w| w.subscribe_to_invalidate(&subscriber));
+                inner.inputs.iter().for_each(|x| x.subscribe_to_invalidate(&subscriber));
+                inner.bias.subscribe_to_invalidate(&subscriber);
+            }
+            result as DynamicComputeNodeRef
+        }).collect()
+    }
+
+    // The weights row by row, then the biases
+    pub fn parameters(&self) -> Vec<impl InputNodeRef> {
+        self.weights.iter().flatten().chain(&self.biases).cloned().collect()
+    }
+}
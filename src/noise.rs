@@ -0,0 +1,130 @@
+use crate::compgraph::*;
+use crate::strict;
+use crate::compgraph::internals::*;
+use crate::random::SplitMix64;
+
+// 2D procedural noise nodes for terrain, texture and similar pipelines
+//
+// The seed and both coordinates are child nodes, so changing any of them invalidates
+// the noise and everything built on it. All noise functions return values in about [-1, 1]
+
+struct NoiseNode<S, X, Y> {
+    seed: S,
+    x: X,
+    y: Y,
+    noise: fn(u64, f64, f64) -> f64
+}
+
+impl<S: ComputeNodeRef, X: ComputeNodeRef, Y: ComputeNodeRef> ComputeMut for NoiseNode<S, X, Y> {
+    fn compute(&mut self) -> Float {
+        let seed = self.seed.compute().to_bits() as u64;
+        (self.noise)(seed, self.x.compute() as f64, self.y.compute() as f64) as Float
+    }
+}
+
+fn noise_node(
+    seed: impl ComputeNodeRef + 'static, x: impl ComputeNodeRef + 'static, y: impl ComputeNodeRef + 'static,
+    noise: fn(u64, f64, f64) -> f64
+) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(NoiseNode { seed, x, y, noise });
+    let subscriber = result.clone() as _;
+    {
+        let inner = &result.borrow().inner;
+        inner.seed.subscribe_to_invalidate(&subscriber);
+        inner.x.subscribe_to_invalidate(&subscriber);
+        inner.y.subscribe_to_invalidate(&subscriber);
+    }
+    result
+}
+
+pub fn value(seed: impl ComputeNodeRef + 'static, x: impl ComputeNodeRef + 'static, y: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+    noise_node(seed, x, y, value_noise)
+}
+
+pub fn perlin(seed: impl ComputeNodeRef + 'static, x: impl ComputeNodeRef + 'static, y: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+    noise_node(seed, x, y, perlin_noise)
+}
+
+pub fn simplex(seed: impl ComputeNodeRef + 'static, x: impl ComputeNodeRef + 'static, y: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+    noise_node(seed, x, y, simplex_noise)
+}
+
+fn lattice_hash(seed: u64, ix: i64, iy: i64) -> u64 {
+    let mut rng = SplitMix64::new(seed);
+    rng = SplitMix64::new(rng.next_u64() ^ ix as u64);
+    rng = SplitMix64::new(rng.next_u64() ^ iy as u64);
+    rng.next_u64()
+}
+
+// Uniform in [-1, 1]
+fn lattice_value(seed: u64, ix: i64, iy: i64) -> f64 {
+    (lattice_hash(seed, ix, iy) >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+}
+
+// Unit vector at a random angle
+fn lattice_gradient(seed: u64, ix: i64, iy: i64) -> (f64, f64) {
+    let angle = std::f64::consts::TAU * (lattice_value(seed, ix, iy) + 1.0) / 2.0;
+    let (sin, cos) = strict::sin_cos_f64(angle);
+    (cos, sin)
+}
+
+// Quintic fade with zero first and second derivatives at 0 and 1
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    // saturated for huge coordinates, and wrapping around from there: the lattice is hashed
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let (ix1, iy1) = (ix.wrapping_add(1), iy.wrapping_add(1));
+    lerp(
+        lerp(lattice_value(seed, ix, iy), lattice_value(seed, ix1, iy), tx),
+        lerp(lattice_value(seed, ix, iy1), lattice_value(seed, ix1, iy1), tx),
+        ty
+    )
+}
+
+fn perlin_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let (fx, fy) = (x - x0, y - y0);
+    let corner = |dx: i64, dy: i64| {
+        let (gx, gy) = lattice_gradient(seed, ix.wrapping_add(dx), iy.wrapping_add(dy));
+        gx * (fx - dx as f64) + gy * (fy - dy as f64)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+    // unit gradients give at most sqrt(1/2) in 2D
+    std::f64::consts::SQRT_2 * lerp(lerp(corner(0, 0), corner(1, 0), tx), lerp(corner(0, 1), corner(1, 1), tx), ty)
+}
+
+fn simplex_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let skew = (3f64.sqrt() - 1.0) / 2.0;
+    let unskew = (3.0 - 3f64.sqrt()) / 6.0;
+
+    let s = (x + y) * skew;
+    let (i, j) = ((x + s).floor(), (y + s).floor());
+    let t = (i + j) * unskew;
+    let (x0, y0) = (x - (i - t), y - (j - t));
+    let (di, dj) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (0, 0, x0, y0),
+        (di, dj, x0 - di as f64 + unskew, y0 - dj as f64 + unskew),
+        (1, 1, x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew)
+    ];
+    let total: f64 = corners.iter().map(|&(ci, cj, cx, cy)| {
+        let falloff = 0.5 - cx * cx - cy * cy;
+        if falloff <= 0.0 {
+            return 0.0;
+        }
+        let (gx, gy) = lattice_gradient(seed, (i as i64).wrapping_add(ci), (j as i64).wrapping_add(cj));
+        falloff.powi(4) * (gx * cx + gy * cy)
+    }).sum();
+    // scales the maximum contribution of unit gradients to about 1
+    (70.0 * total).clamp(-1.0, 1.0)
+}
@@ -0,0 +1,80 @@
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+use crate::compgraph::*;
+use crate::strict;
+use crate::compgraph::internals::*;
+
+// Arithmetic shared by all value types that can back a numeric graph
+//
+// `define_nodes!` only produces `Float` nodes; graph-building code written against
+// `Numeric` and the nodes below runs unchanged on both `f32` and `Fixed`
+pub trait Numeric:
+    Copy + PartialOrd + ComputeNodeRef<Self> + 'static
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+}
+
+impl Numeric for f32 {
+    const ZERO: f32 = 0.0;
+    const ONE: f32 = 1.0;
+
+    fn from_f32(value: f32) -> f32 { value }
+    fn to_f32(self) -> f32 { self }
+    fn abs(self) -> f32 { f32::abs(self) }
+    fn sqrt(self) -> f32 { f32::sqrt(self) }
+    fn sin(self) -> f32 { strict::sin(self) }
+    fn cos(self) -> f32 { strict::cos(self) }
+}
+
+pub fn add<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| a + b)
+}
+
+pub fn sub<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| a - b)
+}
+
+pub fn mul<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| a * b)
+}
+
+pub fn div<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| a / b)
+}
+
+pub fn min<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| if b < a { b } else { a })
+}
+
+pub fn max<N: Numeric>(a: impl ComputeNodeRef<N> + 'static, b: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    binary_node(a, b, |a, b| if b > a { b } else { a })
+}
+
+pub fn neg<N: Numeric>(x: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    unary_node(x, |x| -x)
+}
+
+pub fn abs<N: Numeric>(x: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    unary_node(x, N::abs)
+}
+
+pub fn sqrt<N: Numeric>(x: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    unary_node(x, N::sqrt)
+}
+
+pub fn sin<N: Numeric>(x: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    unary_node(x, N::sin)
+}
+
+pub fn cos<N: Numeric>(x: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+    unary_node(x, N::cos)
+}
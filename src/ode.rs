@@ -0,0 +1,165 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::stateful::*;
+
+// Integrators for systems of ODEs dy/dt = f(t, y), one subgraph per component of f
+//
+// The state variables and the time are nodes that the derivative subgraphs are built from.
+// A step evaluates the derivatives at the points the method needs (by temporarily setting
+// the state nodes), then moves the states to their new values. Attached to a `Clock`,
+// the system advances with every `tick(dt)`
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Euler,
+    Rk4
+}
+
+struct System {
+    method: Method,
+    initial: Vec<Float>,
+    states: Vec<Rc<RefCell<InputNodeImpl>>>,
+    time: Rc<RefCell<InputNodeImpl>>,
+    derivatives: Vec<Option<DynamicComputeNodeRef>>
+}
+
+impl System {
+    fn point(&self) -> (Float, Vec<Float>) {
+        (self.time.compute(), self.states.iter().map(|state| state.compute()).collect())
+    }
+
+    // Without running the work the changes queue, so that nothing sees the stage points of a
+    // step, nor a half-updated state: that is left to whoever steps the system, once the
+    // stepper isn't borrowed
+    fn set_point(&self, t: Float, y: &[Float]) {
+        self.time.borrow_mut().assign(t);
+        for (state, &value) in self.states.iter().zip(y) {
+            state.borrow_mut().assign(value);
+        }
+    }
+
+    // Components without a derivative stay constant
+    fn derivatives(&self) -> Vec<Float> {
+        self.derivatives.iter().map(|derivative| derivative.as_ref().map_or(0.0, |d| d.compute())).collect()
+    }
+
+    fn derivatives_at(&self, t: Float, y: &[Float]) -> Vec<Float> {
+        self.set_point(t, y);
+        self.derivatives()
+    }
+
+    fn next_states(&self, dt: Float) -> Vec<Float> {
+        let (t0, y0) = self.point();
+        let offset = |k: &[Float], scale: Float| -> Vec<Float> {
+            y0.iter().zip(k).map(|(y, k)| y + scale * k).collect()
+        };
+        let k1 = self.derivatives();
+        match self.method {
+            Method::Euler => offset(&k1, dt),
+            Method::Rk4 => {
+                let k2 = self.derivatives_at(t0 + dt / 2.0, &offset(&k1, dt / 2.0));
+                let k3 = self.derivatives_at(t0 + dt / 2.0, &offset(&k2, dt / 2.0));
+                let k4 = self.derivatives_at(t0 + dt, &offset(&k3, dt));
+                self.set_point(t0, &y0);
+                let slope: Vec<Float> = (0..y0.len()).map(|i| (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]) / 6.0).collect();
+                offset(&slope, dt)
+            }
+        }
+    }
+}
+
+// The system's node in the graph of stateful nodes, its value is the integration time
+struct OdeStepper {
+    system: Rc<RefCell<System>>,
+    prepare: Rc<dyn Fn(Float)>,
+    next_states: Rc<RefCell<Vec<Float>>>
+}
+
+impl ComputeMut for OdeStepper {
+    fn compute(&mut self) -> Float {
+        self.system.borrow().time.compute()
+    }
+    fn name(&self) -> &'static str { "ode" }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(Vec::new()) }
+}
+
+impl ComputeNodeMut for OdeStepper {
+    fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.system.borrow().time.subscribe_to_invalidate(subscriber)
+    }
+    fn version(&self) -> u64 {
+        self.system.borrow().time.version()
+    }
+}
+
+impl StepMut for OdeStepper {
+    fn prepare_step(&self) -> Rc<dyn Fn(Float)> {
+        self.prepare.clone()
+    }
+    fn commit_step(&mut self, dt: Float) {
+        let next_states = std::mem::take(&mut *self.next_states.borrow_mut());
+        if !next_states.is_empty() {
+            let system = self.system.borrow();
+            let t = system.time.compute();
+            system.set_point(t + dt, &next_states);
+        }
+    }
+    fn reset(&mut self) {
+        let system = self.system.borrow();
+        system.set_point(0.0, &system.initial);
+    }
+}
+
+#[derive(Clone)]
+pub struct OdeSystem {
+    system: Rc<RefCell<System>>,
+    stepper: DynamicStatefulNodeRef
+}
+
+impl OdeSystem {
+    pub fn new(initial: &[Float], method: Method) -> OdeSystem {
+        let system = Rc::new(RefCell::new(System {
+            method,
+            initial: initial.to_vec(),
+            states: initial.iter().map(|&value| InputNodeImpl::new_ref(value)).collect(),
+            time: InputNodeImpl::new_ref(0.0),
+            derivatives: vec![None; initial.len()]
+        }));
+        let next_states = Rc::new(RefCell::new(Vec::new()));
+        let prepare: Rc<dyn Fn(Float)> = {
+            let system = system.clone();
+            let next_states = next_states.clone();
+            Rc::new(move |dt| *next_states.borrow_mut() = system.borrow().next_states(dt))
+        };
+        let stepper = Rc::new(RefCell::new(OdeStepper { system: system.clone(), prepare, next_states }));
+        OdeSystem { system, stepper }
+    }
+
+    pub fn state(&self, index: usize) -> DynamicComputeNodeRef {
+        self.system.borrow().states[index].clone()
+    }
+
+    pub fn time(&self) -> DynamicComputeNodeRef {
+        self.system.borrow().time.clone()
+    }
+
+    pub fn set_derivative(&self, index: usize, derivative: &DynamicComputeNodeRef) {
+        self.system.borrow_mut().derivatives[index] = Some(derivative.clone());
+    }
+
+    // For attaching to a `Clock`
+    pub fn node(&self) -> DynamicStatefulNodeRef {
+        self.stepper.clone()
+    }
+
+    pub fn step(&self, dt: Float) {
+        self.stepper.step(dt)
+    }
+
+    // Back to the initial states at time zero
+    pub fn reset(&self) {
+        self.stepper.reset()
+    }
+}
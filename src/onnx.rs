@@ -0,0 +1,303 @@
+use std::{collections::HashMap, fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::proto::{Writer, Reader, Value};
+use crate::registry::{NodeRegistry, RegistryError};
+
+// Export of graphs as ONNX models, for viewers like Netron and other runtimes
+//
+// Nodes map to ONNX operators by name and number of children (so a node named `add` is
+// assumed to add), all values being scalar float tensors. Inputs become graph inputs with
+// their current values as defaults, constants become initializers, and values from outside
+// the graph (a clock's time, ...) extra graph inputs. Nodes without an ONNX equivalent are
+// exported as custom operators in the `rust_compgraph` domain, named after the node, and
+// listed in the model's metadata
+
+const OPSET_VERSION: i64 = 13;
+pub const CUSTOM_DOMAIN: &str = "rust_compgraph";
+
+// (node name, children, ONNX operator)
+const OPERATORS: &[(&str, usize, &str)] = &[
+    ("add", 2, "Add"), ("sub", 2, "Sub"), ("mul", 2, "Mul"), ("div", 2, "Div"), ("pow", 2, "Pow"),
+    ("min", 2, "Min"), ("max", 2, "Max"),
+    ("neg", 1, "Neg"), ("abs", 1, "Abs"), ("sqrt", 1, "Sqrt"), ("exp", 1, "Exp"), ("ln", 1, "Log"),
+    ("sin", 1, "Sin"), ("cos", 1, "Cos"), ("tan", 1, "Tan"), ("tanh", 1, "Tanh"),
+    ("relu", 1, "Relu"), ("sigmoid", 1, "Sigmoid")
+];
+
+fn onnx_operator(name: &str, children: usize) -> Option<&'static str> {
+    OPERATORS.iter().find(|&&(n, c, _)| n == name && c == children).map(|&(_, _, op)| op)
+}
+
+struct Operator {
+    op_type: String,
+    custom: bool,
+    inputs: Vec<String>,
+    output: String
+}
+
+#[derive(Default)]
+struct Model {
+    // graph inputs with their default values, if any
+    inputs: Vec<(String, Option<Float>)>,
+    constants: Vec<(String, Float)>,
+    operators: Vec<Operator>,
+    custom_ops: Vec<&'static str>
+}
+
+impl Model {
+    fn child_value(&mut self, child: Child, names: &HashMap<*const (), String>) -> String {
+        match child {
+            Child::Node(node) => names[&node_address(&node)].clone(),
+            Child::Constant(value) => {
+                let name = format!("c{}", self.constants.len());
+                self.constants.push((name.clone(), value));
+                name
+            }
+            Child::Opaque => {
+                let name = format!("external{}", self.inputs.len());
+                self.inputs.push((name.clone(), None));
+                name
+            }
+        }
+    }
+
+    fn build(output: &impl ComputeNodeRef) -> (Model, String) {
+        let mut model = Model::default();
+        let output = match output.as_child() {
+            Child::Node(node) => node,
+            child => {
+                let value = model.child_value(child, &HashMap::new());
+                model.operators.push(Operator { op_type: "Identity".into(), custom: false, inputs: vec![value], output: "output".into() });
+                return (model, "output".into());
+            }
+        };
+
+        let mut names = HashMap::new();
+        for (index, node) in topological_order(&output).into_iter().enumerate() {
+            let (name, children) = { let node = node.borrow(); (node.name(), node.children()) };
+            let value = format!("n{}", index);
+            if is_input(&node) {
+                model.inputs.push((value.clone(), Some(node.compute())));
+            } else {
+                let inputs = children.iter().map(|child| model.child_value(child.clone(), &names)).collect();
+                let operator = onnx_operator(name, children.len());
+                if operator.is_none() && !model.custom_ops.contains(&name) {
+                    model.custom_ops.push(name);
+                }
+                model.operators.push(Operator {
+                    op_type: operator.unwrap_or(name).into(), custom: operator.is_none(), inputs, output: value.clone()
+                });
+            }
+            names.insert(node_address(&node), value);
+        }
+        let output = names[&node_address(&output)].clone();
+        (model, output)
+    }
+}
+
+fn scalar_value_info(writer: &mut Writer, name: &str) {
+    writer.string(1, name);
+    // TypeProto.tensor_type with FLOAT elements and an empty (scalar) shape
+    writer.message(2, |t| t.message(1, |tensor| {
+        tensor.int(1, 1);
+        tensor.message(2, |_| {});
+    }));
+}
+
+fn scalar_tensor(writer: &mut Writer, name: &str, value: Float) {
+    writer.int(2, 1);
+    writer.float(4, value);
+    writer.string(8, name);
+}
+
+// Serializes the graph `output` depends on as an ONNX `ModelProto`
+pub fn export(output: &impl ComputeNodeRef) -> Vec<u8> {
+    let (model, output) = Model::build(output);
+    let mut writer = Writer::new();
+    writer.int(1, 8); // IR version
+    writer.string(2, "rust-compgraph");
+    writer.message(7, |graph| {
+        for operator in &model.operators {
+            graph.message(1, |node| {
+                operator.inputs.iter().for_each(|input| node.string(1, input));
+                node.string(2, &operator.output);
+                node.string(3, &operator.output);
+                node.string(4, &operator.op_type);
+                if operator.custom {
+                    node.string(6, &format!("compgraph node `{}`", operator.op_type));
+                    node.string(7, CUSTOM_DOMAIN);
+                }
+            });
+        }
+        graph.string(2, "compgraph");
+        let defaults = model.inputs.iter().filter_map(|(name, value)| value.map(|value| (name, value)));
+        for (name, value) in model.constants.iter().map(|(name, value)| (name, *value)).chain(defaults) {
+            graph.message(5, |tensor| scalar_tensor(tensor, name, value));
+        }
+        for (name, _) in &model.inputs {
+            graph.message(11, |input| scalar_value_info(input, name));
+        }
+        graph.message(12, |info| scalar_value_info(info, &output));
+    });
+    writer.message(8, |opset| opset.int(2, OPSET_VERSION));
+    if !model.custom_ops.is_empty() {
+        writer.message(8, |opset| {
+            opset.string(1, CUSTOM_DOMAIN);
+            opset.int(2, 1);
+        });
+        writer.message(14, |entry| {
+            entry.string(1, "rust_compgraph.custom_ops");
+            entry.string(2, &model.custom_ops.join(","));
+        });
+    }
+    writer.into_bytes()
+}
+
+// Import of ONNX models restricted to scalar elementwise operators
+//
+// Operators are looked up in a `NodeRegistry`, standard ones under the node name they
+// export from and custom `rust_compgraph` ones under their own name. Graph inputs become
+// new inputs (with the initializer of the same name as their value), other initializers
+// constants
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    Malformed(&'static str),
+    // a tensor that isn't a float scalar
+    UnsupportedTensor(String),
+    UnsupportedOperator(String),
+    UnknownValue(String),
+    Registry(RegistryError)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Malformed(what) => write!(f, "malformed model: {}", what),
+            ImportError::UnsupportedTensor(name) => write!(f, "tensor `{}` is not a float scalar", name),
+            ImportError::UnsupportedOperator(op) => write!(f, "unsupported operator `{}`", op),
+            ImportError::UnknownValue(name) => write!(f, "value `{}` is used before it is defined", name),
+            ImportError::Registry(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<RegistryError> for ImportError {
+    fn from(error: RegistryError) -> ImportError {
+        ImportError::Registry(error)
+    }
+}
+
+pub struct ImportedGraph {
+    pub inputs: Vec<(String, Input)>,
+    pub outputs: Vec<(String, Operand)>
+}
+
+impl ImportedGraph {
+    pub fn input(&self, name: &str) -> Option<&Input> {
+        self.inputs.iter().find(|(n, _)| n == name).map(|(_, input)| input)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&Operand> {
+        self.outputs.iter().find(|(n, _)| n == name).map(|(_, output)| output)
+    }
+}
+
+fn fields<'a>(bytes: &'a [u8], what: &'static str) -> Result<Vec<(u64, Value<'a>)>, ImportError> {
+    Reader::new(bytes).fields().ok_or(ImportError::Malformed(what))
+}
+
+fn string<'a>(value: &Value<'a>, what: &'static str) -> Result<&'a str, ImportError> {
+    value.as_str().ok_or(ImportError::Malformed(what))
+}
+
+fn value_info_name(bytes: &[u8]) -> Result<&str, ImportError> {
+    let fields = fields(bytes, "value info")?;
+    let name = fields.iter().find(|(field, _)| *field == 1).ok_or(ImportError::Malformed("value info without name"))?;
+    string(&name.1, "value info name")
+}
+
+fn scalar_tensor_value(bytes: &[u8]) -> Result<(String, Float), ImportError> {
+    let mut name = String::new();
+    let (mut values, mut elements, mut data_type) = (Vec::new(), 1, 1);
+    for (field, value) in fields(bytes, "tensor")? {
+        match (field, value) {
+            (1, Value::Varint(dim)) => elements = dim.checked_mul(elements).ok_or(ImportError::Malformed("tensor dimensions"))?,
+            (2, Value::Varint(t)) => data_type = t,
+            (4, Value::Fixed32(bits)) => values.push(f32::from_bits(bits)),
+            (4, Value::Bytes(packed)) | (9, Value::Bytes(packed)) => {
+                values.extend(packed.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])));
+            }
+            (8, value) => name = string(&value, "tensor name")?.to_string(),
+            _ => {}
+        }
+    }
+    if data_type != 1 || elements != 1 || values.len() != 1 {
+        return Err(ImportError::UnsupportedTensor(name));
+    }
+    Ok((name, values[0] as Float))
+}
+
+fn node_name(op_type: &str, domain: &str, children: usize) -> Result<String, ImportError> {
+    if domain == CUSTOM_DOMAIN {
+        return Ok(op_type.to_string());
+    }
+    OPERATORS.iter().find(|&&(_, c, op)| op == op_type && c == children).map(|&(name, _, _)| name.to_string())
+        .ok_or_else(|| ImportError::UnsupportedOperator(op_type.to_string()))
+}
+
+pub fn import(model: &[u8], registry: &NodeRegistry) -> Result<ImportedGraph, ImportError> {
+    let graph = fields(model, "model")?.into_iter().find(|(field, _)| *field == 7)
+        .and_then(|(_, graph)| graph.as_bytes()).ok_or(ImportError::Malformed("model without graph"))?;
+    let graph = fields(graph, "graph")?;
+
+    let mut initializers = HashMap::new();
+    for (_, tensor) in graph.iter().filter(|(field, _)| *field == 5) {
+        let (name, value) = scalar_tensor_value(tensor.as_bytes().ok_or(ImportError::Malformed("initializer"))?)?;
+        initializers.insert(name, value);
+    }
+
+    let mut values: HashMap<String, Operand> = HashMap::new();
+    let mut inputs = Vec::new();
+    for (_, info) in graph.iter().filter(|(field, _)| *field == 11) {
+        let name = value_info_name(info.as_bytes().ok_or(ImportError::Malformed("graph input"))?)?;
+        let input = InputNodeImpl::new_ref(initializers.remove(name).unwrap_or(0.0));
+        values.insert(name.to_string(), Operand::Node(input.clone()));
+        inputs.push((name.to_string(), input));
+    }
+    values.extend(initializers.into_iter().map(|(name, value)| (name, Operand::Constant(value))));
+
+    for (_, node) in graph.iter().filter(|(field, _)| *field == 1) {
+        let (mut children, mut outputs, mut op_type, mut domain) = (Vec::new(), Vec::new(), "", "");
+        for (field, value) in fields(node.as_bytes().ok_or(ImportError::Malformed("node"))?, "node")? {
+            match field {
+                1 => {
+                    let name = string(&value, "node input")?;
+                    children.push(values.get(name).cloned().ok_or_else(|| ImportError::UnknownValue(name.to_string()))?);
+                }
+                2 => outputs.push(string(&value, "node output")?),
+                4 => op_type = string(&value, "node operator")?,
+                7 => domain = string(&value, "node domain")?,
+                _ => {}
+            }
+        }
+        let output = outputs.first().ok_or(ImportError::Malformed("node without output"))?;
+        let result = if op_type == "Identity" && domain.is_empty() && children.len() == 1 {
+            children.remove(0)
+        } else {
+            Operand::Node(registry.create(&node_name(op_type, domain, children.len())?, &children)?)
+        };
+        values.insert(output.to_string(), result);
+    }
+
+    let mut outputs = Vec::new();
+    for (_, info) in graph.iter().filter(|(field, _)| *field == 12) {
+        let name = value_info_name(info.as_bytes().ok_or(ImportError::Malformed("graph output"))?)?;
+        let output = values.get(name).cloned().ok_or_else(|| ImportError::UnknownValue(name.to_string()))?;
+        outputs.push((name.to_string(), output));
+    }
+    Ok(ImportedGraph { inputs, outputs })
+}
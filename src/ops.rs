@@ -0,0 +1,79 @@
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::strict;
+
+// Scalar math nodes with derivative rules
+//
+// Named like the ONNX operators they correspond to, so that they export as those
+
+define_nodes! {
+    pub add(a, b) { a + b } grad { [1.0, 1.0] }
+    pub sub(a, b) { a - b } grad { [1.0, -1.0] }
+    pub mul(a, b) { a * b } grad { [b, a] }
+    pub div(a, b) { a / b } grad { [1.0 / b, -a / (b * b)] }
+    pub pow(a, b) { strict::powf(a, b) } grad { [b * strict::powf(a, b - 1.0), strict::powf(a, b) * strict::ln(a)] }
+    pub min(a, b) { a.min(b) } grad { if a <= b { [1.0, 0.0] } else { [0.0, 1.0] } }
+    pub max(a, b) { a.max(b) } grad { if a >= b { [1.0, 0.0] } else { [0.0, 1.0] } }
+    pub neg(x) { -x } grad { [-1.0] }
+    pub abs(x) { x.abs() } grad { [if x == 0.0 { 0.0 } else { x.signum() }] }
+    pub sqrt(x) { x.sqrt() } grad { [0.5 / x.sqrt()] }
+    pub exp(x) { strict::exp(x) } grad { [strict::exp(x)] }
+    pub ln(x) { strict::ln(x) } grad { [1.0 / x] }
+    pub sin(x) { strict::sin(x) } grad { [strict::cos(x)] }
+    pub cos(x) { strict::cos(x) } grad { [-strict::sin(x)] }
+    pub tan(x) { strict::tan(x) } grad { [1.0 / (strict::cos(x) * strict::cos(x))] }
+}
+
+// Sum and mean of any number of terms, for the long reductions of generated graphs. Terms
+// are added left to right in `f64` with Neumaier's compensated summation, which carries the
+// low bits each addition rounds off, so the error doesn't grow with the number of terms and
+// the result is the exactly rounded sum but for extreme cancellations
+pub(crate) fn compensated_sum(values: impl IntoIterator<Item = Float>) -> Float {
+    let (sum, compensation) = values.into_iter().fold((0.0, 0.0), |(sum, compensation): (f64, f64), value| {
+        let value = value as f64;
+        let total = sum + value;
+        let lost = if sum.abs() >= value.abs() { (sum - total) + value } else { (value - total) + sum };
+        (total, compensation + lost)
+    });
+    (sum + compensation) as Float
+}
+
+struct Reduction<X> {
+    terms: Vec<X>,
+    mean: bool
+}
+
+impl<X: ComputeNodeRef> ComputeMut for Reduction<X> {
+    fn compute(&mut self) -> Float {
+        let sum = compensated_sum(self.terms.iter().map(ComputeNodeRef::compute));
+        if self.mean { sum / self.terms.len() as Float } else { sum }
+    }
+    fn name(&self) -> &'static str { if self.mean { "mean" } else { "sum" } }
+    fn children(&self) -> Vec<Child> {
+        self.terms.iter().map(ComputeNodeRef::as_child).collect()
+    }
+    fn partials(&mut self) -> Option<Vec<Float>> {
+        let partial = if self.mean { 1.0 / self.terms.len() as Float } else { 1.0 };
+        Some(vec![partial; self.terms.len()])
+    }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(reduction(children.to_vec(), self.mean))
+    }
+}
+
+fn reduction(terms: Vec<impl ComputeNodeRef + 'static>, mean: bool) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(Reduction { terms, mean });
+    let subscriber = result.clone() as _;
+    result.borrow().inner.terms.iter().for_each(|term| term.subscribe_to_invalidate(&subscriber));
+    result
+}
+
+// 0 without terms
+pub fn sum(terms: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+    reduction(terms, false)
+}
+
+pub fn mean(terms: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+    assert!(!terms.is_empty(), "mean: no terms");
+    reduction(terms, true)
+}
@@ -0,0 +1,234 @@
+use crate::compgraph::*;
+use crate::autodiff::{self, NoDerivative};
+use crate::strict;
+
+// Optimizers minimizing a scalar loss node by adjusting trainable inputs
+//
+// Each step computes the gradient of the loss with respect to the parameters and `set()`s
+// them to their new values, so the next step only recomputes the parts of the graph that
+// depend on the parameters. Steps return the loss before the update
+//
+// The learning rate is constant unless replaced by a schedule, a function of the step count
+// (starting from zero), with `with_schedule`
+
+pub trait Optimizer {
+    fn step(&mut self) -> Result<Float, NoDerivative>;
+}
+
+// The part shared by all optimizers
+struct Problem<L, P> {
+    loss: L,
+    parameters: Vec<P>,
+    schedule: Box<dyn Fn(usize) -> Float>,
+    steps: usize
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Problem<L, P> {
+    fn new(loss: L, parameters: Vec<P>, learning_rate: Float) -> Problem<L, P> {
+        Problem { loss, parameters, schedule: Box::new(move |_| learning_rate), steps: 0 }
+    }
+
+    // The loss, its gradient with respect to each parameter and the learning rate for this step
+    fn begin_step(&mut self) -> Result<(Float, Vec<Float>, Float), NoDerivative> {
+        let gradients = autodiff::gradients(&self.loss)?;
+        let gradient = self.parameters.iter().map(|parameter| gradients.wrt(parameter)).collect();
+        let learning_rate = (self.schedule)(self.steps);
+        self.steps += 1;
+        Ok((self.loss.compute(), gradient, learning_rate))
+    }
+
+    // All at once, so that nothing sees the parameters half-updated
+    fn apply(&self, deltas: impl IntoIterator<Item = Float>) {
+        set_all(self.parameters.iter().zip(deltas).map(|(parameter, delta)| (parameter, parameter.compute() + delta)));
+    }
+}
+
+// Plain gradient descent
+pub struct Sgd<L, P> {
+    problem: Problem<L, P>
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Sgd<L, P> {
+    pub fn new(loss: L, parameters: Vec<P>, learning_rate: Float) -> Sgd<L, P> {
+        Sgd { problem: Problem::new(loss, parameters, learning_rate) }
+    }
+
+    pub fn with_schedule(mut self, schedule: impl Fn(usize) -> Float + 'static) -> Sgd<L, P> {
+        self.problem.schedule = Box::new(schedule);
+        self
+    }
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Optimizer for Sgd<L, P> {
+    fn step(&mut self) -> Result<Float, NoDerivative> {
+        let (loss, gradient, learning_rate) = self.problem.begin_step()?;
+        self.problem.apply(gradient.iter().map(|g| -learning_rate * g));
+        Ok(loss)
+    }
+}
+
+// Gradient descent with a velocity that keeps `momentum` of the previous update
+pub struct Momentum<L, P> {
+    problem: Problem<L, P>,
+    momentum: Float,
+    velocity: Vec<Float>
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Momentum<L, P> {
+    pub fn new(loss: L, parameters: Vec<P>, learning_rate: Float, momentum: Float) -> Momentum<L, P> {
+        let velocity = vec![0.0; parameters.len()];
+        Momentum { problem: Problem::new(loss, parameters, learning_rate), momentum, velocity }
+    }
+
+    pub fn with_schedule(mut self, schedule: impl Fn(usize) -> Float + 'static) -> Momentum<L, P> {
+        self.problem.schedule = Box::new(schedule);
+        self
+    }
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Optimizer for Momentum<L, P> {
+    fn step(&mut self) -> Result<Float, NoDerivative> {
+        let (loss, gradient, learning_rate) = self.problem.begin_step()?;
+        for (v, g) in self.velocity.iter_mut().zip(&gradient) {
+            *v = self.momentum * *v - learning_rate * g;
+        }
+        self.problem.apply(self.velocity.iter().copied());
+        Ok(loss)
+    }
+}
+
+// Divides the step by a running root mean square of each parameter's gradient
+pub struct RmsProp<L, P> {
+    problem: Problem<L, P>,
+    decay: Float,
+    epsilon: Float,
+    mean_square: Vec<Float>
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> RmsProp<L, P> {
+    pub fn new(loss: L, parameters: Vec<P>, learning_rate: Float) -> RmsProp<L, P> {
+        let mean_square = vec![0.0; parameters.len()];
+        RmsProp { problem: Problem::new(loss, parameters, learning_rate), decay: 0.9, epsilon: 1e-8, mean_square }
+    }
+
+    pub fn with_decay(mut self, decay: Float) -> RmsProp<L, P> {
+        self.decay = decay;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: impl Fn(usize) -> Float + 'static) -> RmsProp<L, P> {
+        self.problem.schedule = Box::new(schedule);
+        self
+    }
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Optimizer for RmsProp<L, P> {
+    fn step(&mut self) -> Result<Float, NoDerivative> {
+        let (loss, gradient, learning_rate) = self.problem.begin_step()?;
+        let deltas: Vec<Float> = self.mean_square.iter_mut().zip(&gradient).map(|(s, g)| {
+            *s = self.decay * *s + (1.0 - self.decay) * g * g;
+            -learning_rate * g / (s.sqrt() + self.epsilon)
+        }).collect();
+        self.problem.apply(deltas);
+        Ok(loss)
+    }
+}
+
+// Momentum and RMSProp combined, with bias correction for the zero-initialized averages
+pub struct Adam<L, P> {
+    problem: Problem<L, P>,
+    beta1: Float,
+    beta2: Float,
+    epsilon: Float,
+    mean: Vec<Float>,
+    mean_square: Vec<Float>
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Adam<L, P> {
+    pub fn new(loss: L, parameters: Vec<P>, learning_rate: Float) -> Adam<L, P> {
+        let n = parameters.len();
+        Adam {
+            problem: Problem::new(loss, parameters, learning_rate),
+            beta1: 0.9, beta2: 0.999, epsilon: 1e-8, mean: vec![0.0; n], mean_square: vec![0.0; n]
+        }
+    }
+
+    pub fn with_betas(mut self, beta1: Float, beta2: Float) -> Adam<L, P> {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: impl Fn(usize) -> Float + 'static) -> Adam<L, P> {
+        self.problem.schedule = Box::new(schedule);
+        self
+    }
+}
+
+impl<L: ComputeNodeRef, P: InputNodeRef + 'static> Optimizer for Adam<L, P> {
+    fn step(&mut self) -> Result<Float, NoDerivative> {
+        let (loss, gradient, learning_rate) = self.problem.begin_step()?;
+        let t = self.problem.steps as i32;
+        let (correction1, correction2) = (1.0 - self.beta1.powi(t), 1.0 - self.beta2.powi(t));
+        let deltas: Vec<Float> = gradient.iter().enumerate().map(|(i, g)| {
+            self.mean[i] = self.beta1 * self.mean[i] + (1.0 - self.beta1) * g;
+            self.mean_square[i] = self.beta2 * self.mean_square[i] + (1.0 - self.beta2) * g * g;
+            let (m, v) = (self.mean[i] / correction1, self.mean_square[i] / correction2);
+            -learning_rate * m / (v.sqrt() + self.epsilon)
+        }).collect();
+        self.problem.apply(deltas);
+        Ok(loss)
+    }
+}
+
+// Learning rate schedules
+
+// Multiplies the rate by `factor` every `every` steps
+pub fn step_decay(initial: Float, factor: Float, every: usize) -> impl Fn(usize) -> Float {
+    move |step| initial * factor.powi((step / every) as i32)
+}
+
+pub fn exponential_decay(initial: Float, decay: Float) -> impl Fn(usize) -> Float {
+    move |step| initial * decay.powi(step as i32)
+}
+
+// From `initial` down to `minimum` along half a cosine over `steps` steps, then stays there
+pub fn cosine_annealing(initial: Float, minimum: Float, steps: usize) -> impl Fn(usize) -> Float {
+    move |step| {
+        let progress = step.min(steps) as Float / steps as Float;
+        minimum + (initial - minimum) * (1.0 + strict::cos(std::f32::consts::PI * progress)) / 2.0
+    }
+}
+
+// Training loop over a dataset of batches
+//
+// For each epoch, every batch is handed to `bind`, which sets the graph's data inputs to it,
+// and then the optimizer takes a step. `on_epoch` is called with each epoch's metrics, which
+// are also returned
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochMetrics {
+    pub epoch: usize,
+    // mean of the batch losses, each from before its update
+    pub loss: Float,
+    pub batches: usize
+}
+
+pub fn fit<D: IntoIterator + Clone>(
+    optimizer: &mut impl Optimizer, data: D, epochs: usize,
+    mut bind: impl FnMut(D::Item), mut on_epoch: impl FnMut(&EpochMetrics)
+) -> Result<Vec<EpochMetrics>, NoDerivative> {
+    let mut history = Vec::with_capacity(epochs);
+    for epoch in 0..epochs {
+        let (mut total, mut batches) = (0.0, 0);
+        for batch in data.clone() {
+            bind(batch);
+            total += optimizer.step()?;
+            batches += 1;
+        }
+        let metrics = EpochMetrics { epoch, loss: total / batches.max(1) as Float, batches };
+        on_epoch(&metrics);
+        history.push(metrics);
+    }
+    Ok(history)
+}
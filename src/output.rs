@@ -0,0 +1,184 @@
+use std::{rc::{Rc, Weak}, cell::RefCell, sync::mpsc::Sender};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Observed outputs: handles on graph outputs that pass each new value on to any number of
+// listeners, closures or channels, without subscribing to invalidation by hand
+//
+// An on-demand output recomputes when asked for its value with `get()`, or `refresh()`. An
+// eager one recomputes by itself right after any change that invalidated it (at the end of
+// the `set()` or clock tick). Either way listeners are only called when the value actually
+// changed, and in the order they were added. Listeners can change inputs themselves
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Evaluation {
+    OnDemand,
+    Eager
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ListenerId(usize);
+
+enum Sink {
+    Callback(Box<dyn FnMut(Float)>),
+    // dropped once the receiving end is
+    Channel(Sender<Float>)
+}
+
+struct OutputState {
+    node: Box<dyn Fn() -> Float>,
+    evaluation: Evaluation,
+    value: Float,
+    dirty: bool,
+    listeners: Vec<(ListenerId, Sink)>,
+    next_id: usize,
+    _flag: Option<Rc<RefCell<OutputFlag>>>
+}
+
+struct OutputFlag {
+    state: Weak<RefCell<OutputState>>
+}
+
+impl InvalidateCacheMut for OutputFlag {
+    fn invalidate_cache(&mut self) {
+        let Some(state) = self.state.upgrade() else { return };
+        let mut state_ref = state.borrow_mut();
+        if !std::mem::replace(&mut state_ref.dirty, true) && state_ref.evaluation == Evaluation::Eager {
+            // the graph can't be computed while it's being invalidated
+            let state = self.state.clone();
+            defer(move || if let Some(state) = state.upgrade() {
+                Output { state }.refresh();
+            });
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Output {
+    state: Rc<RefCell<OutputState>>
+}
+
+impl Output {
+    pub fn new(node: impl ComputeNodeRef + 'static, evaluation: Evaluation) -> Output {
+        let value = node.compute();
+        let state = Rc::new(RefCell::new(OutputState {
+            node: Box::new(|| 0.0), evaluation, value, dirty: false, listeners: Vec::new(), next_id: 0, _flag: None
+        }));
+        let flag = Rc::new(RefCell::new(OutputFlag { state: Rc::downgrade(&state) }));
+        node.subscribe_to_invalidate(&(flag.clone() as _));
+        {
+            let mut state = state.borrow_mut();
+            state.node = Box::new(move || node.compute());
+            state._flag = Some(flag);
+        }
+        Output { state }
+    }
+
+    // The current value, recomputed first if it's out of date
+    pub fn get(&self) -> Float {
+        self.refresh();
+        self.state.borrow().value
+    }
+
+    // Whether the value is out of date
+    pub fn is_dirty(&self) -> bool {
+        self.state.borrow().dirty
+    }
+
+    // Recomputes the value if it's out of date, calling the listeners if it changed, and
+    // returns whether it did
+    pub fn refresh(&self) -> bool {
+        let value = {
+            let mut state = self.state.borrow_mut();
+            if !std::mem::replace(&mut state.dirty, false) {
+                return false;
+            }
+            let value = (state.node)();
+            if value.to_bits() == state.value.to_bits() {
+                return false;
+            }
+            state.value = value;
+            value
+        };
+        // taken out while they run, so that they can use the output
+        let mut listeners = std::mem::take(&mut self.state.borrow_mut().listeners);
+        listeners.retain_mut(|(_, sink)| match sink {
+            Sink::Callback(callback) => {
+                callback(value);
+                true
+            }
+            Sink::Channel(sender) => sender.send(value).is_ok()
+        });
+        let mut state = self.state.borrow_mut();
+        // with the ones added meanwhile after them
+        listeners.append(&mut state.listeners);
+        state.listeners = listeners;
+        true
+    }
+
+    fn add(&self, sink: Sink) -> ListenerId {
+        let mut state = self.state.borrow_mut();
+        let id = ListenerId(state.next_id);
+        state.next_id += 1;
+        state.listeners.push((id, sink));
+        id
+    }
+
+    // Unlike a binding, the callback is only called on changes, not right away
+    pub fn listen(&self, callback: impl FnMut(Float) + 'static) -> ListenerId {
+        self.add(Sink::Callback(Box::new(callback)))
+    }
+
+    // Sends every new value, e.g. to another thread
+    pub fn forward(&self, sender: Sender<Float>) -> ListenerId {
+        self.add(Sink::Channel(sender))
+    }
+
+    pub fn unlisten(&self, id: ListenerId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let count = state.listeners.len();
+        state.listeners.retain(|(listener, _)| *listener != id);
+        state.listeners.len() < count
+    }
+}
+
+// Watching a node: its changes as a queue of `(version, value)` events (see
+// `ComputeNodeRef::version`), for consumers that take updates at their own pace rather than
+// through callbacks. The node is recomputed eagerly after every change that invalidates it,
+// queueing an event when the value changed. Iterating takes the events queued so far and
+// ends when it has caught up; it can be resumed later for the ones queued since
+pub struct Watch {
+    events: Rc<RefCell<std::collections::VecDeque<(u64, Float)>>>,
+    _output: Output
+}
+
+pub fn watch<N: ComputeNodeRef + 'static>(node: N) -> Watch {
+    let events: Rc<RefCell<std::collections::VecDeque<_>>> = Default::default();
+    let output = Output::new(node.clone(), Evaluation::Eager);
+    let queue = events.clone();
+    output.listen(move |value| queue.borrow_mut().push_back((node.version(), value)));
+    Watch { events, _output: output }
+}
+
+impl Watch {
+    // Skips to the last event, if any
+    pub fn latest(&mut self) -> Option<(u64, Float)> {
+        let mut events = self.events.borrow_mut();
+        let latest = events.pop_back();
+        events.clear();
+        latest
+    }
+
+    pub fn pending(&self) -> usize {
+        self.events.borrow().len()
+    }
+}
+
+impl Iterator for Watch {
+    type Item = (u64, Float);
+
+    fn next(&mut self) -> Option<(u64, Float)> {
+        self.events.borrow_mut().pop_front()
+    }
+}
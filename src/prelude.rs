@@ -0,0 +1,21 @@
+// The names most graphs are built from, for a single `use rust_compgraph::prelude::*`
+//
+// The standard nodes are the scalar math of `ops` and the interpolation of `interp`; other
+// node libraries (`nn`, `dsp`, `logic`, ...) reuse names like `tanh` and stay in their modules
+
+pub use crate::compgraph::{
+    Float, ComputeNodeRef, ComputeNodeMut, InputNodeRef, IntoNodeRef, DynamicComputeNodeRef, Input, Child, Operand,
+    create_input, create_typed_input, cutoff, memoize
+};
+// the traits, so that the methods of nodes borrowed out of their handles can be called
+pub use crate::compgraph::internals::{ComputeMut, InvalidateCacheMut};
+pub use crate::define_nodes;
+
+pub use crate::ops::*;
+pub use crate::interp::{lerp, smoothstep, spline};
+
+pub use crate::stateful::{StatefulNodeRef, DynamicStatefulNodeRef};
+pub use crate::time::Clock;
+pub use crate::autodiff::gradients;
+pub use crate::binding::Bindings;
+pub use crate::output::{Output, Evaluation, watch};
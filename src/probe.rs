@@ -0,0 +1,143 @@
+use std::{rc::Rc, cell::RefCell, sync::mpsc::Sender};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Probes: pass-through nodes reporting the values flowing through them
+//
+// `probe("label", x)` computes exactly what `x` does, and hands every value it computes,
+// with its label, to the sinks registered on the thread: callbacks, channels, a log on
+// stderr or a record kept in memory. Values served from the probe's cache aren't reported
+// again, so each report is a recomputation. Sinks run in the order they were registered,
+// and can compute the graph or register sinks themselves
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SinkId(usize);
+
+type Callback = Box<dyn FnMut(&str, Float)>;
+
+enum Sink {
+    Callback(Callback),
+    // dropped once the receiving end is
+    Channel(Sender<(String, Float)>)
+}
+
+#[derive(Default)]
+struct Sinks {
+    sinks: Vec<(SinkId, Sink)>,
+    next_id: usize
+}
+
+thread_local! {
+    static SINKS: RefCell<Sinks> = RefCell::new(Sinks::default());
+}
+
+fn add(sink: Sink) -> SinkId {
+    SINKS.with(|sinks| {
+        let mut sinks = sinks.borrow_mut();
+        let id = SinkId(sinks.next_id);
+        sinks.next_id += 1;
+        sinks.sinks.push((id, sink));
+        id
+    })
+}
+
+pub fn add_sink(callback: impl FnMut(&str, Float) + 'static) -> SinkId {
+    add(Sink::Callback(Box::new(callback)))
+}
+
+// Sends the values with their labels, e.g. to another thread
+pub fn forward(sender: Sender<(String, Float)>) -> SinkId {
+    add(Sink::Channel(sender))
+}
+
+// Prints `label = value` lines to stderr
+pub fn log() -> SinkId {
+    add_sink(|label, value| eprintln!("{} = {}", label, value))
+}
+
+// Keeps the values in memory, until the record is dropped
+pub struct ProbeRecord {
+    id: SinkId,
+    values: Rc<RefCell<Vec<(String, Float)>>>
+}
+
+impl ProbeRecord {
+    // `(label, value)` in the order they were computed
+    pub fn values(&self) -> Vec<(String, Float)> {
+        self.values.borrow().clone()
+    }
+    // Of one probe
+    pub fn values_of(&self, label: &str) -> Vec<Float> {
+        self.values.borrow().iter().filter(|(l, _)| l == label).map(|&(_, value)| value).collect()
+    }
+    pub fn clear(&self) {
+        self.values.borrow_mut().clear()
+    }
+}
+
+impl Drop for ProbeRecord {
+    fn drop(&mut self) {
+        remove_sink(self.id);
+    }
+}
+
+pub fn record() -> ProbeRecord {
+    let values = Rc::new(RefCell::new(Vec::new()));
+    let sink_values = values.clone();
+    let id = add_sink(move |label, value| sink_values.borrow_mut().push((label.to_string(), value)));
+    ProbeRecord { id, values }
+}
+
+pub fn remove_sink(id: SinkId) -> bool {
+    SINKS.with(|sinks| {
+        let mut sinks = sinks.borrow_mut();
+        let count = sinks.sinks.len();
+        sinks.sinks.retain(|(sink, _)| *sink != id);
+        sinks.sinks.len() < count
+    })
+}
+
+fn report(label: &str, value: Float) {
+    // taken out while they run, so that they can use the graph and the sinks
+    let mut sinks = SINKS.with(|sinks| std::mem::take(&mut sinks.borrow_mut().sinks));
+    sinks.retain_mut(|(_, sink)| match sink {
+        Sink::Callback(callback) => {
+            callback(label, value);
+            true
+        }
+        Sink::Channel(sender) => sender.send((label.to_string(), value)).is_ok()
+    });
+    SINKS.with(|state| {
+        let mut state = state.borrow_mut();
+        // with the ones added meanwhile after them
+        sinks.append(&mut state.sinks);
+        state.sinks = sinks;
+    });
+}
+
+struct Probe<X> {
+    label: String,
+    x: X
+}
+
+impl<X: ComputeNodeRef> ComputeMut for Probe<X> {
+    fn compute(&mut self) -> Float {
+        let value = self.x.compute();
+        report(&self.label, value);
+        value
+    }
+    fn name(&self) -> &'static str { "probe" }
+    fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(probe(self.label.clone(), children[0].clone()))
+    }
+}
+
+pub fn probe(label: impl Into<String>, x: impl IntoNodeRef) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(Probe { label: label.into(), x: x.into_node_ref() });
+    let subscriber = result.clone() as _;
+    result.borrow().inner.x.subscribe_to_invalidate(&subscriber);
+    result
+}
@@ -0,0 +1,82 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use crate::compgraph::*;
+use crate::graph::{self, NodeId};
+
+// Profiling of the time nodes take to compute, and a cache policy built on it
+//
+// An `AutoCache` evaluates its output a node at a time, children first, timing each node
+// that has to be recomputed: as its children are computed already, that is the time of the
+// node itself. After `warmup` evaluations it turns the cache off for the nodes that took
+// less than `threshold` on average, which are cheaper to recompute whenever their parents
+// need them than to cache, and keeps it for the others. From then on it evaluates the output
+// directly. Nodes that never had to be recomputed during the warm-up keep their caches
+
+pub struct AutoCache {
+    output: DynamicComputeNodeRef,
+    threshold: Duration,
+    warmup: usize,
+    evaluations: usize,
+    // the recomputations of each node and the time they took
+    timings: HashMap<*const (), (DynamicComputeNodeRef, u32, Duration)>,
+    uncached: Vec<NodeId>
+}
+
+impl AutoCache {
+    pub fn new(output: &DynamicComputeNodeRef, threshold: Duration, warmup: usize) -> AutoCache {
+        AutoCache {
+            output: output.clone(), threshold, warmup, evaluations: 0, timings: HashMap::new(), uncached: Vec::new()
+        }
+    }
+
+    pub fn evaluate(&mut self) -> Float {
+        if self.evaluations >= self.warmup {
+            return self.output.compute();
+        }
+        for node in topological_order(&self.output) {
+            let version = node.version();
+            let start = Instant::now();
+            node.compute();
+            let elapsed = start.elapsed();
+            if node.version() != version {
+                let (_, count, total) = self.timings.entry(node_address(&node)).or_insert_with(|| (node.clone(), 0, Duration::ZERO));
+                *count += 1;
+                *total += elapsed;
+            }
+        }
+        self.evaluations += 1;
+        if self.evaluations == self.warmup {
+            self.apply();
+        }
+        self.output.compute()
+    }
+
+    fn apply(&mut self) {
+        for (node, count, total) in self.timings.values() {
+            // the output stays cached, so that asking for its value twice doesn't recompute it
+            if *total / *count < self.threshold && node_address(node) != node_address(&self.output)
+                && node.borrow_mut().set_caching(false) {
+                self.uncached.push(graph::node_id(node));
+            }
+        }
+        self.uncached.sort();
+    }
+
+    // The mean compute time of each node recomputed so far in the warm-up, slowest first
+    pub fn profile(&self) -> Vec<(NodeId, Duration)> {
+        let mut profile: Vec<_> = self.timings.values()
+            .map(|(node, count, total)| (graph::node_id(node), *total / *count))
+            .collect();
+        profile.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        profile
+    }
+
+    // The nodes the cache was turned off for, once warmed up
+    pub fn uncached(&self) -> &[NodeId] {
+        &self.uncached
+    }
+
+    pub fn is_warmed_up(&self) -> bool {
+        self.evaluations >= self.warmup
+    }
+}
@@ -0,0 +1,139 @@
+// Minimal protocol buffers wire format encoding, for the formats built on it (ONNX)
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const FIXED32: u64 = 5;
+const LENGTH_DELIMITED: u64 = 2;
+
+#[derive(Default)]
+pub(crate) struct Writer {
+    bytes: Vec<u8>
+}
+
+impl Writer {
+    pub(crate) fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.varint(field << 3 | wire_type);
+    }
+
+    pub(crate) fn int(&mut self, field: u64, value: i64) {
+        self.key(field, VARINT);
+        self.varint(value as u64);
+    }
+
+    pub(crate) fn float(&mut self, field: u64, value: f32) {
+        self.key(field, FIXED32);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, field: u64, value: &[u8]) {
+        self.key(field, LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub(crate) fn string(&mut self, field: u64, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    pub(crate) fn message(&mut self, field: u64, build: impl FnOnce(&mut Writer)) {
+        let mut message = Writer::new();
+        build(&mut message);
+        self.bytes(field, &message.bytes);
+    }
+}
+
+pub(crate) enum Value<'a> {
+    Varint(u64),
+    // no format here uses 64-bit fixed fields, so they're only skipped
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32)
+}
+
+impl<'a> Value<'a> {
+    pub(crate) fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self { Value::Bytes(bytes) => Some(bytes), _ => None }
+    }
+    pub(crate) fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+}
+
+// Decoding, where `None` means malformed input
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn next_field(&mut self) -> Option<Option<(u64, Value<'a>)>> {
+        if self.bytes.is_empty() {
+            return Some(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            VARINT => Value::Varint(self.varint()?),
+            FIXED64 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            LENGTH_DELIMITED => {
+                let length = self.varint()? as usize;
+                Value::Bytes(self.take(length)?)
+            }
+            FIXED32 => Value::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().ok()?)),
+            _ => return None
+        };
+        Some(Some((key >> 3, value)))
+    }
+
+    // All fields, or `None` if the message is malformed
+    pub(crate) fn fields(mut self) -> Option<Vec<(u64, Value<'a>)>> {
+        let mut fields = Vec::new();
+        while let Some(field) = self.next_field()? {
+            fields.push(field);
+        }
+        Some(fields)
+    }
+}
@@ -0,0 +1,132 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::compgraph::*;
+use crate::strict;
+use crate::compgraph::internals::*;
+
+// Seeded random-value nodes for Monte-Carlo style graphs
+//
+// Every node created from a `RandomSource` draws from its own stream, derived from
+// the seed, the node's creation index and the number of `resample()` calls so far.
+// Values are therefore reproducible across runs and don't depend on evaluation order.
+// A sample stays fixed (and cached) until `resample()` invalidates all of the source's nodes
+
+// SplitMix64, small and good enough for simulation purposes (not cryptography)
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Standard normal, by the Box-Muller transform; `1 - u` keeps the logarithm's argument
+    // in (0, 1]
+    pub(crate) fn next_normal(&mut self) -> f64 {
+        let radius = (-2.0 * strict::ln_f64(1.0 - self.next_f64())).sqrt();
+        let angle = std::f64::consts::TAU * self.next_f64();
+        radius * strict::sin_cos_f64(angle).1
+    }
+}
+
+struct SourceState {
+    seed: u64,
+    epoch: u64,
+    next_index: u64,
+    invalidate_publisher: InvalidatePublisher
+}
+
+#[derive(Clone)]
+pub struct RandomSource {
+    state: Rc<RefCell<SourceState>>
+}
+
+struct RandomNode<A, B> {
+    a: A,
+    b: B,
+    source: Rc<RefCell<SourceState>>,
+    index: u64,
+    sample: fn(&mut SplitMix64, f64, f64) -> f64
+}
+
+impl<A: ComputeNodeRef, B: ComputeNodeRef> ComputeMut for RandomNode<A, B> {
+    fn compute(&mut self) -> Float {
+        let (seed, epoch) = {
+            let source = self.source.borrow();
+            (source.seed, source.epoch)
+        };
+        let mut rng = SplitMix64::new(seed);
+        rng = SplitMix64::new(rng.next_u64() ^ self.index);
+        rng = SplitMix64::new(rng.next_u64() ^ epoch);
+        (self.sample)(&mut rng, self.a.compute() as f64, self.b.compute() as f64) as Float
+    }
+}
+
+impl RandomSource {
+    pub fn new(seed: u64) -> RandomSource {
+        RandomSource { state: Rc::new(RefCell::new(SourceState {
+            seed, epoch: 0, next_index: 0, invalidate_publisher: InvalidatePublisher::new()
+        })) }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.state.borrow().seed
+    }
+
+    // Draws new values for all nodes of this source
+    pub fn resample(&self) {
+        let mut state = self.state.borrow_mut();
+        state.epoch += 1;
+        state.invalidate_publisher.publish_invalidate();
+    }
+
+    // Restarts the sequence of samples, as if the source had just been created with `seed`
+    pub fn reseed(&self, seed: u64) {
+        let mut state = self.state.borrow_mut();
+        state.seed = seed;
+        state.epoch = 0;
+        state.invalidate_publisher.publish_invalidate();
+    }
+
+    fn node(
+        &self, a: impl ComputeNodeRef + 'static, b: impl ComputeNodeRef + 'static, sample: fn(&mut SplitMix64, f64, f64) -> f64
+    ) -> DynamicComputeNodeRef {
+        let index = {
+            let mut state = self.state.borrow_mut();
+            state.next_index += 1;
+            state.next_index
+        };
+        let result = CachingNodeWrapper::new_ref(RandomNode {
+            a, b, source: self.state.clone(), index, sample
+        });
+        let subscriber = result.clone() as _;
+        {
+            let inner = &result.borrow().inner;
+            inner.a.subscribe_to_invalidate(&subscriber);
+            inner.b.subscribe_to_invalidate(&subscriber);
+        }
+        self.state.borrow_mut().invalidate_publisher.subscribe_to_invalidate(&subscriber);
+        result
+    }
+
+    // Uniform in [low, high)
+    pub fn uniform(&self, low: impl ComputeNodeRef + 'static, high: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+        self.node(low, high, |rng, low, high| low + (high - low) * rng.next_f64())
+    }
+
+    pub fn normal(&self, mean: impl ComputeNodeRef + 'static, std_dev: impl ComputeNodeRef + 'static) -> DynamicComputeNodeRef {
+        self.node(mean, std_dev, |rng, mean, std_dev| mean + std_dev * rng.next_normal())
+    }
+}
@@ -0,0 +1,161 @@
+use std::{rc::Rc, cell::RefCell, fmt, error::Error, time::{Duration, Instant}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Recording and replaying input changes, for deterministic regression tests of applications
+//
+// A recorder hands out named wrappers of inputs which log every `set()` before passing it on.
+// The log can be written out as text, one event per line, and read back; `replay` sets the
+// inputs of another graph (usually a fresh one built the same way) in the logged order, by
+// name. Timestamps are the time since the recorder was created, for inspecting a log; replay
+// itself doesn't wait between events
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub time: Duration,
+    pub input: String,
+    pub value: Float
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Log {
+    pub events: Vec<Event>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayError {
+    // `line` counts from 1
+    Parse { line: usize, message: String },
+    UnknownInput(String)
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Parse { line, message } => write!(f, "{} on line {}", message, line),
+            ReplayError::UnknownInput(name) => write!(f, "no input bound to `{}`", name)
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+// Lines of `<seconds> <input> <value>`, with values written exactly so that they read back
+// the same bits
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{:.9} {} {:?}", event.time.as_secs_f64(), event.input, event.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Log {
+    pub fn parse(text: &str) -> Result<Log, ReplayError> {
+        let events = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(index, line)| {
+            let error = |message: &str| ReplayError::Parse { line: index + 1, message: message.to_string() };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [time, input, value] = fields[..] else {
+                return Err(error("expected time, input and value"));
+            };
+            let time = time.parse::<f64>().ok().and_then(|time| Duration::try_from_secs_f64(time).ok())
+                .ok_or_else(|| error("invalid time"))?;
+            let value = value.parse().map_err(|_| error("invalid value"))?;
+            Ok(Event { time, input: input.to_string(), value })
+        }).collect::<Result<_, _>>()?;
+        Ok(Log { events })
+    }
+
+    // Fails before setting anything if an event's input has no binding
+    pub fn replay<P: InputNodeRef>(&self, bindings: &[(&str, P)]) -> Result<(), ReplayError> {
+        self.replay_with(bindings, |_| {})
+    }
+
+    // Calls `observe` after each event, e.g. to check the outputs at every step
+    pub fn replay_with<P: InputNodeRef>(&self, bindings: &[(&str, P)], mut observe: impl FnMut(&Event)) -> Result<(), ReplayError> {
+        let inputs = self.events.iter().map(|event| {
+            bindings.iter().find(|(name, _)| *name == event.input).map(|(_, input)| input)
+                .ok_or_else(|| ReplayError::UnknownInput(event.input.clone()))
+        }).collect::<Result<Vec<_>, _>>()?;
+        for (event, input) in self.events.iter().zip(inputs) {
+            input.set(event.value);
+            observe(event);
+        }
+        Ok(())
+    }
+}
+
+pub struct Recorder {
+    start: Instant,
+    log: Rc<RefCell<Log>>
+}
+
+impl Default for Recorder {
+    fn default() -> Recorder {
+        Recorder::new()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder { start: Instant::now(), log: Rc::new(RefCell::new(Log::default())) }
+    }
+
+    // The wrapper is used in place of `input`, both for building the graph and setting it
+    pub fn record<P: InputNodeRef + 'static>(&self, name: &str, input: P) -> RecordedInput<P> {
+        assert!(!name.is_empty() && !name.contains(char::is_whitespace), "record: invalid input name `{}`", name);
+        RecordedInput { input, name: name.into(), start: self.start, log: self.log.clone() }
+    }
+
+    // The events so far
+    pub fn log(&self) -> Log {
+        self.log.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.log.borrow_mut().events.clear()
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordedInput<P> {
+    input: P,
+    name: Rc<str>,
+    start: Instant,
+    log: Rc<RefCell<Log>>
+}
+
+impl<P: InputNodeRef> ComputeNodeRef for RecordedInput<P> {
+    fn compute(&self) -> Float {
+        self.input.compute()
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.input.subscribe_to_invalidate(subscriber)
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef> {
+        self.input.as_dynamic()
+    }
+    fn version(&self) -> u64 {
+        self.input.version()
+    }
+}
+
+impl<P: InputNodeRef> RecordedInput<P> {
+    fn log(&self, value: Float) {
+        let event = Event { time: self.start.elapsed(), input: self.name.to_string(), value };
+        self.log.borrow_mut().events.push(event);
+    }
+}
+
+impl<P: InputNodeRef> InputNodeRef for RecordedInput<P> {
+    fn set(&self, value: Float) {
+        self.log(value);
+        self.input.set(value)
+    }
+    fn assign(&self, value: Float) where Self: 'static {
+        self.log(value);
+        self.input.assign(value)
+    }
+}
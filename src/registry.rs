@@ -0,0 +1,84 @@
+use std::{rc::Rc, collections::HashMap, fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::{ops, nn};
+
+// Node constructors looked up by name, for building graphs from data (model files,
+// formulas) rather than from code. `NodeRegistry::builtin()` has the nodes of `ops` and
+// `nn`; custom nodes are added with `register`
+
+pub type NodeConstructor = Rc<dyn Fn(&[Operand]) -> DynamicComputeNodeRef>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    UnknownNode(String),
+    WrongArity { node: String, expected: usize, found: usize }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::UnknownNode(node) => write!(f, "unknown node `{}`", node),
+            RegistryError::WrongArity { node, expected, found } => {
+                write!(f, "node `{}` takes {} children, found {}", node, expected, found)
+            }
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+#[derive(Clone, Default)]
+pub struct NodeRegistry {
+    constructors: HashMap<String, (usize, NodeConstructor)>
+}
+
+impl NodeRegistry {
+    pub fn new() -> NodeRegistry {
+        NodeRegistry::default()
+    }
+
+    pub fn builtin() -> NodeRegistry {
+        let mut registry = NodeRegistry::new();
+        registry.register("add", 2, |c| ops::add(c[0].clone(), c[1].clone()));
+        registry.register("sub", 2, |c| ops::sub(c[0].clone(), c[1].clone()));
+        registry.register("mul", 2, |c| ops::mul(c[0].clone(), c[1].clone()));
+        registry.register("div", 2, |c| ops::div(c[0].clone(), c[1].clone()));
+        registry.register("pow", 2, |c| ops::pow(c[0].clone(), c[1].clone()));
+        registry.register("min", 2, |c| ops::min(c[0].clone(), c[1].clone()));
+        registry.register("max", 2, |c| ops::max(c[0].clone(), c[1].clone()));
+        registry.register("neg", 1, |c| ops::neg(c[0].clone()));
+        registry.register("abs", 1, |c| ops::abs(c[0].clone()));
+        registry.register("sqrt", 1, |c| ops::sqrt(c[0].clone()));
+        registry.register("exp", 1, |c| ops::exp(c[0].clone()));
+        registry.register("ln", 1, |c| ops::ln(c[0].clone()));
+        registry.register("sin", 1, |c| ops::sin(c[0].clone()));
+        registry.register("cos", 1, |c| ops::cos(c[0].clone()));
+        registry.register("tan", 1, |c| ops::tan(c[0].clone()));
+        registry.register("relu", 1, |c| nn::relu(c[0].clone()));
+        registry.register("sigmoid", 1, |c| nn::sigmoid(c[0].clone()));
+        registry.register("tanh", 1, |c| nn::tanh(c[0].clone()));
+        registry
+    }
+
+    // Replaces any constructor registered under the same name
+    pub fn register(&mut self, name: &str, arity: usize, constructor: impl Fn(&[Operand]) -> DynamicComputeNodeRef + 'static) {
+        self.constructors.insert(name.to_string(), (arity, Rc::new(constructor)));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        self.constructors.get(name).map(|&(arity, _)| arity)
+    }
+
+    pub fn create(&self, name: &str, children: &[Operand]) -> Result<DynamicComputeNodeRef, RegistryError> {
+        let (arity, constructor) = self.constructors.get(name).ok_or_else(|| RegistryError::UnknownNode(name.to_string()))?;
+        if *arity != children.len() {
+            return Err(RegistryError::WrongArity { node: name.to_string(), expected: *arity, found: children.len() });
+        }
+        Ok(constructor(children))
+    }
+}
@@ -0,0 +1,263 @@
+use std::{rc::Rc, cell::RefCell, collections::{BTreeMap, BTreeSet, HashMap}, fmt, error::Error, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::expr::{self, Expr, ExprError};
+use crate::registry::NodeRegistry;
+
+// Graphs defined in files, rebuilt when the file changes
+//
+// A description has a definition per line, `name = formula` with the formulas of `expr`,
+// and `#` comments. Definitions can use each other in any order; the names a description
+// uses without defining them are its inputs. Each definition has a node that stays the same
+// across reloads, computing whatever the definition's formula is at the time, so handles
+// taken with `output` keep working, and each input is kept across reloads while the
+// description uses it, with its value. A description is checked as a whole before anything
+// changes: a reload that fails (a bad formula, definitions depending on themselves, an output
+// handed out that is no longer defined) leaves the graph as it was
+//
+// `poll` reloads the file when its modification time or length changed since the last load,
+// for calling from the program's loop
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReloadError {
+    Io { path: String, message: String },
+    // `line` counts from 1
+    Syntax { line: usize, message: String },
+    Formula { line: usize, error: ExprError },
+    Cycle { name: String },
+    RemovedOutput(String)
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReloadError::Io { path, message } => write!(f, "can't read `{}`: {}", path, message),
+            ReloadError::Syntax { line, message } => write!(f, "{} on line {}", message, line),
+            ReloadError::Formula { line, error } => write!(f, "{} on line {}", error, line),
+            ReloadError::Cycle { name } => write!(f, "`{}` would depend on itself", name),
+            ReloadError::RemovedOutput(name) => write!(f, "output `{}` is no longer defined", name)
+        }
+    }
+}
+
+impl Error for ReloadError {}
+
+struct Definition {
+    formula: Operand
+}
+
+impl ComputeMut for Definition {
+    fn compute(&mut self) -> Float {
+        self.formula.compute()
+    }
+    fn name(&self) -> &'static str { "definition" }
+    fn children(&self) -> Vec<Child> { vec![self.formula.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+}
+
+type DefinitionRef = Rc<RefCell<CachingNodeWrapper<Definition>>>;
+
+pub struct LiveGraph {
+    registry: NodeRegistry,
+    definitions: BTreeMap<String, (String, DefinitionRef)>,
+    inputs: BTreeMap<String, Input>,
+    // the outputs handed out, which reloads must keep
+    handed_out: BTreeSet<String>,
+    // the file and what it was like when last loaded
+    file: Option<(PathBuf, Option<(SystemTime, u64)>)>,
+    reloads: usize
+}
+
+impl Default for LiveGraph {
+    fn default() -> LiveGraph {
+        LiveGraph::new()
+    }
+}
+
+impl LiveGraph {
+    // Formulas can call the builtin nodes
+    pub fn new() -> LiveGraph {
+        LiveGraph::with_registry(NodeRegistry::builtin())
+    }
+
+    pub fn with_registry(registry: NodeRegistry) -> LiveGraph {
+        LiveGraph {
+            registry, definitions: BTreeMap::new(), inputs: BTreeMap::new(), handed_out: BTreeSet::new(), file: None, reloads: 0
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<LiveGraph, ReloadError> {
+        LiveGraph::load_with_registry(path, NodeRegistry::builtin())
+    }
+
+    pub fn load_with_registry(path: impl AsRef<Path>, registry: NodeRegistry) -> Result<LiveGraph, ReloadError> {
+        let mut graph = LiveGraph::with_registry(registry);
+        graph.file = Some((path.as_ref().to_path_buf(), None));
+        graph.poll()?;
+        Ok(graph)
+    }
+
+    // Reloads the file if it changed, `true` if it did. A failed reload isn't retried until
+    // the file changes again
+    pub fn poll(&mut self) -> Result<bool, ReloadError> {
+        let Some((path, seen)) = &mut self.file else { return Ok(false) };
+        let io_error = |error: std::io::Error| ReloadError::Io { path: path.display().to_string(), message: error.to_string() };
+        let metadata = std::fs::metadata(&*path).map_err(io_error)?;
+        let stamp = (metadata.modified().map_err(io_error)?, metadata.len());
+        if *seen == Some(stamp) {
+            return Ok(false);
+        }
+        *seen = Some(stamp);
+        let text = std::fs::read_to_string(&*path).map_err(io_error)?;
+        self.update(&text)?;
+        Ok(true)
+    }
+
+    // Replaces the description with `text`
+    pub fn update(&mut self, text: &str) -> Result<(), ReloadError> {
+        let mut parsed: Vec<(String, String, Expr, usize)> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let syntax = |message: String| ReloadError::Syntax { line: line_number, message };
+            let (name, formula) = line.split_once('=').ok_or_else(|| syntax("expected `name = formula`".to_string()))?;
+            let (name, formula) = (name.trim(), formula.trim());
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(syntax(format!("invalid name `{}`", name)));
+            }
+            if parsed.iter().any(|(defined, ..)| defined == name) {
+                return Err(syntax(format!("`{}` is defined twice", name)));
+            }
+            let expr = expr::parse(formula).map_err(|error| ReloadError::Formula { line: line_number, error })?;
+            parsed.push((name.to_string(), formula.to_string(), expr, line_number));
+        }
+        let defined: HashMap<&str, &Expr> = parsed.iter().map(|(name, _, expr, _)| (name.as_str(), expr)).collect();
+        if let Some(name) = self.handed_out.iter().find(|name| !defined.contains_key(name.as_str())) {
+            return Err(ReloadError::RemovedOutput(name.clone()));
+        }
+        if let Some(name) = cycle(&defined) {
+            return Err(ReloadError::Cycle { name: name.to_string() });
+        }
+
+        // built against the nodes of the definitions, so that nothing changes until they
+        // are all rewired
+        let mut definitions = BTreeMap::new();
+        for (name, ..) in &parsed {
+            let node = self.definitions.get(name).map(|(_, node)| node.clone())
+                .unwrap_or_else(|| CachingNodeWrapper::new_ref(Definition { formula: Operand::Constant(0.0) }));
+            definitions.insert(name.clone(), node);
+        }
+        let mut inputs = BTreeMap::new();
+        let mut formulas = Vec::new();
+        for (name, _, expr, line) in &parsed {
+            let formula = expr.build(&self.registry, &mut |variable| {
+                if let Some(node) = definitions.get(variable) {
+                    return Some(Operand::Node(node.clone()));
+                }
+                let input = inputs.entry(variable.to_string())
+                    .or_insert_with(|| self.inputs.get(variable).cloned().unwrap_or_else(create_input));
+                Some(Operand::Node(input.clone()))
+            }).map_err(|error| ReloadError::Formula { line: *line, error })?;
+            formulas.push((name, formula));
+        }
+
+        for (name, formula) in formulas {
+            let node = &definitions[name];
+            formula.subscribe_to_invalidate(&(node.clone() as _));
+            let mut node = node.borrow_mut();
+            node.inner.formula = formula;
+            node.invalidate_cache();
+        }
+        self.definitions = parsed.into_iter()
+            .map(|(name, formula, ..)| {
+                let node = definitions.remove(&name).unwrap();
+                (name, (formula, node))
+            })
+            .collect();
+        self.inputs = inputs;
+        self.reloads += 1;
+        run_deferred();
+        Ok(())
+    }
+
+    // The node of a definition, which reloads keep
+    pub fn output(&mut self, name: &str) -> Option<DynamicComputeNodeRef> {
+        let (_, node) = self.definitions.get(name)?;
+        self.handed_out.insert(name.to_string());
+        Some(node.clone() as DynamicComputeNodeRef)
+    }
+
+    // Same, for looking at the graph; the definition can be removed by later reloads
+    pub fn node(&self, name: &str) -> Option<DynamicComputeNodeRef> {
+        self.definitions.get(name).map(|(_, node)| node.clone() as DynamicComputeNodeRef)
+    }
+
+    pub fn input(&self, name: &str) -> Option<Input> {
+        self.inputs.get(name).cloned()
+    }
+
+    pub fn value(&self, name: &str) -> Option<Float> {
+        self.definitions.get(name).map(|(_, node)| node.compute())
+    }
+
+    // Computes a formula over the definitions and inputs, without adding it to the graph
+    pub fn evaluate(&self, formula: &str) -> Result<Float, ExprError> {
+        let expr = expr::parse(formula)?;
+        let value = expr.build(&self.registry, &mut |variable| {
+            match self.definitions.get(variable) {
+                Some((_, node)) => Some(Operand::Node(node.clone())),
+                None => self.inputs.get(variable).map(|input| Operand::Node(input.clone()))
+            }
+        })?;
+        Ok(value.compute())
+    }
+
+    // The formula of a definition, as written
+    pub fn formula(&self, name: &str) -> Option<&str> {
+        self.definitions.get(name).map(|(formula, _)| formula.as_str())
+    }
+
+    // By name, as are `inputs`
+    pub fn definitions(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = &str> {
+        self.inputs.keys().map(String::as_str)
+    }
+
+    // The descriptions loaded so far, the first one included
+    pub fn reloads(&self) -> usize {
+        self.reloads
+    }
+}
+
+// A definition depending on itself through the definitions, if any
+fn cycle<'a>(definitions: &HashMap<&'a str, &Expr>) -> Option<&'a str> {
+    // 1 while being visited, 2 once done
+    fn visit<'a>(name: &'a str, definitions: &HashMap<&'a str, &Expr>, state: &mut HashMap<&'a str, u8>) -> Option<&'a str> {
+        match state.get(name) {
+            Some(1) => return Some(name),
+            Some(_) => return None,
+            None => {}
+        }
+        state.insert(name, 1);
+        for variable in definitions[name].variables() {
+            if let Some((&used, _)) = definitions.get_key_value(variable) {
+                if let Some(name) = visit(used, definitions, state) {
+                    return Some(name);
+                }
+            }
+        }
+        state.insert(name, 2);
+        None
+    }
+    let mut state = HashMap::new();
+    let mut names: Vec<&str> = definitions.keys().copied().collect();
+    names.sort();
+    names.into_iter().find_map(|name| visit(name, definitions, &mut state))
+}
@@ -0,0 +1,107 @@
+use std::{sync::mpsc::{self, Receiver, Sender}, thread, time::{Duration, Instant}};
+
+use crate::compgraph::*;
+use crate::time::Clock;
+use crate::binding::{Bindings, BindingId};
+use crate::atomic::{AtomicInputs, AtomicWriter};
+
+// A main loop for interactive graphs
+//
+// Each frame of a scheduler advances its clock by one period, sets the inputs fed by
+// channels to the last value received on them, then recomputes the outputs that became
+// dirty and calls the listeners of those that changed (see `Bindings`). `frame()` runs a
+// single frame, for applications that have their own loop; `run` runs frames at the tick
+// rate on the current thread. Channels and atomic inputs (see `atomic`) are the ways for
+// other threads to feed the graph, which itself stays on the scheduler's thread
+
+struct Channel {
+    receiver: Receiver<Float>,
+    set: Box<dyn Fn(Float)>
+}
+
+pub struct Scheduler {
+    clock: Clock,
+    period: Float,
+    channels: Vec<Channel>,
+    atomic: AtomicInputs,
+    bindings: Bindings
+}
+
+impl Scheduler {
+    // `rate` in frames per second
+    pub fn new(rate: Float) -> Scheduler {
+        Scheduler::with_clock(Clock::new(), rate)
+    }
+
+    pub fn with_clock(clock: Clock, rate: Float) -> Scheduler {
+        assert!(rate > 0.0, "scheduler: tick rate must be positive");
+        Scheduler { clock, period: 1.0 / rate, channels: Vec::new(), atomic: AtomicInputs::new(), bindings: Bindings::new() }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn period(&self) -> Float {
+        self.period
+    }
+
+    // Feeds `input` from a channel, returning its sending end. Values sent between two frames
+    // are coalesced, the input only takes the last one
+    pub fn channel(&mut self, input: impl InputNodeRef + 'static) -> Sender<Float> {
+        let (sender, receiver) = mpsc::channel();
+        self.channels.push(Channel { receiver, set: Box::new(move |value| input.set(value)) });
+        sender
+    }
+
+    // Feeds `input` from an atomic cell, for writers that mustn't block, synced each frame
+    pub fn atomic(&mut self, input: impl InputNodeRef + 'static) -> AtomicWriter {
+        self.atomic.add(input)
+    }
+
+    // Like `Bindings::bind`, `callback` is called with the current value right away
+    pub fn listen(&mut self, output: impl ComputeNodeRef + 'static, callback: impl FnMut(Float) + 'static) -> BindingId {
+        self.bindings.bind(output, callback)
+    }
+
+    // Higher priorities are called first within a frame
+    pub fn listen_with_priority(
+        &mut self, output: impl ComputeNodeRef + 'static, priority: i32, callback: impl FnMut(Float) + 'static
+    ) -> BindingId {
+        self.bindings.bind_with_priority(output, priority, callback)
+    }
+
+    pub fn unlisten(&mut self, id: BindingId) -> bool {
+        self.bindings.unbind(id)
+    }
+
+    // Runs one frame and returns how many listeners were called
+    pub fn frame(&mut self) -> usize {
+        self.clock.tick(self.period);
+        for channel in &self.channels {
+            if let Some(value) = channel.receiver.try_iter().last() {
+                (channel.set)(value);
+            }
+        }
+        self.atomic.sync();
+        self.bindings.update()
+    }
+
+    // Runs frames until `keep_running` returns false, called before each one. Frames are
+    // scheduled at fixed times, so a slow frame is made up for by shorter waits after it
+    pub fn run(&mut self, mut keep_running: impl FnMut(&Scheduler) -> bool) {
+        let period = Duration::from_secs_f64(self.period as f64);
+        let mut next = Instant::now();
+        while keep_running(self) {
+            self.frame();
+            next += period;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                // too far behind to catch up without a burst of frames
+                next = now;
+            }
+        }
+    }
+}
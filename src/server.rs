@@ -0,0 +1,290 @@
+use std::{io::{self, Read, Write}, net::{TcpListener, TcpStream, ToSocketAddrs, SocketAddr}, time::{Duration, Instant}};
+
+use crate::compgraph::*;
+use crate::config::Config;
+
+// A graph served over HTTP, for dashboards and other programs to use as a calculation service
+//
+// The server holds named inputs and outputs, and answers with JSON objects of numbers:
+//     GET  /inputs          the inputs' values
+//     GET  /outputs         the outputs' values
+//     GET  /outputs/NAME    one output's value
+//     POST /inputs          sets the inputs of the JSON object in the body, all at once (see
+//                           `config`), answering with the outputs' values
+//     GET  /events          server-sent events, `event: NAME` and `data: VALUE` for each
+//                           output when subscribing and whenever its value changes
+// The graph isn't `Send`, so the server runs on its thread: `poll` answers the requests
+// waiting and sends the events due without blocking, for calling from the program's loop,
+// and `run` keeps polling. Sockets are only read and written as far as they are ready, a
+// request and its answer possibly over several polls. Each request gets its own connection,
+// closed after the answer; NaN and infinities are `null`
+//
+// Clients can't hold up the graph's thread: requests with headers over `MAX_HEADER` bytes or
+// bodies over `MAX_BODY` are answered 413, connections still unanswered or unwritten after
+// `TIMEOUT` are dropped, as are event streams more than `MAX_BACKLOG` bytes behind
+
+const MAX_HEADER: usize = 8 * 1024;
+const MAX_BODY: usize = 64 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BACKLOG: usize = 64 * 1024;
+
+struct Connection {
+    stream: TcpStream,
+    // the request read so far, then the answer not written yet
+    buffer: Vec<u8>,
+    answered: bool,
+    since: Instant
+}
+
+struct EventStream {
+    stream: TcpStream,
+    // written as the client reads
+    backlog: Vec<u8>,
+    // the values last sent
+    sent: Vec<Option<Float>>
+}
+
+enum Request {
+    Incomplete,
+    Complete { method: String, path: String, body: Vec<u8> },
+    Refused(&'static str, &'static str)
+}
+
+pub struct GraphServer {
+    listener: TcpListener,
+    inputs: Vec<(String, Input)>,
+    outputs: Vec<(String, Operand)>,
+    connections: Vec<Connection>,
+    streams: Vec<EventStream>
+}
+
+impl GraphServer {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<GraphServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(GraphServer { listener, inputs: Vec::new(), outputs: Vec::new(), connections: Vec::new(), streams: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn add_input(&mut self, name: &str, input: Input) {
+        self.inputs.push((name.to_string(), input));
+    }
+
+    pub fn add_output(&mut self, name: &str, output: impl ComputeNodeRef + 'static) {
+        let output = output.as_dynamic().map_or_else(|| Operand::Constant(output.compute()), Operand::Node);
+        self.outputs.push((name.to_string(), output));
+    }
+
+    // Answers the requests read in full and sends the events of changed outputs, returning the
+    // number of requests answered
+    pub fn poll(&mut self) -> io::Result<usize> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    // a client failing mid-request only loses its own answer
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.connections.push(Connection { stream, buffer: Vec::new(), answered: false, since: Instant::now() });
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error)
+            }
+        }
+        let mut answered = 0;
+        for mut connection in std::mem::take(&mut self.connections) {
+            if connection.since.elapsed() > TIMEOUT {
+                continue;
+            }
+            if !connection.answered {
+                let Ok(closed) = read_available(&mut connection.stream, &mut connection.buffer) else { continue };
+                let (status, json) = match parse(&connection.buffer) {
+                    Request::Incomplete if closed => continue,
+                    Request::Incomplete => {
+                        self.connections.push(connection);
+                        continue;
+                    }
+                    Request::Refused(status, message) => (status, error(message)),
+                    Request::Complete { method, path, body } => {
+                        if (method.as_str(), path.as_str()) == ("GET", "/events") {
+                            self.subscribe(connection.stream);
+                            answered += 1;
+                            continue;
+                        }
+                        self.route(&method, &path, &body)
+                    }
+                };
+                connection.buffer = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status, json.len(), json
+                ).into_bytes();
+                connection.answered = true;
+                answered += 1;
+            }
+            // closed once written
+            if write_available(&mut connection.stream, &mut connection.buffer).is_ok() && !connection.buffer.is_empty() {
+                self.connections.push(connection);
+            }
+        }
+        self.send_events();
+        Ok(answered)
+    }
+
+    // Polls every `interval`, until polling fails
+    pub fn run(&mut self, interval: Duration) -> io::Result<()> {
+        loop {
+            self.poll()?;
+            std::thread::sleep(interval);
+        }
+    }
+
+    fn route(&mut self, method: &str, path: &str, body: &[u8]) -> (&'static str, String) {
+        match (method, path) {
+            ("GET", "/inputs") => ("200 OK", self.input_values()),
+            ("GET", "/outputs") => ("200 OK", self.output_values()),
+            ("GET", path) if path.starts_with("/outputs/") => {
+                match self.outputs.iter().find(|(name, _)| *name == path["/outputs/".len()..]) {
+                    Some((_, output)) => ("200 OK", number(output.compute())),
+                    None => ("404 Not Found", error("no such output"))
+                }
+            }
+            ("POST" | "PUT", "/inputs") => match self.set_inputs(body) {
+                Ok(()) => ("200 OK", self.output_values()),
+                Err(message) => ("400 Bad Request", error(&message))
+            },
+            (_, "/inputs" | "/outputs" | "/events") => ("405 Method Not Allowed", error("method not allowed")),
+            _ => ("404 Not Found", error("not found"))
+        }
+    }
+
+    fn subscribe(&mut self, stream: TcpStream) {
+        let backlog = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n".to_vec();
+        self.streams.push(EventStream { stream, backlog, sent: vec![None; self.outputs.len()] });
+    }
+
+    fn send_events(&mut self) {
+        if self.streams.is_empty() {
+            return;
+        }
+        let values: Vec<Float> = self.outputs.iter().map(|(_, output)| output.compute()).collect();
+        let outputs = &self.outputs;
+        self.streams.retain_mut(|EventStream { stream, backlog, sent }| {
+            // outputs added since the stream subscribed
+            sent.resize(outputs.len(), None);
+            for (((name, _), value), sent) in outputs.iter().zip(&values).zip(sent.iter_mut()) {
+                if sent.map(Float::to_bits) != Some(value.to_bits()) {
+                    *sent = Some(*value);
+                    backlog.extend(format!("event: {}\ndata: {}\n\n", name, number(*value)).into_bytes());
+                }
+            }
+            // dropped once the client is gone, or reads too slowly
+            write_available(stream, backlog).is_ok() && backlog.len() <= MAX_BACKLOG
+        });
+    }
+    fn set_inputs(&self, body: &[u8]) -> Result<(), String> {
+        let text = std::str::from_utf8(body).map_err(|_| "the body isn't UTF-8".to_string())?;
+        let config = Config::from_json(text).map_err(|error| error.to_string())?;
+        let bindings: Vec<(&str, Input)> = self.inputs.iter().map(|(name, input)| (name.as_str(), input.clone())).collect();
+        if let Some(unknown) = config.keys().find(|key| !bindings.iter().any(|(name, _)| name == key)) {
+            return Err(format!("no input named `{}`", unknown));
+        }
+        config.apply(&bindings);
+        Ok(())
+    }
+
+    fn input_values(&self) -> String {
+        object(self.inputs.iter().map(|(name, input)| (name.as_str(), input.compute())))
+    }
+
+    fn output_values(&self) -> String {
+        object(self.outputs.iter().map(|(name, output)| (name.as_str(), output.compute())))
+    }
+}
+
+// Reads what has arrived, up to a request's size, returning whether the client closed
+fn read_available(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> io::Result<bool> {
+    let mut chunk = [0; 4096];
+    while buffer.len() <= MAX_HEADER + MAX_BODY {
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(true),
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error)
+        }
+    }
+    Ok(false)
+}
+
+// Writes as much as the client takes, removing it from `buffer`
+fn write_available(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> io::Result<()> {
+    while !buffer.is_empty() {
+        match stream.write(buffer) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buffer.drain(..n);
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error)
+        }
+    }
+    Ok(())
+}
+
+fn parse(buffer: &[u8]) -> Request {
+    let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return if buffer.len() > MAX_HEADER { Request::Refused("413 Content Too Large", "headers too large") } else { Request::Incomplete };
+    };
+    if end > MAX_HEADER {
+        return Request::Refused("413 Content Too Large", "headers too large");
+    }
+    let head = String::from_utf8_lossy(&buffer[..end]);
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or("").split_whitespace();
+    let (method, path) = (parts.next().unwrap_or("").to_string(), parts.next().unwrap_or("").to_string());
+    let mut length = 0;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        if name.eq_ignore_ascii_case("content-length") {
+            match value.trim().parse() {
+                Ok(value) => length = value,
+                Err(_) => return Request::Refused("400 Bad Request", "invalid length")
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Request::Refused("413 Content Too Large", "body too large");
+    }
+    match buffer.get(end + 4..end + 4 + length) {
+        Some(body) => Request::Complete { method, path, body: body.to_vec() },
+        None => Request::Incomplete
+    }
+}
+
+fn number(value: Float) -> String {
+    if value.is_finite() { value.to_string() } else { "null".to_string() }
+}
+
+fn string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c)
+        }
+    }
+    quoted + "\""
+}
+
+fn object<'a>(values: impl Iterator<Item = (&'a str, Float)>) -> String {
+    let members: Vec<String> = values.map(|(name, value)| format!("{}:{}", string(name), number(value))).collect();
+    format!("{{{}}}", members.join(","))
+}
+
+fn error(message: &str) -> String {
+    format!("{{\"error\":{}}}", string(message))
+}
@@ -0,0 +1,135 @@
+use std::{rc::Rc, cell::RefCell, collections::HashMap, fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::expr::{self, ExprError};
+use crate::registry::NodeRegistry;
+use crate::limits::Limits;
+
+// Spreadsheet-style cells: named values defined by formulas over other cells
+//
+// Each cell is a node that formulas referring to it depend on, so editing a cell recomputes
+// exactly the cells that use it, and only once their values are asked for. Setting a formula
+// rewires the cell to the graph built from it; cells a formula refers to are created empty
+// (with the value 0) if they don't exist yet. A formula that would make a cell depend on
+// itself is rejected and the cell keeps its previous formula, as is one exceeding the sheet's
+// limits, which apply to the whole graph of the cell, through the cells it refers to
+
+struct CellNode {
+    formula: Operand
+}
+
+impl ComputeMut for CellNode {
+    fn compute(&mut self) -> Float {
+        self.formula.compute()
+    }
+    fn name(&self) -> &'static str { "cell" }
+    fn children(&self) -> Vec<Child> { vec![self.formula.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+}
+
+struct Cell {
+    formula: String,
+    node: Rc<RefCell<CachingNodeWrapper<CellNode>>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SheetError {
+    Formula(ExprError),
+    Cycle { cell: String }
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetError::Formula(error) => write!(f, "{}", error),
+            SheetError::Cycle { cell } => write!(f, "cell `{}` would depend on itself", cell)
+        }
+    }
+}
+
+impl Error for SheetError {}
+
+impl From<ExprError> for SheetError {
+    fn from(error: ExprError) -> SheetError {
+        SheetError::Formula(error)
+    }
+}
+
+pub struct Sheet {
+    cells: HashMap<String, Cell>,
+    registry: NodeRegistry,
+    limits: Limits
+}
+
+impl Default for Sheet {
+    fn default() -> Sheet {
+        Sheet::new()
+    }
+}
+
+impl Sheet {
+    // Formulas can call the builtin nodes
+    pub fn new() -> Sheet {
+        Sheet::with_registry(NodeRegistry::builtin())
+    }
+
+    pub fn with_registry(registry: NodeRegistry) -> Sheet {
+        Sheet { cells: HashMap::new(), registry, limits: Limits::new() }
+    }
+
+    // For formulas set from then on
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    fn cell(&mut self, name: &str) -> &Cell {
+        self.cells.entry(name.to_string()).or_insert_with(|| Cell {
+            formula: String::new(),
+            node: CachingNodeWrapper::new_ref(CellNode { formula: Operand::Constant(0.0) })
+        })
+    }
+
+    pub fn set(&mut self, name: &str, formula: &str) -> Result<(), SheetError> {
+        let expr = expr::parse_with_limits(formula, &self.limits)?;
+        let node = self.cell(name).node.clone();
+        for variable in expr.variables() {
+            self.cell(variable);
+        }
+        let cells = &self.cells;
+        let value = expr.build(&self.registry, &mut |variable| Some(Operand::Node(cells[variable].node.clone())))?;
+
+        let cell_address = node_address(&(node.clone() as DynamicComputeNodeRef));
+        if let Some(value) = value.as_dynamic() {
+            if topological_order(&value).iter().any(|dependency| node_address(dependency) == cell_address) {
+                return Err(SheetError::Cycle { cell: name.to_string() });
+            }
+            self.limits.check(&value).map_err(ExprError::from)?;
+        }
+
+        value.subscribe_to_invalidate(&(node.clone() as _));
+        let mut node = node.borrow_mut();
+        node.inner.formula = value;
+        node.invalidate_cache();
+        self.cells.get_mut(name).unwrap().formula = formula.to_string();
+        Ok(())
+    }
+
+    pub fn value(&self, name: &str) -> Option<Float> {
+        self.cells.get(name).map(|cell| cell.node.compute())
+    }
+
+    // The formula as it was set, empty for cells only created by references to them
+    pub fn formula(&self, name: &str) -> Option<&str> {
+        self.cells.get(name).map(|cell| cell.formula.as_str())
+    }
+
+    // The cell's node, for using the cell's value in other graphs
+    pub fn node(&self, name: &str) -> Option<DynamicComputeNodeRef> {
+        self.cells.get(name).map(|cell| cell.node.clone() as DynamicComputeNodeRef)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cells.keys().map(String::as_str)
+    }
+}
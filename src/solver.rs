@@ -0,0 +1,149 @@
+use std::{rc::{Rc, Weak}, cell::{Cell, RefCell}, fmt, error::Error};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::autodiff::{self, NoDerivative};
+
+// Fixed-point iteration for implicit equations x = g(x) that can't be written as a DAG
+//
+// `g` is a subgraph built from the iteration variable (and any other nodes). Computing the
+// solver node sets the variable and evaluates `g` repeatedly, starting from the previous
+// solution, until two iterates are within `tolerance`. If that takes more than
+// `max_iterations`, the result is NaN
+//
+// The iteration variable must only be used inside `g`: setting it invalidates all of its
+// dependents while the solver is computing
+
+// Forwards invalidations of `g` to the solver, except for those caused by the iteration itself
+struct IterationGate {
+    solver: Option<Weak<RefCell<dyn InvalidateCacheMut>>>,
+    iterating: Rc<Cell<bool>>
+}
+
+impl InvalidateCacheMut for IterationGate {
+    fn invalidate_cache(&mut self) {
+        if !self.iterating.get() {
+            if let Some(solver) = self.solver.as_ref().and_then(Weak::upgrade) {
+                solver.borrow_mut().invalidate_cache();
+            }
+        }
+    }
+}
+
+struct FixedPointNode {
+    variable: Rc<RefCell<InputNodeImpl>>,
+    body: DynamicComputeNodeRef,
+    initial_guess: Float,
+    tolerance: Float,
+    max_iterations: usize,
+    previous_solution: Option<Float>,
+    iterating: Rc<Cell<bool>>,
+    _gate: Rc<RefCell<IterationGate>>
+}
+
+impl FixedPointNode {
+    fn iterate(&mut self) -> Option<Float> {
+        let mut x = self.previous_solution.unwrap_or(self.initial_guess);
+        // assigned, not set: the work queued meanwhile runs after the solver, which is borrowed
+        self.variable.borrow_mut().assign(x);
+        for _ in 0..self.max_iterations {
+            let next = self.body.compute();
+            if (next - x).abs() <= self.tolerance {
+                return Some(next);
+            }
+            x = next;
+            self.variable.borrow_mut().assign(x);
+        }
+        // leaves `g` cached, otherwise changes to its inputs would stop propagating at it
+        self.body.compute();
+        None
+    }
+}
+
+impl ComputeMut for FixedPointNode {
+    fn compute(&mut self) -> Float {
+        self.iterating.set(true);
+        let solution = self.iterate();
+        self.iterating.set(false);
+        self.previous_solution = solution.filter(|x| x.is_finite());
+        solution.unwrap_or(Float::NAN)
+    }
+    fn name(&self) -> &'static str { "fixed_point" }
+}
+
+pub fn fixed_point(
+    g: impl FnOnce(DynamicComputeNodeRef) -> DynamicComputeNodeRef,
+    initial_guess: Float, tolerance: Float, max_iterations: usize
+) -> DynamicComputeNodeRef {
+    let variable = InputNodeImpl::new_ref(initial_guess);
+    let body = g(variable.clone());
+    let iterating = Rc::new(Cell::new(false));
+    let gate = Rc::new(RefCell::new(IterationGate { solver: None, iterating: iterating.clone() }));
+    body.subscribe_to_invalidate(&(gate.clone() as _));
+
+    let result = CachingNodeWrapper::new_ref(FixedPointNode {
+        variable, body, initial_guess, tolerance, max_iterations, previous_solution: None, iterating, _gate: gate.clone()
+    });
+    gate.borrow_mut().solver = Some(Rc::downgrade(&result) as _);
+    result
+}
+
+// Newton's method for `output(input) = target`, with the derivative from autodiff
+//
+// Starts from the current value of `input` and stops once the output is within a relative
+// tolerance of the target. On success the input is left at the solution, which is also
+// returned; on failure it is restored
+
+const NEWTON_TOLERANCE: Float = 1e-5;
+const NEWTON_MAX_ITERATIONS: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SolveError {
+    NoDerivative(NoDerivative),
+    ZeroDerivative { input: Float },
+    NotConverged { output: Float }
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::NoDerivative(error) => write!(f, "{}", error),
+            SolveError::ZeroDerivative { input } => write!(f, "zero derivative at input {}", input),
+            SolveError::NotConverged { output } => write!(f, "no convergence, last output {}", output)
+        }
+    }
+}
+
+impl Error for SolveError {}
+
+impl From<NoDerivative> for SolveError {
+    fn from(error: NoDerivative) -> SolveError {
+        SolveError::NoDerivative(error)
+    }
+}
+
+pub fn solve_for(output: &impl ComputeNodeRef, target: Float, input: &impl InputNodeRef) -> Result<Float, SolveError> {
+    let start = input.compute();
+    let result = newton(output, target, input);
+    if result.is_err() {
+        input.set(start);
+    }
+    result
+}
+
+fn newton(output: &impl ComputeNodeRef, target: Float, input: &impl InputNodeRef) -> Result<Float, SolveError> {
+    let mut x = input.compute();
+    for _ in 0..NEWTON_MAX_ITERATIONS {
+        let residual = output.compute() - target;
+        if residual.abs() <= NEWTON_TOLERANCE * target.abs().max(1.0) {
+            return Ok(x);
+        }
+        let slope = autodiff::gradients(output)?.wrt(input);
+        if slope == 0.0 || !slope.is_finite() {
+            return Err(SolveError::ZeroDerivative { input: x });
+        }
+        x -= residual / slope;
+        input.set(x);
+    }
+    Err(SolveError::NotConverged { output: output.compute() })
+}
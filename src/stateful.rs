@@ -0,0 +1,325 @@
+use std::{rc::{Rc, Weak}, cell::{Cell, RefCell}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Nodes that keep state between evaluations (running sums, delays, integrators)
+//
+// A stateful node's output only changes on `step()` or `reset()`: stepping samples the
+// input node, advances the state and invalidates everything downstream, just like `set()`
+// does for inputs. Between steps the output is constant, however the input changes
+//
+// Nodes attached to a `Clock` are stepped by `tick(dt)`, which first samples the inputs of
+// all attached nodes and only then advances them, so chains of stateful nodes update as if
+// simultaneously
+
+// The state machine behind a stateful node, implement it for custom stateful nodes
+pub trait State {
+    fn output(&self) -> Float;
+    fn advance(&mut self, input: Float, dt: Float);
+    fn reset(&mut self);
+}
+
+// Steps happen in two phases: `prepare_step` returns a function that computes the node's next
+// state from the current state of the graph (without borrowing the node itself, which its
+// inputs may depend on), and `commit_step` then switches over to it
+pub trait StepMut {
+    fn prepare_step(&self) -> Rc<dyn Fn(Float)>;
+    fn commit_step(&mut self, dt: Float);
+    fn reset(&mut self);
+}
+
+pub trait StatefulNodeMut: ComputeNodeMut + StepMut {}
+
+impl<T: ComputeNodeMut + StepMut + ?Sized> StatefulNodeMut for T {}
+
+pub trait StatefulNodeRef: ComputeNodeRef {
+    fn step(&self, dt: Float);
+    fn reset(&self);
+}
+
+pub type DynamicStatefulNodeRef = Rc<RefCell<dyn StatefulNodeMut>>;
+
+impl ComputeNodeRef for DynamicStatefulNodeRef {
+    fn compute(&self) -> Float {
+        self.borrow_mut().compute()
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.borrow_mut().subscribe_to_invalidate(subscriber)
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef> {
+        Some(self.clone())
+    }
+    fn version(&self) -> u64 {
+        self.borrow().version()
+    }
+}
+
+impl StatefulNodeRef for DynamicStatefulNodeRef {
+    fn step(&self, dt: Float) {
+        let prepare = self.borrow().prepare_step();
+        prepare(dt);
+        self.borrow_mut().commit_step(dt);
+        run_deferred();
+    }
+    fn reset(&self) {
+        self.borrow_mut().reset();
+        run_deferred();
+    }
+}
+
+struct StatefulNodeImpl<S: State> {
+    // samples the input into `sampled_input`
+    sample: Rc<dyn Fn(Float)>,
+    sampled_input: Rc<Cell<Option<Float>>>,
+    state: S,
+    invalidate_publisher: InvalidatePublisher,
+    // bumped on every step and reset
+    version: u64
+}
+
+impl<S: State> StatefulNodeImpl<S> {
+    fn new(input: impl Fn() -> Float + 'static, state: S) -> StatefulNodeImpl<S> {
+        let sampled_input = Rc::new(Cell::new(None));
+        StatefulNodeImpl {
+            sample: {
+                let sampled_input = sampled_input.clone();
+                Rc::new(move |_dt| sampled_input.set(Some(input())))
+            },
+            sampled_input,
+            state,
+            invalidate_publisher: InvalidatePublisher::new(),
+            version: 0
+        }
+    }
+}
+
+impl<S: State> ComputeMut for StatefulNodeImpl<S> {
+    fn compute(&mut self) -> Float {
+        self.state.output()
+    }
+    fn name(&self) -> &'static str { "stateful" }
+    // only changes on steps, so has no derivative with respect to the current graph
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(Vec::new()) }
+}
+
+impl<S: State> ComputeNodeMut for StatefulNodeImpl<S> {
+    fn subscribe_to_invalidate(&mut self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.invalidate_publisher.subscribe_to_invalidate(subscriber)
+    }
+    fn version(&self) -> u64 { self.version }
+    fn subscribers(&self) -> Vec<Rc<RefCell<dyn InvalidateCacheMut>>> {
+        self.invalidate_publisher.subscribers()
+    }
+    fn dangling_subscribers(&self) -> usize {
+        self.invalidate_publisher.dangling()
+    }
+    fn compact(&mut self) -> usize {
+        self.invalidate_publisher.compact()
+    }
+}
+
+impl<S: State> StepMut for StatefulNodeImpl<S> {
+    fn prepare_step(&self) -> Rc<dyn Fn(Float)> {
+        self.sample.clone()
+    }
+    fn commit_step(&mut self, dt: Float) {
+        if let Some(input) = self.sampled_input.take() {
+            self.state.advance(input, dt);
+            self.version += 1;
+            self.invalidate_publisher.publish_invalidate();
+        }
+    }
+    fn reset(&mut self) {
+        self.state.reset();
+        self.version += 1;
+        self.invalidate_publisher.publish_invalidate();
+    }
+}
+
+pub fn stateful_node(input: impl ComputeNodeRef + 'static, state: impl State + 'static) -> DynamicStatefulNodeRef {
+    Rc::new(RefCell::new(StatefulNodeImpl::new(move || input.compute(), state)))
+}
+
+// Running sum of the sampled inputs
+struct Accumulator {
+    sum: Float
+}
+
+impl State for Accumulator {
+    fn output(&self) -> Float { self.sum }
+    fn advance(&mut self, input: Float, _dt: Float) { self.sum += input }
+    fn reset(&mut self) { self.sum = 0.0 }
+}
+
+pub fn accumulator(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    stateful_node(x, Accumulator { sum: 0.0 })
+}
+
+// The input sampled at the previous step
+struct Delay {
+    initial: Float,
+    previous: Float
+}
+
+impl State for Delay {
+    fn output(&self) -> Float { self.previous }
+    fn advance(&mut self, input: Float, _dt: Float) { self.previous = input }
+    fn reset(&mut self) { self.previous = self.initial }
+}
+
+pub fn delay(x: impl ComputeNodeRef + 'static, initial: Float) -> DynamicStatefulNodeRef {
+    stateful_node(x, Delay { initial, previous: initial })
+}
+
+// A delay whose input is connected after construction, so that the input can depend on the
+// delay itself (IIR filters, recurrences). Delays are the only way to close a cycle: the
+// delayed edge is held weakly, and stepping reads the value around the loop before
+// advancing. Until connected, or once the input is dropped, it samples `initial`
+#[derive(Clone)]
+pub struct FeedbackDelay {
+    node: DynamicStatefulNodeRef,
+    source: Rc<RefCell<Option<WeakComputeNodeRef>>>
+}
+
+type WeakComputeNodeRef = Weak<RefCell<dyn ComputeNodeMut>>;
+
+impl FeedbackDelay {
+    pub fn new(initial: Float) -> FeedbackDelay {
+        let source: Rc<RefCell<Option<WeakComputeNodeRef>>> = Rc::new(RefCell::new(None));
+        let node = Rc::new(RefCell::new(StatefulNodeImpl::new(
+            {
+                let source = source.clone();
+                move || {
+                    let source = source.borrow().as_ref().and_then(Weak::upgrade);
+                    source.map_or(initial, |source| source.compute())
+                }
+            },
+            Delay { initial, previous: initial }
+        )));
+        FeedbackDelay { node, source }
+    }
+
+    pub fn connect(&self, source: &DynamicComputeNodeRef) {
+        *self.source.borrow_mut() = Some(Rc::downgrade(source));
+    }
+
+    // For attaching to a `Clock`
+    pub fn node(&self) -> DynamicStatefulNodeRef {
+        self.node.clone()
+    }
+}
+
+impl ComputeNodeRef for FeedbackDelay {
+    fn compute(&self) -> Float {
+        self.node.compute()
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.node.subscribe_to_invalidate(subscriber)
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef> {
+        Some(self.node.clone())
+    }
+    fn version(&self) -> u64 {
+        self.node.version()
+    }
+}
+
+impl StatefulNodeRef for FeedbackDelay {
+    fn step(&self, dt: Float) {
+        self.node.step(dt)
+    }
+    fn reset(&self) {
+        self.node.reset()
+    }
+}
+
+// Trapezoidal rule; the first step has no previous sample and uses the rectangle rule
+struct Integrator {
+    initial: Float,
+    integral: Float,
+    previous_input: Option<Float>
+}
+
+impl State for Integrator {
+    fn output(&self) -> Float { self.integral }
+    fn advance(&mut self, input: Float, dt: Float) {
+        let previous = self.previous_input.unwrap_or(input);
+        self.integral += (previous + input) / 2.0 * dt;
+        self.previous_input = Some(input);
+    }
+    fn reset(&mut self) {
+        self.integral = self.initial;
+        self.previous_input = None;
+    }
+}
+
+pub fn integrator(x: impl ComputeNodeRef + 'static, initial: Float) -> DynamicStatefulNodeRef {
+    stateful_node(x, Integrator { initial, integral: initial, previous_input: None })
+}
+
+// Streaming statistics of the sampled inputs, for aggregating telemetry inside the graph.
+// Mean, min and max are NaN until the first step, the variance (the sample variance) is 0
+// until the second
+
+// Welford's algorithm, which doesn't lose precision to cancellation like sums of squares do
+struct RunningMoments {
+    count: usize,
+    mean: f64,
+    squared_deviations: f64,
+    output: fn(&RunningMoments) -> Float
+}
+
+impl State for RunningMoments {
+    fn output(&self) -> Float { (self.output)(self) }
+    fn advance(&mut self, input: Float, _dt: Float) {
+        self.count += 1;
+        let deviation = input as f64 - self.mean;
+        self.mean += deviation / self.count as f64;
+        self.squared_deviations += deviation * (input as f64 - self.mean);
+    }
+    fn reset(&mut self) {
+        self.count = 0;
+        self.mean = 0.0;
+        self.squared_deviations = 0.0;
+    }
+}
+
+fn running_moments(x: impl ComputeNodeRef + 'static, output: fn(&RunningMoments) -> Float) -> DynamicStatefulNodeRef {
+    stateful_node(x, RunningMoments { count: 0, mean: 0.0, squared_deviations: 0.0, output })
+}
+
+pub fn running_count(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    running_moments(x, |moments| moments.count as Float)
+}
+
+pub fn running_mean(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    running_moments(x, |moments| if moments.count == 0 { Float::NAN } else { moments.mean as Float })
+}
+
+pub fn running_variance(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    running_moments(x, |moments| {
+        if moments.count < 2 { 0.0 } else { (moments.squared_deviations / (moments.count - 1) as f64) as Float }
+    })
+}
+
+struct RunningExtremum {
+    extremum: Option<Float>,
+    pick: fn(Float, Float) -> Float
+}
+
+impl State for RunningExtremum {
+    fn output(&self) -> Float { self.extremum.unwrap_or(Float::NAN) }
+    fn advance(&mut self, input: Float, _dt: Float) {
+        self.extremum = Some(self.extremum.map_or(input, |extremum| (self.pick)(extremum, input)));
+    }
+    fn reset(&mut self) { self.extremum = None }
+}
+
+pub fn running_min(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    stateful_node(x, RunningExtremum { extremum: None, pick: Float::min })
+}
+
+pub fn running_max(x: impl ComputeNodeRef + 'static) -> DynamicStatefulNodeRef {
+    stateful_node(x, RunningExtremum { extremum: None, pick: Float::max })
+}
@@ -0,0 +1,222 @@
+use std::{marker::PhantomData, ops::{Add, Sub, Mul, Div, Neg}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::strict;
+
+// Graphs as nested generic types, for small hot graphs
+//
+// A static graph is a tree of plain values whose type spells out the whole computation,
+// `Binary<Times, Var, Unary<Sin, Var>>` for `x * sin(y)`, so that evaluating it is a single
+// monomorphized function the compiler can inline through: no `Rc`, no `RefCell`, no dynamic
+// dispatch and no caches. Its inputs are the positions of a slice of values given to `eval`.
+// Graphs are built with the operators and the methods of `StaticNode`:
+//     let f = var(0) * var(1).sin() + 1.0;
+//     f.eval(&[2.0, 0.5])
+// and can be embedded in a dynamic graph as a single node with `into_node`, which computes
+// the whole tree whenever any of its children changes
+
+pub trait StaticNode: Copy + 'static {
+    fn eval(&self, inputs: &[Float]) -> Float;
+
+    fn sqrt(self) -> Unary<Sqrt, Self> { Unary::new(self) }
+    fn abs(self) -> Unary<Abs, Self> { Unary::new(self) }
+    fn exp(self) -> Unary<Exp, Self> { Unary::new(self) }
+    fn ln(self) -> Unary<Ln, Self> { Unary::new(self) }
+    fn sin(self) -> Unary<Sin, Self> { Unary::new(self) }
+    fn cos(self) -> Unary<Cos, Self> { Unary::new(self) }
+    fn pow<B: IntoStatic>(self, exponent: B) -> Binary<Pow, Self, B::Node> { Binary::new(self, exponent.into_static()) }
+    fn min<B: IntoStatic>(self, other: B) -> Binary<Min, Self, B::Node> { Binary::new(self, other.into_static()) }
+    fn max<B: IntoStatic>(self, other: B) -> Binary<Max, Self, B::Node> { Binary::new(self, other.into_static()) }
+
+    // A node of a dynamic graph computing this one, with `children` as its inputs in order
+    fn into_node(self, children: Vec<impl ComputeNodeRef + 'static>) -> DynamicComputeNodeRef {
+        let result = CachingNodeWrapper::new_ref(Embedded { graph: self, children, values: Vec::new() });
+        let subscriber = result.clone() as _;
+        result.borrow().inner.children.iter().for_each(|child| child.subscribe_to_invalidate(&subscriber));
+        result
+    }
+}
+
+// The input at a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Var(usize);
+
+pub fn var(position: usize) -> Var {
+    Var(position)
+}
+
+impl StaticNode for Var {
+    fn eval(&self, inputs: &[Float]) -> Float { inputs[self.0] }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Const(pub Float);
+
+impl StaticNode for Const {
+    fn eval(&self, _inputs: &[Float]) -> Float { self.0 }
+}
+
+pub trait UnaryOp: Copy + 'static {
+    fn apply(x: Float) -> Float;
+}
+
+pub trait BinaryOp: Copy + 'static {
+    fn apply(a: Float, b: Float) -> Float;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Unary<Op, X> {
+    x: X,
+    _op: PhantomData<Op>
+}
+
+impl<Op: UnaryOp, X: StaticNode> Unary<Op, X> {
+    fn new(x: X) -> Unary<Op, X> {
+        Unary { x, _op: PhantomData }
+    }
+}
+
+impl<Op: UnaryOp, X: StaticNode> StaticNode for Unary<Op, X> {
+    fn eval(&self, inputs: &[Float]) -> Float {
+        Op::apply(self.x.eval(inputs))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binary<Op, A, B> {
+    a: A,
+    b: B,
+    _op: PhantomData<Op>
+}
+
+impl<Op: BinaryOp, A: StaticNode, B: StaticNode> Binary<Op, A, B> {
+    fn new(a: A, b: B) -> Binary<Op, A, B> {
+        Binary { a, b, _op: PhantomData }
+    }
+}
+
+impl<Op: BinaryOp, A: StaticNode, B: StaticNode> StaticNode for Binary<Op, A, B> {
+    fn eval(&self, inputs: &[Float]) -> Float {
+        Op::apply(self.a.eval(inputs), self.b.eval(inputs))
+    }
+}
+
+macro_rules! unary_ops {
+    ($($op:ident($x:ident) $body:expr;)*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct $op;
+
+            impl UnaryOp for $op {
+                fn apply($x: Float) -> Float { $body }
+            }
+        )*
+    };
+}
+
+macro_rules! binary_ops {
+    ($($op:ident($a:ident, $b:ident) $body:expr;)*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct $op;
+
+            impl BinaryOp for $op {
+                fn apply($a: Float, $b: Float) -> Float { $body }
+            }
+        )*
+    };
+}
+
+unary_ops! {
+    Negate(x) -x;
+    Sqrt(x) x.sqrt();
+    Abs(x) x.abs();
+    Exp(x) strict::exp(x);
+    Ln(x) strict::ln(x);
+    Sin(x) strict::sin(x);
+    Cos(x) strict::cos(x);
+}
+
+binary_ops! {
+    Plus(a, b) a + b;
+    Minus(a, b) a - b;
+    Times(a, b) a * b;
+    Over(a, b) a / b;
+    Pow(a, b) strict::powf(a, b);
+    Min(a, b) a.min(b);
+    Max(a, b) a.max(b);
+}
+
+// The arithmetic operators on static nodes and constants, for each kind of node
+macro_rules! operators {
+    ($([$($generics:tt)*] $node:ty;)*) => {
+        $(
+            impl<$($generics)* Rhs: IntoStatic> Add<Rhs> for $node {
+                type Output = Binary<Plus, $node, Rhs::Node>;
+                fn add(self, rhs: Rhs) -> Self::Output { Binary::new(self, rhs.into_static()) }
+            }
+            impl<$($generics)* Rhs: IntoStatic> Sub<Rhs> for $node {
+                type Output = Binary<Minus, $node, Rhs::Node>;
+                fn sub(self, rhs: Rhs) -> Self::Output { Binary::new(self, rhs.into_static()) }
+            }
+            impl<$($generics)* Rhs: IntoStatic> Mul<Rhs> for $node {
+                type Output = Binary<Times, $node, Rhs::Node>;
+                fn mul(self, rhs: Rhs) -> Self::Output { Binary::new(self, rhs.into_static()) }
+            }
+            impl<$($generics)* Rhs: IntoStatic> Div<Rhs> for $node {
+                type Output = Binary<Over, $node, Rhs::Node>;
+                fn div(self, rhs: Rhs) -> Self::Output { Binary::new(self, rhs.into_static()) }
+            }
+            impl<$($generics)*> Neg for $node {
+                type Output = Unary<Negate, $node>;
+                fn neg(self) -> Self::Output { Unary::new(self) }
+            }
+        )*
+    };
+}
+
+operators! {
+    [] Var;
+    [] Const;
+    [Op: UnaryOp, X: StaticNode,] Unary<Op, X>;
+    [Op: BinaryOp, A: StaticNode, B: StaticNode,] Binary<Op, A, B>;
+}
+
+// Static nodes, and numbers as constants
+pub trait IntoStatic {
+    type Node: StaticNode;
+    fn into_static(self) -> Self::Node;
+}
+
+impl<N: StaticNode> IntoStatic for N {
+    type Node = N;
+    fn into_static(self) -> N { self }
+}
+
+impl IntoStatic for Float {
+    type Node = Const;
+    fn into_static(self) -> Const { Const(self) }
+}
+
+struct Embedded<G, X> {
+    graph: G,
+    children: Vec<X>,
+    // reused between evaluations
+    values: Vec<Float>
+}
+
+impl<G: StaticNode, X: ComputeNodeRef> ComputeMut for Embedded<G, X> {
+    fn compute(&mut self) -> Float {
+        self.values.clear();
+        self.values.extend(self.children.iter().map(ComputeNodeRef::compute));
+        self.graph.eval(&self.values)
+    }
+    fn name(&self) -> &'static str { "static_graph" }
+    fn children(&self) -> Vec<Child> {
+        self.children.iter().map(ComputeNodeRef::as_child).collect()
+    }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(self.graph.into_node(children.to_vec()))
+    }
+}
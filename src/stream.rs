@@ -0,0 +1,118 @@
+use std::{rc::Rc, cell::RefCell, collections::{HashMap, BTreeMap}, future::Future, pin::Pin, task::{Context, Poll}};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+
+// Asynchronous sources: nodes taking their values from a stream of items
+//
+// `stream_source` makes a read-only node and a future that drives it: run on an async
+// executor, the future polls the stream through `poll_next` and sets the node to each item
+// as it arrives, invalidating its dependents like `set()`. The future completes when the
+// stream ends, leaving the node at the last item. `poll_next` has the signature of
+// `Stream::poll_next` from the `futures` crate, so a stream is wrapped with
+// `move |cx| stream.poll_next_unpin(cx)`
+
+#[derive(Clone)]
+pub struct StreamSource {
+    input: Input
+}
+
+impl ComputeNodeRef for StreamSource {
+    fn compute(&self) -> Float {
+        self.input.compute()
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.input.subscribe_to_invalidate(subscriber)
+    }
+    fn as_dynamic(&self) -> Option<DynamicComputeNodeRef> {
+        Some(self.input.clone())
+    }
+    fn version(&self) -> u64 {
+        self.input.version()
+    }
+}
+
+pub struct StreamDriver<F> {
+    input: Input,
+    poll_next: F
+}
+
+impl<F: FnMut(&mut Context) -> Poll<Option<Float>> + Unpin> Future for StreamDriver<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let driver = self.get_mut();
+        // takes all the items that are ready, only the last one is ever seen
+        loop {
+            match (driver.poll_next)(cx) {
+                Poll::Ready(Some(value)) => driver.input.set(value),
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+// The source starts at `initial` until the first item arrives
+pub fn stream_source<F: FnMut(&mut Context) -> Poll<Option<Float>> + Unpin>(
+    initial: Float, poll_next: F
+) -> (StreamSource, StreamDriver<F>) {
+    let input = InputNodeImpl::new_ref(initial);
+    (StreamSource { input: input.clone() }, StreamDriver { input, poll_next })
+}
+
+// Record-driven evaluation: one output per input record, e.g. per row of a CSV file
+//
+// Each record sets the inputs bound to the names it has values for; inputs whose value stays
+// the same aren't set, so the parts of the graph that only depend on unchanged columns keep
+// their cached values between records. A name missing from a record keeps the input at its
+// value from the previous records
+
+pub trait Record {
+    fn get(&self, name: &str) -> Option<Float>;
+}
+
+impl Record for HashMap<String, Float> {
+    fn get(&self, name: &str) -> Option<Float> {
+        HashMap::get(self, name).copied()
+    }
+}
+
+impl Record for BTreeMap<String, Float> {
+    fn get(&self, name: &str) -> Option<Float> {
+        BTreeMap::get(self, name).copied()
+    }
+}
+
+impl Record for [(&str, Float)] {
+    fn get(&self, name: &str) -> Option<Float> {
+        self.iter().find(|(field, _)| *field == name).map(|&(_, value)| value)
+    }
+}
+
+impl<const N: usize> Record for [(&str, Float); N] {
+    fn get(&self, name: &str) -> Option<Float> {
+        Record::get(self.as_slice(), name)
+    }
+}
+
+impl<R: Record + ?Sized> Record for &R {
+    fn get(&self, name: &str) -> Option<Float> {
+        (**self).get(name)
+    }
+}
+
+pub fn evaluate_stream<'a, R: Record, P: InputNodeRef>(
+    output: &'a impl ComputeNodeRef, records: impl IntoIterator<Item = R> + 'a, bindings: &'a [(&str, P)]
+) -> impl Iterator<Item = Float> + 'a {
+    records.into_iter().map(move |record| {
+        for (name, input) in bindings {
+            if let Some(value) = record.get(name) {
+                if value.to_bits() != input.compute().to_bits() {
+                    input.set(value);
+                }
+            }
+        }
+        output.compute()
+    })
+}
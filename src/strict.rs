@@ -0,0 +1,363 @@
+use std::cell::Cell;
+
+use crate::compgraph::Float;
+
+// Bit-identical floating point across platforms
+//
+// Rust never contracts `a * b + c` into a fused multiply-add nor reorders arithmetic behind
+// the program's back, and `+`, `-`, `*`, `/` and `sqrt` are correctly rounded everywhere, so
+// graphs built from those alone already compute the same bits on every platform and with
+// every way of evaluating them (cached or not, folded by `graph::specialize`, ...). What
+// differs is the math library: `sin`, `exp`, `powf` and the like are left to the platform
+// and its version. With strict mode on, the math functions of the node libraries use the
+// implementations below instead, which are built from correctly rounded `f64` operations
+// only. Reductions (`ops::sum`, the weighted sum of an `nn` neuron, ...) add their terms in
+// the order given, whether strict or not.
+//
+// Strict mode is set per thread, like the graphs themselves, and should be set before the
+// first evaluation, as values cached before that aren't recomputed. Results are only
+// portable among targets with IEEE 754 `f64` arithmetic, which excludes x87-only targets
+// such as i586. Nodes that call math functions themselves should call those of this module
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_enabled(enabled: bool) {
+    STRICT.with(|strict| strict.set(enabled))
+}
+
+pub fn is_enabled() -> bool {
+    STRICT.with(Cell::get)
+}
+
+// Runs `f` in strict mode, restoring the previous mode afterwards
+pub fn with_strict<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            set_enabled(self.0)
+        }
+    }
+    let _restore = Restore(is_enabled());
+    set_enabled(true);
+    f()
+}
+
+pub fn exp(x: Float) -> Float {
+    if is_enabled() { portable::exp(x as f64) as Float } else { x.exp() }
+}
+
+pub fn ln(x: Float) -> Float {
+    if is_enabled() { portable::ln(x as f64) as Float } else { x.ln() }
+}
+
+pub fn ln_1p(x: Float) -> Float {
+    if is_enabled() { portable::ln_1p(x as f64) as Float } else { x.ln_1p() }
+}
+
+pub fn powf(a: Float, b: Float) -> Float {
+    if is_enabled() { portable::powf(a as f64, b as f64) as Float } else { a.powf(b) }
+}
+
+pub fn sin(x: Float) -> Float {
+    if is_enabled() { portable::sin(x as f64) as Float } else { x.sin() }
+}
+
+pub fn cos(x: Float) -> Float {
+    if is_enabled() { portable::cos(x as f64) as Float } else { x.cos() }
+}
+
+pub fn tan(x: Float) -> Float {
+    if is_enabled() { portable::tan(x as f64) as Float } else { x.tan() }
+}
+
+pub fn tanh(x: Float) -> Float {
+    if is_enabled() { portable::tanh(x as f64) as Float } else { x.tanh() }
+}
+
+pub fn sin_cos(x: Float) -> (Float, Float) {
+    if is_enabled() { (sin(x), cos(x)) } else { x.sin_cos() }
+}
+
+pub fn hypot(a: Float, b: Float) -> Float {
+    if is_enabled() { portable::hypot(a as f64, b as f64) as Float } else { a.hypot(b) }
+}
+
+pub fn atan2(y: Float, x: Float) -> Float {
+    if is_enabled() { portable::atan2(y as f64, x as f64) as Float } else { y.atan2(x) }
+}
+
+// For nodes computing in `f64` internally
+pub(crate) fn ln_f64(x: f64) -> f64 {
+    if is_enabled() { portable::ln(x) } else { x.ln() }
+}
+
+pub(crate) fn sin_cos_f64(x: f64) -> (f64, f64) {
+    if is_enabled() { (portable::sin(x), portable::cos(x)) } else { x.sin_cos() }
+}
+
+// Range reduction followed by Taylor series, evaluated in `f64` so that the results are
+// within an ulp or so of the exact ones once rounded to `Float`. Arguments of the trig
+// functions are reduced with a three-part π/2, which loses accuracy (but not determinism)
+// beyond |x| ≈ 1e6
+mod portable {
+    use std::f64::consts::{LOG2_E, FRAC_2_PI, SQRT_2, PI, FRAC_PI_2, FRAC_PI_4, FRAC_PI_6};
+
+    const LN_2_HI: f64 = 0.693_147_180_369_123_8;
+    const LN_2_LO: f64 = 1.908_214_929_270_587_7e-10;
+    const FRAC_PI_2_HI: f64 = 1.570_796_326_734_125_6;
+    const FRAC_PI_2_MID: f64 = 6.077_100_506_303_966e-11;
+    const FRAC_PI_2_LO: f64 = 2.022_266_248_795_950_6e-21;
+    const SQRT_3: f64 = 1.732_050_807_568_877_2;
+    const TAN_PI_12: f64 = 0.267_949_192_431_122_7;
+
+    // Σ coefficients[i] * x^i
+    fn polynomial(x: f64, coefficients: &[f64]) -> f64 {
+        coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c)
+    }
+
+    // Taylor coefficients ±1 / (first + step * i)!, alternating in sign if `alternating`,
+    // computed once at compile time
+    const fn taylor<const N: usize>(first: usize, step: usize, alternating: bool) -> [f64; N] {
+        let mut coefficients = [0.0; N];
+        let mut i = 0;
+        while i < N {
+            let mut factorial = 1.0;
+            let mut n = 1;
+            while n <= first + step * i {
+                factorial *= n as f64;
+                n += 1;
+            }
+            coefficients[i] = if alternating && i % 2 == 1 { -1.0 / factorial } else { 1.0 / factorial };
+            i += 1;
+        }
+        coefficients
+    }
+
+    const EXP_SERIES: [f64; 15] = taylor(0, 1, false);
+    const SIN_SERIES: [f64; 9] = taylor(1, 2, true);
+    const COS_SERIES: [f64; 10] = taylor(0, 2, true);
+    const SINH_SERIES: [f64; 10] = taylor(1, 2, false);
+
+    // ±1 / (2i + 1), alternating in sign if `alternating`, the series of atanh and atan
+    const fn odd_reciprocals<const N: usize>(alternating: bool) -> [f64; N] {
+        let mut coefficients = [0.0; N];
+        let mut i = 0;
+        while i < N {
+            let reciprocal = 1.0 / (2 * i + 1) as f64;
+            coefficients[i] = if alternating && i % 2 == 1 { -reciprocal } else { reciprocal };
+            i += 1;
+        }
+        coefficients
+    }
+
+    const ATANH_SERIES: [f64; 12] = odd_reciprocals(false);
+    const ATAN_SERIES: [f64; 16] = odd_reciprocals(true);
+
+    pub(super) fn exp(x: f64) -> f64 {
+        if x.is_nan() {
+            return x;
+        }
+        if x > 709.0 {
+            return f64::INFINITY;
+        }
+        if x < -745.0 {
+            return 0.0;
+        }
+        let k = (x * LOG2_E).round();
+        let r = (x - k * LN_2_HI) - k * LN_2_LO;
+        let series = polynomial(r, &EXP_SERIES);
+        scale(series, k as i32)
+    }
+
+    // x * 2^k, in two steps for results in the subnormal range
+    fn scale(x: f64, k: i32) -> f64 {
+        let power = |k: i32| f64::from_bits(((k + 1023) as u64) << 52);
+        if k < -1000 {
+            x * power(k + 1000) * power(-1000)
+        } else if k > 1000 {
+            x * power(k - 1000) * power(1000)
+        } else {
+            x * power(k)
+        }
+    }
+
+    pub(super) fn ln(x: f64) -> f64 {
+        if x.is_nan() || x < 0.0 {
+            return f64::NAN;
+        }
+        if x == 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        if x.is_infinite() {
+            return x;
+        }
+        // x = m * 2^e with m in [√2/2, √2)
+        let (x, bias) = if x < f64::MIN_POSITIVE { (x * 18_014_398_509_481_984.0, -54) } else { (x, 0) };
+        let bits = x.to_bits();
+        let mut e = ((bits >> 52) & 0x7ff) as i32 - 1023 + bias;
+        let mut m = f64::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+        if m > SQRT_2 {
+            m /= 2.0;
+            e += 1;
+        }
+        // ln m = 2 atanh(f), with |f| < 0.172
+        let f = (m - 1.0) / (m + 1.0);
+        let f2 = f * f;
+        let ln_m = 2.0 * f * polynomial(f2, &ATANH_SERIES);
+        e as f64 * LN_2_HI + (e as f64 * LN_2_LO + ln_m)
+    }
+
+    pub(super) fn ln_1p(x: f64) -> f64 {
+        let u = 1.0 + x;
+        if u == 1.0 {
+            x
+        } else if u.is_infinite() {
+            u
+        } else {
+            // corrects for the rounding of 1 + x
+            ln(u) * (x / (u - 1.0))
+        }
+    }
+
+    pub(super) fn powf(a: f64, b: f64) -> f64 {
+        if b == 0.0 || a == 1.0 {
+            return 1.0;
+        }
+        if a.is_nan() || b.is_nan() {
+            return f64::NAN;
+        }
+        let integer = b == b.round();
+        let odd = integer && (b / 2.0) != (b / 2.0).round();
+        if a < 0.0 || (a == 0.0 && a.is_sign_negative()) {
+            if !integer && a != 0.0 {
+                return f64::NAN;
+            }
+            let magnitude = powf(-a, b);
+            return if odd { -magnitude } else { magnitude };
+        }
+        if a == 0.0 {
+            return if b > 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        if a.is_infinite() {
+            return if b > 0.0 { f64::INFINITY } else { 0.0 };
+        }
+        exp(b * ln(a))
+    }
+
+    // r in [-π/4, π/4] and the quadrant x is in
+    fn reduce(x: f64) -> (f64, u8) {
+        let k = (x * FRAC_2_PI).round();
+        let r = ((x - k * FRAC_PI_2_HI) - k * FRAC_PI_2_MID) - k * FRAC_PI_2_LO;
+        (r, k.rem_euclid(4.0) as u8)
+    }
+
+    fn sin_series(r: f64) -> f64 {
+        r * polynomial(r * r, &SIN_SERIES)
+    }
+
+    fn cos_series(r: f64) -> f64 {
+        polynomial(r * r, &COS_SERIES)
+    }
+
+    pub(super) fn sin(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        let (r, quadrant) = reduce(x);
+        match quadrant {
+            0 => sin_series(r),
+            1 => cos_series(r),
+            2 => -sin_series(r),
+            _ => -cos_series(r)
+        }
+    }
+
+    pub(super) fn cos(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        let (r, quadrant) = reduce(x);
+        match quadrant {
+            0 => cos_series(r),
+            1 => -sin_series(r),
+            2 => -cos_series(r),
+            _ => sin_series(r)
+        }
+    }
+
+    pub(super) fn tan(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        let (r, quadrant) = reduce(x);
+        if quadrant % 2 == 0 {
+            sin_series(r) / cos_series(r)
+        } else {
+            -cos_series(r) / sin_series(r)
+        }
+    }
+
+    pub(super) fn tanh(x: f64) -> f64 {
+        if x.is_nan() {
+            return x;
+        }
+        if x.abs() > 22.0 {
+            return x.signum();
+        }
+        if x.abs() < 1.0 {
+            // sinh x / cosh x, cosh x = √(1 + sinh² x)
+            let sinh = x * polynomial(x * x, &SINH_SERIES);
+            return sinh / (1.0 + sinh * sinh).sqrt();
+        }
+        let e = exp(2.0 * x);
+        (e - 1.0) / (e + 1.0)
+    }
+
+    // For `Float` arguments, whose squares are exact in `f64` and can't overflow
+    pub(super) fn hypot(a: f64, b: f64) -> f64 {
+        if a.is_infinite() || b.is_infinite() {
+            return f64::INFINITY;
+        }
+        (a * a + b * b).sqrt()
+    }
+
+    // atan x = π/2 - atan(1/x) = π/6 + atan((x√3 - 1) / (x + √3)), reducing x to
+    // [0, tan π/12] for the series
+    fn atan(x: f64) -> f64 {
+        if x < 0.0 {
+            -atan(-x)
+        } else if x > 1.0 {
+            FRAC_PI_2 - atan(1.0 / x)
+        } else if x > TAN_PI_12 {
+            FRAC_PI_6 + atan((x * SQRT_3 - 1.0) / (x + SQRT_3))
+        } else {
+            x * polynomial(x * x, &ATAN_SERIES)
+        }
+    }
+
+    pub(super) fn atan2(y: f64, x: f64) -> f64 {
+        if x.is_nan() || y.is_nan() {
+            return f64::NAN;
+        }
+        // the half turn towards y's sign, also for y = -0
+        let pi = PI.copysign(y);
+        if x.is_infinite() {
+            return match (y.is_infinite(), x > 0.0) {
+                (true, true) => FRAC_PI_4.copysign(y),
+                (true, false) => 3.0 * FRAC_PI_4.copysign(y),
+                (false, true) => 0.0f64.copysign(y),
+                (false, false) => pi
+            };
+        }
+        if y.is_infinite() || (x == 0.0 && y != 0.0) {
+            return FRAC_PI_2.copysign(y);
+        }
+        if x == 0.0 {
+            return if x.is_sign_negative() { pi } else { y };
+        }
+        let angle = atan(y / x);
+        if x > 0.0 { angle } else { angle + pi }
+    }
+}
@@ -3,13 +3,73 @@ use std::rc::Rc;
 
 use crate::*;
 use crate::internals::InvalidateCacheMut;
+use crate::numeric::{self, Numeric};
+use crate::fixed::Fixed;
+use crate::units::{self, Quantity, Unit};
+use crate::integer::{self, Integer};
+use crate::logic;
+use crate::random::RandomSource;
+use crate::noise;
+use crate::time::{self, Clock, Track, Easing};
+use crate::stateful::{self, StatefulNodeRef};
+use crate::ode::{self, OdeSystem};
+use crate::solver;
+use crate::autodiff;
+use crate::analysis::{self, SensitivityMethod, Distribution};
+use crate::optim::{self, Optimizer};
+use crate::loss;
+use crate::nn;
+use crate::onnx;
+use crate::ops;
+use crate::registry::NodeRegistry;
+use crate::graph::{self, Change};
+use crate::expr::{self, Expr};
+use crate::sheet::{Sheet, SheetError};
+use crate::binding::Bindings;
+use crate::scheduler::Scheduler;
+use crate::record::{Recorder, Log, ReplayError};
+use crate::cache::ResultCache;
+use crate::limits::{Limits, LimitError};
+use crate::output::{self, Output, Evaluation};
+use crate::stream;
+use crate::audio::{self, Block, BlockProcessor};
+use crate::dsp;
+use crate::interp;
 
 define_nodes! {
-    add(a, b) { a + b }
-    mul(a, b) { a * b }
-    sin(x) { x.sin() }
-    pow_f32(x, e) { x.powf(e) }
+    add(a, b) { a + b } grad { [1.0, 1.0] }
+    mul(a, b) { a * b } grad { [b, a] }
+    sin(x) { x.sin() } grad { [x.cos()] }
+    pow_f32(x, e) { x.powf(e) } grad { [e * x.powf(e - 1.0), x.powf(e) * x.ln()] }
     pub add3(a, b, c) { a + b + c } // test parsing of `pub` in macro
+    never(x) { unreachable!("computed {}", x) }
+    cos(x) { x.cos() } grad { [-x.sin()] }
+    div(a, b) { a / b } grad { [1.0 / b, -a / (b * b)] }
+    /// Moves from `a` towards `b`
+    /// by `alpha`
+    #[inline]
+    blend(a, b, alpha = 0.25) { a + (b - a) * alpha } grad { [1.0 - alpha, alpha, b - a] }
+    // 1 + x + ... + x^DEGREE
+    geometric<const DEGREE: usize>(x) { (0..=DEGREE as i32).map(|k| x.powi(k)).sum() }
+        grad { [(1..=DEGREE as i32).map(|k| k as Float * x.powi(k - 1)).sum()] }
+    shaped<S, const SCALE: usize>(x, gain = 1.0) where S: Shape + Default + Clone { gain * S::shape(x) * SCALE as Float }
+    #[cfg(test)]
+    scaled(x, gain = 2.0, offset = -1.0) -> Scaled { value, slope } { Scaled { value: x * gain + offset, slope: gain } }
+    #[constant]
+    nyquist(rate) { rate / 2.0 } grad { [0.5] }
+    #[constant]
+    lerp(a, b, t = 0.5) { a + (b - a) * t }
+}
+
+trait Shape {
+    fn shape(x: Float) -> Float;
+}
+
+#[derive(Default, Clone)]
+struct Square;
+
+impl Shape for Square {
+    fn shape(x: Float) -> Float { x * x }
 }
 
 fn round(x: f32, precision: u32) -> f32 {
@@ -182,3 +242,2908 @@ fn nodes_are_dynamic() {
     _node = add3(_node.clone(), _node.clone(), 3.0);
     assert_eq!(_node.compute(), 27.0);
 }
+
+#[test]
+fn fixed_arithmetic() {
+    let a = Fixed::from_f32(1.5);
+    let b = Fixed::from_int(2);
+    assert_eq!(a * b, Fixed::from_int(3));
+    assert_eq!(a / b, Fixed::from_f32(0.75));
+    assert_eq!(a - b, Fixed::from_f32(-0.5));
+    assert_eq!(Fixed::MAX + Fixed::ONE, Fixed::MAX);
+    assert_eq!(Fixed::ONE / Fixed::ZERO, Fixed::MAX);
+    assert_eq!(Fixed::from_int(9).sqrt(), Fixed::from_int(3));
+    assert!((Fixed::from_f32(1.0).sin().to_f32() - 1f32.sin()).abs() < 1e-3);
+    assert!((Fixed::from_f32(-4.0).cos().to_f32() - (-4f32).cos()).abs() < 1e-3);
+}
+
+#[test]
+fn numeric_graph_in_float_and_fixed() {
+    // hypotenuse scaled by a cosine, written once against `Numeric`
+    fn build<N: Numeric>(x: impl ComputeNodeRef<N> + 'static, y: impl ComputeNodeRef<N> + 'static) -> DynamicComputeNodeRef<N> {
+        numeric::mul(
+            numeric::sqrt(numeric::add(numeric::mul(x.clone(), x), numeric::mul(y.clone(), y))),
+            numeric::cos(N::from_f32(0.5))
+        )
+    }
+
+    let xf = create_typed_input(3.0f32);
+    let yf = create_typed_input(4.0f32);
+    let graph_float = build(xf.clone(), yf.clone());
+
+    let xq = create_typed_input(Fixed::from_int(3));
+    let yq = create_typed_input(Fixed::from_int(4));
+    let graph_fixed = build(xq.clone(), yq.clone());
+
+    assert!((graph_float.compute() - graph_fixed.compute().to_f32()).abs() < 1e-3);
+
+    xf.set(6.0);
+    yf.set(8.0);
+    xq.set(Fixed::from_int(6));
+    yq.set(Fixed::from_int(8));
+    assert!((graph_float.compute() - graph_fixed.compute().to_f32()).abs() < 1e-3);
+}
+
+#[test]
+fn units_are_checked_at_construction() {
+    let distance = Quantity::new(create_input(), Unit::METER);
+    let time = Quantity::new(create_input(), Unit::SECOND);
+    let offset = Quantity::new(2.0, Unit::METER);
+
+    let speed = units::div(&distance, &time).unwrap();
+    assert_eq!(speed.unit(), Unit::METER / Unit::SECOND);
+    assert_eq!(speed.unit().to_string(), "m s^-1");
+
+    let mismatch = units::add(&distance, &time).err().unwrap();
+    assert_eq!(mismatch.to_string(), "unit mismatch: expected [m], found [s]");
+    assert!(units::sub(&speed, &distance).is_err());
+
+    let travelled = units::add(&units::mul(&speed, &time).unwrap(), &offset).unwrap();
+    assert_eq!(travelled.unit(), Unit::METER);
+    assert!(travelled.node_in(Unit::SECOND).is_err());
+    let travelled_node = travelled.node_in(Unit::METER).unwrap();
+
+    distance.node_in(Unit::METER).unwrap().set(10.0);
+    time.node_in(Unit::SECOND).unwrap().set(4.0);
+    assert_eq!(travelled_node.compute(), 12.0);
+    distance.node_in(Unit::METER).unwrap().set(20.0);
+    assert_eq!(travelled.compute(), 22.0);
+
+    // exponents out of range are errors, not wrapped around
+    let huge = Unit::METER.powi(127).unwrap();
+    assert_eq!(Unit::METER.powi(-128).unwrap().powi(-1), Err(units::UnitOverflow));
+    assert_eq!(huge.checked_mul(Unit::METER), Err(units::UnitOverflow));
+    let extreme = Quantity::new(create_input(), huge);
+    assert_eq!(units::mul(&extreme, &distance).err(), Some(units::UnitOverflow));
+    assert_eq!(units::div(&distance, &extreme).unwrap().unit(), Unit::METER.powi(-126).unwrap());
+    let inverse = units::div(&time, &extreme).unwrap();
+    assert!(units::div(&inverse, &distance).and_then(|q| units::div(&q, &distance)).is_err());
+}
+
+#[test]
+fn integer_overflow_policies() {
+    let wrapping = create_typed_input(integer::Wrap::from_i64(i64::MAX));
+    let wrapped = integer::add(wrapping.clone(), integer::Wrap::from_i64(1));
+    assert_eq!(wrapped.compute().to_i64(), Some(i64::MIN));
+
+    let saturating = create_typed_input(integer::Saturate::from_i64(i64::MAX));
+    let saturated = integer::add(saturating.clone(), integer::Saturate::from_i64(1));
+    assert_eq!(saturated.compute().to_i64(), Some(i64::MAX));
+
+    let checked = create_typed_input(integer::Checked::from_i64(i64::MAX - 1));
+    let sum = integer::add(checked.clone(), Some(1));
+    let product = integer::mul(sum.clone(), Some(2));
+    let as_float = integer::to_float(sum.clone());
+    assert_eq!(sum.compute(), Some(i64::MAX));
+    assert_eq!(product.compute(), None);
+    checked.set(Some(i64::MAX));
+    assert_eq!(sum.compute(), None);
+    assert!(as_float.compute().is_nan());
+
+    // values beyond f32's 24-bit mantissa stay exact
+    checked.set(Some(1 << 40));
+    assert_eq!(integer::sub(sum.clone(), Some(1 << 40)).compute(), Some(1));
+    assert_eq!(integer::from_float::<integer::Checked>(1e30).compute(), None);
+    assert_eq!(integer::from_float::<integer::Saturate>(-2.5).compute().to_i64(), Some(-3));
+
+    // division by zero is defined, as any input value can be a divisor
+    let divisor = create_typed_input(integer::Wrap::from_i64(0));
+    assert_eq!(integer::div(wrapping.clone(), divisor.clone()).compute().to_i64(), Some(0));
+    assert_eq!(integer::rem(wrapping.clone(), divisor).compute().to_i64(), Some(0));
+    let divisor = create_typed_input(integer::Saturate::from_i64(0));
+    assert_eq!(integer::div(saturating.clone(), divisor.clone()).compute().to_i64(), Some(0));
+    assert_eq!(integer::rem(saturating.clone(), divisor.clone()).compute().to_i64(), Some(0));
+    saturating.set(integer::Saturate::from_i64(i64::MIN));
+    divisor.set(integer::Saturate::from_i64(-1));
+    assert_eq!(integer::div(saturating.clone(), divisor.clone()).compute().to_i64(), Some(i64::MAX));
+    assert_eq!(integer::rem(saturating, divisor).compute().to_i64(), Some(0));
+    assert_eq!(integer::div(checked, Some(0)).compute(), None);
+}
+
+#[test]
+fn logic_gating() {
+    let temperature = create_input();
+    let enabled = create_typed_input(true);
+    let overheated = logic::gt(temperature.clone(), 90.0);
+    let running = logic::and(enabled.clone(), logic::not(overheated.clone()));
+    let power = logic::select(running.clone(), mul(temperature.clone(), 2.0), 0.0);
+
+    temperature.set(20.0);
+    assert!(running.compute());
+    assert_eq!(power.compute(), 40.0);
+
+    temperature.set(95.0);
+    assert!(!running.compute());
+    assert_eq!(power.compute(), 0.0);
+    assert_eq!(logic::to_float(overheated.clone()).compute(), 1.0);
+    assert!(!logic::xor(overheated, enabled.clone()).compute());
+
+    // unused branches and short-circuited operands are never computed
+    assert_eq!(logic::select(false, never(temperature.clone()), 1.0).compute(), 1.0);
+    let never_true = logic::gt(never(temperature.clone()), 0.0);
+    assert!(!logic::and(false, never_true.clone()).compute());
+    assert!(logic::or(true, never_true).compute());
+}
+
+#[test]
+fn random_nodes_are_reproducible() {
+    let draw = |first_then_second: bool| {
+        let source = RandomSource::new(42);
+        let u = source.uniform(-1.0, 1.0);
+        let n = source.normal(10.0, 2.0);
+        if first_then_second {
+            (u.compute(), n.compute())
+        } else {
+            let n = n.compute();
+            (u.compute(), n)
+        }
+    };
+    assert_eq!(draw(true), draw(false));
+
+    let source = RandomSource::new(7);
+    let high = create_input();
+    high.set(10.0);
+    let u = source.uniform(5.0, high.clone());
+    let doubled = mul(u.clone(), 2.0);
+    let first = doubled.compute();
+    assert_eq!(doubled.compute(), first);
+    assert!((10.0..20.0).contains(&first));
+
+    source.resample();
+    assert_ne!(doubled.compute(), first);
+    source.reseed(7);
+    assert_eq!(doubled.compute(), first);
+
+    let n = source.normal(1.0, 0.5);
+    let samples: Vec<Float> = (0..4000).map(|_| {
+        source.resample();
+        n.compute()
+    }).collect();
+    let mean = samples.iter().sum::<Float>() / samples.len() as Float;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<Float>() / samples.len() as Float;
+    assert!((mean - 1.0).abs() < 0.05);
+    assert!((variance.sqrt() - 0.5).abs() < 0.05);
+}
+
+#[test]
+fn noise_nodes() {
+    let seed = create_input();
+    let x = create_input();
+    let y = create_input();
+    let generators: [fn(_, _, _) -> DynamicComputeNodeRef; 3] = [noise::value, noise::perlin, noise::simplex];
+    for generator in generators {
+        let node = generator(seed.clone(), x.clone(), y.clone());
+        let mut distinct = 0;
+        let mut previous = None;
+        for i in 0..200 {
+            x.set(i as Float * 0.37);
+            y.set(i as Float * 0.11 - 5.0);
+            let value = node.compute();
+            assert!((-1.0..=1.0).contains(&value));
+            if previous != Some(value) {
+                distinct += 1;
+            }
+            previous = Some(value);
+
+            // continuous: a tiny step barely changes the value
+            x.set(i as Float * 0.37 + 1e-3);
+            assert!((node.compute() - value).abs() < 0.05);
+        }
+        assert!(distinct > 150);
+
+        x.set(1.3);
+        y.set(2.7);
+        let before = node.compute();
+        assert_eq!(generator(seed.clone(), x.clone(), y.clone()).compute(), before);
+        seed.set(1.0);
+        assert_ne!(node.compute(), before);
+        seed.set(0.0);
+
+        // beyond the lattice coordinates
+        x.set(Float::INFINITY);
+        assert!(node.compute().is_nan());
+        x.set(-1e30);
+        assert!((-1.0..=1.0).contains(&node.compute()));
+    }
+
+    // gradient noise vanishes on the integer lattice
+    assert_eq!(noise::perlin(3.0, 4.0, -2.0).compute(), 0.0);
+}
+
+#[test]
+fn clock_ticks() {
+    let clock = Clock::new();
+    let position = add(1.0, mul(clock.time(), 3.0));
+    let speed = mul(clock.delta(), 60.0);
+    assert_eq!(position.compute(), 1.0);
+    assert_eq!(speed.compute(), 0.0);
+
+    clock.tick(0.5);
+    clock.tick(0.25);
+    assert_eq!(position.compute(), 3.25);
+    assert_eq!(speed.compute(), 15.0);
+    assert_eq!(clock.frame(), 2);
+
+    // many small steps don't accumulate rounding drift
+    for _ in 0..100_000 {
+        clock.tick(0.01);
+    }
+    assert!((clock.now() - 1000.75).abs() < 1e-3);
+
+    clock.reset();
+    assert_eq!(position.compute(), 1.0);
+    assert_eq!(clock.frame(), 0);
+}
+
+#[test]
+fn stateful_nodes() {
+    let x = create_input();
+    let sum = stateful::accumulator(x.clone());
+    let previous = stateful::delay(x.clone(), -1.0);
+    let doubled_sum = mul(sum.clone(), 2.0);
+
+    x.set(3.0);
+    assert_eq!(doubled_sum.compute(), 0.0);
+    assert_eq!(previous.compute(), -1.0);
+    sum.step(1.0);
+    previous.step(1.0);
+    x.set(4.0);
+    sum.step(1.0);
+    assert_eq!(doubled_sum.compute(), 14.0);
+    assert_eq!(previous.compute(), 3.0);
+    sum.reset();
+    previous.reset();
+    assert_eq!(doubled_sum.compute(), 0.0);
+    assert_eq!(previous.compute(), -1.0);
+
+    let area = stateful::integrator(2.0, 1.0);
+    for _ in 0..10 {
+        area.step(0.1);
+    }
+    assert!((area.compute() - 3.0).abs() < 1e-5);
+
+    // stepped by the clock: the chain of delays sees the values of the previous tick
+    let clock = Clock::new();
+    let frame = add(clock.time(), 0.0);
+    let delayed = stateful::delay(frame.clone(), 0.0);
+    let delayed_twice = stateful::delay(delayed.clone(), 0.0);
+    let speed = stateful::integrator(1.0, 0.0);
+    clock.attach(&delayed_twice);
+    clock.attach(&delayed);
+    clock.attach(&speed);
+    for _ in 0..3 {
+        clock.tick(1.0);
+    }
+    assert_eq!(frame.compute(), 3.0);
+    assert_eq!(delayed.compute(), 2.0);
+    assert_eq!(delayed_twice.compute(), 1.0);
+    assert_eq!(speed.compute(), 3.0);
+    clock.reset();
+    assert_eq!(delayed.compute(), 0.0);
+    assert_eq!(speed.compute(), 0.0);
+}
+
+#[test]
+fn feedback_through_delay() {
+    // y[n] = x[n] + 0.5 * y[n - 1]
+    let x = create_input();
+    let previous_y = stateful::FeedbackDelay::new(0.0);
+    let y = add(x.clone(), mul(0.5, previous_y.clone()));
+    previous_y.connect(&y);
+
+    x.set(1.0);
+    assert_eq!(y.compute(), 1.0);
+    for n in 1..=10 {
+        previous_y.step(1.0);
+        assert_eq!(y.compute(), 2.0 - 0.5f32.powi(n));
+    }
+    previous_y.reset();
+    assert_eq!(y.compute(), 1.0);
+
+    // Fibonacci, with both delays stepped together by the clock
+    let clock = Clock::new();
+    let a = stateful::FeedbackDelay::new(0.0);
+    let b = stateful::FeedbackDelay::new(1.0);
+    let next_b = add(a.clone(), b.clone());
+    a.connect(&(b.node() as DynamicComputeNodeRef));
+    b.connect(&next_b);
+    clock.attach(&a.node());
+    clock.attach(&b.node());
+    for _ in 0..10 {
+        clock.tick(1.0);
+    }
+    assert_eq!((a.compute(), b.compute()), (55.0, 89.0));
+
+    // the cycle is closed with a weak edge, so dropping the graph frees it
+    let weak_y = Rc::downgrade(&y);
+    drop(y);
+    assert!(weak_y.upgrade().is_none());
+    previous_y.step(1.0);
+    assert_eq!(previous_y.compute(), 0.0);
+}
+
+#[test]
+fn ode_integrators() {
+    // dy/dt = -y
+    let decay = |method| {
+        let system = OdeSystem::new(&[1.0], method);
+        system.set_derivative(0, &mul(-1.0, system.state(0)));
+        for _ in 0..10 {
+            system.step(0.1);
+        }
+        system.state(0).compute()
+    };
+    assert!((decay(ode::Method::Euler) - 0.9f32.powi(10)).abs() < 1e-5);
+    assert!((decay(ode::Method::Rk4) - (-1f32).exp()).abs() < 1e-5);
+
+    // harmonic oscillator x' = v, v' = -x, driven by a clock for one full period
+    let clock = Clock::new();
+    let oscillator = OdeSystem::new(&[1.0, 0.0], ode::Method::Rk4);
+    let (x, v) = (oscillator.state(0), oscillator.state(1));
+    let energy = add(mul(x.clone(), x.clone()), mul(v.clone(), v.clone()));
+    oscillator.set_derivative(0, &v);
+    oscillator.set_derivative(1, &mul(-1.0, x.clone()));
+    clock.attach(&oscillator.node());
+    let steps = 200;
+    for _ in 0..steps {
+        clock.tick(std::f32::consts::TAU / steps as Float);
+    }
+    assert!((x.compute() - 1.0).abs() < 1e-4);
+    assert!(v.compute().abs() < 1e-4);
+    assert!((energy.compute() - 1.0).abs() < 1e-4);
+    assert!((oscillator.time().compute() - std::f32::consts::TAU).abs() < 1e-4);
+
+    // time-dependent: dy/dt = t
+    let system = OdeSystem::new(&[0.0], ode::Method::Rk4);
+    system.set_derivative(0, &add(system.time(), 0.0));
+    for _ in 0..4 {
+        system.step(0.5);
+    }
+    assert!((system.state(0).compute() - 2.0).abs() < 1e-6);
+    system.reset();
+    assert_eq!(system.state(0).compute(), 0.0);
+    assert_eq!(system.time().compute(), 0.0);
+}
+
+#[test]
+fn fixed_point_solver() {
+    let dottie = solver::fixed_point(cos, 0.0, 1e-6, 100);
+    assert!((dottie.compute() - 0.739085).abs() < 1e-5);
+
+    // Heron's method: x = (x + a / x) / 2 converges to sqrt(a)
+    let a = create_input();
+    a.set(2.0);
+    let root = solver::fixed_point(|x| mul(0.5, add(x.clone(), div(a.clone(), x))), 1.0, 1e-6, 50);
+    let doubled = mul(root.clone(), 2.0);
+    assert!((doubled.compute() - 2.0 * 2f32.sqrt()).abs() < 1e-5);
+    a.set(9.0);
+    assert!((doubled.compute() - 6.0).abs() < 1e-5);
+    a.set(16.0);
+    assert!((root.compute() - 4.0).abs() < 1e-5);
+
+    // x = 2 - x oscillates between 0 and 2 from 0
+    let oscillating = solver::fixed_point(|x| add(2.0, mul(-1.0, x)), 0.0, 1e-6, 20);
+    assert!(oscillating.compute().is_nan());
+}
+
+#[test]
+fn newton_solver() {
+    let x = create_input();
+    let y = create_input();
+    let graph = add(mul(x.clone(), x.clone()), sin(y.clone()));
+    x.set(3.0);
+    y.set(0.0);
+
+    let gradients = autodiff::gradients(&graph).unwrap();
+    assert_eq!(gradients.wrt(&x), 6.0);
+    assert_eq!(gradients.wrt(&y), 1.0);
+    assert_eq!(gradients.wrt(&create_input()), 0.0);
+
+    let root = solver::solve_for(&graph, 2.0, &x).unwrap();
+    assert!((root - 2f32.sqrt()).abs() < 1e-5);
+    assert_eq!(x.compute(), root);
+    assert!((graph.compute() - 2.0).abs() < 1e-4);
+
+    // no real root: the input is restored
+    x.set(1.0);
+    assert!(solver::solve_for(&graph, -1.0, &x).is_err());
+    assert_eq!(x.compute(), 1.0);
+
+    let opaque = add3(x.clone(), 1.0, 2.0);
+    assert_eq!(
+        solver::solve_for(&opaque, 0.0, &x),
+        Err(solver::SolveError::NoDerivative(autodiff::NoDerivative { node: "add3" }))
+    );
+}
+
+// Least squares line y = w x + b through (1, 3) and (2, 5)
+fn line_fit_loss(w: &(impl InputNodeRef + 'static), b: &(impl InputNodeRef + 'static)) -> DynamicComputeNodeRef {
+    let residual = |x: f32, y: f32| {
+        let r = add(add(mul(w.clone(), x), b.clone()), -y);
+        mul(r.clone(), r)
+    };
+    add(residual(1.0, 3.0), residual(2.0, 5.0))
+}
+
+#[test]
+fn gradient_descent() {
+    let w = create_input();
+    let b = create_input();
+    let loss = line_fit_loss(&w, &b);
+
+    let mut sgd = optim::Sgd::new(loss.clone(), vec![w.clone(), b.clone()], 0.1);
+    let initial_loss = sgd.step().unwrap();
+    assert_eq!(initial_loss, 34.0);
+    for _ in 0..500 {
+        sgd.step().unwrap();
+    }
+    assert!(loss.compute() < 1e-6);
+    assert!((w.compute() - 2.0).abs() < 1e-2);
+    assert!((b.compute() - 1.0).abs() < 1e-2);
+}
+
+#[test]
+fn adaptive_optimizers() {
+    fn fit(mut optimizer: impl Optimizer, steps: usize) {
+        for _ in 0..steps {
+            optimizer.step().unwrap();
+        }
+    }
+    fn check(w: &impl ComputeNodeRef, b: &impl ComputeNodeRef) {
+        assert!((w.compute() - 2.0).abs() < 1e-2, "w = {}", w.compute());
+        assert!((b.compute() - 1.0).abs() < 1e-2, "b = {}", b.compute());
+    }
+
+    let (w, b) = (create_input(), create_input());
+    fit(optim::Momentum::new(line_fit_loss(&w, &b), vec![w.clone(), b.clone()], 0.02, 0.9), 500);
+    check(&w, &b);
+
+    let (w, b) = (create_input(), create_input());
+    let schedule = optim::exponential_decay(0.05, 0.999);
+    fit(optim::RmsProp::new(line_fit_loss(&w, &b), vec![w.clone(), b.clone()], 0.0).with_schedule(schedule), 2000);
+    check(&w, &b);
+
+    let (w, b) = (create_input(), create_input());
+    fit(optim::Adam::new(line_fit_loss(&w, &b), vec![w.clone(), b.clone()], 0.1), 2000);
+    check(&w, &b);
+
+    let decay = optim::step_decay(1.0, 0.5, 10);
+    assert_eq!((decay(0), decay(9), decay(10), decay(25)), (1.0, 1.0, 0.5, 0.25));
+    let cosine = optim::cosine_annealing(1.0, 0.1, 100);
+    assert_eq!((cosine(0), cosine(100), cosine(200)), (1.0, 0.1, 0.1));
+    assert!((cosine(50) - 0.55).abs() < 1e-6);
+}
+
+#[test]
+fn loss_nodes() {
+    let p = create_input();
+    let q = create_input();
+    p.set(1.0);
+    q.set(4.0);
+
+    let mse = loss::mse(vec![p.clone(), q.clone()], vec![2.0, 2.0]);
+    assert_eq!(mse.compute(), 2.5);
+    let gradients = autodiff::gradients(&mse).unwrap();
+    assert_eq!((gradients.wrt(&p), gradients.wrt(&q)), (-1.0, 2.0));
+
+    assert_eq!(loss::mae(vec![p.clone(), q.clone()], vec![2.0, 2.0]).compute(), 1.5);
+    let huber = loss::huber(vec![p.clone(), q.clone()], vec![2.0, 2.0], 1.0);
+    assert_eq!(huber.compute(), (0.5 + 1.5) / 2.0);
+    let gradients = autodiff::gradients(&huber).unwrap();
+    assert_eq!((gradients.wrt(&p), gradients.wrt(&q)), (-0.5, 0.5));
+
+    // saturated logits stay finite
+    p.set(100.0);
+    let cross_entropy = loss::cross_entropy(vec![p.clone()], vec![0.0]);
+    assert_eq!(cross_entropy.compute(), 100.0);
+    p.set(0.0);
+    assert!((cross_entropy.compute() - 2f32.ln()).abs() < 1e-6);
+    assert_eq!(autodiff::gradients(&cross_entropy).unwrap().wrt(&p), 0.5);
+
+    let mut sgd = optim::Sgd::new(mse.clone(), vec![p.clone(), q.clone()], 0.25);
+    for _ in 0..50 {
+        sgd.step().unwrap();
+    }
+    assert!(mse.compute() < 1e-6);
+}
+
+#[test]
+fn dense_network() {
+    let x = [create_input(), create_input()];
+    x[0].set(2.0);
+    x[1].set(-1.0);
+    let layer = nn::Dense::new(2, 1, 7);
+    let parameters = layer.parameters();
+    let (w0, w1, b) = (parameters[0].compute(), parameters[1].compute(), parameters[2].compute());
+    assert_eq!(b, 0.0);
+    let y = layer.forward(&x).remove(0);
+    assert_eq!(y.compute(), 2.0 * w0 - w1);
+    let gradients = autodiff::gradients(&y).unwrap();
+    assert_eq!((gradients.wrt(&parameters[0]), gradients.wrt(&parameters[2])), (2.0, 1.0));
+    assert_eq!(gradients.wrt(&x[1]), w1);
+
+    assert_eq!((nn::relu(-1.0).compute(), nn::relu(2.0).compute()), (0.0, 2.0));
+    assert_eq!(nn::sigmoid(0.0).compute(), 0.5);
+    assert_eq!(autodiff::gradients(&nn::sigmoid(x[0].clone())).unwrap().wrt(&x[0]), nn::sigmoid(2.0).compute() * (1.0 - nn::sigmoid(2.0).compute()));
+
+    // XOR needs the hidden layer
+    let hidden = nn::Dense::new(2, 4, 1);
+    let output = nn::Dense::new(4, 1, 2);
+    let samples = [([0.0, 0.0], 0.0), ([0.0, 1.0], 1.0), ([1.0, 0.0], 1.0), ([1.0, 1.0], 0.0)];
+    let predictions: Vec<_> = samples.iter().map(|(input, _)| {
+        let h: Vec<_> = hidden.forward(input).into_iter().map(nn::tanh).collect();
+        output.forward(&h).remove(0)
+    }).collect();
+    let loss = loss::mse(predictions.clone(), samples.iter().map(|&(_, target)| target).collect());
+    let parameters = hidden.parameters().into_iter().chain(output.parameters()).collect();
+    let mut adam = optim::Adam::new(loss.clone(), parameters, 0.05);
+    for _ in 0..500 {
+        adam.step().unwrap();
+    }
+    for (prediction, (_, target)) in predictions.iter().zip(samples) {
+        assert!((prediction.compute() - target).abs() < 0.1, "{} vs {}", prediction.compute(), target);
+    }
+}
+
+#[test]
+fn training_loop() {
+    let (w, b) = (create_input(), create_input());
+    let (x, y) = (create_input(), create_input());
+    let residual = add(add(mul(w.clone(), x.clone()), b.clone()), mul(y.clone(), -1.0));
+    let loss = mul(residual.clone(), residual);
+
+    let data = vec![(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)];
+    let mut sgd = optim::Sgd::new(loss, vec![w.clone(), b.clone()], 0.05);
+    let mut reported = 0;
+    let history = optim::fit(&mut sgd, &data, 100, |&(data_x, data_y)| {
+        x.set(data_x);
+        y.set(data_y);
+    }, |metrics| {
+        assert_eq!(metrics.epoch, reported);
+        reported += 1;
+    }).unwrap();
+
+    assert_eq!(reported, 100);
+    assert_eq!(history[0].batches, 4);
+    assert!(history[99].loss < history[0].loss);
+    assert!((w.compute() - 2.0).abs() < 1e-2);
+    assert!((b.compute() - 1.0).abs() < 1e-2);
+}
+
+#[test]
+fn gradient_checking() {
+    define_nodes! {
+        wrong_square(x) { x * x } grad { [x] }
+    }
+    let x = create_input();
+    let y = create_input();
+    x.set(1.5);
+    y.set(0.5);
+
+    let graph = add(mul(x.clone(), sin(y.clone())), div(x.clone(), add(y.clone(), 2.0)));
+    let checks = autodiff::check_gradients(&graph, &[x.clone(), y.clone()], 1e-2).unwrap();
+    assert!(checks.iter().all(|check| check.error < 1e-3), "{:?}", checks);
+    assert_eq!((x.compute(), y.compute()), (1.5, 0.5));
+
+    let checks = autodiff::check_gradients(&wrong_square(x.clone()), std::slice::from_ref(&x), 1e-2).unwrap();
+    assert_eq!(checks[0].analytic, 1.5);
+    assert!((checks[0].numeric - 3.0).abs() < 1e-3);
+    assert!(checks[0].error > 0.4);
+}
+
+#[test]
+fn onnx_export() {
+    fn contains(bytes: &[u8], text: &str) -> bool {
+        bytes.windows(text.len()).any(|window| window == text.as_bytes())
+    }
+    let x = create_input();
+    let clock = Clock::new();
+    let graph = add3(mul(x.clone(), 2.0), nn::tanh(x.clone()), clock.time());
+    let model = onnx::export(&graph);
+
+    for op in ["Mul", "Tanh", "add3", onnx::CUSTOM_DOMAIN, "rust_compgraph.custom_ops", "external"] {
+        assert!(contains(&model, op), "{} missing", op);
+    }
+    assert!(!contains(&model, "Add"));
+}
+
+#[test]
+fn onnx_round_trip() {
+    let x = create_input();
+    let y = create_input();
+    x.set(0.5);
+    y.set(2.0);
+    let shared = ops::mul(x.clone(), y.clone());
+    let graph = add3(ops::sin(shared.clone()), nn::relu(shared), ops::pow(y.clone(), 3.0));
+    let model = onnx::export(&graph);
+
+    // add3 is custom, and has to be registered for import
+    let registry = NodeRegistry::builtin();
+    assert!(matches!(onnx::import(&model, &registry), Err(onnx::ImportError::Registry(_))));
+    let mut registry = registry;
+    registry.register("add3", 3, |children| add3(children[0].clone(), children[1].clone(), children[2].clone()));
+    let imported = onnx::import(&model, &registry).unwrap();
+
+    assert_eq!(imported.inputs.len(), 2);
+    assert_eq!(imported.outputs.len(), 1);
+    let output = &imported.outputs[0].1;
+    assert_eq!(output.compute(), graph.compute());
+    // inputs are fresh, with the exported values
+    let (x2, y2) = (&imported.inputs[0].1, &imported.inputs[1].1);
+    assert_eq!((x2.compute(), y2.compute()), (0.5, 2.0));
+    x2.set(-1.0);
+    x.set(-1.0);
+    assert_eq!(output.compute(), graph.compute());
+    assert_eq!(output.compute(), (-2f32).sin() + 0.0 + 8.0);
+
+    assert!(matches!(onnx::import(&model[..model.len() - 3], &registry), Err(onnx::ImportError::Malformed(_))));
+    let constant = onnx::import(&onnx::export(&2.5), &registry).unwrap();
+    assert!(matches!(constant.outputs[0].1, Operand::Constant(2.5)));
+
+    // a tensor whose dimensions overflow, to a product of 1 if wrapped around
+    fn varint(bytes: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+    fn message(bytes: &mut Vec<u8>, field: u64, content: &[u8]) {
+        varint(bytes, field << 3 | 2);
+        varint(bytes, content.len() as u64);
+        bytes.extend_from_slice(content);
+    }
+    let mut tensor = Vec::new();
+    for dim in [3, 0xAAAA_AAAA_AAAA_AAAB] {
+        varint(&mut tensor, 1 << 3);
+        varint(&mut tensor, dim);
+    }
+    tensor.push(4 << 3 | 5);
+    tensor.extend_from_slice(&1f32.to_le_bytes());
+    message(&mut tensor, 8, b"c");
+    let (mut graph, mut model) = (Vec::new(), Vec::new());
+    message(&mut graph, 5, &tensor);
+    message(&mut model, 7, &graph);
+    assert!(matches!(onnx::import(&model, &registry), Err(onnx::ImportError::Malformed("tensor dimensions"))));
+}
+
+#[test]
+fn graph_diff() {
+    let x = create_input();
+    let shared = ops::sin(x.clone());
+    let old = ops::add(ops::mul(x.clone(), 2.0), shared.clone());
+    let new = ops::add(ops::mul(x.clone(), 3.0), ops::min(shared.clone(), 1.0));
+
+    assert!(graph::diff(&old, &old).is_empty());
+    let diff = graph::diff(&old, &new);
+    assert_eq!(diff.changes, vec![
+        Change::ChangedConstant { path: vec![0, 1], old: 2.0, new: 3.0 },
+        Change::ChangedNode { path: vec![1], old: "sin".into(), new: "min".into() },
+        Change::Removed { path: vec![1, 0], node: "input".into() },
+        Change::Added { path: vec![1, 0], node: "sin".into() },
+        Change::Added { path: vec![1, 1], node: "1".into() }
+    ]);
+    let dot = diff.to_dot();
+    assert!(dot.starts_with("digraph diff {"));
+    assert!(dot.contains("label=\"2 -> 3\", color=orange"));
+    assert!(dot.contains("label=\"1\", color=green"));
+    assert!(dot.contains("label=\"input\", color=red"));
+
+    // same operands, different operation
+    let diff = graph::diff(&ops::add(x.clone(), 1.0), &ops::sub(x.clone(), 1.0));
+    assert_eq!(diff.changes, vec![Change::ChangedNode { path: vec![], old: "add".into(), new: "sub".into() }]);
+
+    // diamonds are compared and drawn once per node, and shared nodes aren't looked into
+    let diamonds = |scale: Float| (0..40).fold(ops::mul(x.clone(), scale), |node, _| ops::add(node.clone(), node));
+    let diff = graph::diff(&diamonds(2.0), &diamonds(3.0));
+    assert_eq!(diff.changes, vec![Change::ChangedConstant { path: [vec![0; 40], vec![1]].concat(), old: 2.0, new: 3.0 }]);
+    assert_eq!(diff.to_dot().matches("label=").count(), 40 + 3);
+    let deep = diamonds(2.0);
+    let diff = graph::diff(&ops::add(deep.clone(), 1.0), &ops::add(deep, 2.0));
+    assert_eq!(diff.to_dot().matches("label=").count(), 3);
+}
+
+#[test]
+fn graph_cloning() {
+    let x = create_input();
+    let y = create_input();
+    x.set(1.0);
+    y.set(2.0);
+    let sum = ops::add(x.clone(), y.clone());
+    let counter = stateful::accumulator(x.clone());
+    let graph = ops::add(ops::mul(sum.clone(), sum), loss::mse(vec![x.clone()], vec![y.clone()]));
+    let graph = ops::add(graph, counter.clone());
+    assert_eq!(graph.compute(), 10.0);
+
+    let (copy, inputs) = graph::clone_graph(&graph);
+    assert_eq!(inputs.len(), 2);
+    assert_eq!(copy.compute(), 10.0);
+    let (x2, y2) = (inputs.get(&x).unwrap(), inputs.get(&y).unwrap());
+    x2.set(0.0);
+    y2.set(3.0);
+    assert_eq!(copy.compute(), 9.0 + 9.0);
+    assert_eq!(graph.compute(), 10.0);
+    assert!(graph::diff(&graph, &copy).is_empty());
+
+    // the stateful node is shared
+    counter.step(1.0);
+    assert_eq!(copy.compute(), 19.0);
+    assert_eq!(graph.compute(), 11.0);
+    assert!(inputs.get(&create_input()).is_none());
+}
+
+#[test]
+fn subgraph_extraction() {
+    let x = create_input();
+    let y = create_input();
+    x.set(1.0);
+    y.set(2.0);
+    let inner = ops::mul(x.clone(), y.clone());
+    let scaled = ops::mul(inner.clone(), 3.0);
+    let graph = ops::add(ops::sin(scaled.clone()), y.clone());
+
+    // sin(_ * 3) + y as a function of the cut point
+    let (subgraph, inputs) = graph::extract(&graph, std::slice::from_ref(&inner));
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0].compute(), 2.0);
+    assert_eq!(subgraph.compute(), graph.compute());
+    inputs[0].set(0.0);
+    assert_eq!(subgraph.compute(), 2.0);
+    // y isn't on the boundary, so it's shared
+    y.set(5.0);
+    assert_eq!(subgraph.compute(), 5.0);
+
+    // the original graph is untouched
+    assert_eq!(graph.compute(), 15f32.sin() + 5.0);
+    let (whole, inputs) = graph::extract(&graph, &[x.as_dynamic().unwrap(), y.as_dynamic().unwrap()]);
+    inputs[0].set(2.0);
+    assert_eq!(whole.compute(), 30f32.sin() + 5.0);
+}
+
+#[test]
+fn node_replacement() {
+    let x = create_input();
+    x.set(2.0);
+    let square = ops::mul(x.clone(), x.clone());
+    let graph = ops::add(square.clone(), 1.0);
+    assert_eq!(graph.compute(), 5.0);
+
+    graph::replace(&square, &ops::exp(x.clone())).unwrap();
+    assert_eq!(graph.compute(), 2f32.exp() + 1.0);
+    // the consumers now follow the new node's inputs
+    x.set(0.0);
+    assert_eq!(graph.compute(), 2.0);
+    assert_eq!(square.borrow().name(), "exp");
+    let gradients = autodiff::gradients(&graph).unwrap();
+    assert_eq!(gradients.wrt(&x), 1.0);
+
+    // nodes subscribing later follow it too
+    let doubled = ops::mul(square.clone(), 2.0);
+    assert_eq!(doubled.compute(), 2.0);
+    x.set(1.0);
+    assert_eq!(doubled.compute(), 2.0 * 1f32.exp());
+
+    assert_eq!(graph::replace(&square, &graph), Err(graph::ReplaceError::Cycle));
+    assert_eq!(
+        graph::replace(&x.as_dynamic().unwrap(), &ops::exp(3.0)),
+        Err(graph::ReplaceError::Unsupported { node: "input" })
+    );
+}
+
+#[test]
+fn graph_templates() {
+    let scale = create_input();
+    scale.set(2.0);
+    // a * scale + exp(1)
+    let template = graph::GraphTemplate::new(1, |inputs| {
+        ops::add(ops::mul(inputs[0].clone(), scale.clone()), ops::exp(1.0))
+    });
+    assert_eq!(template.arity(), 1);
+
+    let x = create_input();
+    x.set(3.0);
+    let first = template.instantiate(&[Operand::Node(x.as_dynamic().unwrap())]);
+    let second = template.instantiate(&[Operand::Constant(5.0)]);
+    assert_eq!(first.compute(), 6.0 + 1f32.exp());
+    assert_eq!(second.compute(), 10.0 + 1f32.exp());
+    x.set(1.0);
+    scale.set(3.0);
+    assert_eq!(first.compute(), 3.0 + 1f32.exp());
+    assert_eq!(second.compute(), 15.0 + 1f32.exp());
+
+    let constant_part = |instance: &Operand| match instance.as_child() {
+        Child::Node(node) => match &node.borrow().children()[1] {
+            Child::Node(child) => node_address(child),
+            _ => unreachable!()
+        },
+        _ => unreachable!()
+    };
+    assert_ne!(constant_part(&first), constant_part(&second));
+    let template = template.with_shared_constants(true);
+    let (first, second) = (template.instantiate(&[Operand::Constant(1.0)]), template.instantiate(&[Operand::Constant(2.0)]));
+    assert_eq!(constant_part(&first), constant_part(&second));
+    assert_eq!(second.compute(), 6.0 + 1f32.exp());
+}
+
+#[test]
+fn specialization() {
+    let x = create_input();
+    let y = create_input();
+    x.set(1.0);
+    y.set(1.0);
+    let graph = ops::add(ops::mul(x.clone(), y.clone()), ops::mul(ops::sin(y.clone()), ops::exp(1.0)));
+
+    let specialized = graph::specialize(&graph, &[(y.as_dynamic().unwrap(), 2.0)]).unwrap();
+    let folded = 2f32.sin() * 1f32.exp();
+    assert_eq!(specialized.compute(), 2.0 + folded);
+    x.set(3.0);
+    assert_eq!(specialized.compute(), 6.0 + folded);
+    // x * 2 + constant, with the input shared
+    let node = specialized.as_dynamic().unwrap();
+    assert_eq!(compgraph::topological_order(&node).len(), 3);
+    assert!(matches!(node.borrow().children()[1], Child::Constant(value) if value == folded));
+    // the original graph still depends on y
+    assert_eq!(graph.compute(), 3.0 + 1f32.sin() * 1f32.exp());
+
+    let constant = graph::specialize(&graph, &[(x.as_dynamic().unwrap(), 0.5), (y.as_dynamic().unwrap(), 4.0)]).unwrap();
+    assert!(constant.as_dynamic().is_none());
+    assert_eq!(constant.compute(), 2.0 + 4f32.sin() * 1f32.exp());
+
+    // nodes of the node libraries are rebuilt, or fail when they can't be
+    let product = numeric::mul(x.clone(), numeric::add(y.clone(), 1.0));
+    let specialized = graph::specialize(&product, &[(y.as_dynamic().unwrap(), 2.0)]).unwrap();
+    assert_eq!(specialized.compute(), 9.0);
+    y.set(5.0);
+    assert_eq!(specialized.compute(), 9.0);
+    let rounded = integer::to_float(integer::from_float::<integer::Wrap>(y.clone()));
+    assert!(matches!(graph::specialize(&rounded, &[(y.as_dynamic().unwrap(), 2.0)]), Err(graph::NotRebuildable { node: "node" })));
+}
+
+#[test]
+fn sensitivity_report() {
+    let a = create_input();
+    let b = create_input();
+    let c = create_input();
+    a.set(2.0);
+    b.set(20.0);
+    c.set(0.0);
+    // a^2 + b / 10 + c
+    let graph = add(add(mul(a.clone(), a.clone()), div(b.clone(), 10.0)), c.clone());
+    let inputs = [("a", a.clone()), ("b", b.clone()), ("c", c.clone())];
+
+    let report = analysis::sensitivity(&graph, &inputs, 0.01, SensitivityMethod::Gradient).unwrap();
+    let names: Vec<&str> = report.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+    assert_eq!(report[0].derivative, 4.0);
+    assert!((report[0].elasticity - 8.0 / 6.0).abs() < 1e-6);
+    assert!((report[1].change - 0.02).abs() < 1e-6);
+
+    let perturbed = analysis::sensitivity(&graph, &inputs, 0.01, SensitivityMethod::Perturbation).unwrap();
+    for (estimate, measured) in report.iter().zip(&perturbed) {
+        assert_eq!(estimate.name, measured.name);
+        assert!((estimate.change - measured.change).abs() < 1e-3, "{:?} {:?}", estimate, measured);
+    }
+    assert_eq!((a.compute(), b.compute(), c.compute()), (2.0, 20.0, 0.0));
+}
+
+#[test]
+fn monte_carlo() {
+    let length = create_input();
+    let width = create_input();
+    length.set(1.0);
+    width.set(1.0);
+    let perimeter = add(mul(length.clone(), 2.0), mul(width.clone(), 2.0));
+    let inputs = [
+        (length.clone(), Distribution::Normal { mean: 10.0, std_dev: 0.1 }),
+        (width.clone(), Distribution::Uniform { low: 4.0, high: 6.0 })
+    ];
+
+    let result = analysis::monte_carlo(&perimeter, &inputs, 20000, 7);
+    // mean 2 * 10 + 2 * 5, variance 4 * 0.01 + 4 * 4 / 12
+    assert!((result.mean - 30.0).abs() < 0.05, "{}", result.mean);
+    assert!((result.variance - (0.04 + 4.0 / 3.0)).abs() < 0.05, "{}", result.variance);
+    assert!(result.min() > 27.0 && result.max() < 33.0);
+    assert!((result.percentile(50.0) - 30.0).abs() < 0.1);
+    assert!(result.percentile(5.0) < result.percentile(95.0));
+    assert_eq!(result.percentile(0.0), result.min());
+    assert_eq!(result.samples().len(), 20000);
+    assert_eq!((length.compute(), width.compute()), (1.0, 1.0));
+    assert_eq!(analysis::monte_carlo(&perimeter, &inputs, 100, 7).mean, analysis::monte_carlo(&perimeter, &inputs, 100, 7).mean);
+
+    let tolerance = [(length.clone(), Distribution::Triangular { low: 9.0, mode: 10.0, high: 12.0 })];
+    let result = analysis::monte_carlo(&length, &tolerance, 20000, 1);
+    assert!(result.min() >= 9.0 && result.max() <= 12.0);
+    assert!((result.mean - 31.0 / 3.0).abs() < 0.02, "{}", result.mean);
+}
+
+#[test]
+fn streaming_statistics() {
+    let clock = Clock::new();
+    let x = create_input();
+    let count = stateful::running_count(x.clone());
+    let mean = stateful::running_mean(x.clone());
+    let variance = stateful::running_variance(x.clone());
+    let low = stateful::running_min(x.clone());
+    let high = stateful::running_max(x.clone());
+    for node in [&count, &mean, &variance, &low, &high] {
+        clock.attach(node);
+    }
+    assert_eq!(count.compute(), 0.0);
+    assert!(mean.compute().is_nan() && low.compute().is_nan());
+    assert_eq!(variance.compute(), 0.0);
+
+    let spread = ops::sub(high.clone(), low.clone());
+    for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+        x.set(value);
+        clock.tick(1.0);
+    }
+    assert_eq!(count.compute(), 8.0);
+    assert_eq!(mean.compute(), 5.0);
+    assert!((variance.compute() - 32.0 / 7.0).abs() < 1e-5);
+    assert_eq!((low.compute(), high.compute()), (2.0, 9.0));
+    assert_eq!(spread.compute(), 7.0);
+
+    clock.reset();
+    assert_eq!(count.compute(), 0.0);
+    assert!(spread.compute().is_nan());
+}
+
+#[test]
+fn expressions() {
+    assert_eq!(expr::parse("-2 ^ 2").unwrap().build(&NodeRegistry::builtin(), &mut |_| None).unwrap().compute(), -4.0);
+    assert_eq!(expr::parse("2 ^ 3 ^ 2").unwrap().build(&NodeRegistry::builtin(), &mut |_| None).unwrap().compute(), 512.0);
+    let parsed = expr::parse("x * (1.5e1 - y) / max(x, 2)").unwrap();
+    assert_eq!(parsed.variables(), ["x", "y"]);
+    assert!(matches!(&parsed, Expr::Call { function, .. } if function == "div"));
+
+    let x = create_input();
+    x.set(3.0);
+    let graph = parsed.build(&NodeRegistry::builtin(), &mut |name| match name {
+        "x" => x.as_dynamic().map(Operand::Node),
+        "y" => Some(Operand::Constant(5.0)),
+        _ => None
+    }).unwrap();
+    assert_eq!(graph.compute(), 10.0);
+    x.set(1.0);
+    assert_eq!(graph.compute(), 5.0);
+
+    assert!(matches!(expr::parse("1 +"), Err(expr::ExprError::Parse { position: 3, .. })));
+    assert!(matches!(expr::parse("(1 + 2"), Err(expr::ExprError::Parse { .. })));
+    assert!(matches!(expr::parse("1 2"), Err(expr::ExprError::Parse { position: 2, .. })));
+    let unknown = expr::parse("frobnicate(1)").unwrap().build(&NodeRegistry::builtin(), &mut |_| None);
+    assert!(matches!(unknown, Err(expr::ExprError::Registry(_))));
+}
+
+#[test]
+fn spreadsheet() {
+    let mut sheet = Sheet::new();
+    sheet.set("price", "20").unwrap();
+    sheet.set("total", "price * quantity * (1 + tax)").unwrap();
+    // referenced cells are created empty
+    assert_eq!(sheet.value("total"), Some(0.0));
+    assert_eq!(sheet.formula("quantity"), Some(""));
+    sheet.set("quantity", "3").unwrap();
+    sheet.set("tax", "0.5").unwrap();
+    assert_eq!(sheet.value("total"), Some(90.0));
+
+    // cells built on the total follow it through edits
+    let doubled = mul(sheet.node("total").unwrap(), 2.0);
+    sheet.set("price", "10").unwrap();
+    assert_eq!(doubled.compute(), 90.0);
+    sheet.set("total", "price + quantity").unwrap();
+    assert_eq!(doubled.compute(), 26.0);
+    // the old formula no longer counts
+    sheet.set("tax", "2").unwrap();
+    assert_eq!(doubled.compute(), 26.0);
+
+    sheet.set("a", "total + 1").unwrap();
+    assert_eq!(sheet.set("price", "a * 2"), Err(SheetError::Cycle { cell: "price".to_string() }));
+    assert_eq!(sheet.set("a", "a"), Err(SheetError::Cycle { cell: "a".to_string() }));
+    assert_eq!(sheet.formula("price"), Some("10"));
+    assert_eq!(sheet.value("a"), Some(14.0));
+    assert!(matches!(sheet.set("b", "sqrt(4"), Err(SheetError::Formula(_))));
+    assert_eq!(sheet.value("b"), None);
+    assert_eq!(sheet.value("missing"), None);
+}
+
+#[test]
+fn ui_bindings() {
+    let x = create_input();
+    x.set(1.0);
+    let label = Rc::new(RefCell::new(Vec::new()));
+    let wakes = Rc::new(std::cell::Cell::new(0));
+
+    let mut bindings = Bindings::new();
+    bindings.set_waker({
+        let wakes = wakes.clone();
+        move || wakes.set(wakes.get() + 1)
+    });
+    let id = bindings.bind(mul(x.clone(), x.clone()), {
+        let label = label.clone();
+        move |value| label.borrow_mut().push(value)
+    });
+    assert_eq!(*label.borrow(), [1.0]);
+    assert!(!bindings.is_dirty());
+
+    x.set(2.0);
+    x.set(3.0);
+    assert_eq!(wakes.get(), 1);
+    assert!(bindings.is_dirty());
+    assert_eq!(bindings.update(), 1);
+    assert_eq!(*label.borrow(), [1.0, 9.0]);
+
+    // changes that leave the output as it was don't reach the callback
+    x.set(-3.0);
+    assert_eq!(bindings.update(), 0);
+    assert_eq!(wakes.get(), 2);
+    assert!(bindings.unbind(id));
+    x.set(0.0);
+    assert_eq!(bindings.update(), 0);
+    assert_eq!(*label.borrow(), [1.0, 9.0]);
+}
+
+#[test]
+fn stream_sources() {
+    use std::{future::Future, pin::Pin, task::{Context, Poll, Waker}, collections::VecDeque};
+
+    // items arrive in bursts, with `None` standing for "nothing ready yet"
+    let feed = Rc::new(RefCell::new(VecDeque::from([Some(1.0), None, Some(2.0), Some(3.0), None])));
+    let (sensor, mut driver) = stream::stream_source(0.0, {
+        let feed = feed.clone();
+        move |_: &mut Context| match feed.borrow_mut().pop_front() {
+            Some(Some(value)) => Poll::Ready(Some(value)),
+            Some(None) => Poll::Pending,
+            None => Poll::Ready(None)
+        }
+    });
+    let scaled = mul(sensor.clone(), 10.0);
+    assert_eq!(scaled.compute(), 0.0);
+
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(Pin::new(&mut driver).poll(&mut cx), Poll::Pending);
+    assert_eq!(scaled.compute(), 10.0);
+    assert_eq!(Pin::new(&mut driver).poll(&mut cx), Poll::Pending);
+    assert_eq!(scaled.compute(), 30.0);
+    assert_eq!(Pin::new(&mut driver).poll(&mut cx), Poll::Ready(()));
+    assert_eq!(sensor.compute(), 3.0);
+}
+
+#[test]
+fn record_streams() {
+    struct SetCounter(usize);
+    impl InvalidateCacheMut for SetCounter {
+        fn invalidate_cache(&mut self) {
+            self.0 += 1;
+        }
+    }
+    let price = create_input();
+    let rate = create_input();
+    let rate_sets = Rc::new(RefCell::new(SetCounter(0)));
+    rate.subscribe_to_invalidate(&(rate_sets.clone() as _));
+    let converted = mul(mul(rate.clone(), 2.0), price.clone());
+    let bindings = [("price", price.clone()), ("rate", rate.clone())];
+
+    let rows = [
+        [("price", 10.0), ("rate", 1.5)],
+        [("price", 20.0), ("rate", 1.5)],
+        [("price", 30.0), ("rate", 2.0)]
+    ];
+    let outputs: Vec<Float> = stream::evaluate_stream(&converted, &rows, &bindings).collect();
+    assert_eq!(outputs, [30.0, 60.0, 120.0]);
+    // the rate was only set when it changed
+    assert_eq!(rate_sets.borrow().0, 2);
+
+    // missing fields keep their values
+    let records: Vec<std::collections::HashMap<String, Float>> = vec![
+        [("price".to_string(), 1.0)].into_iter().collect(),
+        [("rate".to_string(), 0.5)].into_iter().collect()
+    ];
+    let outputs: Vec<Float> = stream::evaluate_stream(&converted, records, &bindings).collect();
+    assert_eq!(outputs, [4.0, 1.0]);
+}
+
+#[test]
+fn audio_blocks() {
+    let volume = create_input();
+    volume.set(2.0);
+    let processor = BlockProcessor::<8>::new(|input| {
+        let boosted = audio::gain(input.clone(), volume.clone());
+        let mixed = audio::add(boosted, Block::splat(0.25));
+        audio::clip(audio::map(mixed, Float::abs), 1.0)
+    });
+
+    let input = [-1.0, -0.5, -0.25, 0.0, 0.1, 0.2, 0.3, 0.4];
+    let mut output = [0.0; 8];
+    processor.process(&input, &mut output);
+    assert_eq!(output, [1.0, 0.75, 0.25, 0.25, 0.45, 0.65, 0.85, 1.0]);
+
+    volume.set(0.0);
+    processor.process(&input, &mut output);
+    assert_eq!(output, [0.25; 8]);
+
+    let ring = audio::mul(Block([1.0, -1.0, 2.0, 0.5]), Block::splat(2.0));
+    let difference = audio::sub(ring.clone(), Block::<4>::SILENCE);
+    assert_eq!(difference.compute()[2], 4.0);
+    assert_eq!(ring.compute(), Block([2.0, -2.0, 4.0, 1.0]));
+
+    // the limit is a node that can take any value
+    let limit = create_input();
+    limit.set(-1.0);
+    let clipped = audio::clip(ring, limit.clone());
+    assert_eq!(clipped.compute(), Block([1.0, -1.0, 1.0, 1.0]));
+    limit.set(Float::NAN);
+    assert_eq!(clipped.compute(), Block([2.0, -2.0, 4.0, 1.0]));
+}
+
+#[test]
+fn dsp_nodes() {
+    let clock = Clock::new();
+    let dt = 1.0 / 8000.0;
+    // a sine at the clock's time, `frequency` Hz
+    let tone = |frequency: Float| sin(mul(clock.time(), std::f32::consts::TAU * frequency));
+    let (low, high) = (tone(50.0), tone(2000.0));
+    let filters = [
+        dsp::low_pass(low.clone(), 200.0, std::f32::consts::FRAC_1_SQRT_2),
+        dsp::low_pass(high.clone(), 200.0, std::f32::consts::FRAC_1_SQRT_2),
+        dsp::high_pass(low.clone(), 200.0, std::f32::consts::FRAC_1_SQRT_2),
+        dsp::band_pass(tone(500.0), 500.0, 2.0)
+    ];
+    for filter in &filters {
+        clock.attach(filter);
+    }
+    // the amplitude once settled
+    let mut peaks = [0.0 as Float; 4];
+    for step in 0..4000 {
+        clock.tick(dt);
+        if step >= 2000 {
+            for (peak, filter) in peaks.iter_mut().zip(&filters) {
+                *peak = peak.max(filter.compute().abs());
+            }
+        }
+    }
+    assert!(peaks[0] > 0.95 && peaks[0] < 1.05, "{:?}", peaks);
+    assert!(peaks[1] < 0.02, "{:?}", peaks);
+    assert!(peaks[2] < 0.1, "{:?}", peaks);
+    assert!(peaks[3] > 0.95 && peaks[3] < 1.05, "{:?}", peaks);
+
+    let target = create_input();
+    target.set(1.0);
+    let smoothed = dsp::one_pole(target.clone(), 0.1, 0.0);
+    for _ in 0..100 {
+        smoothed.step(0.001);
+    }
+    assert!((smoothed.compute() - (1.0 - (-1.0 as Float).exp())).abs() < 1e-3);
+
+    let gate = create_input();
+    let envelope = dsp::adsr(gate.clone(), 0.1, 0.1, 0.5, 0.2);
+    let run = |seconds: Float| {
+        for _ in 0..(seconds * 1000.0).round() as usize {
+            envelope.step(0.001);
+        }
+        envelope.compute()
+    };
+    gate.set(1.0);
+    assert!((run(0.05) - 0.5).abs() < 1e-3);
+    // within a step of the end of the attack
+    assert!((run(0.1) - 0.75).abs() < 1e-2);
+    assert!((run(0.5) - 0.5).abs() < 1e-3);
+    gate.set(0.0);
+    assert!((run(0.1) - 0.25).abs() < 1e-3);
+    assert_eq!(run(0.2), 0.0);
+
+    // without release, off at once, even by an empty step
+    let percussive = dsp::adsr(gate.clone(), 0.0, 0.0, 1.0, 0.0);
+    gate.set(1.0);
+    percussive.step(0.001);
+    assert_eq!(percussive.compute(), 1.0);
+    gate.set(0.0);
+    percussive.step(0.0);
+    assert_eq!(percussive.compute(), 0.0);
+    gate.set(1.0);
+    percussive.step(0.001);
+    assert_eq!(percussive.compute(), 1.0);
+}
+
+#[test]
+fn fft_nodes() {
+    let signal = create_typed_input(Block::<16>::SILENCE);
+    let spectrum = audio::spectrum(signal.clone());
+    let (magnitude, phase) = (audio::magnitude(spectrum.clone()), audio::phase(spectrum.clone()));
+
+    // a cosine in bin 2, and a sine in bin 5 at half the amplitude
+    signal.set(Block(std::array::from_fn(|n| {
+        let t = n as Float / 16.0 * std::f32::consts::TAU;
+        (2.0 * t).cos() + 0.5 * (5.0 * t).sin()
+    })));
+    let bins = magnitude.compute();
+    for k in 0..16 {
+        let expected = match k { 2 | 14 => 8.0, 5 | 11 => 4.0, _ => 0.0 };
+        assert!((bins[k] - expected).abs() < 1e-4, "bin {}: {}", k, bins[k]);
+    }
+    assert!(phase.compute()[2].abs() < 1e-4);
+    assert!((phase.compute()[5] + std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+
+    signal.set(Block::splat(1.0));
+    assert_eq!(magnitude.compute()[0], 16.0);
+    assert_eq!(spectrum.compute().im, [0.0; 16]);
+}
+
+#[test]
+fn interpolation() {
+    let t = create_input();
+    t.set(0.25);
+    let blend = interp::lerp(10.0, 20.0, t.clone());
+    assert_eq!(blend.compute(), 12.5);
+    assert_eq!(autodiff::gradients(&blend).unwrap().wrt(&t), 10.0);
+
+    let eased = interp::smoothstep(0.0, 2.0, t.clone());
+    t.set(1.0);
+    assert_eq!(eased.compute(), 0.5);
+    t.set(3.0);
+    assert_eq!(eased.compute(), 1.0);
+    t.set(0.5);
+    let checks = autodiff::check_gradients(&eased, std::slice::from_ref(&t), 1e-2).unwrap();
+    assert!(checks[0].error < 1e-3, "{:?}", checks);
+
+    // the peak's height is an input
+    let peak = create_input();
+    peak.set(1.0);
+    let x = create_input();
+    let curve = interp::spline(x.clone(), vec![(2.0, Operand::Constant(0.0)), (0.0, Operand::Constant(0.0)), (1.0, Operand::Node(peak.as_dynamic().unwrap()))]);
+    x.set(0.5);
+    assert!((curve.compute() - 0.6875).abs() < 1e-6);
+    x.set(1.0);
+    assert_eq!(curve.compute(), 1.0);
+    peak.set(2.0);
+    x.set(1.5);
+    assert!((curve.compute() - 1.375).abs() < 1e-6);
+    // held outside the points
+    x.set(-1.0);
+    assert_eq!(curve.compute(), 0.0);
+    x.set(Float::NAN);
+    assert!(curve.compute().is_nan());
+
+    let line = interp::spline(x.clone(), vec![(0.0, 1.0), (1.0, 3.0), (4.0, 9.0)]);
+    x.set(2.5);
+    assert!((line.compute() - 6.0).abs() < 1e-5);
+    let (copy, _) = graph::clone_graph(&line);
+    assert_eq!(copy.compute(), line.compute());
+}
+
+#[test]
+fn lookup_tables() {
+    let x = create_input();
+    let table = interp::LookupTable::sample(|x| x * x, 0.0, 4.0, 5);
+    let square = interp::lookup(x.clone(), table.clone());
+    x.set(2.5);
+    assert_eq!(square.compute(), 6.5);
+    assert_eq!(autodiff::gradients(&square).unwrap().wrt(&x), 5.0);
+    x.set(4.0);
+    assert_eq!(square.compute(), 16.0);
+    x.set(5.0);
+    assert_eq!(square.compute(), 16.0);
+    assert_eq!(autodiff::gradients(&square).unwrap().wrt(&x), 0.0);
+
+    let extrapolated = interp::lookup(x.clone(), table.clone().with_out_of_range(interp::OutOfRange::Extrapolate));
+    assert_eq!(extrapolated.compute(), 23.0);
+    let strict = interp::lookup(x.clone(), table.clone().with_out_of_range(interp::OutOfRange::Nan));
+    assert!(strict.compute().is_nan());
+    // one period of a triangle wave
+    let wave = interp::lookup(x.clone(), interp::LookupTable::new(0.0, 1.0, vec![0.0, 1.0, 0.0]).with_out_of_range(interp::OutOfRange::Wrap));
+    x.set(2.25);
+    assert_eq!(wave.compute(), 0.5);
+    x.set(-0.25);
+    assert_eq!(wave.compute(), 0.5);
+    x.set(-1.0);
+    assert_eq!((wave.compute(), strict.compute().is_nan(), extrapolated.compute()), (0.0, true, -1.0));
+}
+
+#[test]
+#[should_panic(expected = "lookup table: at least two values needed")]
+fn lookup_table_of_no_samples() {
+    interp::LookupTable::sample(|x| x, 0.0, 1.0, 0);
+}
+
+#[test]
+fn piecewise_functions() {
+    // 10% up to the threshold, 30% above it
+    let income = create_input();
+    let threshold = create_input();
+    threshold.set(1000.0);
+    let tax = interp::piecewise(income.clone(), vec![threshold.clone()], vec![
+        mul(income.clone(), 0.1),
+        add(100.0, mul(ops::sub(income.clone(), threshold.clone()), 0.3))
+    ]);
+    income.set(500.0);
+    assert_eq!(tax.compute(), 50.0);
+    income.set(2000.0);
+    assert_eq!(tax.compute(), 400.0);
+    assert!((autodiff::gradients(&tax).unwrap().wrt(&income) - 0.3).abs() < 1e-6);
+
+    // moving the breakpoint invalidates the result
+    threshold.set(3000.0);
+    assert_eq!(tax.compute(), 200.0);
+    assert!((autodiff::gradients(&tax).unwrap().wrt(&income) - 0.1).abs() < 1e-6);
+
+    // the unselected segments aren't computed
+    let x = create_input();
+    let guarded = interp::piecewise(x.clone(), vec![0.0], vec![Operand::Constant(0.0), Operand::Node(never(x.clone()))]);
+    x.set(-1.0);
+    assert_eq!(guarded.compute(), 0.0);
+}
+
+#[test]
+fn slew_limiting() {
+    let clock = Clock::new();
+    let knob = create_input();
+    let smoothed = dsp::slew_limiter(knob.clone(), 2.0, 4.0, 0.0);
+    clock.attach(&smoothed);
+
+    knob.set(1.0);
+    clock.tick(0.25);
+    assert_eq!(smoothed.compute(), 0.5);
+    clock.tick(0.25);
+    clock.tick(0.25);
+    assert_eq!(smoothed.compute(), 1.0);
+    // falls twice as fast
+    knob.set(-1.0);
+    clock.tick(0.25);
+    assert_eq!(smoothed.compute(), 0.0);
+    clock.reset();
+    assert_eq!(smoothed.compute(), 0.0);
+    clock.tick(Float::NAN);
+    assert_eq!(smoothed.compute(), -1.0);
+}
+
+#[test]
+#[should_panic(expected = "rates must be non-negative")]
+fn slew_limiting_negative_rate() {
+    dsp::slew_limiter(create_input(), 2.0, -4.0, 0.0);
+}
+
+#[test]
+fn keyframe_animation() {
+    let clock = Clock::new();
+    let track = Track::new()
+        .keyframe(2.0, 10.0, Easing::EaseInOut)
+        .keyframe(0.0, 0.0, Easing::Linear)
+        .keyframe(3.0, 0.0, Easing::Step);
+    let position = clock.animate(track.clone());
+    let doubled = mul(position.clone(), 2.0);
+    assert_eq!(doubled.compute(), 0.0);
+
+    clock.tick(1.0);
+    assert_eq!(position.compute(), 5.0);
+    assert_eq!(autodiff::gradients(&position).unwrap().wrt(&clock.time()), 0.0);
+    clock.tick(0.5);
+    assert_eq!(doubled.compute(), 2.0 * (10.0 * 0.84375));
+    // held until the step
+    clock.tick(1.0);
+    assert_eq!(position.compute(), 10.0);
+    clock.tick(1.0);
+    assert_eq!(position.compute(), 0.0);
+
+    let looping = time::animation(clock.time(), track.looping(true));
+    assert_eq!(clock.now(), 3.5);
+    // 0.5 into the second loop
+    assert_eq!(looping.compute(), 10.0 * 0.25 * 0.25 * (3.0 - 0.5));
+    let t = create_input();
+    t.set(0.5);
+    let linear = time::animation(t.clone(), Track::new().keyframe(0.0, 1.0, Easing::Linear).keyframe(1.0, 3.0, Easing::Linear));
+    assert_eq!(autodiff::gradients(&linear).unwrap().wrt(&t), 2.0);
+}
+
+#[test]
+fn frame_scheduler() {
+    let mut scheduler = Scheduler::new(4.0);
+    let speed = create_input();
+    let sender = scheduler.channel(speed.clone());
+    let distance = mul(scheduler.clock().time(), speed);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    scheduler.listen(distance, move |value| log.borrow_mut().push(value));
+
+    sender.send(1.0).unwrap();
+    sender.send(2.0).unwrap();
+    assert_eq!(scheduler.frame(), 1);
+    assert_eq!(*seen.borrow(), [0.0, 0.5]);
+
+    let worker = std::thread::spawn(move || sender.send(4.0).unwrap());
+    worker.join().unwrap();
+    let mut frames = 0;
+    scheduler.run(|_| { frames += 1; frames <= 2 });
+    assert_eq!(scheduler.clock().frame(), 3);
+    assert_eq!(*seen.borrow(), [0.0, 0.5, 2.0, 3.0]);
+}
+
+#[test]
+fn binding_priorities() {
+    let temperature = create_input();
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let mut bindings = Bindings::new();
+    let log = order.clone();
+    bindings.bind(mul(temperature.clone(), 2.0), move |_| log.borrow_mut().push("gauge"));
+    let log = order.clone();
+    bindings.bind_with_priority(temperature.clone(), 10, move |_| log.borrow_mut().push("interlock"));
+    let log = order.clone();
+    bindings.bind_with_priority(add(temperature.clone(), 1.0), -1, move |_| log.borrow_mut().push("label"));
+    let log = order.clone();
+    bindings.bind(div(temperature.clone(), 3.0), move |_| log.borrow_mut().push("chart"));
+    order.borrow_mut().clear();
+
+    temperature.set(90.0);
+    assert_eq!(bindings.update(), 4);
+    assert_eq!(*order.borrow(), ["interlock", "gauge", "chart", "label"]);
+}
+
+#[test]
+fn rate_limiting() {
+    let clock = Clock::new();
+    let sensor = create_input();
+    let throttled = mul(time::throttle(sensor.clone(), &clock, 1.0), 10.0);
+    let debounced = time::debounce(sensor.clone(), &clock, 0.5);
+    assert_eq!(throttled.compute(), 0.0);
+
+    // the first change passes at once
+    sensor.set(1.0);
+    assert_eq!(throttled.compute(), 10.0);
+    clock.tick(0.25);
+    sensor.set(2.0);
+    assert_eq!(throttled.compute(), 10.0);
+    clock.tick(0.25);
+    sensor.set(3.0);
+    assert_eq!(throttled.compute(), 10.0);
+    clock.tick(0.5);
+    assert_eq!(throttled.compute(), 30.0);
+
+    assert_eq!(debounced.compute(), 3.0);
+    sensor.set(4.0);
+    clock.tick(0.25);
+    sensor.set(5.0);
+    clock.tick(0.25);
+    assert_eq!(debounced.compute(), 3.0);
+    clock.tick(0.5);
+    assert_eq!(debounced.compute(), 5.0);
+    assert_eq!(graph::clone_graph(&debounced).0.compute(), 5.0);
+
+    // a computed node passes on every change, not only the first
+    let debounced = time::debounce(add(&sensor, 1.0), &clock, 0.5);
+    assert_eq!(debounced.compute(), 6.0);
+    sensor.set(6.0);
+    clock.tick(0.25);
+    sensor.set(7.0);
+    clock.tick(0.25);
+    assert_eq!(debounced.compute(), 6.0);
+    clock.tick(0.25);
+    assert_eq!(debounced.compute(), 8.0);
+}
+
+#[test]
+fn equality_cutoff() {
+    struct Invalidations(usize);
+    impl InvalidateCacheMut for Invalidations {
+        fn invalidate_cache(&mut self) {
+            self.0 += 1;
+        }
+    }
+    let x = create_input();
+    let capped = cutoff(ops::min(x.clone(), 10.0));
+    let doubled = mul(capped.clone(), 2.0);
+    let invalidations = Rc::new(RefCell::new(Invalidations(0)));
+    doubled.subscribe_to_invalidate(&(invalidations.clone() as _));
+
+    x.set(20.0);
+    assert_eq!(doubled.compute(), 20.0);
+    // saturated: the changes stop at the cutoff
+    x.set(30.0);
+    x.set(40.0);
+    assert_eq!(invalidations.borrow().0, 0);
+    assert_eq!(doubled.compute(), 20.0);
+    x.set(5.0);
+    assert_eq!(invalidations.borrow().0, 1);
+    assert_eq!(doubled.compute(), 10.0);
+
+    // changes through a clock tick are checked as well
+    let clock = Clock::new();
+    let phase = cutoff(mul(clock.time(), 0.0));
+    let shifted = add(phase, 1.0);
+    assert_eq!(shifted.compute(), 1.0);
+    shifted.subscribe_to_invalidate(&(invalidations.clone() as _));
+    clock.tick(1.0);
+    assert_eq!(invalidations.borrow().0, 1);
+}
+
+#[test]
+fn diamond_invalidation() {
+    struct Invalidations(usize);
+    impl InvalidateCacheMut for Invalidations {
+        fn invalidate_cache(&mut self) {
+            self.0 += 1;
+        }
+    }
+    let x = create_input();
+    let left = mul(x.clone(), 2.0);
+    let right = add(x.clone(), 1.0);
+    let squared = mul(x.clone(), x.clone());
+    let observer = Rc::new(RefCell::new(Invalidations(0)));
+    for node in [&left, &right, &squared] {
+        node.subscribe_to_invalidate(&(observer.clone() as _));
+    }
+    let joined = add(add(left, right), squared);
+    assert_eq!(joined.compute(), 1.0);
+
+    x.set(1.0);
+    assert_eq!(observer.borrow().0, 1);
+    assert_eq!(joined.compute(), 5.0);
+    x.set(2.0);
+    assert_eq!(observer.borrow().0, 2);
+    assert_eq!(joined.compute(), 11.0);
+}
+
+#[test]
+fn node_versions() {
+    let x = create_input();
+    let y = create_input();
+    let sum = add(x.clone(), 1.0);
+    let total = mul(sum.clone(), y.clone());
+    assert_eq!((x.version(), total.version(), 2.0.version()), (0, 0, 0));
+    total.compute();
+    let seen = total.version();
+    assert_eq!(seen, 1);
+
+    x.set(3.0);
+    assert_eq!(x.version(), 1);
+    assert_eq!(total.version(), seen);
+    total.compute();
+    assert!(total.version() > seen);
+    // cached, so unchanged
+    let seen = total.version();
+    total.compute();
+    assert_eq!(total.version(), seen);
+    y.set(2.0);
+    assert_eq!(sum.version(), 2);
+    total.compute();
+    assert_eq!(sum.version(), 2);
+
+    let clock = Clock::new();
+    let time = clock.time();
+    clock.tick(0.5);
+    clock.reset();
+    assert_eq!(time.version(), 2);
+}
+
+#[test]
+fn record_and_replay() {
+    fn build() -> ([impl InputNodeRef + 'static; 2], DynamicComputeNodeRef) {
+        let price = create_input();
+        let quantity = create_input();
+        let total = mul(price.clone(), quantity.clone());
+        ([price, quantity], total)
+    }
+
+    let recorder = Recorder::new();
+    let ([price, quantity], total) = build();
+    let (price, quantity) = (recorder.record("price", price), recorder.record("quantity", quantity));
+    let mut totals = Vec::new();
+    for (p, q) in [(1.5, 2.0), (0.1, 3.0)] {
+        price.set(p);
+        quantity.set(q);
+        totals.push(total.compute());
+    }
+    let log = recorder.log();
+    assert_eq!(log.events.iter().map(|event| event.input.as_str()).collect::<Vec<_>>(), ["price", "quantity", "price", "quantity"]);
+    assert!(log.events.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+    let log = Log::parse(&log.to_string()).unwrap();
+    assert_eq!(log, recorder.log());
+    let ([price, quantity], total) = build();
+    let bindings = [("price", price), ("quantity", quantity)];
+    let mut replayed = Vec::new();
+    log.replay_with(&bindings, |event| if event.input == "quantity" { replayed.push(total.compute()) }).unwrap();
+    assert_eq!(replayed, totals);
+
+    assert_eq!(log.replay(&bindings[..1]), Err(ReplayError::UnknownInput("quantity".to_string())));
+    assert!(matches!(Log::parse("0.5 price"), Err(ReplayError::Parse { line: 1, .. })));
+}
+
+#[test]
+fn persistent_result_cache() {
+    fn build() -> ([impl InputNodeRef; 2], DynamicComputeNodeRef) {
+        let (a, b) = (create_input(), create_input());
+        let output = ops::exp(add(mul(a.clone(), 2.0), b.clone()));
+        ([a, b], output)
+    }
+    let path = std::env::temp_dir().join(format!("compgraph-cache-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let ([a, b], output) = build();
+    assert_eq!(graph::structural_hash(&output), graph::structural_hash(&build().1));
+    assert_ne!(graph::structural_hash(&output), graph::structural_hash(&ops::exp(add(mul(a.clone(), 3.0), b.clone()))));
+    let mut cache = ResultCache::open(&path).unwrap();
+    a.set(1.0);
+    assert_eq!(cache.evaluate(&output), 2.0f32.exp());
+    b.set(-2.0);
+    assert_eq!(cache.evaluate(&output), 1.0);
+    assert_eq!((cache.hits(), cache.misses()), (0, 2));
+    cache.save().unwrap();
+
+    // a later run, with the graph built anew
+    let ([a, b], output) = build();
+    let mut cache = ResultCache::open(&path).unwrap();
+    assert_eq!(cache.len(), 2);
+    a.set(1.0);
+    b.set(-2.0);
+    assert_eq!(cache.evaluate(&output), 1.0);
+    assert_eq!((cache.hits(), cache.misses()), (1, 0));
+
+    // depends on the clock, so never cached
+    let clock = Clock::new();
+    let timed = add(clock.time(), 1.0);
+    cache.evaluate(&timed);
+    clock.tick(1.0);
+    assert_eq!(cache.evaluate(&timed), 2.0);
+    assert_eq!(cache.len(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn memoized_nodes() {
+    struct Expensive {
+        x: DynamicComputeNodeRef,
+        calls: Rc<std::cell::Cell<usize>>
+    }
+    impl internals::ComputeMut for Expensive {
+        fn compute(&mut self) -> Float {
+            self.calls.set(self.calls.get() + 1);
+            self.x.compute() * 10.0
+        }
+        fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    }
+    let x = create_input();
+    let calls = Rc::new(std::cell::Cell::new(0));
+    let node = Rc::new(RefCell::new(internals::CachingNodeWrapper::new(Expensive { x: x.as_dynamic().unwrap(), calls: calls.clone() })));
+    x.subscribe_to_invalidate(&(node.clone() as _));
+    let node: DynamicComputeNodeRef = node;
+    assert!(memoize(&node, 2));
+    assert!(!memoize(&x.as_dynamic().unwrap(), 2));
+
+    for value in [1.0, 2.0, 1.0, 2.0, 1.0] {
+        x.set(value);
+        assert_eq!(node.compute(), value * 10.0);
+    }
+    assert_eq!(calls.get(), 2);
+    // evicts 2, the least recently used
+    x.set(3.0);
+    node.compute();
+    x.set(2.0);
+    assert_eq!(node.compute(), 20.0);
+    assert_eq!(calls.get(), 4);
+
+    memoize(&node, 0);
+    x.set(3.0);
+    node.compute();
+    assert_eq!(calls.get(), 5);
+}
+
+#[test]
+fn observed_outputs() {
+    let x = create_input();
+    let doubled = mul(x.clone(), 2.0);
+    let eager = Output::new(doubled.clone(), Evaluation::Eager);
+    let on_demand = Output::new(add(doubled, 1.0), Evaluation::OnDemand);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    let first = eager.listen(move |value| log.borrow_mut().push(value));
+    let (sender, receiver) = std::sync::mpsc::channel();
+    eager.forward(sender);
+
+    x.set(1.0);
+    assert_eq!(*seen.borrow(), [2.0]);
+    assert!(!eager.is_dirty() && on_demand.is_dirty());
+    // unchanged value, no notification
+    x.set(1.0);
+    assert!(eager.unlisten(first));
+    x.set(3.0);
+    assert_eq!(*seen.borrow(), [2.0]);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [2.0, 6.0]);
+
+    let log = seen.clone();
+    on_demand.listen(move |value| log.borrow_mut().push(value));
+    assert_eq!(on_demand.get(), 7.0);
+    assert!(!on_demand.refresh());
+    assert_eq!(*seen.borrow(), [2.0, 7.0]);
+    drop(receiver);
+    x.set(0.0);
+    assert_eq!(eager.get(), 0.0);
+}
+
+#[test]
+fn watched_nodes() {
+    let x = create_input();
+    let capped = ops::min(x.clone(), 10.0);
+    let mut changes = output::watch(capped.clone());
+    assert_eq!(changes.next(), None);
+
+    for value in [1.0, 2.0, 20.0, 30.0] {
+        x.set(value);
+    }
+    assert_eq!(changes.pending(), 3);
+    let events: Vec<(u64, Float)> = changes.by_ref().collect();
+    assert_eq!(events.iter().map(|&(_, value)| value).collect::<Vec<_>>(), [1.0, 2.0, 10.0]);
+    assert!(events.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(events[2].0, capped.version() - 1);
+
+    x.set(4.0);
+    x.set(5.0);
+    assert_eq!(changes.latest(), Some((capped.version(), 5.0)));
+    assert_eq!(changes.next(), None);
+}
+
+#[test]
+fn struct_outputs() {
+    define_nodes! {
+        #[uncached]
+        projectile(speed, gravity, t) -> Trajectory { height, velocity } {
+            Trajectory { height: speed * t - 0.5 * gravity * t * t, velocity: speed - gravity * t }
+        }
+    }
+    let t = create_input();
+    let Trajectory { height, velocity } = projectile(20.0, 10.0, t.clone());
+    let energy = add(mul(10.0, height.clone()), mul(0.5, mul(velocity.clone(), velocity.clone())));
+    assert_eq!((height.compute(), velocity.compute()), (0.0, 20.0));
+    t.set(1.0);
+    assert_eq!(height.compute(), 15.0);
+    assert_eq!(velocity.compute(), 10.0);
+    assert_eq!(energy.compute(), 200.0);
+    assert_eq!(Trajectory::default(), Trajectory { height: 0.0, velocity: 0.0 });
+
+    // graph algorithms see through the fields to the node's children
+    assert_eq!(height.borrow().name(), "projectile.height");
+    assert_eq!(graph::traverse(&energy).filter(|node| is_input(node.node())).count(), 1);
+    assert!(graph::to_dot(&[("energy", energy.clone())]).contains("input"));
+    let (copy, _) = graph::clone_graph(&energy);
+    assert_eq!(copy.compute(), 200.0);
+    t.set(2.0);
+    assert_eq!(graph::specialize(&velocity, &[(t.clone(), 1.0)]).unwrap().compute(), 10.0);
+    assert!(matches!(graph::specialize(&velocity, &[(t.clone(), 1.0)]), Ok(Operand::Constant(_))));
+}
+
+#[test]
+fn default_parameters() {
+    let x = create_input();
+    let blended = blend::defaults(0.0, x.clone());
+    x.set(8.0);
+    assert_eq!(blended.compute(), 2.0);
+    assert_eq!(blend(0.0, x.clone(), 0.5).compute(), 4.0);
+    // the default is a constant child
+    assert!(matches!(blended.borrow().children()[2], Child::Constant(alpha) if alpha == 0.25));
+    assert_eq!(autodiff::gradients(&blended).unwrap().wrt(&x), 0.25);
+
+    let Scaled { value, slope } = scaled::defaults(x.clone());
+    assert_eq!((value.compute(), slope.compute()), (15.0, 2.0));
+}
+
+#[test]
+fn node_attributes() {
+    let blended = blend(1.0, 2.0, 0.5);
+    assert_eq!(blended.borrow().doc(), [" Moves from `a` towards `b`", " by `alpha`"]);
+    assert!(add(1.0, 2.0).borrow().doc().is_empty());
+    assert_eq!(scaled::defaults(1.0).value.compute(), 1.0);
+}
+
+#[test]
+fn generic_nodes() {
+    let x = create_input();
+    let cubic = geometric::<3>(x.clone());
+    x.set(2.0);
+    assert_eq!(cubic.compute(), 15.0);
+    assert_eq!(autodiff::gradients(&cubic).unwrap().wrt(&x), 1.0 + 4.0 + 12.0);
+    let (copy, inputs) = graph::clone_graph(&cubic);
+    inputs.get(&x.as_dynamic().unwrap()).unwrap().set(1.0);
+    assert_eq!(copy.compute(), 4.0);
+
+    assert_eq!(shaped::<Square, 2>(x.clone(), 0.5).compute(), 4.0);
+    assert_eq!(shaped::defaults::<Square, 3>(x.clone()).compute(), 12.0);
+}
+
+#[test]
+fn node_body_scoping() {
+    mod limit {
+        pub const CEILING: crate::Float = 10.0;
+    }
+    fn clamp(x: Float, ceiling: Float) -> Float {
+        x.min(ceiling)
+    }
+    define_nodes! {
+        // `limit` is both a parameter and the module above
+        capped(x, limit) {
+            if x < 0.0 {
+                return 0.0;
+            }
+            clamp(x, limit.min(limit::CEILING))
+        } grad {
+            if x < 0.0 || x > limit.min(limit::CEILING) {
+                return [0.0, 0.0];
+            }
+            [1.0, 0.0]
+        }
+    }
+    let x = create_input();
+    let node = capped(x.clone(), 20.0);
+    x.set(-1.0);
+    assert_eq!(node.compute(), 0.0);
+    x.set(5.0);
+    assert_eq!(node.compute(), 5.0);
+    assert_eq!(autodiff::gradients(&node).unwrap().wrt(&x), 1.0);
+    x.set(15.0);
+    assert_eq!(node.compute(), 10.0);
+    assert_eq!(autodiff::gradients(&node).unwrap().wrt(&x), 0.0);
+}
+
+#[test]
+fn borrowed_children() {
+    let x = create_input();
+    let y = create_input();
+    let sum = add(&x, mul(&y, 2.0));
+    let wave = sin(&sum);
+    let blended = blend::defaults(&x, &wave);
+    x.set(1.0);
+    y.set(0.5);
+    assert_eq!(sum.compute(), 2.0);
+    assert_eq!(wave.compute(), (2.0 as Float).sin());
+    assert_eq!(blended.compute(), 1.0 + ((2.0 as Float).sin() - 1.0) * 0.25);
+    // the handles are shared with the nodes, not moved into them
+    assert_eq!(Rc::strong_count(&x), 3);
+    assert_eq!(autodiff::gradients(&sum).unwrap().wrt(&y), 2.0);
+}
+
+#[test]
+fn numeric_literals() {
+    let x = create_input();
+    let line = add(mul(&x, 3), -1);
+    let half = div(&x, 2u8);
+    let precise = mul(&x, 0.1f64);
+    x.set(2.0);
+    assert_eq!(line.compute(), 5.0);
+    assert_eq!(half.compute(), 1.0);
+    assert_eq!(precise.compute(), 2.0 * 0.1);
+    assert!(matches!(half.borrow().children()[1], Child::Constant(c) if c == 2.0));
+    assert_eq!(autodiff::gradients(&line).unwrap().wrt(&x), 3.0);
+}
+
+#[test]
+fn prelude() {
+    use crate::prelude::*;
+
+    define_nodes! {
+        squared(x) { x * x } grad { [2.0 * x] }
+    }
+    let x = create_input();
+    let y = lerp(&x, squared(&x), 0.5);
+    let output = Output::new(sqrt(&y), Evaluation::OnDemand);
+    x.set(4.0);
+    assert_eq!(output.get(), 10.0_f32.sqrt());
+    assert_eq!(gradients(&y).unwrap().wrt(&x), 0.5 + 0.5 * 8.0);
+    assert_eq!(y.borrow().name(), "lerp");
+}
+
+#[test]
+fn graph_ownership() {
+    let x = create_input();
+    let mut owner = graph::Graph::new();
+    let shared = Rc::downgrade(&owner.keep(mul(&x, 2)));
+    assert!(shared.upgrade().is_some());
+    assert_eq!(owner.keep(3.0), 3.0);
+    assert_eq!(owner.len(), 1);
+
+    let output = add(shared.upgrade().unwrap(), 1);
+    let (temporary, result) = owner.scope(|owner| {
+        let temporary = Rc::downgrade(&owner.keep(ops::sin(&x)));
+        owner.keep(shared.upgrade().unwrap());
+        (temporary, owner.len())
+    });
+    assert_eq!(result, 2);
+    assert!(temporary.upgrade().is_none());
+    assert_eq!(owner.len(), 1);
+
+    drop(output);
+    let shared_node = shared.upgrade().unwrap() as DynamicComputeNodeRef;
+    assert!(owner.release(&shared_node));
+    assert!(!owner.release(&shared_node));
+    drop(shared_node);
+    assert!(shared.upgrade().is_none());
+
+    let output = add(mul(&x, &x), 1);
+    owner.keep_all(&output);
+    assert_eq!(owner.len(), 3);
+    assert!(owner.contains(&output));
+    owner.clear();
+    assert!(owner.is_empty());
+}
+
+#[test]
+fn scoped_construction() {
+    let (built, (x, doubled, output)) = graph::Graph::build(|ctx| {
+        let x = create_input();
+        let left = ctx.prefix("left", |_| mul(&x, 2));
+        let right = ctx.prefix("right", |ctx| ctx.prefix("inner", |_| mul(&x, 2)));
+        // the same node, made once
+        assert!(Rc::ptr_eq(&left, &right));
+        assert_eq!(ctx.statistics().shared, 1);
+        ctx.share_common(false);
+        let separate = mul(&x, 2);
+        assert!(!Rc::ptr_eq(&left, &separate));
+        (x, Rc::downgrade(&left), add(&left, &separate))
+    });
+    let statistics = built.statistics();
+    assert_eq!((statistics.nodes, statistics.shared), (4, 1));
+    assert_eq!(statistics.by_name.get("mul"), Some(&2));
+    assert_eq!(statistics.by_name.get("input"), Some(&1));
+    assert_eq!(built.label(&doubled.upgrade().unwrap()), Some("left/mul"));
+    assert_eq!(built.label(&output), Some("add"));
+
+    x.set(3.0);
+    assert_eq!(output.compute(), 12.0);
+    drop(output);
+    assert!(doubled.upgrade().is_some());
+    // torn down with the graph
+    drop(built);
+    assert!(doubled.upgrade().is_none());
+    // nothing is tracked outside of builds
+    assert!(!Rc::ptr_eq(&mul(&x, 2), &mul(&x, 2)));
+
+    // nodes made during a nested build go to it, while each context works on its own build
+    let (outer, (inner, (sine, shared))) = graph::Graph::build(|outer| {
+        outer.prefix("outer", |outer| graph::Graph::build(|_| {
+            let sine = outer.keep(sin(&x));
+            outer.share_common(false);
+            (sine, Rc::ptr_eq(&mul(&x, 2), &mul(&x, 2)))
+        }))
+    });
+    assert!(shared);
+    assert_eq!((inner.len(), inner.label(&sine)), (2, Some("sin")));
+    assert_eq!((outer.len(), outer.statistics().nodes), (1, 0));
+}
+
+#[test]
+fn resource_limits() {
+    let limited = |text: &str, limits: Limits| expr::parse_with_limits(text, &limits).map(|_| ());
+    assert_eq!(limited("1 + 2 * 3", Limits::new().nodes(2)), Ok(()));
+    assert_eq!(limited("1 + 2 * 3", Limits::new().nodes(1)), Err(expr::ExprError::Limit(LimitError::Nodes { limit: 1 })));
+    assert_eq!(limited("((((1))))", Limits::new().depth(3)), Err(expr::ExprError::Limit(LimitError::Depth { limit: 3 })));
+    let long = vec!["x"; 100_000].join(" + ");
+    assert_eq!(limited(&long, Limits::new().depth(100)), Err(expr::ExprError::Limit(LimitError::Depth { limit: 100 })));
+
+    let x = create_input();
+    let output = add(mul(&x, 2), 1);
+    assert_eq!(Limits::new().nodes(3).depth(3).check(&output), Ok(()));
+    assert_eq!(Limits::new().nodes(2).check(&output), Err(LimitError::Nodes { limit: 2 }));
+    assert_eq!(Limits::new().depth(2).check(&output), Err(LimitError::Depth { limit: 2 }));
+    x.set(1.0);
+    assert_eq!(Limits::new().steps(2).evaluate(&output), Ok(3.0));
+    x.set(2.0);
+    assert_eq!(Limits::new().steps(1).evaluate(&output), Err(LimitError::Steps { limit: 1 }));
+    // what was computed stays so
+    assert_eq!(Limits::new().steps(1).evaluate(&output), Ok(5.0));
+
+    let mut sheet = Sheet::new();
+    sheet.set_limits(Limits::new().depth(3));
+    sheet.set("a", "1").unwrap();
+    sheet.set("b", "a + 1").unwrap();
+    assert_eq!(sheet.set("c", "b + 1"), Err(SheetError::Formula(expr::ExprError::Limit(LimitError::Depth { limit: 3 }))));
+    assert_eq!(sheet.formula("c"), Some(""));
+}
+
+#[test]
+fn graph_traversal() {
+    let x = create_input();
+    let total = stateful::accumulator(x.clone());
+    let output = add(mul(&x, 2), total);
+    let views: Vec<_> = graph::traverse(&output).map(|node| (node.position(), node.name(), node.kind())).collect();
+    assert_eq!(views, [
+        (0, "input", graph::NodeKind::Input),
+        (1, "mul", graph::NodeKind::Operation),
+        (2, "stateful", graph::NodeKind::Source),
+        (3, "add", graph::NodeKind::Operation)
+    ]);
+    let last = graph::traverse(&output).last().unwrap();
+    assert!(Rc::ptr_eq(last.node(), &output));
+    assert!(matches!(last.children(), [Child::Node(_), Child::Node(_)]));
+
+    #[derive(Default)]
+    struct Counts {
+        inputs: usize,
+        sources: usize,
+        constants: usize
+    }
+    impl graph::Visitor for Counts {
+        fn visit_input(&mut self, _node: &graph::NodeView) {
+            self.inputs += 1;
+        }
+        fn visit_source(&mut self, _node: &graph::NodeView) {
+            self.sources += 1;
+        }
+        fn visit_operation(&mut self, node: &graph::NodeView) {
+            self.constants += node.children().iter().filter(|child| matches!(child, Child::Constant(_))).count();
+        }
+    }
+    let mut counts = Counts::default();
+    graph::visit(&output, &mut counts);
+    assert_eq!((counts.inputs, counts.sources, counts.constants), (1, 1, 1));
+}
+
+#[test]
+fn node_ids() {
+    let x = create_input();
+    let output = add(mul(&x, 2), 1);
+    let x_dynamic = x.clone() as DynamicComputeNodeRef;
+    let id = graph::node_id(&output);
+    assert_eq!(graph::node_id(&output), id);
+    assert_ne!(graph::node_id(&x_dynamic), id);
+    assert!(Rc::ptr_eq(&graph::node(id).unwrap(), &output));
+    let ids: Vec<_> = graph::traverse(&output).map(|node| node.id()).collect();
+    assert_eq!(ids[0], graph::node_id(&x_dynamic));
+    assert_eq!(ids[2], id);
+
+    let mut owner = graph::Graph::new();
+    owner.keep(x.clone());
+    assert!(owner.node(ids[0]).is_some());
+    assert!(owner.node(id).is_none());
+
+    drop(output);
+    assert!(graph::node(id).is_none());
+    // a later node gets a new id, wherever it is
+    assert_ne!(graph::node_id(&add(&x, 1)), id);
+}
+
+#[test]
+fn graph_edges() {
+    let x = create_input();
+    let square = mul(&x, &x);
+    let output = add(&square, ops::sin(&x));
+    let smooth = interp::smoothstep(0.0, 1.0, &x);
+    let _output = Output::new(x.clone(), Evaluation::Eager);
+    let x_dynamic = x.clone() as DynamicComputeNodeRef;
+
+    let names: Vec<_> = graph::parents(&x_dynamic).iter().map(|parent| parent.borrow().name()).collect();
+    // once for both of `mul`'s subscriptions, and not the output listening
+    assert_eq!(names, ["mul", "sin", "smoothstep"]);
+    assert!(Rc::ptr_eq(&graph::parents(&square)[0], &output));
+    assert!(graph::parents(&output).is_empty());
+    assert!(matches!(graph::children(&smooth)[..], [Child::Constant(_), Child::Constant(_), Child::Node(_)]));
+
+    drop(output);
+    assert!(graph::parents(&square).is_empty());
+}
+
+#[test]
+fn dependents_of_inputs() {
+    let x = create_input();
+    let y = create_input();
+    let square = mul(&x, &x);
+    let output = add(&square, &y);
+    let _shown = Output::new(output.clone(), Evaluation::Eager);
+    let _other = ops::cos(&y);
+
+    let dependents = graph::dependents_of(&x);
+    let names: Vec<_> = dependents.nodes.iter().map(|node| node.borrow().name()).collect();
+    assert_eq!(names, ["mul", "add"]);
+    assert_eq!(dependents.listeners, 1);
+    assert_eq!(graph::dependents_of(&y).nodes.len(), 2);
+    assert!(graph::dependents_of(&output).nodes.is_empty());
+    assert!(graph::dependents_of(&1.0).nodes.is_empty());
+}
+
+#[test]
+fn graph_validation() {
+    // a node that never subscribed to its child, so misses its changes
+    struct Forgetful {
+        x: DynamicComputeNodeRef
+    }
+    impl internals::ComputeMut for Forgetful {
+        fn compute(&mut self) -> Float {
+            self.x.compute()
+        }
+        fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    }
+    let x = create_input();
+    let y = create_input();
+    let mut owner = graph::Graph::new();
+    let output = owner.keep(add(&x, 1));
+    let constant = owner.keep(mul(2.0, 3.0));
+    let unused = owner.keep(ops::sin(&y));
+    output.compute();
+    assert_eq!(owner.validate(std::slice::from_ref(&output)).orphaned.len(), 2);
+
+    drop(mul(&x, 3));
+    let doubled = mul(&x, 2);
+    let forgetful: DynamicComputeNodeRef = internals::CachingNodeWrapper::new_ref(Forgetful { x: doubled });
+    forgetful.compute();
+    x.set(1.0);
+
+    let report = owner.validate(&[output.clone(), constant.clone(), forgetful.clone()]);
+    assert!(!report.is_healthy());
+    assert_eq!(report.orphaned, [graph::node_id(&unused)]);
+    assert_eq!(report.unreachable_outputs, [graph::node_id(&constant)]);
+    assert_eq!(report.inconsistent_caches, [graph::node_id(&forgetful)]);
+    // the dropped subscriber of `x` went with `set`, but `y` hasn't changed since
+    owner.release(&unused);
+    drop(unused);
+    let y_dynamic = y.clone() as DynamicComputeNodeRef;
+    let report = owner.validate(&[output.clone(), y_dynamic.clone()]);
+    assert_eq!(report.dangling_subscribers, [(graph::node_id(&y_dynamic), 1)]);
+    assert!(graph::Graph::new().validate(&[output]).is_healthy());
+}
+
+#[test]
+fn graph_compaction() {
+    let x = create_input();
+    let y = create_input();
+    let mut owner = graph::Graph::new();
+    let old: DynamicComputeNodeRef = owner.keep(add(&x, 1));
+    let output: DynamicComputeNodeRef = owner.keep(mul(&old, 2));
+    owner.keep(ops::sin(&y));
+    assert_eq!(output.compute(), 2.0);
+    let new: DynamicComputeNodeRef = add(&x, 2);
+    graph::replace(&old, &new).unwrap();
+    drop(old);
+
+    let y_dynamic = y.clone() as DynamicComputeNodeRef;
+    let outputs = [output.clone(), y_dynamic];
+    assert!(!owner.validate(&outputs).is_healthy());
+    // the sine was only kept by the graph, while `output` still computes through `old`
+    let compaction = owner.compact(&outputs);
+    assert_eq!(compaction, graph::Compaction { released: 1, subscribers: 1 });
+    assert_eq!(owner.len(), 2);
+    assert!(owner.validate(&outputs).is_healthy());
+    assert_eq!(owner.compact(&outputs), graph::Compaction::default());
+    x.set(3.0);
+    assert_eq!(output.compute(), 10.0);
+}
+
+#[test]
+fn strict_math() {
+    use crate::strict;
+    let close = |a: Float, b: Float| a == b || (a - b).abs() <= 4.0 * Float::EPSILON * b.abs().max(Float::MIN_POSITIVE);
+    let x = create_input();
+    let graph = add(ops::sin(&x), ops::pow(ops::exp(&x), 0.5));
+    let loose: Vec<Float> = (-40..40).map(|i| { x.set(i as Float * 0.37); graph.compute() }).collect();
+    let strict_values = strict::with_strict(|| {
+        (-40..40).map(|i| { x.set(i as Float * 0.37); graph.compute() }).collect::<Vec<_>>()
+    });
+    assert!(!strict::is_enabled());
+    for (a, b) in strict_values.iter().zip(&loose) {
+        assert!((a - b).abs() <= 1e-5 * b.abs().max(1.0), "{} {}", a, b);
+    }
+
+    strict::with_strict(|| {
+        for i in -2000..2000 {
+            let x = i as Float * 0.0173;
+            assert!(close(strict::sin(x), x.sin()), "sin {}", x);
+            assert!(close(strict::cos(x), x.cos()), "cos {}", x);
+            assert!(close(strict::exp(x / 40.0), (x / 40.0).exp()), "exp {}", x);
+            assert!(close(strict::tanh(x / 10.0), (x / 10.0).tanh()), "tanh {}", x);
+            assert!(close(strict::ln(x.abs() + 1e-3), (x.abs() + 1e-3).ln()), "ln {}", x);
+        }
+        assert_eq!(strict::powf(-2.0, 3.0), -8.0);
+        assert!(strict::powf(-2.0, 0.5).is_nan());
+        assert_eq!(strict::powf(0.0, -1.0), Float::INFINITY);
+        assert_eq!(strict::ln_1p(1e-10), 1e-10);
+
+        for i in -200..200 {
+            let (y, x) = (i as Float * 0.173, (i * 7 % 23) as Float * 0.5);
+            assert!(close(strict::hypot(x, y), x.hypot(y)), "hypot {} {}", x, y);
+            assert!((strict::atan2(y, x) - y.atan2(x)).abs() <= 2.0 * Float::EPSILON * y.atan2(x).abs().max(1e-30), "atan2 {} {}", y, x);
+        }
+        let special = [0.0, -0.0, 1.0, -1.0, Float::INFINITY, Float::NEG_INFINITY];
+        for y in special {
+            for x in special {
+                assert_eq!(strict::atan2(y, x).to_bits(), y.atan2(x).to_bits(), "atan2 {} {}", y, x);
+            }
+        }
+        assert_eq!(strict::hypot(Float::NAN, Float::NEG_INFINITY), Float::INFINITY);
+        let (sin, cos) = strict::sin_cos(1.0);
+        assert_eq!((sin, cos), (strict::sin(1.0), strict::cos(1.0)));
+    });
+}
+
+#[test]
+fn compensated_sums() {
+    let terms = vec![0.1 as Float; 100_000];
+    let naive: Float = terms.iter().sum();
+    assert!((naive - 10_000.0).abs() > 0.5);
+    assert_eq!(ops::sum(terms).compute(), 10_000.0);
+    assert_eq!(ops::sum(Vec::<Float>::new()).compute(), 0.0);
+    // large terms cancelling out don't swallow the small ones
+    assert_eq!(ops::sum(vec![1.0, 1e8, 1.0, -1e8]).compute(), 2.0);
+
+    let x = create_input();
+    let y = create_input();
+    let mean = ops::mean(vec![x.clone(), y.clone()]);
+    x.set(1.0);
+    y.set(4.0);
+    assert_eq!(mean.compute(), 2.5);
+    let gradients = autodiff::gradients(&mean).unwrap();
+    assert_eq!(gradients.wrt(&x), 0.5);
+}
+
+#[test]
+fn uncertainty_propagation() {
+    let length = create_input();
+    let width = create_input();
+    length.set(2.0);
+    width.set(3.0);
+    let area = mul(&length, &width);
+    let difference = ops::sub(&length, &length);
+    let both = add(&area, &difference);
+    let uncertainties = uncertainty::propagate(&both, &[(length.clone(), 0.1), (width.clone(), 0.2)]).unwrap();
+    let measured = uncertainties.of(&area).unwrap();
+    assert_eq!(measured.value, 6.0);
+    // √((3 * 0.1)² + (2 * 0.2)²)
+    assert!((measured.error - 0.5).abs() < 1e-6);
+    // correlated errors cancel
+    assert_eq!(uncertainties.of(&difference).unwrap().error, 0.0);
+    assert_eq!(uncertainties.of(&length).unwrap().to_string(), "2 ± 0.1");
+    assert_eq!(uncertainties.of(&create_input()), None);
+}
+
+#[test]
+fn assertion_nodes() {
+    use crate::assertions::{assert_finite, assert_in_range, checked};
+    let x = create_input();
+    let checked_ratio = assert_finite(div(1.0, &x));
+    let output = add(mul(&checked_ratio, 2.0), assert_in_range(&x, -10.0, 10.0));
+    x.set(4.0);
+    assert_eq!(checked(&output), Ok(4.5));
+
+    x.set(0.0);
+    let error = checked(&output).unwrap_err();
+    assert_eq!(error.node, graph::node_id(&checked_ratio));
+    assert_eq!(error.path, "add/mul/assert_finite");
+    assert_eq!(error.to_string(), format!("assertion {} failed at add/mul/assert_finite: value is inf", error.node));
+    x.set(20.0);
+    assert_eq!(checked(&output).unwrap_err().message, "20 is outside [-10, 10]");
+    x.set(1.0);
+    assert_eq!(output.compute(), 3.0);
+    let gradients = autodiff::gradients(&output).unwrap();
+    assert_eq!(gradients.wrt(&x), -1.0);
+}
+
+#[test]
+fn probes() {
+    use crate::probe::{self, probe};
+    let x = create_input();
+    let record = probe::record();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let channel = probe::forward(sender);
+    let output = add(probe("doubled", mul(&x, 2)), probe("x", &x));
+    x.set(1.0);
+    assert_eq!(output.compute(), 3.0);
+    // cached, so not reported again
+    assert_eq!(output.compute(), 3.0);
+    x.set(2.0);
+    output.compute();
+    assert_eq!(record.values_of("doubled"), [2.0, 4.0]);
+    assert_eq!(record.values().len(), 4);
+    assert!(probe::remove_sink(channel));
+    x.set(3.0);
+    output.compute();
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [
+        ("doubled".to_string(), 2.0), ("x".to_string(), 1.0), ("doubled".to_string(), 4.0), ("x".to_string(), 2.0)
+    ]);
+    assert_eq!(record.values().len(), 6);
+}
+
+#[test]
+fn step_through_debugging() {
+    use crate::debugger::{Debugger, Control};
+    let x = create_input();
+    x.set(3.0);
+    let output: DynamicComputeNodeRef = add(mul(&x, 2), 1);
+    let mut debugger = Debugger::new(&output);
+    let first = debugger.pending().unwrap();
+    assert_eq!(first.view().name(), "input");
+    assert_eq!(debugger.step().unwrap().value(), Some(3.0));
+    let product = debugger.pending().unwrap();
+    assert_eq!(product.view().name(), "mul");
+    assert_eq!(product.children(), [Some(3.0), Some(2.0)]);
+    assert_eq!(product.was_cached(), Some(false));
+
+    let mut names = Vec::new();
+    let aborted = debugger.run(|_| Control::Continue, |step| {
+        names.push(step.view().name());
+        if step.value() == Some(6.0) { Control::Abort } else { Control::Continue }
+    }).unwrap_err();
+    assert_eq!(names, ["mul"]);
+    assert!(aborted.computed);
+    assert_eq!(aborted.to_string(), format!("evaluation aborted after node {} (`mul`)", aborted.node));
+    assert_eq!(debugger.finish(), 7.0);
+    assert!(debugger.pending().is_none());
+    assert_eq!(Debugger::new(&output).pending().unwrap().was_cached(), None);
+}
+
+#[test]
+fn uncached_nodes() {
+    thread_local! {
+        static EVALUATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+    fn counted(x: Float) -> Float {
+        EVALUATIONS.with(|evaluations| evaluations.set(evaluations.get() + 1));
+        x
+    }
+    define_nodes! {
+        /// Adds without caching
+        #[uncached]
+        inline_add(a, b) { counted(a + b) } grad { [1.0, 1.0] }
+    }
+    let x = create_input();
+    let sum = inline_add(&x, 1);
+    let output = mul(sum.clone(), 2.0);
+    x.set(1.0);
+    assert_eq!(sum.compute(), 2.0);
+    assert_eq!(sum.compute(), 2.0);
+    assert_eq!(EVALUATIONS.with(|evaluations| evaluations.get()), 2);
+    assert_eq!(sum.borrow().is_cached(), None);
+    assert_eq!(sum.borrow().doc(), [" Adds without caching"]);
+    // the parent caches, and is still invalidated through the uncached node
+    assert_eq!(output.compute(), 4.0);
+    assert_eq!(output.compute(), 4.0);
+    assert_eq!(EVALUATIONS.with(|evaluations| evaluations.get()), 3);
+    x.set(2.0);
+    assert_eq!(output.compute(), 6.0);
+    assert_eq!(graph::parents(&sum).len(), 1);
+    assert_eq!(autodiff::gradients(&output).unwrap().wrt(&x), 2.0);
+}
+
+#[test]
+fn automatic_cache_policy() {
+    use std::time::Duration;
+    define_nodes! {
+        slow(x) { std::thread::sleep(Duration::from_millis(2)); x }
+    }
+    let x = create_input();
+    let cheap = add(&x, 1);
+    let expensive = slow(cheap.clone());
+    let output: DynamicComputeNodeRef = mul(expensive.clone(), 2.0);
+    let mut policy = profile::AutoCache::new(&output, Duration::from_micros(500), 3);
+    for i in 0..3 {
+        assert!(!policy.is_warmed_up());
+        x.set(i as Float);
+        assert_eq!(policy.evaluate(), 2.0 * (i as Float + 1.0));
+    }
+    assert!(policy.is_warmed_up());
+    assert_eq!(policy.profile()[0].0, graph::node_id(&expensive));
+    assert_eq!(policy.uncached(), [graph::node_id(&cheap)]);
+    assert_eq!(cheap.borrow().is_cached(), None);
+    assert_eq!(expensive.borrow().is_cached(), Some(true));
+    x.set(5.0);
+    assert_eq!(expensive.borrow().is_cached(), Some(false));
+    assert_eq!(policy.evaluate(), 12.0);
+    // an uncached node still invalidates what is computed from it
+    x.set(6.0);
+    assert_eq!(output.compute(), 14.0);
+}
+
+#[test]
+fn static_graphs() {
+    use crate::static_graph::{StaticNode, var};
+    let f = var(0) * var(1).sin() + 1.0;
+    assert_eq!(f.eval(&[2.0, 0.0]), 1.0);
+    assert_eq!((-var(0)).pow(2.0).eval(&[3.0]), 9.0);
+    assert_eq!(var(0).max(var(1) / 2.0).eval(&[1.0, 4.0]), 2.0);
+
+    let x = create_input();
+    let y = create_input();
+    let node = (var(0) * var(1) - 1.0).into_node(vec![x.clone(), y.clone()]);
+    x.set(2.0);
+    y.set(3.0);
+    assert_eq!(node.compute(), 5.0);
+    y.set(4.0);
+    assert_eq!(node.compute(), 7.0);
+}
+
+const CUTOFF: Float = nyquist::evaluate(48000.0);
+const MIDPOINT: Float = lerp::evaluate(2.0, 4.0, 0.5);
+
+#[test]
+fn constant_nodes() {
+    assert_eq!(CUTOFF, 24000.0);
+    assert_eq!(MIDPOINT, 3.0);
+    // still nodes on values known only at runtime
+    let rate = create_input();
+    let half = nyquist(&rate);
+    rate.set(44100.0);
+    assert_eq!(half.compute(), 22050.0);
+    assert_eq!(lerp(&rate, 0.0, 1.0).compute(), 0.0);
+    assert_eq!(lerp::defaults(0.0, &rate).compute(), 22050.0);
+}
+
+#[test]
+fn input_snapshots() {
+    let (graph, (x, y, output)) = graph::Graph::build(|_| {
+        let x = create_input();
+        let y = create_input();
+        let output = mul(&x, &y);
+        (x, y, output)
+    });
+    x.set(2.0);
+    y.set(3.0);
+    let base = graph.snapshot_inputs();
+    assert_eq!(base.len(), 2);
+    assert_eq!(base.get(&(x.clone() as _)), Some(2.0));
+    assert_eq!(output.compute(), 6.0);
+
+    y.set(5.0);
+    let scenario = graph.snapshot_inputs();
+    assert_eq!(base.changes(&scenario), [(graph::node_id(&(y.clone() as _)), Some(3.0), Some(5.0))]);
+    assert_eq!(output.compute(), 10.0);
+
+    assert_eq!(graph.restore_inputs(&base), 1);
+    assert_eq!(output.compute(), 6.0);
+    // nothing to change
+    assert_eq!(graph.restore_inputs(&base), 0);
+    assert_eq!(graph.snapshot_inputs(), base);
+}
+
+#[test]
+fn input_configuration() {
+    use crate::config::{Config, ConfigError};
+    let json = Config::from_json(r#"{ "gain": 2, "filter": { "cutoff": 0.5, "q": 7e-1 }, "extra": -1 }"#).unwrap();
+    let toml = Config::from_toml("gain = 2\nextra = -1 # unused\n\n[filter]\ncutoff = 0.5\nq = 0.7\n").unwrap();
+    assert_eq!(json, toml);
+    assert_eq!(json.get("filter.q"), Some(0.7));
+    assert_eq!(Config::from_toml("gain = 2\n[filter\n"), Err(ConfigError::Parse { line: 2, message: "unclosed table header".into() }));
+    assert!(matches!(Config::from_json("{\n\"gain\": [1]\n}"), Err(ConfigError::Parse { line: 2, .. })));
+
+    let gain = create_input();
+    let cutoff = create_input();
+    let q = create_input();
+    let resonance = create_input();
+    let output = add(mul(&gain, &cutoff), &q);
+    let eager = Output::new(output.clone(), Evaluation::Eager);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let values = seen.clone();
+    eager.listen(move |value| values.borrow_mut().push(value));
+    let bindings = [("gain", gain.clone()), ("filter.cutoff", cutoff.clone()), ("filter.q", q.clone()), ("filter.resonance", resonance.clone())];
+
+    // nothing is set when a key doesn't match
+    assert_eq!(json.apply_strict(&bindings), Err(ConfigError::Mismatch { unknown: vec!["extra".into()], missing: vec!["filter.resonance".into()] }));
+    assert_eq!(output.compute(), 0.0);
+    let report = json.apply(&bindings);
+    assert_eq!(report.set, ["filter.cutoff", "filter.q", "gain"]);
+    assert!(!report.is_complete());
+    assert_eq!(output.compute(), 1.7);
+    // the listener only saw the whole configuration
+    assert_eq!(*seen.borrow(), [1.7]);
+}
+
+#[test]
+fn environment_and_argument_inputs() {
+    use crate::config::{Config, ConfigError};
+    let vars = [("MODEL_GAIN", "2"), ("MODEL_FILTER__CUTOFF", "0.25"), ("PATH", "/bin"), ("MODEL_Q", "0.5")]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    let mut config = Config::from_vars("MODEL_", vars).unwrap();
+    assert_eq!(config.keys().collect::<Vec<_>>(), ["filter.cutoff", "gain", "q"]);
+    assert!(matches!(Config::from_vars("MODEL_", [("MODEL_GAIN".to_string(), "loud".to_string())]), Err(ConfigError::Invalid { .. })));
+
+    let (options, rest) = Config::from_args(["model.toml", "--set", "gain=3", "-v", "--set=q=1e-1"]).unwrap();
+    assert_eq!(rest, ["model.toml", "-v"]);
+    assert!(matches!(Config::from_args(["--set"]), Err(ConfigError::Invalid { .. })));
+    assert!(matches!(Config::from_args(["--set", "gain"]), Err(ConfigError::Invalid { .. })));
+    config.merge(options);
+    assert_eq!(config.get("gain"), Some(3.0));
+
+    let gain = create_input();
+    let cutoff = create_input();
+    let q = create_input();
+    let output = add(mul(&gain, &cutoff), &q);
+    let report = config.apply_strict(&[("gain", gain), ("filter.cutoff", cutoff), ("q", q)]).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(output.compute(), 0.85);
+}
+
+#[test]
+fn hot_reload() {
+    use crate::reload::{LiveGraph, ReloadError};
+    let path = std::env::temp_dir().join(format!("compgraph-reload-{}.txt", std::process::id()));
+    std::fs::write(&path, "# gain stage\nout = gain * x\ngain = 2\n").unwrap();
+    let mut live = LiveGraph::load(&path).unwrap();
+    assert_eq!(live.inputs().collect::<Vec<_>>(), ["x"]);
+    let out = live.output("out").unwrap();
+    let x = live.input("x").unwrap();
+    x.set(3.0);
+    assert_eq!(out.compute(), 6.0);
+    assert_eq!(live.poll(), Ok(false));
+
+    // the same output handle and input, with a new formula and a new input
+    std::fs::write(&path, "out = gain * x + offset\ngain = 3\n").unwrap();
+    assert_eq!(live.poll(), Ok(true));
+    assert_eq!(out.compute(), 9.0);
+    live.input("offset").unwrap().set(1.0);
+    assert_eq!(out.compute(), 10.0);
+    assert_eq!(live.reloads(), 2);
+
+    // failed reloads change nothing
+    assert_eq!(live.update("out = a\na = out + 1\n"), Err(ReloadError::Cycle { name: "a".into() }));
+    assert_eq!(live.update("gain = 1\n"), Err(ReloadError::RemovedOutput("out".into())));
+    assert!(matches!(live.update("out = x +\n"), Err(ReloadError::Formula { line: 1, .. })));
+    assert_eq!(live.formula("out"), Some("gain * x + offset"));
+    assert_eq!(out.compute(), 10.0);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn graph_dot() {
+    let x = create_input();
+    let shared = mul(&x, 2.0);
+    let a: DynamicComputeNodeRef = add(&shared, &x);
+    let b: DynamicComputeNodeRef = sin(&shared);
+    let dot = graph::to_dot(&[("a", a), ("b", b)]);
+    assert!(dot.starts_with("digraph compgraph {"));
+    // shared nodes once
+    assert_eq!(dot.matches("[label=\"mul\"]").count(), 1);
+    assert_eq!(dot.matches("[label=\"input\"]").count(), 1);
+    assert!(dot.contains("o1 [label=\"b\", shape=ellipse];"));
+}
+
+#[test]
+fn live_graph_evaluation() {
+    use crate::reload::LiveGraph;
+    let mut live = LiveGraph::new();
+    live.update("area = width * height").unwrap();
+    live.input("width").unwrap().set(3.0);
+    live.input("height").unwrap().set(4.0);
+    assert_eq!(live.evaluate("area / 2 + width"), Ok(9.0));
+    assert_eq!(live.evaluate("depth"), Err(expr::ExprError::UnknownVariable("depth".into())));
+    // nothing was added
+    assert_eq!(live.definitions().collect::<Vec<_>>(), ["area"]);
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn graph_server() {
+    use std::{io::{Read, Write, BufRead, BufReader}, net::TcpStream, time::Duration};
+    use crate::server::GraphServer;
+    let x = create_input();
+    let y = create_input();
+    let mut server = GraphServer::bind("127.0.0.1:0").unwrap();
+    server.add_input("x", x.clone());
+    server.add_input("y", y.clone());
+    server.add_output("sum", add(&x, &y));
+    server.add_output("product", mul(&x, &y));
+    let address = server.local_addr().unwrap();
+
+    let client = std::thread::spawn(move || {
+        let request = |text: String| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(text.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            (head.lines().next().unwrap().to_string(), body.to_string())
+        };
+        let post = |body: &str| request(format!("POST /inputs HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body));
+
+        let mut events = BufReader::new(TcpStream::connect(address).unwrap());
+        events.get_mut().write_all(b"GET /events HTTP/1.1\r\n\r\n").unwrap();
+        let mut next_event = || {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                events.read_line(&mut line).unwrap();
+                if line == "\n" && lines.iter().any(|line: &String| line.starts_with("data")) {
+                    return lines.join("");
+                }
+                lines.push(line);
+            }
+        };
+        assert!(next_event().ends_with("event: sum\ndata: 0\n"));
+        assert_eq!(next_event(), "event: product\ndata: 0\n");
+
+        assert_eq!(post(r#"{"x": 2, "y": 3}"#), ("HTTP/1.1 200 OK".to_string(), r#"{"sum":5,"product":6}"#.to_string()));
+        assert_eq!(next_event(), "event: sum\ndata: 5\n");
+        assert_eq!(post(r#"{"z": 1}"#).0, "HTTP/1.1 400 Bad Request");
+        assert_eq!(request("GET /outputs/product HTTP/1.1\r\n\r\n".to_string()).1, "6");
+        assert_eq!(request("GET /inputs HTTP/1.1\r\n\r\n".to_string()).1, r#"{"x":2,"y":3}"#);
+        assert_eq!(request("GET /nothing HTTP/1.1\r\n\r\n".to_string()).0, "HTTP/1.1 404 Not Found");
+
+        // a client sending slowly doesn't hold up the others
+        let mut slow = TcpStream::connect(address).unwrap();
+        slow.write_all(b"GET /inputs HTTP/1.1\r\n").unwrap();
+        assert_eq!(request("POST /inputs HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n".to_string()).0, "HTTP/1.1 413 Content Too Large");
+        assert_eq!(request(format!("GET /inputs HTTP/1.1\r\nX: {}\r\n\r\n", "x".repeat(10_000))).0, "HTTP/1.1 413 Content Too Large");
+        assert_eq!(post(&format!("{}1{}", r#"{"a":"#.repeat(1000), "}".repeat(1000))).0, "HTTP/1.1 400 Bad Request");
+        slow.write_all(b"\r\n").unwrap();
+        let mut response = String::new();
+        slow.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with(r#"{"x":2,"y":3}"#));
+    });
+    while !client.is_finished() {
+        server.poll().unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    client.join().unwrap();
+    assert_eq!(x.compute(), 2.0);
+}
+
+#[test]
+fn protobuf_exchange() {
+    use crate::exchange::{self, ExchangeError};
+    let x = create_input();
+    let y = create_input();
+    x.set(0.5);
+    y.set(2.0);
+    let shared = ops::mul(x.clone(), y.clone());
+    let sum = Operand::Node(ops::add(ops::sin(shared.clone()), shared.clone()));
+    let outputs = [("sum", sum.clone()), ("product", Operand::Node(shared)), ("half", Operand::Constant(0.5))];
+    let message = exchange::encode(&[("x", x.clone()), ("y", y.clone())], &outputs);
+    assert!(exchange::SCHEMA.contains("message Graph"));
+
+    let decoded = exchange::decode(&message, &NodeRegistry::builtin()).unwrap();
+    assert_eq!(decoded.inputs.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["x", "y"]);
+    assert_eq!(decoded.output("sum").unwrap().compute(), sum.compute());
+    assert!(matches!(decoded.output("half"), Some(Operand::Constant(0.5))));
+    decoded.input("y").unwrap().set(3.0);
+    y.set(3.0);
+    assert_eq!(decoded.output("sum").unwrap().compute(), sum.compute());
+    assert_eq!(decoded.output("product").unwrap().compute(), 1.5);
+    assert!(matches!(exchange::decode(&message[..message.len() - 2], &NodeRegistry::builtin()), Err(ExchangeError::Malformed(_))));
+
+    let values = exchange::encode_values(&[("x", 1.5), ("y", -2.0)]);
+    assert_eq!(exchange::decode_values(&values).unwrap(), [("x".to_string(), 1.5), ("y".to_string(), -2.0)]);
+}
+
+#[test]
+fn msgpack_exchange() {
+    use crate::exchange::{self, ExchangeError};
+    let x = create_input();
+    x.set(1.5);
+    let scaled = Operand::Node(ops::mul(ops::add(&x, 300), 0.5));
+    let message = exchange::encode_msgpack(&[("x", x.clone())], &[("scaled", scaled.clone()), ("two", Operand::Constant(2.0))]);
+    // a map of two entries
+    assert_eq!(message[0], 0x82);
+
+    let decoded = exchange::decode_msgpack(&message, &NodeRegistry::builtin()).unwrap();
+    assert_eq!(decoded.output("scaled").unwrap().compute(), 150.75);
+    assert!(matches!(decoded.output("two"), Some(Operand::Constant(2.0))));
+    decoded.input("x").unwrap().set(-300.0);
+    assert_eq!(decoded.output("scaled").unwrap().compute(), 0.0);
+    assert_eq!(exchange::decode_msgpack(&message[..message.len() - 1], &NodeRegistry::builtin()).err(), Some(ExchangeError::Malformed("graph")));
+    // nested deeper than any graph
+    assert!(exchange::decode_msgpack(&[0x91; 64], &NodeRegistry::builtin()).is_err());
+
+    let names: Vec<String> = (0..20).map(|i| format!("input{}", i)).collect();
+    let values: Vec<(&str, Float)> = names.iter().enumerate().map(|(i, name)| (name.as_str(), i as Float * 0.25)).collect();
+    let encoded = exchange::encode_values_msgpack(&values);
+    let decoded = exchange::decode_values_msgpack(&encoded).unwrap();
+    assert_eq!(decoded.len(), 20);
+    assert_eq!(decoded[19], ("input19".to_string(), 4.75));
+}
+
+#[test]
+fn atomic_inputs() {
+    use crate::atomic::AtomicInputs;
+    let x = create_input();
+    let y = create_input();
+    let sum = add(&x, &y);
+    let eager = Output::new(sum.clone(), Evaluation::Eager);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let values = seen.clone();
+    eager.listen(move |value| values.borrow_mut().push(value));
+
+    let mut inputs = AtomicInputs::new();
+    let (x_writer, y_writer) = (inputs.add(x.clone()), inputs.add(y.clone()));
+    assert_eq!(inputs.sync(), 0);
+    let producers: Vec<_> = [(x_writer, 1.0), (y_writer.clone(), 2.0)].into_iter().map(|(writer, last)| {
+        std::thread::spawn(move || {
+            for i in 0..10_000 {
+                writer.set(i as Float);
+            }
+            writer.set(last);
+        })
+    }).collect();
+    producers.into_iter().for_each(|producer| producer.join().unwrap());
+    // one round for both
+    assert_eq!(inputs.sync(), 2);
+    assert_eq!(*seen.borrow(), [3.0]);
+    assert_eq!(inputs.sync(), 0);
+
+    let mut scheduler = Scheduler::new(100.0);
+    let z = create_input();
+    let writer = scheduler.atomic(z.clone());
+    writer.set(4.0);
+    assert_eq!(z.compute(), 0.0);
+    scheduler.frame();
+    assert_eq!(z.compute(), 4.0);
+}
+
+#[test]
+fn double_buffered_inputs() {
+    use crate::buffered::DoubleBuffered;
+    let x = create_input();
+    let y = create_input();
+    let sum = add(&x, &y);
+    x.set(10.0);
+
+    let mut buffers = DoubleBuffered::new();
+    assert_eq!((buffers.add(x.clone()), buffers.add(y.clone())), (0, 1));
+    let writer = buffers.writer();
+    writer.set(1, 5.0);
+    assert_eq!(writer.values(), [10.0, 5.0]);
+    // not until a flip
+    assert_eq!(sum.compute(), 10.0);
+    assert_eq!(buffers.compute(&sum), 15.0);
+    assert_eq!(buffers.flip(), 0);
+
+    // moving between inputs, whose sum each evaluation sees whole
+    let producer = {
+        let writer = writer.clone();
+        std::thread::spawn(move || {
+            for i in 0..10_000 {
+                writer.update(|values| {
+                    values[0] = i as Float;
+                    values[1] = 15.0 - i as Float;
+                });
+            }
+        })
+    };
+    while !producer.is_finished() {
+        assert_eq!(buffers.compute(&sum), 15.0);
+    }
+    producer.join().unwrap();
+    assert_eq!(buffers.compute(&sum), 15.0);
+    assert_eq!((x.compute(), y.compute()), (9999.0, -9984.0));
+}
+
+#[test]
+fn graph_actor() {
+    use crate::actor::{GraphActor, ActorError};
+    let actor = GraphActor::spawn(|graph| {
+        let x = create_input();
+        let y = create_input();
+        graph.add_output("sum", add(&x, &y));
+        graph.add_output("product", mul(&x, &y));
+        graph.add_input("x", x);
+        graph.add_input("y", y);
+    });
+    let sums = actor.subscribe("sum").wait().unwrap();
+    assert_eq!(sums.recv().unwrap(), 0.0);
+
+    let workers: Vec<_> = (1..=4).map(|i| {
+        let handle = actor.handle();
+        std::thread::spawn(move || {
+            handle.set_all(&[("x", i as Float), ("y", i as Float)]).wait().unwrap();
+            handle.compute("product").wait().unwrap()
+        })
+    }).collect();
+    for worker in workers {
+        // both inputs from the same command
+        let product = worker.join().unwrap();
+        assert!([1.0, 4.0, 9.0, 16.0].contains(&product));
+    }
+    let sums: Vec<Float> = sums.try_iter().collect();
+    assert_eq!(sums.len(), 4);
+    assert!(sums.iter().all(|sum| [2.0, 4.0, 6.0, 8.0].contains(sum)));
+
+    assert_eq!(actor.set_all(&[("x", 1.0), ("z", 2.0)]).wait(), Err(ActorError::UnknownInput("z".to_string())));
+    assert_eq!(actor.compute("difference").wait(), Err(ActorError::UnknownOutput("difference".to_string())));
+    let x = actor.get("x").wait().unwrap();
+    let reply = actor.compute("sum");
+    assert_eq!(reply.wait(), Ok(x * 2.0));
+
+    let handle = actor.handle();
+    drop(actor);
+    assert_eq!(handle.compute("sum").wait(), Err(ActorError::Stopped));
+}
+
+#[test]
+fn finite_difference_fallback() {
+    use crate::autodiff::Fallback;
+    let x = create_input();
+    let y = create_input();
+    x.set(3.0);
+    y.set(2.0);
+    // add3 has no `grad` block
+    let graph = mul(add3(mul(x.clone(), x.clone()), y.clone(), 1.0), y.clone());
+    assert_eq!(autodiff::gradients(&graph).err(), Some(autodiff::NoDerivative { node: "add3" }));
+
+    let gradients = autodiff::gradients_with(&graph, Fallback::CentralDifference { epsilon: 1e-2 }).unwrap();
+    assert!((gradients.wrt(&x) - 12.0).abs() < 1e-2);
+    assert!((gradients.wrt(&y) - 14.0).abs() < 1e-2);
+    // the graph is left as it was
+    assert_eq!(graph.compute(), 24.0);
+    assert_eq!(autodiff::gradients_with(&x, Fallback::Fail).unwrap().wrt(&x), 1.0);
+
+    // the nodes probed are left out of a build in progress
+    let (built, ()) = graph::Graph::build(|_| {
+        let graph = add3(x.clone(), y.clone(), 1.0);
+        let gradients = autodiff::gradients_with(&graph, Fallback::CentralDifference { epsilon: 1e-2 }).unwrap();
+        assert!((gradients.wrt(&y) - 1.0).abs() < 1e-2);
+    });
+    assert_eq!(built.len(), 1);
+    assert_eq!(built.statistics().shared, 0);
+}
+
+#[test]
+fn eager_outputs_on_solvers() {
+    let a = create_input();
+    a.set(2.0);
+    let root = solver::fixed_point(|x| mul(0.5, add(x.clone(), div(a.clone(), x))), 1.0, 1e-6, 50);
+    let first = Output::new(root.clone(), Evaluation::Eager);
+    let second = Output::new(root.clone(), Evaluation::Eager);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let values = seen.clone();
+    second.listen(move |value| values.borrow_mut().push(value));
+    a.set(0.49);
+    assert!((first.get() - 0.7).abs() < 1e-5);
+    assert_eq!(seen.borrow().len(), 1);
+
+    // the stage points of a step, and the restore after them, aren't seen
+    let system = OdeSystem::new(&[0.0], ode::Method::Rk4);
+    system.set_derivative(0, &add(system.time(), 1.0));
+    let state = Output::new(system.state(0), Evaluation::Eager);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let values = seen.clone();
+    state.listen(move |value| values.borrow_mut().push(value));
+    system.step(1.0);
+    system.step(1.0);
+    system.reset();
+    assert_eq!(*seen.borrow(), [1.5, 4.0, 0.0]);
+}
+
+#[test]
+fn wrapped_inputs_in_transactions() {
+    use crate::config::Config;
+    use crate::atomic::AtomicInputs;
+    // a wrapper that can only be set, whose assignments are queued
+    #[derive(Clone)]
+    struct Forwarding(Input);
+    impl ComputeNodeRef for Forwarding {
+        fn compute(&self) -> Float { self.0.compute() }
+        fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+            self.0.subscribe_to_invalidate(subscriber)
+        }
+    }
+    impl InputNodeRef for Forwarding {
+        fn set(&self, value: Float) { self.0.set(value) }
+    }
+
+    let config = Config::from_json(r#"{ "x": 1, "y": 2 }"#).unwrap();
+    let recorder = Recorder::new();
+    let (x, y) = (create_input(), create_input());
+    let sum = add(&x, &y);
+    let eager = Output::new(sum.clone(), Evaluation::Eager);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let values = seen.clone();
+    eager.listen(move |value| values.borrow_mut().push(value));
+
+    config.apply(&[("x", recorder.record("x", x.clone())), ("y", recorder.record("y", y.clone()))]);
+    let mut inputs = AtomicInputs::new();
+    inputs.add(recorder.record("y", y.clone())).set(3.0);
+    inputs.sync();
+    let log = recorder.log();
+    assert_eq!(log.events.iter().map(|event| (event.input.as_str(), event.value)).collect::<Vec<_>>(), [("x", 1.0), ("y", 2.0), ("y", 3.0)]);
+
+    config.apply(&[("x", Forwarding(y.clone())), ("y", Forwarding(x.clone()))]);
+    assert_eq!(*seen.borrow(), [3.0, 4.0, 3.0]);
+}
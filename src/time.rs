@@ -0,0 +1,376 @@
+use std::{rc::{Rc, Weak}, cell::RefCell};
+
+use crate::compgraph::*;
+use crate::compgraph::internals::*;
+use crate::stateful::*;
+
+// A clock driving time-dependent graphs (animations, simulations)
+//
+// Instead of `set()`ting a time input by hand each frame, the application calls
+// `clock.tick(dt)` once per frame, which advances the time and invalidates everything
+// that depends on it in a single step. Stateful nodes attached to the clock are stepped
+// by each tick, before the time advances
+
+struct ClockState {
+    // accumulated in f64 so that long runs don't drift
+    time: f64,
+    delta: Float,
+    frame: u64,
+    // bumped on every tick and reset, unlike the frame
+    version: u64,
+    invalidate_publisher: InvalidatePublisher,
+    stateful_nodes: Vec<Weak<RefCell<dyn StatefulNodeMut>>>
+}
+
+#[derive(Clone)]
+pub struct Clock {
+    state: Rc<RefCell<ClockState>>
+}
+
+// Read-only node exposing the clock's current time or its last time step
+#[derive(Clone)]
+pub struct TimeInput {
+    clock: Rc<RefCell<ClockState>>,
+    read: fn(&ClockState) -> Float
+}
+
+impl ComputeNodeRef for TimeInput {
+    fn compute(&self) -> Float {
+        (self.read)(&self.clock.borrow())
+    }
+    fn subscribe_to_invalidate(&self, subscriber: &Rc<RefCell<dyn InvalidateCacheMut>>) {
+        self.clock.borrow_mut().invalidate_publisher.subscribe_to_invalidate(subscriber)
+    }
+    fn version(&self) -> u64 {
+        self.clock.borrow().version
+    }
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock { state: Rc::new(RefCell::new(ClockState {
+            time: 0.0, delta: 0.0, frame: 0, version: 0, invalidate_publisher: InvalidatePublisher::new(), stateful_nodes: Vec::new()
+        })) }
+    }
+
+    pub fn time(&self) -> TimeInput {
+        TimeInput { clock: self.state.clone(), read: |clock| clock.time as Float }
+    }
+
+    // The `dt` of the last tick, zero before the first one
+    pub fn delta(&self) -> TimeInput {
+        TimeInput { clock: self.state.clone(), read: |clock| clock.delta }
+    }
+
+    pub fn now(&self) -> f64 {
+        self.state.borrow().time
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.state.borrow().frame
+    }
+
+    // Steps the node on every tick for as long as it is alive
+    pub fn attach(&self, node: &DynamicStatefulNodeRef) {
+        self.state.borrow_mut().stateful_nodes.push(Rc::downgrade(node))
+    }
+
+    fn attached_nodes(&self) -> Vec<DynamicStatefulNodeRef> {
+        let mut state = self.state.borrow_mut();
+        state.stateful_nodes.retain(|node| node.strong_count() > 0);
+        state.stateful_nodes.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    pub fn tick(&self, dt: Float) {
+        // prepare all nodes before committing any, so that no node sees another's new state
+        let nodes = self.attached_nodes();
+        for node in &nodes {
+            let prepare = node.borrow().prepare_step();
+            prepare(dt);
+        }
+        for node in &nodes {
+            node.borrow_mut().commit_step(dt);
+        }
+
+        {
+            let mut state = self.state.borrow_mut();
+            state.time += dt as f64;
+            state.delta = dt;
+            state.frame += 1;
+            state.version += 1;
+        }
+        self.publish_invalidate();
+    }
+
+    // Without the state borrowed, so that subscribers can read the clock
+    fn publish_invalidate(&self) {
+        let mut publisher = std::mem::replace(&mut self.state.borrow_mut().invalidate_publisher, InvalidatePublisher::new());
+        publisher.publish_invalidate();
+        let mut state = self.state.borrow_mut();
+        publisher.append(&mut state.invalidate_publisher);
+        state.invalidate_publisher = publisher;
+        drop(state);
+        run_deferred();
+    }
+
+    // Rewinds to time zero and frame zero, resetting the attached nodes
+    pub fn reset(&self) {
+        for node in self.attached_nodes() {
+            node.borrow_mut().reset();
+        }
+        {
+            let mut state = self.state.borrow_mut();
+            state.time = 0.0;
+            state.delta = 0.0;
+            state.frame = 0;
+            state.version += 1;
+        }
+        self.publish_invalidate();
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+// Keyframe animation: a track of values at points in time, eased in between
+//
+// An animation node computes the track at its time child, usually the clock's time (see
+// `Clock::animate`), so it follows the clock without any `set()`s. Before the first keyframe
+// the track is at the first value and after the last one at the last value, unless it loops
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    // holds the previous value until the keyframe
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut
+}
+
+impl Easing {
+    // The eased progress through a segment and its derivative
+    fn apply(self, u: Float) -> (Float, Float) {
+        match self {
+            Easing::Step => (if u >= 1.0 { 1.0 } else { 0.0 }, 0.0),
+            Easing::Linear => (u, 1.0),
+            Easing::EaseIn => (u * u, 2.0 * u),
+            Easing::EaseOut => (1.0 - (1.0 - u) * (1.0 - u), 2.0 * (1.0 - u)),
+            Easing::EaseInOut => (u * u * (3.0 - 2.0 * u), 6.0 * u * (1.0 - u))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: Float,
+    pub value: Float,
+    // of the segment leading up to this keyframe
+    pub easing: Easing
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Track {
+    keyframes: Vec<Keyframe>,
+    looping: bool
+}
+
+impl Track {
+    pub fn new() -> Track {
+        Track::default()
+    }
+
+    // Keyframes can be added in any order
+    pub fn keyframe(mut self, time: Float, value: Float, easing: Easing) -> Track {
+        let index = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        self.keyframes.insert(index, Keyframe { time, value, easing });
+        self
+    }
+
+    // Repeats the track from its first keyframe after the last one
+    pub fn looping(mut self, looping: bool) -> Track {
+        self.looping = looping;
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // The value at `time` and its derivative with respect to time
+    pub fn sample(&self, time: Float) -> (Float, Float) {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return (0.0, 0.0);
+        };
+        let duration = last.time - first.time;
+        let time = if self.looping && duration > 0.0 { first.time + (time - first.time).rem_euclid(duration) } else { time };
+        if time <= first.time {
+            return (first.value, 0.0);
+        }
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == self.keyframes.len() {
+            return (last.value, 0.0);
+        }
+        let (from, to) = (self.keyframes[next - 1], self.keyframes[next]);
+        let span = to.time - from.time;
+        let (progress, slope) = to.easing.apply((time - from.time) / span);
+        (from.value + (to.value - from.value) * progress, (to.value - from.value) * slope / span)
+    }
+}
+
+struct Animation<T> {
+    time: T,
+    track: Rc<Track>
+}
+
+impl<T: ComputeNodeRef> ComputeMut for Animation<T> {
+    fn compute(&mut self) -> Float {
+        self.track.sample(self.time.compute()).0
+    }
+    fn name(&self) -> &'static str { "animation" }
+    fn children(&self) -> Vec<Child> { vec![self.time.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![self.track.sample(self.time.compute()).1]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        Some(animation_node(children[0].clone(), self.track.clone()))
+    }
+}
+
+fn animation_node(time: impl ComputeNodeRef + 'static, track: Rc<Track>) -> DynamicComputeNodeRef {
+    let result = CachingNodeWrapper::new_ref(Animation { time, track });
+    let subscriber = result.clone() as _;
+    result.borrow().inner.time.subscribe_to_invalidate(&subscriber);
+    result
+}
+
+pub fn animation(time: impl ComputeNodeRef + 'static, track: Track) -> DynamicComputeNodeRef {
+    animation_node(time, Rc::new(track))
+}
+
+impl Clock {
+    // The track played from time zero of the clock
+    pub fn animate(&self, track: Track) -> DynamicComputeNodeRef {
+        animation(self.time(), track)
+    }
+}
+
+// Rate limiting: nodes passing on the changes of a frequently changing node (a sensor input,
+// a slider dragged around) to their dependents at most once per `interval` of clock time
+//
+// `throttle` passes the first change on at once and holds back the ones that follow within
+// the interval, passing them on together at the first tick after it. `debounce` waits for
+// the changes to settle: it passes them on at the first tick at least `interval` after the
+// last one. While held back, dependents keep computing with the node's cached value
+//
+// A node only passes on an invalidation while it has a cached value, so the limited node is
+// recomputed after each change (once the change has spread), to hear of the next one
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RateLimit {
+    Throttle,
+    Debounce
+}
+
+struct Limiter {
+    mode: RateLimit,
+    interval: f64,
+    time: TimeInput,
+    pending: bool,
+    // of the last release when throttling, of the last change when debouncing
+    since: f64,
+    node: Option<Weak<RefCell<dyn InvalidateCacheMut>>>,
+    // recomputes the limited node
+    rearm: Rc<dyn Fn()>
+}
+
+impl Limiter {
+    fn release(&mut self, now: f64) {
+        self.pending = false;
+        if self.mode == RateLimit::Throttle {
+            self.since = now;
+        }
+        if let Some(node) = self.node.as_ref().and_then(Weak::upgrade) {
+            node.borrow_mut().invalidate_cache();
+        }
+    }
+}
+
+// Subscribed to the limited node and to the clock, told apart by `tick`
+struct LimiterGate {
+    limiter: Rc<RefCell<Limiter>>,
+    tick: bool
+}
+
+impl InvalidateCacheMut for LimiterGate {
+    fn invalidate_cache(&mut self) {
+        let mut limiter = self.limiter.borrow_mut();
+        let now = limiter.time.compute() as f64;
+        if !self.tick {
+            defer({
+                let rearm = limiter.rearm.clone();
+                move || rearm()
+            });
+            limiter.pending = true;
+            if limiter.mode == RateLimit::Debounce {
+                limiter.since = now;
+                return;
+            }
+        }
+        if limiter.pending && now - limiter.since >= limiter.interval {
+            limiter.release(now);
+        }
+    }
+}
+
+struct RateLimited<X> {
+    x: X,
+    limiter: Rc<RefCell<Limiter>>,
+    _gates: [Rc<RefCell<LimiterGate>>; 2]
+}
+
+impl<X: ComputeNodeRef> ComputeMut for RateLimited<X> {
+    fn compute(&mut self) -> Float {
+        self.x.compute()
+    }
+    fn name(&self) -> &'static str {
+        match self.limiter.borrow().mode {
+            RateLimit::Throttle => "throttle",
+            RateLimit::Debounce => "debounce"
+        }
+    }
+    fn children(&self) -> Vec<Child> { vec![self.x.as_child()] }
+    fn partials(&mut self) -> Option<Vec<Float>> { Some(vec![1.0]) }
+    fn rebuild(&self, children: &[Operand]) -> Option<DynamicComputeNodeRef> {
+        let limiter = self.limiter.borrow();
+        Some(rate_limited(children[0].clone(), limiter.time.clone(), limiter.mode, limiter.interval))
+    }
+}
+
+fn rate_limited(x: impl ComputeNodeRef + 'static, time: TimeInput, mode: RateLimit, interval: f64) -> DynamicComputeNodeRef {
+    let rearm: Rc<dyn Fn()> = Rc::new({
+        let x = x.clone();
+        move || { x.compute(); }
+    });
+    rearm();
+    let limiter = Rc::new(RefCell::new(Limiter {
+        mode, interval, time: time.clone(), pending: false, since: f64::NEG_INFINITY, node: None, rearm
+    }));
+    let gates = [false, true].map(|tick| Rc::new(RefCell::new(LimiterGate { limiter: limiter.clone(), tick })));
+    let [source_gate, tick_gate] = gates.clone();
+    x.subscribe_to_invalidate(&(source_gate as _));
+    time.subscribe_to_invalidate(&(tick_gate as _));
+    let result = CachingNodeWrapper::new_ref(RateLimited { x, limiter: limiter.clone(), _gates: gates });
+    let node: Rc<RefCell<dyn InvalidateCacheMut>> = result.clone();
+    limiter.borrow_mut().node = Some(Rc::downgrade(&node));
+    result
+}
+
+pub fn throttle(x: impl ComputeNodeRef + 'static, clock: &Clock, interval: Float) -> DynamicComputeNodeRef {
+    rate_limited(x, clock.time(), RateLimit::Throttle, interval as f64)
+}
+
+pub fn debounce(x: impl ComputeNodeRef + 'static, clock: &Clock, interval: Float) -> DynamicComputeNodeRef {
+    rate_limited(x, clock.time(), RateLimit::Debounce, interval as f64)
+}
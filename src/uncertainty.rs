@@ -0,0 +1,80 @@
+use std::{fmt, collections::HashMap};
+
+use crate::compgraph::*;
+use crate::autodiff::NoDerivative;
+
+// Propagation of measurement uncertainty
+//
+// Inputs holding measured values are given their standard errors, and `propagate` evaluates
+// the graph with a value and a standard error for every node, to first order. The errors
+// travel through the same partial derivatives as `autodiff`'s gradients, but forwards:
+// each node's sensitivities to the measured inputs are found from its children's, so that
+// a quantity correlated with itself (`x - x`, `x * x`) gets the error it should rather than
+// that of independent terms. The measured inputs are assumed independent of each other;
+// other inputs and constants are exact
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub value: Float,
+    pub error: Float
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ± {}", self.value, self.error)
+    }
+}
+
+pub struct Uncertainties {
+    // d node / d measured input, in the order of the inputs given to `propagate`
+    sensitivities: HashMap<*const (), Vec<Float>>,
+    errors: Vec<Float>,
+    // keeps the nodes alive, so that their addresses can't be reused by other nodes
+    _graph: Vec<DynamicComputeNodeRef>
+}
+
+impl Uncertainties {
+    // `None` for nodes the output doesn't depend on
+    pub fn of(&self, node: &impl ComputeNodeRef) -> Option<Measurement> {
+        let sensitivities = self.sensitivities.get(&node_address(&node.as_dynamic()?))?;
+        let variance = sensitivities.iter().zip(&self.errors).map(|(s, e)| (s * e) * (s * e)).sum::<Float>();
+        Some(Measurement { value: node.compute(), error: variance.sqrt() })
+    }
+}
+
+pub fn propagate<P: InputNodeRef>(output: &impl ComputeNodeRef, measured: &[(P, Float)]) -> Result<Uncertainties, NoDerivative> {
+    let errors: Vec<Float> = measured.iter().map(|(_, error)| *error).collect();
+    let mut sensitivities = HashMap::new();
+    let Some(output) = output.as_dynamic() else {
+        return Ok(Uncertainties { sensitivities, errors, _graph: Vec::new() });
+    };
+    // the partials are computed from the children's cached values
+    output.compute();
+    let mut unit = HashMap::new();
+    for (i, (input, _)) in measured.iter().enumerate() {
+        if let Some(input) = input.as_dynamic() {
+            unit.insert(node_address(&input), i);
+        }
+    }
+
+    let graph = topological_order(&output);
+    for node in &graph {
+        let mut node_sensitivities = vec![0.0; measured.len()];
+        if let Some(&i) = unit.get(&node_address(node)) {
+            node_sensitivities[i] = 1.0;
+        } else if !is_input(node) {
+            let mut node = node.borrow_mut();
+            let partials = node.partials().ok_or(NoDerivative { node: node.name() })?;
+            let children = node.children();
+            assert_eq!(partials.len(), children.len(), "node `{}` has a partial for each child", node.name());
+            for (child, partial) in children.into_iter().zip(partials) {
+                let Child::Node(child) = child else { continue };
+                for (s, child_s) in node_sensitivities.iter_mut().zip(&sensitivities[&node_address(&child)]) {
+                    *s += partial * child_s;
+                }
+            }
+        }
+        sensitivities.insert(node_address(node), node_sensitivities);
+    }
+    Ok(Uncertainties { sensitivities, errors, _graph: graph })
+}
@@ -0,0 +1,176 @@
+use std::{error::Error, fmt, ops::{Mul, Div}};
+
+use crate::compgraph::*;
+use crate::numeric;
+
+// Lightweight units of measure: a `Quantity` is a node tagged with the exponents of the
+// SI base units, and combinations are checked when the graph is constructed
+//
+// The checks happen at runtime (stable Rust can't do arithmetic on const generic
+// exponents), but before any value is computed
+
+const BASE_SYMBOLS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Unit {
+    exponents: [i8; 7]
+}
+
+impl Unit {
+    pub const DIMENSIONLESS: Unit = Unit::base(usize::MAX);
+    pub const METER: Unit = Unit::base(0);
+    pub const KILOGRAM: Unit = Unit::base(1);
+    pub const SECOND: Unit = Unit::base(2);
+    pub const AMPERE: Unit = Unit::base(3);
+    pub const KELVIN: Unit = Unit::base(4);
+    pub const MOLE: Unit = Unit::base(5);
+    pub const CANDELA: Unit = Unit::base(6);
+
+    const fn base(index: usize) -> Unit {
+        let mut exponents = [0; 7];
+        if index < exponents.len() {
+            exponents[index] = 1;
+        }
+        Unit { exponents }
+    }
+
+    // Exponents beyond those of an `i8` overflow, as an error
+    pub fn powi(self, n: i8) -> Result<Unit, UnitOverflow> {
+        Unit::exponents(|i| self.exponents[i].checked_mul(n))
+    }
+
+    pub fn checked_mul(self, rhs: Unit) -> Result<Unit, UnitOverflow> {
+        Unit::exponents(|i| self.exponents[i].checked_add(rhs.exponents[i]))
+    }
+
+    pub fn checked_div(self, rhs: Unit) -> Result<Unit, UnitOverflow> {
+        Unit::exponents(|i| self.exponents[i].checked_sub(rhs.exponents[i]))
+    }
+
+    pub fn is_dimensionless(self) -> bool {
+        self == Unit::DIMENSIONLESS
+    }
+
+    fn exponents(exponent: impl Fn(usize) -> Option<i8>) -> Result<Unit, UnitOverflow> {
+        let mut exponents = [0; 7];
+        for (i, e) in exponents.iter_mut().enumerate() {
+            *e = exponent(i).ok_or(UnitOverflow)?;
+        }
+        Ok(Unit { exponents })
+    }
+}
+
+// The operators are for units known not to overflow, like constants, and panic otherwise
+impl Mul for Unit {
+    type Output = Unit;
+    fn mul(self, rhs: Unit) -> Unit {
+        self.checked_mul(rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Div for Unit {
+    type Output = Unit;
+    fn div(self, rhs: Unit) -> Unit {
+        self.checked_div(rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_dimensionless() {
+            return write!(f, "1");
+        }
+        let mut separator = "";
+        for (symbol, &exponent) in BASE_SYMBOLS.iter().zip(&self.exponents) {
+            match exponent {
+                0 => continue,
+                1 => write!(f, "{}{}", separator, symbol)?,
+                _ => write!(f, "{}{}^{}", separator, symbol, exponent)?
+            }
+            separator = " ";
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnitMismatch {
+    pub expected: Unit,
+    pub found: Unit
+}
+
+impl fmt::Display for UnitMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unit mismatch: expected [{}], found [{}]", self.expected, self.found)
+    }
+}
+
+impl Error for UnitMismatch {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnitOverflow;
+
+impl fmt::Display for UnitOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unit exponent out of range")
+    }
+}
+
+impl Error for UnitOverflow {}
+
+// A node together with the unit of the value it computes
+#[derive(Clone)]
+pub struct Quantity<N = DynamicComputeNodeRef> {
+    node: N,
+    unit: Unit
+}
+
+impl<N: ComputeNodeRef + 'static> Quantity<N> {
+    pub fn new(node: N, unit: Unit) -> Quantity<N> {
+        Quantity { node, unit }
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub fn compute(&self) -> Float {
+        self.node.compute()
+    }
+
+    // Unwraps the node for use with untyped nodes, checking that it has the expected unit
+    pub fn node_in(&self, unit: Unit) -> Result<N, UnitMismatch> {
+        check(unit, self.unit)?;
+        Ok(self.node.clone())
+    }
+}
+
+fn check(expected: Unit, found: Unit) -> Result<(), UnitMismatch> {
+    if expected == found { Ok(()) } else { Err(UnitMismatch { expected, found }) }
+}
+
+pub fn add(
+    a: &Quantity<impl ComputeNodeRef + 'static>, b: &Quantity<impl ComputeNodeRef + 'static>
+) -> Result<Quantity, UnitMismatch> {
+    check(a.unit, b.unit)?;
+    Ok(Quantity::new(numeric::add(a.node.clone(), b.node.clone()), a.unit))
+}
+
+pub fn sub(
+    a: &Quantity<impl ComputeNodeRef + 'static>, b: &Quantity<impl ComputeNodeRef + 'static>
+) -> Result<Quantity, UnitMismatch> {
+    check(a.unit, b.unit)?;
+    Ok(Quantity::new(numeric::sub(a.node.clone(), b.node.clone()), a.unit))
+}
+
+pub fn mul(
+    a: &Quantity<impl ComputeNodeRef + 'static>, b: &Quantity<impl ComputeNodeRef + 'static>
+) -> Result<Quantity, UnitOverflow> {
+    Ok(Quantity::new(numeric::mul(a.node.clone(), b.node.clone()), a.unit.checked_mul(b.unit)?))
+}
+
+pub fn div(
+    a: &Quantity<impl ComputeNodeRef + 'static>, b: &Quantity<impl ComputeNodeRef + 'static>
+) -> Result<Quantity, UnitOverflow> {
+    Ok(Quantity::new(numeric::div(a.node.clone(), b.node.clone()), a.unit.checked_div(b.unit)?))
+}